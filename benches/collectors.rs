@@ -0,0 +1,55 @@
+//! Latency of each stat collector on this system, measured through `upkg stat <id>`.
+//!
+//! Run with `cargo bench`; `upkg bench` gives a quicker one-off report.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::process::Command;
+
+const STATS: [&str; 26] = [
+    "installed",
+    "upgradable",
+    "last_update",
+    "download_size",
+    "installed_size",
+    "net_upgrade_size",
+    "orphaned_packages",
+    "cache_size",
+    "mirror_url",
+    "mirror_health",
+    "upgrade_eta",
+    "pending_hooks",
+    "aur_updates",
+    "upgrade_kind",
+    "repo_health",
+    "distro",
+    "testing_updates",
+    "files_db",
+    "group_suggestions",
+    "other_sources",
+    "monthly_data",
+    "data_cost",
+    "store_size",
+    "foreign_packages",
+    "aur_download_size",
+    "upgrade_breakdown",
+];
+
+fn upkg(args: &[&str]) {
+    // Unavailable stats exit non-zero, which still times the collector
+    let _ = Command::new(env!("CARGO_BIN_EXE_upkg")).args(args).output();
+}
+
+fn collectors(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stat");
+    group.sample_size(10);
+
+    // Process startup and config loading, included in every other measurement
+    group.bench_function("startup", |b| b.iter(|| upkg(&["state-dir"])));
+    for stat in STATS {
+        group.bench_function(stat, |b| b.iter(|| upkg(&["stat", stat])));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, collectors);
+criterion_main!(benches);
@@ -0,0 +1,363 @@
+use crate::pacman::ForeignPackage;
+use crate::{distro, paths, storage, util};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+const AUR_RPC_URL: &str = "https://aur.archlinux.org/rpc/v5/info";
+/// A package base's build metadata, by base name.
+const AUR_SRCINFO_URL: &str = "https://aur.archlinux.org/cgit/aur.git/plain/.SRCINFO";
+/// The AUR helpers pacfetch knows, in the order it prefers them.
+const AUR_HELPERS: [&str; 2] = ["paru", "yay"];
+/// Names per request, keeping the query string within the RPC's URL limit.
+const AUR_RPC_CHUNK: usize = 100;
+/// How long an RPC answer is reused, so back-to-back runs don't each ask the AUR.
+const AUR_CACHE_SECS: i64 = 3600;
+
+/// Package metadata from the AUR RPC `info` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AurInfo {
+    pub name: String,
+    /// The package base it's built from, which split packages share.
+    #[serde(default)]
+    pub package_base: String,
+    pub version: String,
+    /// When the package was flagged out-of-date, if it is.
+    pub out_of_date: Option<i64>,
+    pub last_modified: i64,
+    /// None when the package is orphaned.
+    pub maintainer: Option<String>,
+    pub num_votes: u32,
+    pub popularity: f64,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    #[serde(default)]
+    results: Vec<AurInfo>,
+    error: Option<String>,
+}
+
+/// The last RPC answer (~/.cache/pacfetch/aur-info.json) and the names it was asked for.
+#[derive(Serialize, Deserialize)]
+struct InfoCache {
+    fetched: i64,
+    names: Vec<String>,
+    results: Vec<AurInfo>,
+}
+
+impl InfoCache {
+    /// The cached results for `names`, if the cache is fresh and asked for all of them.
+    fn lookup(self, names: &[String], now: i64) -> Option<Vec<AurInfo>> {
+        if now - self.fetched > AUR_CACHE_SECS || !names.iter().all(|n| self.names.contains(n)) {
+            return None;
+        }
+        Some(
+            self.results
+                .into_iter()
+                .filter(|info| names.contains(&info.name))
+                .collect(),
+        )
+    }
+}
+
+/// Look up the given packages in the AUR, reusing an answer from the last hour. Names not
+/// found are absent from the result.
+pub fn info(names: &[String]) -> Result<Vec<AurInfo>, String> {
+    let now = chrono::Local::now().timestamp();
+    let path = paths::cache_file("aur-info.json");
+    if let Some(results) = path
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|contents| serde_json::from_str::<InfoCache>(&contents).ok())
+        .and_then(|cache| cache.lookup(names, now))
+    {
+        return Ok(results);
+    }
+
+    let results = fetch_info(names)?;
+    // A cache that can't be written only costs the next run a request
+    if let Some(path) = path
+        && paths::ensure_parent(&path).is_ok()
+    {
+        let cache = InfoCache {
+            fetched: now,
+            names: names.to_vec(),
+            results,
+        };
+        if let Ok(contents) = serde_json::to_string(&cache)
+            && storage::write_atomic(&path, contents).is_ok()
+        {
+            let _ = paths::return_to_sudo_user(&path);
+        }
+        return Ok(cache.results);
+    }
+    Ok(results)
+}
+
+fn fetch_info(names: &[String]) -> Result<Vec<AurInfo>, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut results = Vec::new();
+    for chunk in names.chunks(AUR_RPC_CHUNK) {
+        let query: Vec<(&str, &str)> = chunk.iter().map(|n| ("arg[]", n.as_str())).collect();
+        let body = client
+            .get(AUR_RPC_URL)
+            .query(&query)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.text())
+            .map_err(|e| format!("AUR request failed: {}", e))?;
+
+        let response: RpcResponse =
+            serde_json::from_str(&body).map_err(|e| format!("Invalid AUR response: {}", e))?;
+        if let Some(error) = response.error {
+            return Err(format!("AUR error: {}", error));
+        }
+        results.extend(response.results);
+    }
+    Ok(results)
+}
+
+/// The first installed AUR helper, e.g. "paru".
+pub fn helper() -> Option<&'static str> {
+    AUR_HELPERS
+        .into_iter()
+        .find(|helper| util::command_exists(helper))
+}
+
+/// Names of foreign packages whose AUR version is newer than the installed one. An
+/// installed helper is asked first, since it also knows about ignored and -git packages.
+pub fn pending_updates(foreign: &[ForeignPackage]) -> Result<Vec<String>, String> {
+    if let Some(names) = helper().and_then(helper_updates) {
+        return Ok(names);
+    }
+    let names: Vec<String> = foreign.iter().map(|p| p.name.clone()).collect();
+    let results = info(&names)?;
+
+    Ok(foreign
+        .iter()
+        .filter(|pkg| {
+            results.iter().any(|i| {
+                i.name == pkg.name
+                    && alpm::vercmp(i.version.as_str(), pkg.version.as_str()) == Ordering::Greater
+            })
+        })
+        .map(|pkg| pkg.name.clone())
+        .collect())
+}
+
+/// The AUR updates `helper -Qua` lists, or None when it couldn't be run. Both helpers exit
+/// 1 when there's nothing to update.
+fn helper_updates(helper: &str) -> Option<Vec<String>> {
+    let output = Command::new(helper).arg("-Qua").output().ok()?;
+    if !matches!(output.status.code(), Some(0 | 1)) {
+        return None;
+    }
+    Some(parse_helper_updates(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Names from "name 1.0-1 -> 1.1-1" lines; warnings and other chatter are skipped.
+fn parse_helper_updates(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| line.contains(" -> "))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
+
+/// MiB the sources of the given AUR packages would download, from the sizes their servers
+/// report. Sources already in an AUR helper's build directory and ones fetched by a VCS
+/// aren't counted, since makepkg reuses the first and can't size the second.
+pub fn download_size_mb(names: &[String]) -> Result<f64, String> {
+    let mut bases: Vec<String> = info(names)?
+        .into_iter()
+        .map(|pkg| {
+            if pkg.package_base.is_empty() {
+                pkg.name
+            } else {
+                pkg.package_base
+            }
+        })
+        .collect();
+    bases.sort();
+    bases.dedup();
+
+    let client = util::http_client().ok_or("could not create HTTP client")?;
+    let arch = distro::machine_arch();
+    let mut bytes = 0;
+    for base in &bases {
+        // One package the AUR can't serve leaves it out rather than failing the whole sum
+        let Ok(srcinfo) = client
+            .get(AUR_SRCINFO_URL)
+            .query(&[("h", base.as_str())])
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.text())
+        else {
+            continue;
+        };
+        let build_dirs = helper_build_dirs(base);
+        for (file, url) in srcinfo_sources(&srcinfo, &arch) {
+            if build_dirs.iter().any(|dir| dir.join(&file).is_file()) {
+                continue;
+            }
+            // content_length() is the body's, which a HEAD response never has
+            bytes += client
+                .head(&url)
+                .send()
+                .ok()
+                .and_then(|r| header_length(r.headers()))
+                .unwrap_or(0);
+        }
+    }
+    Ok(bytes as f64 / 1048576.0)
+}
+
+/// The size a response's Content-Length header gives.
+fn header_length(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    headers
+        .get(reqwest::header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Where the AUR helpers build `base`, and so where they keep its downloaded sources.
+fn helper_build_dirs(base: &str) -> Vec<PathBuf> {
+    util::user_cache_dirs()
+        .into_iter()
+        .flat_map(|cache| {
+            [
+                cache.join("paru/clone").join(base),
+                cache.join("yay").join(base),
+            ]
+        })
+        .collect()
+}
+
+/// The (file name, URL) of each source a .SRCINFO downloads over HTTP for `arch`. A
+/// source is "url" or "name::url", and makepkg saves a bare URL under its last segment.
+fn srcinfo_sources(srcinfo: &str, arch: &str) -> Vec<(String, String)> {
+    let arch_key = format!("source_{}", arch);
+    srcinfo
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once(" = ")?;
+            (key == "source" || key == arch_key).then_some(value)
+        })
+        .filter_map(|source| {
+            let (name, url) = match source.split_once("::") {
+                Some((name, url)) => (Some(name), url),
+                None => (None, source),
+            };
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return None;
+            }
+            let url = url.split('#').next().unwrap_or(url);
+            let file = name
+                .or_else(|| url.rsplit('/').next())
+                .filter(|file| !file.is_empty())?;
+            Some((file.to_string(), url.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_helper_updates() {
+        let output = ":: Looking for AUR upgrades...\n\
+                      paru-bin 2.0.3-1 -> 2.0.4-1\n\
+                      visual-studio-code-bin 1.90.0-1 -> 1.90.1-1\n";
+        assert_eq!(
+            parse_helper_updates(output),
+            ["paru-bin", "visual-studio-code-bin"]
+        );
+        assert!(parse_helper_updates("").is_empty());
+    }
+
+    #[test]
+    fn finds_http_sources_in_srcinfo() {
+        let srcinfo = "pkgbase = example-bin
+\tpkgver = 1.2.0
+\tsource = LICENSE
+\tsource = example.desktop::https://example.com/raw/main/example.desktop
+\tsource = git+https://github.com/example/example.git#tag=v1.2.0
+\tsource_x86_64 = https://example.com/releases/example-1.2.0-x86_64.tar.gz
+\tsource_aarch64 = https://example.com/releases/example-1.2.0-aarch64.tar.gz
+\tsha256sums = SKIP
+
+pkgname = example-bin
+";
+        assert_eq!(
+            srcinfo_sources(srcinfo, "x86_64"),
+            [
+                (
+                    "example.desktop".to_string(),
+                    "https://example.com/raw/main/example.desktop".to_string()
+                ),
+                (
+                    "example-1.2.0-x86_64.tar.gz".to_string(),
+                    "https://example.com/releases/example-1.2.0-x86_64.tar.gz".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reuses_fresh_rpc_answers() {
+        let pkg = |name: &str| AurInfo {
+            name: name.to_string(),
+            package_base: name.to_string(),
+            version: "1.0-1".to_string(),
+            out_of_date: None,
+            last_modified: 0,
+            maintainer: None,
+            num_votes: 0,
+            popularity: 0.0,
+        };
+        let cache = || InfoCache {
+            fetched: 1000,
+            names: vec!["paru".to_string(), "yay".to_string(), "gone".to_string()],
+            results: vec![pkg("paru"), pkg("yay")],
+        };
+        let names = |list: &[&str]| list.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let found = |results: Option<Vec<AurInfo>>| {
+            results.map(|r| r.into_iter().map(|i| i.name).collect::<Vec<_>>())
+        };
+
+        assert_eq!(
+            found(cache().lookup(&names(&["paru", "gone"]), 1000 + AUR_CACHE_SECS)),
+            Some(names(&["paru"]))
+        );
+        assert_eq!(
+            found(cache().lookup(&names(&["paru"]), 1001 + AUR_CACHE_SECS)),
+            None
+        );
+        assert_eq!(found(cache().lookup(&names(&["paru", "new"]), 1000)), None);
+    }
+
+    #[test]
+    fn reads_content_length_headers() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(header_length(&headers), None);
+        headers.insert(
+            reqwest::header::CONTENT_LENGTH,
+            reqwest::header::HeaderValue::from_static("8358342"),
+        );
+        assert_eq!(header_length(&headers), Some(8358342));
+    }
+}
@@ -0,0 +1,198 @@
+use std::io::{self, IsTerminal, Write};
+
+use crate::config::Config;
+use crate::{index, managers, pacman, paths, storage, util};
+
+/// Written once the first-run setup has been offered, so it's only offered once.
+const MARKER_FILE: &str = "bootstrapped";
+
+/// State pacfetch otherwise builds the first time something needs it. The stats snapshot
+/// isn't one: the run the offer comes from saves it anyway.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Task {
+    PackageIndex,
+    FilesDb,
+}
+
+impl Task {
+    fn label(&self) -> &'static str {
+        match self {
+            Task::PackageIndex => "Building the package index",
+            Task::FilesDb => "Syncing the files databases",
+        }
+    }
+
+    /// What the task saves later runs, for the offer.
+    fn benefit(&self) -> &'static str {
+        match self {
+            Task::PackageIndex => "the package index, so search and info start instantly",
+            Task::FilesDb => "the files databases, for provides and command-not-found",
+        }
+    }
+
+    fn run(&self) -> Result<(), String> {
+        match self {
+            Task::PackageIndex => index::rebuild().map(|_| ()),
+            Task::FilesDb => pacman::sync_files_databases(),
+        }
+    }
+}
+
+/// What the tasks depend on, looked up once.
+struct SetupState {
+    pacman: bool,
+    root: bool,
+    index_built: bool,
+    files_db_synced: bool,
+}
+
+impl SetupState {
+    fn current(config: &Config) -> SetupState {
+        let pacman = uses_pacman(config);
+        SetupState {
+            pacman,
+            root: util::is_root(),
+            index_built: !pacman || index::is_built(),
+            files_db_synced: !pacman || pacman::files_db_warning().is_none(),
+        }
+    }
+
+    /// The tasks whose state is missing. The package index and files databases are
+    /// pacman's, and only root can sync the files databases.
+    fn missing_tasks(&self) -> Vec<Task> {
+        let mut tasks = Vec::new();
+        if self.pacman && !self.index_built {
+            tasks.push(Task::PackageIndex);
+        }
+        if self.pacman && self.root && !self.files_db_synced {
+            tasks.push(Task::FilesDb);
+        }
+        tasks
+    }
+}
+
+fn uses_pacman(config: &Config) -> bool {
+    managers::select(config.backend.as_deref()).is_ok_and(|backend| backend.name() == "pacman")
+}
+
+/// Build whatever's missing now, for `pacfetch setup`.
+pub fn setup(config: &Config) {
+    let tasks = SetupState::current(config).missing_tasks();
+    if tasks.is_empty() {
+        println!("Nothing to set up.");
+    } else {
+        run(&tasks);
+    }
+    if uses_pacman(config)
+        && !util::is_root()
+        && let Some(warning) = pacman::files_db_warning()
+    {
+        println!("note: {} as root", warning);
+    }
+}
+
+/// On the first interactive run, offer to build whatever's missing now rather than on
+/// the first command that needs it.
+pub fn offer(config: &Config) {
+    let Some(marker) = paths::state_file(MARKER_FILE) else {
+        return;
+    };
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+    if !should_offer(marker.exists(), interactive) {
+        return;
+    }
+    // Marked whatever the answer, so declining isn't asked again
+    if let Err(e) = paths::ensure_parent(&marker).and_then(|_| {
+        storage::write_atomic(&marker, "").map_err(|e| format!("Failed to save: {}", e))
+    }) {
+        eprintln!("warning: {}", e);
+        return;
+    }
+    let tasks = SetupState::current(config).missing_tasks();
+    if tasks.is_empty() {
+        return;
+    }
+
+    println!("This looks like pacfetch's first run. It can build these now:");
+    for task in &tasks {
+        println!("  {}", task.benefit());
+    }
+    print!("Build them now? [Y/n] ");
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() || !accepts(&answer) {
+        println!("Skipped; run pacfetch setup to build them later.\n");
+        return;
+    }
+    run(&tasks);
+    println!();
+}
+
+/// Only the first run asks, and only when someone is there to answer.
+fn should_offer(marked: bool, interactive: bool) -> bool {
+    !marked && interactive
+}
+
+/// Anything but "n" builds, Enter included.
+fn accepts(answer: &str) -> bool {
+    !answer.trim().eq_ignore_ascii_case("n")
+}
+
+/// Run the tasks in order behind one spinner counting through them. A failed task is
+/// reported and left for the command that needs it to retry.
+fn run(tasks: &[Task]) {
+    let spinner = util::create_spinner("Setting up");
+    let mut failed = 0;
+    for (i, task) in tasks.iter().enumerate() {
+        spinner.set_message(format!("[{}/{}] {}", i + 1, tasks.len(), task.label()));
+        if let Err(e) = task.run() {
+            spinner.suspend(|| eprintln!("warning: {}: {}", task.label(), e));
+            failed += 1;
+        }
+    }
+    spinner.finish_and_clear();
+    println!("Set up {} of {}.", tasks.len() - failed, tasks.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_missing_setup_tasks() {
+        let fresh = SetupState {
+            pacman: true,
+            root: true,
+            index_built: false,
+            files_db_synced: false,
+        };
+        assert_eq!(fresh.missing_tasks(), [Task::PackageIndex, Task::FilesDb]);
+        let user = SetupState {
+            root: false,
+            ..fresh
+        };
+        assert_eq!(user.missing_tasks(), [Task::PackageIndex]);
+        let other_backend = SetupState {
+            pacman: false,
+            ..fresh
+        };
+        assert!(other_backend.missing_tasks().is_empty());
+        let built = SetupState {
+            pacman: true,
+            root: true,
+            index_built: true,
+            files_db_synced: true,
+        };
+        assert!(built.missing_tasks().is_empty());
+    }
+
+    #[test]
+    fn offers_setup_once_and_interactively() {
+        assert!(should_offer(false, true));
+        assert!(!should_offer(true, true));
+        assert!(!should_offer(false, false));
+        assert!(accepts("\n"));
+        assert!(accepts("y\n"));
+        assert!(!accepts(" N \n"));
+    }
+}
@@ -0,0 +1,456 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+#[cfg(target_os = "linux")]
+use crate::pacman;
+use crate::util;
+
+/// Cache directories of AUR helpers that keep built packages, under the user cache dir.
+const AUR_HELPER_CACHES: [&str; 2] = ["paru", "yay"];
+
+/// The same package file found in more than one cache; the first path is the one kept.
+pub struct Duplicate {
+    pub file_name: String,
+    pub size: u64,
+    pub paths: Vec<PathBuf>,
+}
+
+impl Duplicate {
+    /// Bytes freed by hardlinking every copy to the first; copies already linked count once.
+    pub fn reclaimable(&self) -> u64 {
+        let mut inodes: Vec<(u64, u64)> = self
+            .paths
+            .iter()
+            .filter_map(|p| fs::metadata(p).ok())
+            .filter_map(|m| file_id(&m))
+            .collect();
+        inodes.sort_unstable();
+        inodes.dedup();
+        self.size * inodes.len().saturating_sub(1) as u64
+    }
+}
+
+/// Totals for a set of cache directories, walked recursively.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CacheUsage {
+    pub bytes: u64,
+    pub files: u64,
+    /// (files, bytes) per extension, e.g. "pkg.tar.zst" or "sig".
+    pub by_extension: BTreeMap<String, (u64, u64)>,
+}
+
+impl CacheUsage {
+    fn add(&mut self, name: &str, bytes: u64) {
+        self.bytes += bytes;
+        self.files += 1;
+        let entry = self.by_extension.entry(extension_of(name)).or_default();
+        entry.0 += 1;
+        entry.1 += bytes;
+    }
+
+    fn merge(&mut self, other: CacheUsage) {
+        self.bytes += other.bytes;
+        self.files += other.files;
+        for (ext, (files, bytes)) in other.by_extension {
+            let entry = self.by_extension.entry(ext).or_default();
+            entry.0 += files;
+            entry.1 += bytes;
+        }
+    }
+}
+
+/// "pkg.tar.zst" for packages, so compression formats stay apart; the last extension otherwise.
+fn extension_of(name: &str) -> String {
+    if let Some(pos) = name.find(".pkg.tar")
+        && !name.ends_with(".sig")
+        && !name.ends_with(".part")
+    {
+        return name[pos + 1..].to_string();
+    }
+    Path::new(name)
+        .extension()
+        .map(|e| e.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "other".to_string())
+}
+
+/// Files with more than one link already counted, so hardlinks, e.g. from `hardlink` or
+/// `nix-store --optimise`, count once as du counts them.
+type Seen = std::sync::Mutex<HashSet<(u64, u64)>>;
+
+fn first_link(meta: &fs::Metadata, seen: &Seen) -> bool {
+    if link_count(meta) < 2 {
+        return true;
+    }
+    match (file_id(meta), seen.lock()) {
+        (Some(id), Ok(mut seen)) => seen.insert(id),
+        _ => true,
+    }
+}
+
+#[cfg(unix)]
+fn link_count(meta: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.nlink()
+}
+
+#[cfg(not(unix))]
+fn link_count(_meta: &fs::Metadata) -> u64 {
+    1
+}
+
+fn add_file(entry: &fs::DirEntry, usage: &mut CacheUsage, seen: &Seen) {
+    if let Ok(meta) = entry.metadata()
+        && meta.is_file()
+        && first_link(&meta, seen)
+    {
+        usage.add(&entry.file_name().to_string_lossy(), meta.len());
+    }
+}
+
+fn walk(dir: &Path, usage: &mut CacheUsage, seen: &Seen) {
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                pending.push(entry.path());
+            } else {
+                add_file(&entry, usage, seen);
+            }
+        }
+    }
+}
+
+/// A top-level file to measure, or a subdirectory to walk.
+enum Work {
+    File(fs::DirEntry),
+    Dir(PathBuf),
+}
+
+/// Measure the directories recursively, with each file counted once however many links it
+/// has. Their entries are spread over a few threads, so a flat directory such as pacman's
+/// cache is measured as much in parallel as a deep one.
+/// None when none of them can be read.
+pub fn scan(dirs: &[PathBuf]) -> Option<CacheUsage> {
+    let mut work = Vec::new();
+    let mut readable = false;
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        readable = true;
+        for entry in entries.flatten() {
+            // The entry's type comes with the listing; the stat for its size is the slow part
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                work.push(Work::Dir(entry.path()));
+            } else {
+                work.push(Work::File(entry));
+            }
+        }
+    }
+    if !readable {
+        return None;
+    }
+
+    let seen = Seen::default();
+    let threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let chunk = work.len().div_ceil(threads).max(1);
+    let mut usage = CacheUsage::default();
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = work
+            .chunks(chunk)
+            .map(|items| {
+                let seen = &seen;
+                scope.spawn(move || {
+                    let mut usage = CacheUsage::default();
+                    for item in items {
+                        match item {
+                            Work::File(entry) => add_file(entry, &mut usage, seen),
+                            Work::Dir(dir) => walk(dir, &mut usage, seen),
+                        }
+                    }
+                    usage
+                })
+            })
+            .collect();
+        for handle in handles {
+            if let Ok(part) = handle.join() {
+                usage.merge(part);
+            }
+        }
+    });
+    Some(usage)
+}
+
+/// pacman's caches followed by the AUR helper caches that exist.
+fn package_cache_dirs() -> Vec<PathBuf> {
+    #[cfg(target_os = "linux")]
+    let mut dirs = pacman::pacman_cache_dirs();
+    #[cfg(not(target_os = "linux"))]
+    let mut dirs = Vec::new();
+    dirs.extend(helper_cache_dirs());
+    dirs
+}
+
+fn helper_cache_dirs() -> Vec<PathBuf> {
+    util::user_cache_dirs()
+        .into_iter()
+        .flat_map(|base| AUR_HELPER_CACHES.map(|helper| base.join(helper)))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+fn is_package_file(name: &str) -> bool {
+    name.contains(".pkg.tar") && !name.ends_with(".sig") && !name.ends_with(".part")
+}
+
+/// Every package file below `dir`, descending into the per-package clone directories.
+fn package_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() && is_package_file(&entry.file_name().to_string_lossy()) {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+/// Read until `buf` is full or the file ends, so two readers stay in step.
+fn fill(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match reader.read(&mut buf[total..])? {
+            0 => break,
+            n => total += n,
+        }
+    }
+    Ok(total)
+}
+
+fn same_contents(a: &Path, b: &Path) -> bool {
+    let (Ok(a), Ok(b)) = (File::open(a), File::open(b)) else {
+        return false;
+    };
+    let (mut a, mut b) = (BufReader::new(a), BufReader::new(b));
+    let (mut buf_a, mut buf_b) = ([0u8; 8192], [0u8; 8192]);
+    loop {
+        let (Ok(n), Ok(m)) = (fill(&mut a, &mut buf_a), fill(&mut b, &mut buf_b)) else {
+            return false;
+        };
+        if n != m || buf_a[..n] != buf_b[..m] {
+            return false;
+        }
+        if n == 0 {
+            return true;
+        }
+    }
+}
+
+/// A package file in one of the caches.
+#[derive(Debug, Clone)]
+pub struct CachedPackage {
+    pub name: String,
+    pub version: String,
+    pub path: PathBuf,
+}
+
+/// The name and version in a package file name, e.g.
+/// "linux-6.9.3.arch1-1-x86_64.pkg.tar.zst" -> ("linux", "6.9.3.arch1-1").
+fn parse_package_file(file_name: &str) -> Option<(String, String)> {
+    let stem = &file_name[..file_name.find(".pkg.tar")?];
+    let mut parts = stem.rsplitn(4, '-');
+    let _arch = parts.next()?;
+    let rel = parts.next()?;
+    let version = parts.next()?;
+    let name = parts.next()?;
+    Some((name.to_string(), format!("{}-{}", version, rel)))
+}
+
+/// Every package file across the caches, each file name once.
+pub fn cached_packages() -> Vec<CachedPackage> {
+    packages_in(&package_cache_dirs())
+}
+
+/// The packages AUR helpers built, which they keep in their own caches.
+pub fn built_packages() -> Vec<CachedPackage> {
+    packages_in(&helper_cache_dirs())
+}
+
+fn packages_in(dirs: &[PathBuf]) -> Vec<CachedPackage> {
+    let mut seen = std::collections::HashSet::new();
+    dirs.iter()
+        .flat_map(|dir| package_files(dir))
+        .filter_map(|path| {
+            let file_name = path.file_name()?.to_string_lossy().into_owned();
+            let (name, version) = parse_package_file(&file_name)?;
+            seen.insert(file_name).then_some(CachedPackage {
+                name,
+                version,
+                path,
+            })
+        })
+        .collect()
+}
+
+/// Package files present in several caches with identical contents.
+pub fn find_duplicates() -> Vec<Duplicate> {
+    let mut files: Vec<(String, u64, PathBuf)> = package_cache_dirs()
+        .iter()
+        .flat_map(|dir| package_files(dir))
+        .filter_map(|path| {
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            let size = fs::metadata(&path).ok()?.len();
+            Some((name, size, path))
+        })
+        .collect();
+    files.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+
+    let mut duplicates: Vec<Duplicate> = Vec::new();
+    for (name, size, path) in files {
+        match duplicates.last_mut() {
+            Some(dup) if dup.file_name == name && dup.size == size => {
+                if same_contents(&dup.paths[0], &path) {
+                    dup.paths.push(path);
+                }
+            }
+            _ => duplicates.push(Duplicate {
+                file_name: name,
+                size,
+                paths: vec![path],
+            }),
+        }
+    }
+    duplicates.retain(|dup| dup.paths.len() > 1);
+    duplicates
+}
+
+/// The (device, inode) a file's data is known by, which hardlinks share. None where the
+/// platform has no inode numbers, which leaves nothing to hardlink.
+#[cfg(unix)]
+fn file_id(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(unix)]
+fn owner(meta: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::MetadataExt;
+    meta.uid()
+}
+
+#[cfg(not(unix))]
+fn owner(_meta: &fs::Metadata) -> u32 {
+    0
+}
+
+/// Replace every copy with a hardlink to the first, returning the bytes freed. Copies on
+/// another filesystem or owned by someone else are left alone: a user's AUR helper
+/// couldn't replace or remove its copy once it was root's file.
+pub fn hardlink(duplicate: &Duplicate) -> Result<u64, String> {
+    let keep = &duplicate.paths[0];
+    let keep_meta =
+        fs::metadata(keep).map_err(|e| format!("Failed to read {}: {}", keep.display(), e))?;
+    let Some(keep_id) = file_id(&keep_meta) else {
+        return Ok(0);
+    };
+
+    let mut freed = 0;
+    for path in &duplicate.paths[1..] {
+        let Ok(meta) = fs::metadata(path) else {
+            continue;
+        };
+        let Some(id) = file_id(&meta) else {
+            continue;
+        };
+        if id.0 != keep_id.0 || id == keep_id || owner(&meta) != owner(&keep_meta) {
+            continue;
+        }
+
+        // Link next to the copy first so a failure never leaves it missing. A link left
+        // by an interrupted run would make the new one fail, so it goes first.
+        let tmp = path.with_file_name(format!(".{}.pacfetch-link", duplicate.file_name));
+        let _ = fs::remove_file(&tmp);
+        fs::hard_link(keep, &tmp).map_err(|e| {
+            let _ = fs::remove_file(&tmp);
+            format!("Failed to link {}: {}", path.display(), e)
+        })?;
+        fs::rename(&tmp, path).map_err(|e| {
+            let _ = fs::remove_file(&tmp);
+            format!("Failed to replace {}: {}", path.display(), e)
+        })?;
+        freed += duplicate.size;
+    }
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_package_file_names() {
+        assert_eq!(
+            parse_package_file("linux-6.9.3.arch1-1-x86_64.pkg.tar.zst"),
+            Some(("linux".to_string(), "6.9.3.arch1-1".to_string()))
+        );
+        assert_eq!(
+            parse_package_file("lib32-gcc-libs-1:14.1.1+r58-1-x86_64.pkg.tar.xz"),
+            Some(("lib32-gcc-libs".to_string(), "1:14.1.1+r58-1".to_string()))
+        );
+        assert_eq!(parse_package_file("linux-x86_64.pkg.tar.zst"), None);
+        assert_eq!(parse_package_file("notes.txt"), None);
+    }
+
+    #[test]
+    fn scans_flat_and_nested_caches() {
+        let dir = std::env::temp_dir().join(format!("pacfetch-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        for i in 0..40 {
+            fs::write(
+                dir.join(format!("pkg{}-1-1-x86_64.pkg.tar.zst", i)),
+                [0; 10],
+            )
+            .unwrap();
+        }
+        fs::write(dir.join("pkg0-1-1-x86_64.pkg.tar.zst.sig"), [0; 3]).unwrap();
+        fs::write(dir.join("nested/build.log"), [0; 5]).unwrap();
+        #[cfg(unix)]
+        fs::hard_link(
+            dir.join("pkg0-1-1-x86_64.pkg.tar.zst"),
+            dir.join("nested/pkg0-1-1-x86_64.pkg.tar.zst"),
+        )
+        .unwrap();
+
+        let usage = scan(std::slice::from_ref(&dir)).unwrap();
+        assert_eq!((usage.files, usage.bytes), (42, 408));
+        assert_eq!(usage.by_extension["pkg.tar.zst"], (40, 400));
+        assert_eq!(usage.by_extension["sig"], (1, 3));
+        assert_eq!(usage.by_extension["log"], (1, 5));
+        assert!(scan(&[dir.join("missing")]).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
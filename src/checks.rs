@@ -0,0 +1,612 @@
+use crate::util;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+use termimad::crossterm::style::{Color, Stylize};
+
+const MODULES_DIR: &str = "/usr/lib/modules";
+const BOOT_DIR: &str = "/boot";
+/// Where the ESP is usually mounted, for loaders and unified kernel images.
+const ESP_DIRS: [&str; 3] = ["/boot", "/efi", "/boot/efi"];
+
+/// Out-of-tree modules that commonly break on kernel updates, by module name.
+const WATCHED_MODULES: [&str; 2] = ["nvidia", "zfs"];
+
+/// Kernel releases with an installed image, e.g. "6.9.7-arch1-1".
+fn installed_kernels() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(MODULES_DIR) else {
+        return Vec::new();
+    };
+
+    let mut kernels: Vec<String> = entries
+        .flatten()
+        .filter(|e| e.path().join("vmlinuz").exists())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect();
+    kernels.sort();
+    kernels
+}
+
+/// Modules dkms knows about, and the kernels they're installed for.
+fn dkms_status() -> Vec<(String, Vec<String>)> {
+    let Ok(output) = Command::new("dkms").arg("status").output() else {
+        return Vec::new();
+    };
+
+    // Lines look like "nvidia/550.90.07, 6.9.7-arch1-1, x86_64: installed"
+    let mut modules: Vec<(String, Vec<String>)> = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some((fields, state)) = line.rsplit_once(": ") else {
+            continue;
+        };
+        let mut parts = fields.split(", ");
+        let Some(module) = parts.next() else {
+            continue;
+        };
+
+        let entry = match modules.iter_mut().find(|(m, _)| m == module) {
+            Some(entry) => entry,
+            None => {
+                modules.push((module.to_string(), Vec::new()));
+                modules.last_mut().unwrap()
+            }
+        };
+        if let Some(kernel) = parts.next()
+            && state.starts_with("installed")
+        {
+            entry.1.push(kernel.to_string());
+        }
+    }
+    modules
+}
+
+/// Whether `modinfo` can find `module` for the given kernel.
+fn module_available(module: &str, kernel: &str) -> bool {
+    Command::new("modinfo")
+        .args(["-k", kernel, module])
+        .output()
+        .is_ok_and(|o| o.status.success())
+}
+
+/// Check that every DKMS module, and the nvidia/zfs modules, are built for each installed kernel.
+pub fn check_kernel_modules() -> Vec<String> {
+    let kernels = installed_kernels();
+    let mut failures = Vec::new();
+
+    let dkms = if util::command_exists("dkms") {
+        dkms_status()
+    } else {
+        Vec::new()
+    };
+    for (module, built_for) in &dkms {
+        for kernel in kernels.iter().filter(|k| !built_for.contains(k)) {
+            failures.push(format!(
+                "DKMS module {} is not installed for {}",
+                module, kernel
+            ));
+        }
+    }
+
+    if util::command_exists("modinfo") {
+        for module in WATCHED_MODULES {
+            // Only check modules that are present for at least one kernel
+            let present: Vec<bool> = kernels
+                .iter()
+                .map(|k| module_available(module, k))
+                .collect();
+            if !present.contains(&true) {
+                continue;
+            }
+            for (kernel, _) in kernels.iter().zip(present).filter(|(_, p)| !p) {
+                failures.push(format!("{} module is missing for {}", module, kernel));
+            }
+        }
+    }
+
+    failures
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Unified kernel images for a kernel package, e.g. EFI/Linux/arch-linux.efi.
+fn find_uki(pkgbase: &str) -> Option<PathBuf> {
+    ESP_DIRS
+        .iter()
+        .filter_map(|dir| fs::read_dir(Path::new(dir).join("EFI/Linux")).ok())
+        .flat_map(|entries| entries.flatten())
+        .map(|e| e.path())
+        .find(|p| {
+            p.extension().is_some_and(|ext| ext == "efi")
+                && p.file_stem()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|s| s.ends_with(&format!("-{}", pkgbase)) || s == pkgbase)
+        })
+}
+
+/// Text of every loader config: systemd-boot entries and grub.cfg.
+fn loader_configs() -> Vec<(PathBuf, String)> {
+    let mut configs = Vec::new();
+
+    for dir in ESP_DIRS {
+        let Ok(entries) = fs::read_dir(Path::new(dir).join("loader/entries")) else {
+            continue;
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.extension().is_some_and(|ext| ext == "conf")
+                && let Ok(text) = fs::read_to_string(&path)
+            {
+                configs.push((path, text));
+            }
+        }
+    }
+
+    let grub = Path::new(BOOT_DIR).join("grub/grub.cfg");
+    if let Ok(text) = fs::read_to_string(&grub) {
+        configs.push((grub, text));
+    }
+    configs
+}
+
+/// Check that each installed kernel was copied to /boot with a fresh initramfs,
+/// and that the boot loader has an entry for it.
+pub fn check_boot_entries() -> Vec<String> {
+    let mut failures = Vec::new();
+    let configs = loader_configs();
+
+    for kernel in installed_kernels() {
+        let modules = Path::new(MODULES_DIR).join(&kernel);
+        let Ok(pkgbase) = fs::read_to_string(modules.join("pkgbase")) else {
+            continue;
+        };
+        let pkgbase = pkgbase.trim();
+
+        // Unified kernel images carry their own initramfs
+        if let Some(uki) = find_uki(pkgbase) {
+            if modified(&uki) < modified(&modules.join("vmlinuz")) {
+                failures.push(format!("{} was not rebuilt for {}", uki.display(), kernel));
+            }
+            continue;
+        }
+
+        let image = Path::new(BOOT_DIR).join(format!("vmlinuz-{}", pkgbase));
+        match (fs::read(&image), fs::read(modules.join("vmlinuz"))) {
+            (Err(_), _) => {
+                failures.push(format!(
+                    "{} is missing; is {} mounted?",
+                    image.display(),
+                    BOOT_DIR
+                ));
+                continue;
+            }
+            (Ok(boot), Ok(installed)) if boot != installed => {
+                failures.push(format!(
+                    "{} does not match kernel {}",
+                    image.display(),
+                    kernel
+                ));
+            }
+            _ => {}
+        }
+
+        let initramfs = Path::new(BOOT_DIR).join(format!("initramfs-{}.img", pkgbase));
+        match modified(&initramfs) {
+            None => failures.push(format!("{} is missing", initramfs.display())),
+            Some(time) if Some(time) < modified(&image) => {
+                failures.push(format!("{} was not regenerated", initramfs.display()));
+            }
+            _ => {}
+        }
+
+        let image_name = format!("vmlinuz-{}", pkgbase);
+        if !configs.is_empty() && !configs.iter().any(|(_, text)| text.contains(&image_name)) {
+            failures.push(format!("no boot loader entry references {}", image_name));
+        }
+    }
+
+    failures
+}
+
+/// Whether fstab wants `dir` to be its own filesystem.
+fn in_fstab(dir: &str) -> bool {
+    fs::read_to_string("/etc/fstab").is_ok_and(|fstab| {
+        fstab
+            .lines()
+            .filter(|l| !l.trim_start().starts_with('#'))
+            .any(|l| l.split_whitespace().nth(1) == Some(dir))
+    })
+}
+
+fn is_mounted(dir: &str) -> bool {
+    fs::read_to_string("/proc/mounts").is_ok_and(|mounts| {
+        mounts
+            .lines()
+            .any(|l| l.split_whitespace().nth(1) == Some(dir))
+    })
+}
+
+/// Free space on the filesystem holding `dir`, in bytes.
+#[cfg(unix)]
+fn free_space(dir: &str) -> Option<u64> {
+    let path = std::ffi::CString::new(dir).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_space(_dir: &str) -> Option<u64> {
+    None
+}
+
+/// The largest of a kernel package's images in /boot. Images are regenerated one at a
+/// time, each replacing the old one once it's written, so that's the room it needs spare.
+fn largest_boot_image(pkgbase: &str) -> u64 {
+    let names = [
+        format!("vmlinuz-{}", pkgbase),
+        format!("initramfs-{}.img", pkgbase),
+        format!("initramfs-{}-fallback.img", pkgbase),
+    ];
+    names
+        .iter()
+        .filter_map(|name| fs::metadata(Path::new(BOOT_DIR).join(name)).ok())
+        .map(|m| m.len())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Before upgrading kernels or boot loaders, make sure /boot and the ESP are mounted
+/// and /boot has room for the regenerated images.
+pub fn check_boot_ready(packages: &[String]) -> Result<(), String> {
+    for dir in ESP_DIRS {
+        if in_fstab(dir) && !is_mounted(dir) {
+            return Err(format!(
+                "{} is not mounted; mount it before upgrading {}",
+                dir,
+                packages.join(", ")
+            ));
+        }
+    }
+
+    let needed = packages
+        .iter()
+        .map(|p| largest_boot_image(p))
+        .max()
+        .unwrap_or(0);
+    if let Some(free) = free_space(BOOT_DIR)
+        && free < needed
+    {
+        return Err(format!(
+            "{} has {:.1} MiB free but regenerating the largest image for {} needs about {:.1} MiB",
+            BOOT_DIR,
+            free as f64 / 1048576.0,
+            packages.join(", "),
+            needed as f64 / 1048576.0
+        ));
+    }
+
+    Ok(())
+}
+
+/// Services that take the session down with them; reboot instead of restarting these.
+const UNSAFE_RESTARTS: [&str; 6] = [
+    "dbus.service",
+    "dbus-broker.service",
+    "systemd-logind.service",
+    "gdm.service",
+    "sddm.service",
+    "lightdm.service",
+];
+
+/// Processes still running code that an upgrade replaced, grouped into services.
+#[derive(Default)]
+pub struct StaleProcesses {
+    /// systemd services to restart, sorted.
+    pub services: Vec<String>,
+    /// Other processes (by command name) that need restarting by hand.
+    pub processes: Vec<String>,
+    /// PID 1 itself maps a replaced library and needs `systemctl daemon-reexec`.
+    pub init: bool,
+}
+
+/// Whether the process maps a library or executable that was deleted or replaced on disk.
+fn maps_deleted_file(pid: &str) -> bool {
+    fs::read_to_string(format!("/proc/{}/maps", pid)).is_ok_and(|maps| {
+        maps.lines().any(|l| {
+            l.ends_with("(deleted)")
+                && l.split_whitespace().nth(5).is_some_and(|path| {
+                    path.starts_with("/usr/") && !path.starts_with("/usr/share/")
+                })
+        })
+    })
+}
+
+/// The system service a process belongs to, from its cgroup.
+fn service_of(pid: &str) -> Option<String> {
+    let cgroup = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    let path = cgroup.lines().find_map(|l| l.strip_prefix("0::"))?;
+    let unit = path.strip_prefix("/system.slice/")?.split('/').next()?;
+    unit.ends_with(".service").then(|| unit.to_string())
+}
+
+/// Scan /proc for processes using deleted or replaced libraries, like needrestart.
+pub fn find_stale_processes() -> StaleProcesses {
+    let mut stale = StaleProcesses::default();
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return stale;
+    };
+
+    for entry in entries.flatten() {
+        let Ok(pid) = entry.file_name().into_string() else {
+            continue;
+        };
+        if !pid.bytes().all(|b| b.is_ascii_digit()) || !maps_deleted_file(&pid) {
+            continue;
+        }
+
+        if pid == "1" {
+            stale.init = true;
+        } else if let Some(service) = service_of(&pid) {
+            stale.services.push(service);
+        } else if let Ok(comm) = fs::read_to_string(format!("/proc/{}/comm", pid)) {
+            stale.processes.push(comm.trim().to_string());
+        }
+    }
+
+    stale.services.sort();
+    stale.services.dedup();
+    stale.processes.sort();
+    stale.processes.dedup();
+    stale
+}
+
+/// List services using replaced libraries and, on a terminal, offer to restart chosen ones.
+pub fn offer_service_restarts(stale: &StaleProcesses) {
+    if stale.services.is_empty() && stale.processes.is_empty() && !stale.init {
+        return;
+    }
+
+    println!("\nRunning programs still use replaced libraries:");
+    if stale.init {
+        println!("  systemd (run `systemctl daemon-reexec`)");
+    }
+    for (i, service) in stale.services.iter().enumerate() {
+        let note = if UNSAFE_RESTARTS.contains(&service.as_str()) {
+            " (reboot recommended)"
+        } else {
+            ""
+        };
+        println!("  {:>2}) {}{}", i + 1, service, note);
+    }
+    if !stale.processes.is_empty() {
+        println!("  Other processes: {}", stale.processes.join(", "));
+    }
+
+    if stale.services.is_empty() || util::is_plain() || !util::is_root() {
+        return;
+    }
+
+    print!("Restart services? (numbers, 'a' for all, Enter to skip): ");
+    let _ = std::io::Write::flush(&mut std::io::stdout());
+    let mut input = String::new();
+    if std::io::stdin().read_line(&mut input).is_err() {
+        return;
+    }
+
+    let input = input.trim();
+    let selected: Vec<&String> = if input.eq_ignore_ascii_case("a") {
+        stale
+            .services
+            .iter()
+            .filter(|s| !UNSAFE_RESTARTS.contains(&s.as_str()))
+            .collect()
+    } else {
+        input
+            .split([' ', ','])
+            .filter_map(|n| n.parse::<usize>().ok())
+            .filter_map(|n| stale.services.get(n.wrapping_sub(1)))
+            .collect()
+    };
+    if selected.is_empty() {
+        return;
+    }
+
+    match Command::new("systemctl")
+        .arg("restart")
+        .args(&selected)
+        .status()
+    {
+        Ok(status) if status.success() => {
+            let names: Vec<&str> = selected.iter().map(|s| s.as_str()).collect();
+            println!("Restarted {}", names.join(", "));
+        }
+        Ok(status) => eprintln!("error: systemctl exited with {}", status),
+        Err(e) => eprintln!("error: Failed to run systemctl: {}", e),
+    }
+}
+
+/// Desktop packages, the process that runs them, and what to call them.
+const SESSION_COMPONENTS: [(&str, &str, &str); 10] = [
+    ("gnome-shell", "gnome-shell", "GNOME Shell"),
+    ("mutter", "gnome-shell", "GNOME Shell"),
+    ("plasma-workspace", "plasmashell", "Plasma"),
+    ("kwin", "kwin_wayland", "KWin"),
+    ("hyprland", "Hyprland", "Hyprland"),
+    ("sway", "sway", "Sway"),
+    ("xorg-server", "Xorg", "Xorg"),
+    ("xorg-xwayland", "Xwayland", "Xwayland"),
+    ("xfwm4", "xfwm4", "Xfwm"),
+    ("cinnamon", "cinnamon", "Cinnamon"),
+];
+
+/// Packages whose new version only takes effect after a reboot.
+const REBOOT_PACKAGES: [&str; 3] = ["systemd", "glibc", "linux-firmware"];
+
+/// Whether upgrading this package affects the running desktop session or needs a reboot.
+pub fn is_session_package(name: &str) -> bool {
+    SESSION_COMPONENTS
+        .iter()
+        .any(|(package, _, _)| *package == name)
+        || REBOOT_PACKAGES.contains(&name)
+        || matches!(name, "nvidia-utils" | "mesa")
+}
+
+pub fn running_commands() -> Vec<String> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|e| fs::read_to_string(e.path().join("comm")).ok())
+        .map(|comm| comm.trim().to_string())
+        .collect()
+}
+
+/// Names of the systemd services currently running, e.g. "sshd.service".
+pub fn running_services() -> Vec<String> {
+    let Ok(output) = Command::new("systemctl")
+        .args([
+            "list-units",
+            "--type=service",
+            "--state=running",
+            "--no-legend",
+            "--plain",
+        ])
+        .output()
+    else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|l| l.split_whitespace().next().map(str::to_string))
+        .collect()
+}
+
+fn module_loaded(module: &str) -> bool {
+    fs::read_to_string("/proc/modules").is_ok_and(|m| {
+        m.lines()
+            .any(|l| l.split_whitespace().next() == Some(module))
+    })
+}
+
+/// Advice to log out or reboot when the running desktop, compositor, display driver
+/// or kernel was among the upgraded packages.
+pub fn session_advice(upgraded: &[String]) -> Vec<String> {
+    let was_upgraded = |name: &str| upgraded.iter().any(|u| u == name);
+    let running = running_commands();
+    let graphical = running.iter().any(|r| {
+        SESSION_COMPONENTS
+            .iter()
+            .any(|(_, process, _)| process == r)
+    });
+    let mut advice = Vec::new();
+
+    let mut components: Vec<&str> = SESSION_COMPONENTS
+        .iter()
+        .filter(|(package, process, _)| {
+            was_upgraded(package) && running.iter().any(|r| r == process)
+        })
+        .map(|(_, _, label)| *label)
+        .collect();
+    components.dedup();
+    if !components.is_empty() {
+        advice.push(format!(
+            "Log out and back in to start the upgraded {}",
+            components.join(", ")
+        ));
+    }
+
+    if was_upgraded("nvidia-utils") && module_loaded("nvidia") {
+        advice.push(
+            "The nvidia driver was upgraded while loaded; reboot to avoid a version mismatch"
+                .to_string(),
+        );
+    } else if was_upgraded("mesa") && graphical {
+        advice.push("Mesa was upgraded; restart your session to use the new drivers".to_string());
+    }
+
+    let running_kernel = fs::read_to_string("/proc/sys/kernel/osrelease").unwrap_or_default();
+    if !running_kernel.is_empty() && !Path::new(MODULES_DIR).join(running_kernel.trim()).exists() {
+        advice.push(format!(
+            "The running kernel {} was replaced; reboot to use the new one",
+            running_kernel.trim()
+        ));
+    }
+
+    let reboot: Vec<&str> = REBOOT_PACKAGES
+        .iter()
+        .copied()
+        .filter(|p| was_upgraded(p))
+        .collect();
+    if !reboot.is_empty() {
+        advice.push(format!("Reboot to load the new {}", reboot.join(", ")));
+    }
+
+    advice
+}
+
+/// Send a desktop notification when a notifier is available. Failures are ignored.
+pub fn notify(summary: &str, body: &str) {
+    if !util::command_exists("notify-send") {
+        return;
+    }
+    let mut command = match sudo_session_bus() {
+        Some((user, bus)) => {
+            let mut command = Command::new("sudo");
+            command.args(["-u", &user, "env", &bus, "notify-send"]);
+            command
+        }
+        None => Command::new("notify-send"),
+    };
+    let _ = command
+        .args(["--urgency=critical", "--app-name=pacfetch", summary, body])
+        .status();
+}
+
+/// Under sudo, the user who ran it and a DBUS_SESSION_BUS_ADDRESS for their session. Root
+/// has no session bus, so a notification sent as root never reaches the desktop.
+fn sudo_session_bus() -> Option<(String, String)> {
+    if !util::is_root() {
+        return None;
+    }
+    let user = std::env::var("SUDO_USER").ok()?;
+    let uid: u32 = std::env::var("SUDO_UID").ok()?.parse().ok()?;
+    let bus = PathBuf::from(format!("/run/user/{}/bus", uid));
+    bus.exists().then(|| {
+        (
+            user,
+            format!("DBUS_SESSION_BUS_ADDRESS=unix:path={}", bus.display()),
+        )
+    })
+}
+
+/// Print the failures of a check prominently and raise a notification for them.
+pub fn report(check: &str, failures: &[String]) {
+    print_notice(&format!("{} check failed:", check), failures, Color::Red);
+}
+
+/// Print advice after an upgrade and raise a notification for it.
+pub fn advise(heading: &str, advice: &[String]) {
+    print_notice(heading, advice, Color::Yellow);
+}
+
+fn print_notice(heading: &str, lines: &[String], color: Color) {
+    if lines.is_empty() {
+        return;
+    }
+
+    if util::output_mode() == util::OutputMode::Graphics {
+        println!("\n{}", heading.with(color).bold());
+    } else {
+        println!("\n{}", heading);
+    }
+    for line in lines {
+        println!("  {}", line);
+    }
+
+    notify(heading, &lines.join("\n"));
+}
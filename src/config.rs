@@ -1,26 +1,285 @@
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
+use crate::paths;
 use crate::stats::{self, StatId};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct Config {
     #[serde(default)]
     pub display: DisplayConfig,
+    #[serde(default)]
+    pub mirror: MirrorConfig,
+    #[serde(default)]
+    pub tui: TuiConfig,
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    #[serde(default)]
+    pub push: PushConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub filter: FilterConfig,
+    #[serde(default)]
+    pub upgrade: UpgradeConfig,
+    /// The package manager to read stats from, e.g. "pacman"; detected when unset.
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// Other package managers the other sources stat covers, e.g. `["flatpak", "pip"]`.
+    #[serde(default)]
+    pub sources: Vec<String>,
+    #[serde(default)]
+    pub data: DataConfig,
+    #[serde(default)]
+    pub conda: CondaConfig,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct DisplayConfig {
     /// Which stats to display, in order.
     #[serde(default = "stats::default_stats")]
     pub stats: Vec<StatId>,
+    /// Glyphs used for spinners, markers and the logo.
+    #[serde(default)]
+    pub symbols: SymbolSet,
+    /// Whether terminal output is coloured; NO_COLOR turns it off unless this is `always`.
+    #[serde(default)]
+    pub color: ColorMode,
+    /// Per-stat formatting, e.g. `[display.format.last_update]`.
+    #[serde(default)]
+    pub format: HashMap<StatId, StatFormat>,
+    /// Show how each stat changed since the previous run.
+    #[serde(default = "default_true")]
+    pub show_deltas: bool,
+    /// Markdown shown before upgrading instead of the stats, with `{stat_name}` and `{pending}`
+    /// placeholders.
+    #[serde(default)]
+    pub upgrade_template: Option<String>,
+    /// What the last update stat counts as an update.
+    #[serde(default)]
+    pub last_update: LastUpdateEvent,
+}
+
+/// The pacman.log event the last update stat dates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LastUpdateEvent {
+    /// A completed `-Syu`.
+    #[default]
+    FullUpgrade,
+    /// Any completed transaction, such as installing or removing a package.
+    AnyTransaction,
+    /// Any database sync, whether or not an upgrade followed.
+    AnySync,
+}
+
+/// How a duration stat is rendered.
+#[derive(Deserialize, Default, Clone)]
+pub struct StatFormat {
+    #[serde(default)]
+    pub duration: DurationStyle,
+    /// Show the point in time (e.g. the date of the last update) instead of the elapsed time.
+    #[serde(default)]
+    pub absolute: bool,
+    /// strftime format for absolute times; defaults to the locale's date and time.
+    #[serde(default)]
+    pub date_format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DurationStyle {
+    /// "3 days 4 hours"
+    #[default]
+    Long,
+    /// "3d 4h"
+    Compact,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolSet {
+    #[default]
+    Unicode,
+    /// Pure ASCII, for console fonts that can't render the unicode glyphs.
+    Ascii,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMode {
+    /// Coloured unless NO_COLOR is set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+    /// Coloured when pacman.conf enables Color, as pacman's own output is.
+    Pacman,
+}
+
+/// Restricts which mirrorlist entries are considered.
+#[derive(Deserialize, Default, Clone)]
+pub struct MirrorConfig {
+    /// Mirror domains to skip; subdomains match too.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Skip any mirror that isn't served over https.
+    #[serde(default)]
+    pub https_only: bool,
+    /// Only use mirrors listed under these `## Country` headers.
+    #[serde(default)]
+    pub countries: Vec<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TuiConfig {
+    /// Capture the mouse in the dashboard for clicking and wheel scrolling.
+    #[serde(default = "default_true")]
+    pub mouse: bool,
+}
+
+/// What downloads cost on a metered connection.
+#[derive(Deserialize, Default, Clone)]
+pub struct DataConfig {
+    /// Price of a GB (10^9 bytes, as carriers bill) for the data cost stat.
+    #[serde(default)]
+    pub cost_per_gb: Option<f64>,
+    /// Put before prices, e.g. "$".
+    #[serde(default)]
+    pub currency: String,
+}
+
+/// Which environment the conda backend reports on.
+#[derive(Deserialize, Default, Clone)]
+pub struct CondaConfig {
+    /// The environment's name, e.g. "science"; base when unset.
+    #[serde(default)]
+    pub environment: Option<String>,
+}
+
+/// Package caches beyond the CacheDir entries in pacman.conf.
+#[derive(Deserialize, Default, Clone)]
+pub struct CacheConfig {
+    /// Extra directories counted in the cache stat and trimmed by cache cleaning.
+    #[serde(default)]
+    pub extra_dirs: Vec<PathBuf>,
+}
+
+/// Which lines of pacman's upgrade output get passed through.
+#[derive(Deserialize, Clone)]
+pub struct FilterConfig {
+    /// Hide pacman's size summaries and resolving chatter; `--no-filter` turns this off.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Regexes for lines to always show, even ones the built-in rules hide.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Regexes for extra lines to hide.
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Regexes for prompts beyond pacman's English ones, e.g. from a localized pacman.
+    #[serde(default)]
+    pub prompts: Vec<String>,
+}
+
+/// Policies applied when upgrading.
+#[derive(Deserialize, Clone)]
+pub struct UpgradeConfig {
+    /// Leave updates built fewer than this many days ago for a later upgrade, to sit out
+    /// day-one regressions.
+    #[serde(default)]
+    pub min_age_days: Option<u32>,
+    /// Packages `--critical-only` upgrades; globs allowed.
+    #[serde(default = "default_critical")]
+    pub critical: Vec<String>,
+    /// Repos packages are kept on, e.g. `linux = "core"`; an update from any other repo is
+    /// held back.
+    #[serde(default)]
+    pub pin: HashMap<String, String>,
+    /// Which updates this run installs, set by `--critical-only` or `--security-only`.
+    #[serde(skip)]
+    pub scope: UpgradeScope,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UpgradeScope {
+    #[default]
+    All,
+    /// Only the `upgrade.critical` packages.
+    Critical,
+    /// Only updates fixing an advisory arch-audit knows of.
+    Security,
+}
+
+/// Settings for `pacfetch daemon`.
+#[derive(Deserialize, Clone)]
+pub struct DaemonConfig {
+    /// Seconds between stat refreshes.
+    #[serde(default = "default_daemon_interval")]
+    pub interval_secs: u64,
+    /// Address to serve /metrics and /stats on, e.g. "127.0.0.1:9847".
+    #[serde(default)]
+    pub listen: Option<String>,
+    /// Bearer token HTTP clients must send; unauthenticated when unset.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Where `pacfetch push` and the daemon send stats for a central dashboard.
+#[derive(Deserialize, Default, Clone)]
+pub struct PushConfig {
+    /// HTTPS endpoint the stats are POSTed to as JSON.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Sent as a bearer token with each push.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+fn default_daemon_interval() -> u64 {
+    3600
+}
+
+fn default_critical() -> Vec<String> {
+    [
+        "linux",
+        "linux-lts",
+        "linux-zen",
+        "linux-hardened",
+        "glibc",
+        "openssl",
+        "openssh",
+        "sudo",
+        "systemd",
+        "firefox",
+        "chromium",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect()
+}
+
+fn default_true() -> bool {
+    true
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             display: DisplayConfig::default(),
+            mirror: MirrorConfig::default(),
+            tui: TuiConfig::default(),
+            daemon: DaemonConfig::default(),
+            push: PushConfig::default(),
+            cache: CacheConfig::default(),
+            filter: FilterConfig::default(),
+            upgrade: UpgradeConfig::default(),
+            backend: None,
+            sources: Vec::new(),
+            data: DataConfig::default(),
+            conda: CondaConfig::default(),
         }
     }
 }
@@ -29,14 +288,65 @@ impl Default for DisplayConfig {
     fn default() -> Self {
         DisplayConfig {
             stats: stats::default_stats(),
+            symbols: SymbolSet::default(),
+            color: ColorMode::default(),
+            format: HashMap::new(),
+            show_deltas: true,
+            upgrade_template: None,
+            last_update: LastUpdateEvent::default(),
+        }
+    }
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        FilterConfig {
+            enabled: true,
+            allow: Vec::new(),
+            deny: Vec::new(),
+            prompts: Vec::new(),
         }
     }
 }
 
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            interval_secs: default_daemon_interval(),
+            listen: None,
+            token: None,
+        }
+    }
+}
+
+impl Default for UpgradeConfig {
+    fn default() -> Self {
+        UpgradeConfig {
+            min_age_days: None,
+            critical: default_critical(),
+            pin: HashMap::new(),
+            scope: UpgradeScope::All,
+        }
+    }
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        TuiConfig { mouse: true }
+    }
+}
+
+impl DisplayConfig {
+    /// The configured format for a stat, or the default one.
+    pub fn format_of(&self, stat_id: StatId) -> StatFormat {
+        self.format.get(&stat_id).cloned().unwrap_or_default()
+    }
+}
+
 impl Config {
     /// Returns the path to the config file (~/.config/pacfetch.toml).
     fn config_path() -> Option<PathBuf> {
-        dirs::config_dir().map(|p| p.join("pacfetch.toml"))
+        paths::config_file()
     }
 
     pub fn load() -> Self {
@@ -0,0 +1,112 @@
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+
+use crate::{paths, storage, terminal};
+
+/// The state subdirectory crash logs go in.
+const CRASH_DIR: &str = "crashes";
+/// Crash logs kept; older ones are deleted as new ones are written.
+const KEEP_LOGS: usize = 5;
+
+/// Replace the default panic message with one that hands the terminal back first, since
+/// a panic in the TUI or mid-upgrade would otherwise leave it raw, on the alternate
+/// screen and without a cursor. The details go to a crash log rather than the screen.
+/// Worker threads' panics are left to the previous hook: their callers carry on without
+/// the result, with the screen still in use.
+pub fn install_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() != Some("main") {
+            return previous(info);
+        }
+        terminal::restore();
+        let message = panic_message(info);
+        eprintln!("pacfetch crashed: {}", message);
+        match write_log(&message) {
+            Ok(path) => eprintln!("A crash log was saved to {}.", path.display()),
+            Err(e) => eprintln!("The crash log couldn't be saved: {}", e),
+        }
+        eprintln!("Please run pacfetch bug-report and attach its output to an issue.");
+    }));
+}
+
+fn panic_message(info: &PanicHookInfo) -> String {
+    let payload = info.payload_as_str().unwrap_or("unknown error");
+    match info.location() {
+        Some(location) => format!("{} at {}:{}", payload, location.file(), location.line()),
+        None => payload.to_string(),
+    }
+}
+
+fn write_log(message: &str) -> Result<PathBuf, String> {
+    let dir = paths::state_file(CRASH_DIR).ok_or("No state directory")?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let now = chrono::Local::now();
+    let path = dir.join(format!(
+        "crash-{}-{}.log",
+        now.format("%Y%m%d-%H%M%S"),
+        std::process::id()
+    ));
+    let thread = std::thread::current();
+    let contents = format!(
+        "pacfetch {}\ntime: {}\ncommand: {}\nthread: {}\npanic: {}\n\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        now.to_rfc3339(),
+        std::env::args().collect::<Vec<_>>().join(" "),
+        thread.name().unwrap_or("unnamed"),
+        message,
+        Backtrace::force_capture()
+    );
+    storage::write_atomic(&path, contents).map_err(|e| format!("Failed to save: {}", e))?;
+    for old in logs().iter().skip(KEEP_LOGS) {
+        let _ = fs::remove_file(old);
+    }
+    Ok(path)
+}
+
+/// The crash logs on disk, newest first.
+pub fn logs() -> Vec<PathBuf> {
+    let Some(dir) = paths::state_file(CRASH_DIR) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.retain(|name| is_log_name(name));
+    newest_first(&mut names);
+    names.into_iter().map(|name| dir.join(name)).collect()
+}
+
+fn is_log_name(name: &str) -> bool {
+    name.starts_with("crash-") && name.ends_with(".log")
+}
+
+/// Log names start with their time, so they sort by it.
+fn newest_first(names: &mut [String]) {
+    names.sort_by(|a, b| b.cmp(a));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_crash_logs_newest_first() {
+        let mut names = vec![
+            "crash-20240601-120000-41.log".to_string(),
+            "crash-20240715-080000-7.log".to_string(),
+            "crash-20240102-235959-100.log".to_string(),
+        ];
+        newest_first(&mut names);
+        assert_eq!(names[0], "crash-20240715-080000-7.log");
+        assert_eq!(names[2], "crash-20240102-235959-100.log");
+        assert!(!is_log_name("stats.db"));
+    }
+}
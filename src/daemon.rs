@@ -0,0 +1,208 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::{history, push, ui};
+
+const OPENMETRICS_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// The latest stats, already rendered for each endpoint.
+#[derive(Default)]
+struct Rendered {
+    metrics: String,
+    json: String,
+}
+
+/// Refresh stats every `daemon.interval_secs`, pushing them to `push.url` and serving them
+/// over HTTP on `daemon.listen`, whichever are configured.
+pub fn run(config: &Config) -> Result<(), String> {
+    if config.daemon.listen.is_none() && config.push.url.is_none() {
+        return Err("nothing to do: set daemon.listen or push.url in the config".to_string());
+    }
+    let listener = match &config.daemon.listen {
+        Some(listen) => Some(
+            TcpListener::bind(listen)
+                .map_err(|e| format!("Failed to listen on {}: {}", listen, e))?,
+        ),
+        None => None,
+    };
+
+    let rendered = Arc::new(Mutex::new(refresh(config)));
+    let background = Arc::clone(&rendered);
+    let refresh_config = config.clone();
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(Duration::from_secs(
+                refresh_config.daemon.interval_secs.max(1),
+            ));
+            let latest = refresh(&refresh_config);
+            if let Ok(mut current) = background.lock() {
+                *current = latest;
+            }
+        }
+    });
+
+    let Some(listener) = listener else {
+        // Push only: the refresh thread does all the work
+        loop {
+            std::thread::park();
+        }
+    };
+
+    if let Some(listen) = &config.daemon.listen {
+        println!("Serving /metrics and /stats on {}", listen);
+    }
+    for stream in listener.incoming().flatten() {
+        // A thread per connection, so one slow client can't hold up every other scrape
+        let rendered = Arc::clone(&rendered);
+        let token = config.daemon.token.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = serve(stream, &rendered, token.as_deref()) {
+                eprintln!("warning: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn refresh(config: &Config) -> Rendered {
+    let stats = push::gather(config);
+    if let Err(e) = history::record_stats(&stats, chrono::Local::now().timestamp()) {
+        eprintln!("warning: {}", e);
+    }
+    if config.push.url.is_some()
+        && let Err(e) = push::push(&stats, config)
+    {
+        eprintln!("warning: {}", e);
+    }
+    Rendered {
+        metrics: ui::format_openmetrics(&stats),
+        json: ui::format_facts(&stats, config, false),
+    }
+}
+
+/// The parts of an HTTP request the endpoints look at.
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    path: String,
+    authorized: bool,
+}
+
+/// Read the request line and headers; a request is authorized when no token is set, or
+/// when it carries the token as `Authorization: Bearer <token>`.
+fn read_request(mut reader: impl BufRead, token: Option<&str>) -> Result<Request, String> {
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .map_err(|e| format!("Failed to read request: {}", e))?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+
+    let mut authorized = token.is_none();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).unwrap_or(0) == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("authorization")
+            && let Some(token) = token
+            && let Some(sent) = value.trim().strip_prefix("Bearer ")
+            && constant_time_eq(sent.as_bytes(), token.as_bytes())
+        {
+            authorized = true;
+        }
+    }
+    Ok(Request {
+        method,
+        path,
+        authorized,
+    })
+}
+
+/// Compare without stopping at the first difference, so response times don't give the
+/// token away a byte at a time.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn serve(stream: TcpStream, rendered: &Mutex<Rendered>, token: Option<&str>) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(|e| e.to_string())?;
+    let Request {
+        method,
+        path,
+        authorized,
+    } = read_request(BufReader::new(&stream), token)?;
+
+    let (status, content_type, body) = if method != "GET" {
+        (
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n".to_string(),
+        )
+    } else if !authorized {
+        (
+            "401 Unauthorized",
+            "text/plain",
+            "unauthorized\n".to_string(),
+        )
+    } else {
+        let current = rendered
+            .lock()
+            .map_err(|_| "stats lock poisoned".to_string())?;
+        match path.as_str() {
+            "/metrics" => ("200 OK", OPENMETRICS_TYPE, current.metrics.clone()),
+            "/stats" => ("200 OK", "application/json", format!("{}\n", current.json)),
+            _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        }
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    (&stream)
+        .write_all(response.as_bytes())
+        .map_err(|e| format!("Failed to send response: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authorizes_requests_with_the_bearer_token() {
+        let request = |text: &str, token| read_request(text.as_bytes(), token).unwrap();
+        let scrape = "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\
+                      Authorization: Bearer s3cret\r\n\r\n";
+        assert_eq!(
+            request(scrape, Some("s3cret")),
+            Request {
+                method: "GET".to_string(),
+                path: "/metrics".to_string(),
+                authorized: true,
+            }
+        );
+        assert!(!request(scrape, Some("s3cre")).authorized);
+        assert!(!request(scrape, Some("s3cret!")).authorized);
+        assert!(!request("GET /stats HTTP/1.1\r\n\r\n", Some("s3cret")).authorized);
+        assert!(request("GET /stats HTTP/1.1\r\n\r\n", None).authorized);
+        // The scheme has to be there; the bare token isn't accepted
+        assert!(
+            !request(
+                "GET / HTTP/1.1\r\nauthorization: s3cret\r\n\r\n",
+                Some("s3cret")
+            )
+            .authorized
+        );
+    }
+}
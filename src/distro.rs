@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::OnceLock;
+
+const OS_RELEASE_PATH: &str = "/etc/os-release";
+const PACMAN_MIRRORS_CONF_PATH: &str = "/etc/pacman-mirrors.conf";
+
+/// Arch and the derivatives whose repos or mirrors differ from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Distro {
+    Arch,
+    ArchArm,
+    EndeavourOS,
+    Manjaro,
+    CachyOS,
+}
+
+impl Distro {
+    /// Detect the distro from os-release, taking anything Arch-like for Arch. The file is
+    /// read once per run.
+    pub fn detect() -> Distro {
+        static DETECTED: OnceLock<Distro> = OnceLock::new();
+        *DETECTED.get_or_init(|| {
+            let contents = fs::read_to_string(OS_RELEASE_PATH).unwrap_or_default();
+            Self::from_os_release(&contents)
+        })
+    }
+
+    fn from_os_release(contents: &str) -> Distro {
+        let id = os_release_value(contents, "ID").unwrap_or_default();
+        match id.as_str() {
+            "archarm" => Distro::ArchArm,
+            "endeavouros" => Distro::EndeavourOS,
+            "manjaro" | "manjaro-arm" => Distro::Manjaro,
+            "cachyos" => Distro::CachyOS,
+            _ => Distro::Arch,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Distro::Arch => "Arch Linux",
+            Distro::ArchArm => "Arch Linux ARM",
+            Distro::EndeavourOS => "EndeavourOS",
+            Distro::Manjaro => "Manjaro",
+            Distro::CachyOS => "CachyOS",
+        }
+    }
+
+    /// The sync databases to use when pacman.conf can't be read.
+    pub fn default_repos(&self) -> &'static [&'static str] {
+        match self {
+            Distro::Arch | Distro::Manjaro => &["core", "extra", "multilib"],
+            Distro::ArchArm => &["core", "extra", "alarm", "aur"],
+            Distro::EndeavourOS => &["endeavouros", "core", "extra", "multilib"],
+            Distro::CachyOS => &["cachyos", "core", "extra", "multilib"],
+        }
+    }
+
+    /// The file under the root of a mirror in /etc/pacman.d/mirrorlist that records when
+    /// it last synced.
+    pub fn sync_file(&self) -> &'static str {
+        match self {
+            Distro::Arch => "lastsync",
+            // Manjaro mirrors publish a state file per branch instead of lastsync
+            Distro::Manjaro => "state",
+            // ALARM mirrors have no lastsync; core's database changes with every sync
+            Distro::ArchArm => "core/core.db",
+            // Their mirrorlist is Arch's; their own repos have mirrorlists of their own,
+            // which the custom repo check dates by their databases
+            Distro::EndeavourOS | Distro::CachyOS => "lastsync",
+        }
+    }
+}
+
+/// The machine's architecture as pacman names it, e.g. "x86_64", "aarch64" or "armv7h".
+pub fn machine_arch() -> String {
+    #[cfg(unix)]
+    {
+        let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+        if unsafe { libc::uname(&mut name) } == 0 {
+            let machine = unsafe { std::ffi::CStr::from_ptr(name.machine.as_ptr()) };
+            return pacman_arch_name(&machine.to_string_lossy());
+        }
+    }
+    std::env::consts::ARCH.to_string()
+}
+
+/// ALARM's names for the 32-bit ARM machines uname reports, e.g. armv7l -> armv7h.
+fn pacman_arch_name(machine: &str) -> String {
+    match machine {
+        "armv7l" => "armv7h".to_string(),
+        "armv6l" => "armv6h".to_string(),
+        "arm64" => "aarch64".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// The value of `key` in os-release contents, unquoted.
+fn os_release_value(contents: &str, key: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let (k, value) = line.split_once('=')?;
+        (k.trim() == key).then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// The Manjaro branch pacman-mirrors is set to, "stable" unless configured otherwise.
+pub fn manjaro_branch() -> String {
+    let contents = fs::read_to_string(PACMAN_MIRRORS_CONF_PATH).unwrap_or_default();
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "Branch").then(|| value.trim().to_string())
+        })
+        .filter(|branch| !branch.is_empty())
+        .unwrap_or_else(|| "stable".to_string())
+}
+
+/// The sync time a lastsync or Manjaro state file records.
+pub fn parse_sync_file(name: &str, contents: &str) -> Option<i64> {
+    if name == "state" {
+        // e.g. "date=2024-06-10T09:13:44Z" next to the snapshot's hash
+        let date = contents
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("date="))?;
+        chrono::DateTime::parse_from_rfc3339(date.trim())
+            .ok()
+            .map(|t| t.timestamp())
+    } else {
+        contents.trim().parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_derivatives() {
+        let manjaro = "NAME=\"Manjaro Linux\"\nID=\"manjaro\"\nID_LIKE=arch\n";
+        assert_eq!(Distro::from_os_release(manjaro), Distro::Manjaro);
+        assert_eq!(
+            Distro::from_os_release("ID=endeavouros\nID_LIKE=arch\n"),
+            Distro::EndeavourOS
+        );
+        assert_eq!(Distro::from_os_release("ID=arch\n"), Distro::Arch);
+        assert_eq!(Distro::from_os_release("ID=archarm\n"), Distro::ArchArm);
+        assert_eq!(Distro::from_os_release(""), Distro::Arch);
+    }
+
+    #[test]
+    fn names_arm_machines_like_alarm() {
+        assert_eq!(pacman_arch_name("armv7l"), "armv7h");
+        assert_eq!(pacman_arch_name("aarch64"), "aarch64");
+        assert_eq!(pacman_arch_name("x86_64"), "x86_64");
+    }
+
+    #[test]
+    fn parses_sync_files() {
+        assert_eq!(
+            parse_sync_file("lastsync", "1717243205\n"),
+            Some(1717243205)
+        );
+        let state = "date=2024-06-10T09:13:44Z\nstate=3f1e2d\n";
+        assert_eq!(parse_sync_file("state", state), Some(1718010824));
+        assert_eq!(parse_sync_file("state", "garbage"), None);
+    }
+}
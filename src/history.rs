@@ -0,0 +1,748 @@
+use crate::paths;
+use crate::stats::{self, ManagerStats, StatId};
+use crate::storage::{self, Schema};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Number of past upgrades kept for throughput estimates.
+const MAX_UPGRADE_RECORDS: usize = 50;
+/// Days of upgrades the monthly data projection averages.
+const PROJECTION_WINDOW_DAYS: i64 = 90;
+/// The shortest history a projection is made from, so one big upgrade isn't taken for
+/// a trend.
+const PROJECTION_MIN_DAYS: i64 = 7;
+
+/// Sizes and phase durations of one completed upgrade.
+#[derive(Serialize, Deserialize)]
+pub struct UpgradeRecord {
+    pub timestamp: i64,
+    pub download_mb: f64,
+    pub installed_mb: f64,
+    pub download_secs: f64,
+    pub install_secs: f64,
+    /// What the package cache spared the download; absent from older records.
+    #[serde(default)]
+    pub cached_mb: f64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct UpgradeHistory {
+    #[serde(default)]
+    upgrade: Vec<UpgradeRecord>,
+}
+
+/// How long building one AUR package took.
+#[derive(Serialize, Deserialize)]
+pub struct BuildRecord {
+    pub timestamp: i64,
+    pub package: String,
+    pub version: String,
+    pub build_secs: f64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct BuildHistory {
+    #[serde(default)]
+    build: Vec<BuildRecord>,
+}
+
+/// Numeric stats from the previous run, for showing what changed since.
+#[derive(Serialize, Deserialize)]
+pub struct StatsSnapshot {
+    pub timestamp: i64,
+    pub values: BTreeMap<StatId, f64>,
+    /// The package cache's share of the download size.
+    #[serde(default)]
+    pub download_cached_mb: Option<f64>,
+}
+
+impl StatsSnapshot {
+    pub fn capture(stats: &ManagerStats, timestamp: i64) -> Self {
+        let values = [
+            StatId::Installed,
+            StatId::Upgradable,
+            StatId::DownloadSize,
+            StatId::InstalledSize,
+            StatId::NetUpgradeSize,
+            StatId::OrphanedPackages,
+            StatId::CacheSize,
+        ]
+        .into_iter()
+        .filter_map(|id| id.numeric_value(stats).map(|v| (id, v)))
+        .collect();
+        StatsSnapshot {
+            timestamp,
+            values,
+            download_cached_mb: stats.download_cached_mb,
+        }
+    }
+
+    /// Change in a stat since the snapshot, if it moved.
+    pub fn delta(&self, stat_id: StatId, stats: &ManagerStats) -> Option<f64> {
+        let previous = self.values.get(&stat_id)?;
+        let delta = stat_id.numeric_value(stats)? - previous;
+        (delta.abs() >= 0.01).then_some(delta)
+    }
+
+    /// Change in the cached share of the download size since the snapshot, if it moved.
+    pub fn cached_delta(&self, stats: &ManagerStats) -> Option<f64> {
+        let delta = stats.download_cached_mb? - self.download_cached_mb?;
+        (delta.abs() >= 0.01).then_some(delta)
+    }
+}
+
+/// Version 2 added what the package cache saved each upgrade.
+const UPGRADES_SCHEMA: Schema = Schema {
+    version: 2,
+    migrations: &[add_cached_mb],
+};
+const BUILDS_SCHEMA: Schema = Schema {
+    version: 1,
+    migrations: &[],
+};
+const ORPHANS_SCHEMA: Schema = Schema {
+    version: 1,
+    migrations: &[],
+};
+const IGNORED_SCHEMA: Schema = Schema {
+    version: 1,
+    migrations: &[],
+};
+const SNAPSHOT_SCHEMA: Schema = Schema {
+    version: 1,
+    migrations: &[],
+};
+/// The stats history's tables, by the `user_version` each brings the database to.
+const STATS_DB_MIGRATIONS: [&str; 2] = [
+    "CREATE TABLE IF NOT EXISTS stats (
+         timestamp INTEGER NOT NULL,
+         stat TEXT NOT NULL,
+         value REAL NOT NULL
+     );
+     CREATE INDEX IF NOT EXISTS stats_by_name ON stats (stat, timestamp);",
+    "CREATE TABLE usage (
+         timestamp INTEGER NOT NULL,
+         command TEXT NOT NULL,
+         duration_secs REAL NOT NULL,
+         exit_code INTEGER NOT NULL
+     );",
+];
+/// Days of pacfetch's own runs kept for `about --stats`.
+const USAGE_KEEP_DAYS: i64 = 365;
+
+/// Upgrades recorded before the cache savings were tracked get none.
+fn add_cached_mb(table: &mut toml::Table) -> Result<(), String> {
+    let Some(upgrades) = table.get_mut("upgrade").and_then(|u| u.as_array_mut()) else {
+        return Ok(());
+    };
+    for upgrade in upgrades.iter_mut().filter_map(|u| u.as_table_mut()) {
+        upgrade.entry("cached_mb").or_insert(0.0.into());
+    }
+    Ok(())
+}
+
+/// Returns the path to the upgrade history (~/.local/state/pacfetch/upgrades.toml).
+fn history_path() -> Option<PathBuf> {
+    paths::state_file("upgrades.toml")
+}
+
+/// Returns the path to the AUR build history (~/.local/state/pacfetch/builds.toml), the
+/// sudo user's under sudo, since the helpers build as that user.
+fn builds_path() -> Option<PathBuf> {
+    paths::user_state_file("builds.toml")
+}
+
+fn load_builds() -> BuildHistory {
+    builds_path()
+        .and_then(|path| storage::read_toml(&path, &BUILDS_SCHEMA))
+        .and_then(|contents| toml::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Add whatever builds `find` turns up that aren't in the history yet; it's handed the
+/// recorded ones so it can skip timing them again.
+pub fn record_builds(find: impl FnOnce(&[BuildRecord]) -> Vec<BuildRecord>) -> Result<(), String> {
+    let mut history = load_builds();
+    let found = find(&history.build);
+    if found.is_empty() {
+        return Ok(());
+    }
+    history.build.extend(found);
+
+    let path = builds_path().ok_or("could not determine state directory")?;
+    paths::ensure_parent(&path)?;
+    let contents = toml::to_string(&history).map_err(|e| e.to_string())?;
+    storage::write_toml(&path, &BUILDS_SCHEMA, &contents)
+        .map_err(|e| format!("Failed to write build history: {}", e))?;
+    paths::return_to_sudo_user(&path)
+}
+
+/// Estimate how long building these AUR packages takes: each package's average past
+/// build time, or the average over all builds for packages never built before.
+pub fn estimate_build_secs(packages: &[String]) -> Option<f64> {
+    let history = load_builds();
+    if history.build.is_empty() {
+        return None;
+    }
+
+    let average = |records: Vec<&BuildRecord>| -> Option<f64> {
+        (!records.is_empty())
+            .then(|| records.iter().map(|r| r.build_secs).sum::<f64>() / records.len() as f64)
+    };
+    let overall = average(history.build.iter().collect())?;
+
+    Some(
+        packages
+            .iter()
+            .map(|name| {
+                average(
+                    history
+                        .build
+                        .iter()
+                        .filter(|r| &r.package == name)
+                        .collect(),
+                )
+                .unwrap_or(overall)
+            })
+            .sum(),
+    )
+}
+
+/// An orphaned package and when pacfetch first noticed it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OrphanRecord {
+    pub name: String,
+    pub first_seen: i64,
+    /// The check before `first_seen`, when it wasn't an orphan yet; whatever made it one
+    /// happened in between. None when tracking started with it already orphaned.
+    pub previous_check: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct OrphanHistory {
+    last_check: Option<i64>,
+    #[serde(default)]
+    orphan: Vec<OrphanRecord>,
+}
+
+/// Returns the path to the tracked orphans (~/.local/state/pacfetch/orphans.toml).
+fn orphans_path() -> Option<PathBuf> {
+    paths::state_file("orphans.toml")
+}
+
+/// Compare the current orphans with the last check: new ones are stamped with `now`,
+/// ones that are gone are forgotten. Returns the records for the current orphans.
+pub fn track_orphans(current: &[String], now: i64) -> Result<Vec<OrphanRecord>, String> {
+    let path = orphans_path().ok_or("could not determine state directory")?;
+    let mut history: OrphanHistory = storage::read_toml(&path, &ORPHANS_SCHEMA)
+        .and_then(|contents| toml::from_str(&contents).ok())
+        .unwrap_or_default();
+
+    let records: Vec<OrphanRecord> = current
+        .iter()
+        .map(|name| {
+            history
+                .orphan
+                .iter()
+                .find(|r| &r.name == name)
+                .cloned()
+                .unwrap_or_else(|| OrphanRecord {
+                    name: name.clone(),
+                    first_seen: now,
+                    previous_check: history.last_check,
+                })
+        })
+        .collect();
+    history.orphan = records.clone();
+    history.last_check = Some(now);
+
+    paths::ensure_parent(&path)?;
+    let contents = toml::to_string(&history).map_err(|e| e.to_string())?;
+    storage::write_toml(&path, &ORPHANS_SCHEMA, &contents)
+        .map_err(|e| format!("Failed to write orphan history: {}", e))?;
+    Ok(records)
+}
+
+/// A package held back until a fixed version is out or for a while.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Hold {
+    pub name: String,
+    /// Released once the pending upgrade reaches this version.
+    pub until_version: Option<String>,
+    /// Released at this time.
+    pub until: Option<i64>,
+}
+
+impl Hold {
+    /// Whether the hold is over, given the version the pending upgrade would install.
+    #[cfg(target_os = "linux")]
+    pub fn is_released(&self, pending_version: Option<&str>, now: i64) -> bool {
+        let version_out = match (&self.until_version, pending_version) {
+            (Some(wanted), Some(pending)) => {
+                alpm::vercmp(pending, wanted.as_str()) != std::cmp::Ordering::Less
+            }
+            _ => false,
+        };
+        version_out || self.until.is_some_and(|until| now >= until)
+    }
+}
+
+/// Packages `pacfetch ignore` holds back, passed to pacman with `--ignore`.
+#[derive(Serialize, Deserialize, Default)]
+pub struct IgnoreList {
+    /// Held back until removed from the list.
+    #[serde(default)]
+    pub packages: Vec<String>,
+    #[serde(default)]
+    pub hold: Vec<Hold>,
+}
+
+impl IgnoreList {
+    pub fn contains(&self, name: &str) -> bool {
+        self.packages.iter().any(|p| p == name) || self.hold.iter().any(|h| h.name == name)
+    }
+
+    /// All names the list holds back, holds included.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self.packages.clone();
+        names.extend(self.hold.iter().map(|h| h.name.clone()));
+        names
+    }
+}
+
+/// Returns the path to the ignore list (~/.local/state/pacfetch/ignored.toml), the sudo
+/// user's when upgrading with sudo.
+fn ignored_path() -> Option<PathBuf> {
+    paths::user_state_file("ignored.toml")
+}
+
+pub fn load_ignore_list() -> IgnoreList {
+    ignored_path()
+        .and_then(|path| storage::read_toml(&path, &IGNORED_SCHEMA))
+        .and_then(|contents| toml::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_ignore_list(list: &IgnoreList) -> Result<(), String> {
+    let path = ignored_path().ok_or("could not determine state directory")?;
+
+    paths::ensure_parent(&path)?;
+    let contents = toml::to_string(list).map_err(|e| e.to_string())?;
+    storage::write_toml(&path, &IGNORED_SCHEMA, &contents)
+        .map_err(|e| format!("Failed to write ignore list: {}", e))?;
+    paths::return_to_sudo_user(&path)
+}
+
+/// Returns the path to the last run's stats (~/.local/state/pacfetch/last_stats.toml).
+fn snapshot_path() -> Option<PathBuf> {
+    paths::state_file("last_stats.toml")
+}
+
+pub fn load_stats_snapshot() -> Option<StatsSnapshot> {
+    let contents = storage::read_toml(&snapshot_path()?, &SNAPSHOT_SCHEMA)?;
+    toml::from_str(&contents).ok()
+}
+
+pub fn save_stats_snapshot(snapshot: &StatsSnapshot) -> Result<(), String> {
+    let path = snapshot_path().ok_or("could not determine state directory")?;
+
+    paths::ensure_parent(&path)?;
+    let contents = toml::to_string(snapshot).map_err(|e| e.to_string())?;
+    storage::write_toml(&path, &SNAPSHOT_SCHEMA, &contents)
+        .map_err(|e| format!("Failed to write stats snapshot: {}", e))
+}
+
+/// The stats of the last run, for instances that find the lock taken.
+#[derive(Deserialize)]
+struct StatsCache {
+    requested: Vec<StatId>,
+    stats: ManagerStats,
+}
+
+/// Returns the path to the cached stats (~/.cache/pacfetch/stats.json).
+fn stats_cache_path() -> Option<PathBuf> {
+    paths::cache_file("stats.json")
+}
+
+/// The cached stats, if they were gathered for at least the `requested` ones.
+pub fn load_stats_cache(requested: &[StatId]) -> Option<ManagerStats> {
+    let contents = fs::read_to_string(stats_cache_path()?).ok()?;
+    let cache: StatsCache = serde_json::from_str(&contents).ok()?;
+    requested
+        .iter()
+        .all(|id| cache.requested.contains(id))
+        .then_some(cache.stats)
+}
+
+pub fn save_stats_cache(requested: &[StatId], stats: &ManagerStats) -> Result<(), String> {
+    let path = stats_cache_path().ok_or("could not determine cache directory")?;
+    paths::ensure_parent(&path)?;
+
+    let contents = serde_json::json!({ "requested": requested, "stats": stats }).to_string();
+    storage::write_atomic(&path, contents)
+        .map_err(|e| format!("Failed to write stats cache: {}", e))
+}
+
+/// Returns the path to the stats history database (~/.local/state/pacfetch/stats.db).
+fn stats_db_path() -> Option<PathBuf> {
+    paths::state_file("stats.db")
+}
+
+fn open_stats_db() -> Result<Connection, String> {
+    let path = stats_db_path().ok_or("could not determine state directory")?;
+    paths::ensure_parent(&path)?;
+
+    let mut db =
+        Connection::open(&path).map_err(|e| format!("Failed to open stats history: {}", e))?;
+    storage::migrate_db(&mut db, &path, &STATS_DB_MIGRATIONS)
+        .map_err(|e| format!("Failed to set up stats history: {}", e))?;
+    Ok(db)
+}
+
+/// Append every numeric stat of this run to the stats history.
+pub fn record_stats(stats: &ManagerStats, timestamp: i64) -> Result<(), String> {
+    let mut db = open_stats_db()?;
+    let tx = db.transaction().map_err(|e| e.to_string())?;
+    for (name, value) in stats::numeric_metrics(stats) {
+        tx.execute(
+            "INSERT INTO stats (timestamp, stat, value) VALUES (?1, ?2, ?3)",
+            (timestamp, &name, value),
+        )
+        .map_err(|e| format!("Failed to record stats: {}", e))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Failed to record stats: {}", e))
+}
+
+/// Note one pacfetch run, dropping runs older than `USAGE_KEEP_DAYS`. Nothing about the
+/// system is kept, only which command ran, for how long and how it exited.
+pub fn record_usage(
+    command: &str,
+    duration_secs: f64,
+    exit_code: i32,
+    timestamp: i64,
+) -> Result<(), String> {
+    let db = open_stats_db()?;
+    db.execute(
+        "INSERT INTO usage (timestamp, command, duration_secs, exit_code)
+         VALUES (?1, ?2, ?3, ?4)",
+        (timestamp, command, duration_secs, exit_code),
+    )
+    .and_then(|_| {
+        db.execute(
+            "DELETE FROM usage WHERE timestamp < ?1",
+            [timestamp - USAGE_KEEP_DAYS * 86400],
+        )
+    })
+    .map(|_| ())
+    .map_err(|e| format!("Failed to record usage: {}", e))
+}
+
+/// How one command has fared over the recorded runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandUsage {
+    pub command: String,
+    pub runs: u32,
+    pub failures: u32,
+    pub avg_secs: f64,
+    pub max_secs: f64,
+    pub last_run: i64,
+}
+
+/// Each recorded command's runs, most run first.
+pub fn usage_summary() -> Result<Vec<CommandUsage>, String> {
+    let db = open_stats_db()?;
+    let mut statement = db
+        .prepare(
+            "SELECT command, count(*), sum(exit_code != 0), avg(duration_secs),
+                    max(duration_secs), max(timestamp)
+             FROM usage GROUP BY command ORDER BY count(*) DESC, command",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok(CommandUsage {
+                command: row.get(0)?,
+                runs: row.get(1)?,
+                failures: row.get(2)?,
+                avg_secs: row.get(3)?,
+                max_secs: row.get(4)?,
+                last_run: row.get(5)?,
+            })
+        })
+        .map_err(|e| format!("Failed to query usage: {}", e))?;
+    rows.collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to query usage: {}", e))
+}
+
+/// One stat's recorded (timestamp, value) pairs since `since`, oldest first.
+pub fn stat_points(stat: &str, since: i64) -> Result<Vec<(i64, f64)>, String> {
+    let db = open_stats_db()?;
+    let mut statement = db
+        .prepare(
+            "SELECT timestamp, value FROM stats
+             WHERE stat = ?1 AND timestamp >= ?2 ORDER BY timestamp",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map((stat, since), |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| format!("Failed to query stats history: {}", e))?;
+    rows.collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to query stats history: {}", e))
+}
+
+/// Groups history rows for `query_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Bucket {
+    Day,
+    Week,
+    Month,
+}
+
+impl Bucket {
+    fn strftime(&self) -> &'static str {
+        match self {
+            Bucket::Day => "%Y-%m-%d",
+            Bucket::Week => "%Y-W%W",
+            Bucket::Month => "%Y-%m",
+        }
+    }
+}
+
+/// How the rows in one bucket are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Aggregate {
+    Max,
+    Min,
+    Avg,
+    Last,
+}
+
+/// One stat's recorded values since `since`, oldest first, labelled with their local
+/// time. With a bucket, each day/week/month collapses into one aggregated row.
+pub fn query_stats(
+    stat: &str,
+    since: i64,
+    bucket: Option<Bucket>,
+    aggregate: Aggregate,
+) -> Result<Vec<(String, f64)>, String> {
+    let db = open_stats_db()?;
+
+    let sql = match bucket {
+        None => "SELECT strftime('%Y-%m-%d %H:%M', timestamp, 'unixepoch', 'localtime'), value
+                 FROM stats WHERE stat = ?1 AND timestamp >= ?2 ORDER BY timestamp"
+            .to_string(),
+        Some(bucket) => {
+            let combine = match aggregate {
+                Aggregate::Max => "MAX(value)",
+                Aggregate::Min => "MIN(value)",
+                Aggregate::Avg => "AVG(value)",
+                // SQLite returns the row holding MAX(timestamp) for bare columns
+                Aggregate::Last => "value, MAX(timestamp)",
+            };
+            format!(
+                "SELECT strftime('{}', timestamp, 'unixepoch', 'localtime') AS bucket, {}
+                 FROM stats WHERE stat = ?1 AND timestamp >= ?2
+                 GROUP BY bucket ORDER BY MIN(timestamp)",
+                bucket.strftime(),
+                combine
+            )
+        }
+    };
+
+    let mut statement = db.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map((stat, since), |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| format!("Failed to query stats history: {}", e))?;
+    rows.collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to query stats history: {}", e))
+}
+
+fn load_history() -> UpgradeHistory {
+    let Some(path) = history_path() else {
+        return UpgradeHistory::default();
+    };
+
+    let Some(contents) = storage::read_toml(&path, &UPGRADES_SCHEMA) else {
+        return UpgradeHistory::default();
+    };
+
+    toml::from_str(&contents).unwrap_or_default()
+}
+
+pub fn record_upgrade(record: UpgradeRecord) -> Result<(), String> {
+    let path = history_path().ok_or("could not determine state directory")?;
+
+    let mut history = load_history();
+    history.upgrade.push(record);
+    if history.upgrade.len() > MAX_UPGRADE_RECORDS {
+        let excess = history.upgrade.len() - MAX_UPGRADE_RECORDS;
+        history.upgrade.drain(..excess);
+    }
+
+    paths::ensure_parent(&path)?;
+    let contents = toml::to_string(&history).map_err(|e| e.to_string())?;
+    storage::write_toml(&path, &UPGRADES_SCHEMA, &contents)
+        .map_err(|e| format!("Failed to write upgrade history: {}", e))
+}
+
+/// MiB a month of updates is projected to download, from the `(timestamp, download_mb)`
+/// of past upgrades: what the ones in the window downloaded, spread over the days since the
+/// first of them.
+fn project_monthly_mb(downloads: &[(i64, f64)], now: i64) -> Option<f64> {
+    let since = now - PROJECTION_WINDOW_DAYS * 86400;
+    let recent: Vec<&(i64, f64)> = downloads.iter().filter(|(t, _)| *t >= since).collect();
+    let first = recent.iter().map(|(t, _)| *t).min()?;
+    let days = (now - first) as f64 / 86400.0;
+    if days < PROJECTION_MIN_DAYS as f64 {
+        return None;
+    }
+    let total: f64 = recent.iter().map(|(_, mb)| mb).sum();
+    Some(total / days * 30.0)
+}
+
+/// The projected monthly update download in MiB; None until there's a week of history.
+pub fn projected_monthly_download_mb(now: i64) -> Option<f64> {
+    let downloads: Vec<(i64, f64)> = load_history()
+        .upgrade
+        .iter()
+        .map(|r| (r.timestamp, r.download_mb))
+        .collect();
+    project_monthly_mb(&downloads, now)
+}
+
+/// MiB the package cache saved over the recorded upgrades, and how many upgrades that is.
+pub fn total_cache_savings() -> (f64, usize) {
+    sum_cache_savings(&load_history().upgrade)
+}
+
+fn sum_cache_savings(records: &[UpgradeRecord]) -> (f64, usize) {
+    (records.iter().map(|r| r.cached_mb).sum(), records.len())
+}
+
+/// Estimate how long an upgrade of the given size takes, from past download and install throughput.
+pub fn estimate_upgrade_secs(download_mb: f64, installed_mb: f64) -> Option<f64> {
+    let history = load_history();
+
+    let throughput = |size: fn(&UpgradeRecord) -> (f64, f64)| -> Option<f64> {
+        let (total_mb, total_secs) = history
+            .upgrade
+            .iter()
+            .map(size)
+            .filter(|(mb, secs)| *mb > 0.0 && *secs > 0.0)
+            .fold((0.0, 0.0), |(m, s), (mb, secs)| (m + mb, s + secs));
+        (total_secs > 0.0).then(|| total_mb / total_secs)
+    };
+
+    let mut estimate = 0.0;
+    if download_mb >= 0.01 {
+        estimate += download_mb / throughput(|r| (r.download_mb, r.download_secs))?;
+    }
+    if installed_mb >= 0.01 {
+        estimate += installed_mb / throughput(|r| (r.installed_mb, r.install_secs))?;
+    }
+    Some(estimate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StatFormat;
+
+    #[test]
+    fn projects_monthly_downloads() {
+        let day = 86400;
+        let now = 100 * day;
+        // 300 MiB across the 30 days since the first upgrade in the window
+        let downloads = [
+            (now - 200 * day, 5000.0),
+            (now - 30 * day, 100.0),
+            (now - 10 * day, 200.0),
+        ];
+        assert_eq!(project_monthly_mb(&downloads, now), Some(300.0));
+        assert_eq!(project_monthly_mb(&[(now - 2 * day, 400.0)], now), None);
+        assert_eq!(project_monthly_mb(&[], now), None);
+    }
+
+    #[test]
+    fn sums_cache_savings() {
+        let record = |cached_mb| UpgradeRecord {
+            timestamp: 0,
+            download_mb: 100.0,
+            installed_mb: 0.0,
+            download_secs: 0.0,
+            install_secs: 0.0,
+            cached_mb,
+        };
+        assert_eq!(
+            sum_cache_savings(&[record(12.5), record(0.0), record(7.5)]),
+            (20.0, 3)
+        );
+        assert_eq!(sum_cache_savings(&[]), (0.0, 0));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn releases_holds() {
+        let hold = Hold {
+            name: "mesa".to_string(),
+            until_version: Some("1:24.1.2-1".to_string()),
+            until: Some(2000),
+        };
+        assert!(!hold.is_released(Some("1:24.1.1-1"), 1000));
+        assert!(hold.is_released(Some("1:24.1.2-1"), 1000));
+        assert!(hold.is_released(Some("1:24.2.0-1"), 1000));
+        assert!(!hold.is_released(None, 1000));
+        assert!(hold.is_released(None, 2000));
+    }
+
+    #[test]
+    fn snapshots_only_collected_stats() {
+        let stats = ManagerStats {
+            total_upgradable: Some(4),
+            cache_size_mb: Some(120.0),
+            ..ManagerStats::default()
+        };
+        let snapshot = StatsSnapshot::capture(&stats, 0);
+        assert_eq!(snapshot.values.len(), 2);
+        assert_eq!(snapshot.delta(StatId::Installed, &stats), None);
+
+        let later = ManagerStats {
+            total_installed: Some(900),
+            total_upgradable: Some(1),
+            ..ManagerStats::default()
+        };
+        assert_eq!(snapshot.delta(StatId::Upgradable, &later), Some(-3.0));
+        assert_eq!(snapshot.delta(StatId::Installed, &later), None);
+        assert_eq!(snapshot.delta(StatId::CacheSize, &later), None);
+    }
+
+    #[test]
+    fn shows_the_cached_download_as_a_change() {
+        let stats = |cached| ManagerStats {
+            download_size_mb: Some(100.0),
+            download_cached_mb: Some(cached),
+            ..ManagerStats::default()
+        };
+        let snapshot = StatsSnapshot::capture(&stats(4.0), 0);
+        let format = StatFormat::default();
+        let shown = |cached, previous: Option<&StatsSnapshot>| {
+            StatId::DownloadSize.format_value_since(&stats(cached), &format, previous)
+        };
+        assert_eq!(
+            shown(6.5, Some(&snapshot)).as_deref(),
+            Some("100.00 MiB (+2.50 MiB cached)")
+        );
+        assert_eq!(
+            shown(4.0, Some(&snapshot)).as_deref(),
+            Some("100.00 MiB (4.00 MiB cached)")
+        );
+        assert_eq!(
+            shown(6.5, None).as_deref(),
+            Some("100.00 MiB (6.50 MiB cached)")
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use crate::util;
+use std::fs;
+use std::path::Path;
+
+/// System hooks first, so admin hooks with the same file name replace them.
+const HOOK_DIRS: [&str; 2] = ["/usr/share/libalpm/hooks", "/etc/pacman.d/hooks"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookWhen {
+    PreTransaction,
+    PostTransaction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TriggerType {
+    Package,
+    Path,
+}
+
+#[derive(Debug, Clone)]
+struct Trigger {
+    operations: Vec<String>,
+    kind: TriggerType,
+    targets: Vec<String>,
+}
+
+impl Trigger {
+    /// alpm matches targets in order, with `!` patterns excluding what earlier ones matched.
+    fn matches_target(&self, value: &str) -> bool {
+        let mut matched = false;
+        for target in &self.targets {
+            match target.strip_prefix('!') {
+                Some(pattern) if util::glob_match(pattern, value) => matched = false,
+                None if util::glob_match(target, value) => matched = true,
+                _ => {}
+            }
+        }
+        matched
+    }
+}
+
+/// A parsed alpm hook (see alpm-hooks(5)).
+#[derive(Debug, Clone)]
+pub struct Hook {
+    /// File name without the .hook extension.
+    pub name: String,
+    pub description: Option<String>,
+    pub when: HookWhen,
+    triggers: Vec<Trigger>,
+}
+
+impl Hook {
+    /// The description pacman prints while running the hook, falling back to its name.
+    pub fn label(&self) -> &str {
+        self.description.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Whether upgrading or installing a package with these files fires the hook.
+    pub fn triggered_by(&self, package: &str, files: &[String]) -> bool {
+        self.triggers.iter().any(|t| {
+            let operation = t
+                .operations
+                .iter()
+                .any(|op| op == "Upgrade" || op == "Install");
+            operation
+                && match t.kind {
+                    TriggerType::Package => t.matches_target(package),
+                    TriggerType::Path => files.iter().any(|f| t.matches_target(f)),
+                }
+        })
+    }
+}
+
+fn parse_hook(name: &str, contents: &str) -> Option<Hook> {
+    let mut triggers = Vec::new();
+    let mut description = None;
+    let mut when = None;
+    let mut section = "";
+
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = header;
+            if section == "Trigger" {
+                triggers.push(Trigger {
+                    operations: Vec::new(),
+                    kind: TriggerType::Package,
+                    targets: Vec::new(),
+                });
+            }
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim().to_string());
+        match (section, key) {
+            ("Trigger", "Operation") => triggers.last_mut()?.operations.push(value),
+            ("Trigger", "Target") => triggers.last_mut()?.targets.push(value),
+            ("Trigger", "Type") => {
+                triggers.last_mut()?.kind = match value.as_str() {
+                    "Path" | "File" => TriggerType::Path,
+                    _ => TriggerType::Package,
+                }
+            }
+            ("Action", "Description") => description = Some(value),
+            ("Action", "When") => {
+                when = match value.as_str() {
+                    "PreTransaction" => Some(HookWhen::PreTransaction),
+                    "PostTransaction" => Some(HookWhen::PostTransaction),
+                    _ => None,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Some(Hook {
+        name: name.to_string(),
+        description,
+        when: when?,
+        triggers,
+    })
+}
+
+/// All enabled hooks. An /etc hook that is empty or points at /dev/null disables the system one.
+pub fn load_hooks() -> Vec<Hook> {
+    let mut hooks: Vec<Hook> = Vec::new();
+
+    for dir in HOOK_DIRS {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.extension().is_none_or(|ext| ext != "hook") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+
+            hooks.retain(|h| h.name != name);
+            let disabled =
+                fs::read_link(&path).is_ok_and(|target| target == Path::new("/dev/null"));
+            if disabled {
+                continue;
+            }
+            if let Ok(contents) = fs::read_to_string(&path)
+                && let Some(hook) = parse_hook(name, &contents)
+            {
+                hooks.push(hook);
+            }
+        }
+    }
+
+    hooks.sort_by(|a, b| a.name.cmp(&b.name));
+    hooks
+}
@@ -0,0 +1,415 @@
+use rusqlite::{Connection, params, params_from_iter};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::pacman::{self, PackageListing, Provider};
+use crate::paths;
+
+const SYNC_DB_DIR: &str = "/var/lib/pacman/sync";
+/// Changes whenever a package is installed or removed.
+const LOCAL_DB_DIR: &str = "/var/lib/pacman/local";
+
+/// Returns the path to the package index (~/.cache/pacfetch/packages.db), the sudo user's
+/// under sudo.
+fn index_path() -> Option<PathBuf> {
+    paths::user_cache_file("packages.db")
+}
+
+fn open_index() -> Result<Connection, String> {
+    let path = index_path().ok_or("could not determine cache directory")?;
+    paths::ensure_parent(&path)?;
+
+    let db = Connection::open(&path).map_err(|e| format!("Failed to open package index: {}", e))?;
+    set_up(&db)?;
+    paths::return_to_sudo_user(&path)?;
+    Ok(db)
+}
+
+fn set_up(db: &Connection) -> Result<(), String> {
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS packages (
+             name TEXT NOT NULL,
+             version TEXT NOT NULL,
+             description TEXT,
+             repo TEXT NOT NULL,
+             installed INTEGER NOT NULL
+         );
+         CREATE INDEX IF NOT EXISTS packages_by_name ON packages (name);
+         CREATE TABLE IF NOT EXISTS commands (
+             command TEXT NOT NULL,
+             name TEXT NOT NULL,
+             version TEXT NOT NULL,
+             repo TEXT NOT NULL,
+             path TEXT NOT NULL,
+             installed INTEGER NOT NULL
+         );
+         CREATE INDEX IF NOT EXISTS commands_by_command ON commands (command);
+         CREATE TABLE IF NOT EXISTS meta (
+             key TEXT PRIMARY KEY,
+             value INTEGER NOT NULL
+         );",
+    )
+    .map_err(|e| format!("Failed to set up package index: {}", e))
+}
+
+/// When the sync databases with `extension`, e.g. "db" or "files", or the installed set
+/// last changed.
+fn sources_modified(extension: &str) -> i64 {
+    let sync_dbs = fs::read_dir(SYNC_DB_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == extension))
+        .map(|entry| entry.path());
+    sync_dbs
+        .chain([PathBuf::from(LOCAL_DB_DIR)])
+        .filter_map(|path| fs::metadata(path).ok()?.modified().ok())
+        .filter_map(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|age| age.as_secs() as i64)
+        .max()
+        .unwrap_or(0)
+}
+
+fn built_at(db: &Connection) -> Option<i64> {
+    meta(db, "built_at")
+}
+
+fn meta(db: &Connection, key: &str) -> Option<i64> {
+    db.query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+        row.get(0)
+    })
+    .ok()
+}
+
+/// Replace the index's contents with `listings`.
+fn store(db: &mut Connection, listings: &[PackageListing], now: i64) -> Result<(), String> {
+    let tx = db
+        .transaction()
+        .map_err(|e| format!("Failed to update package index: {}", e))?;
+    {
+        tx.execute("DELETE FROM packages", [])
+            .map_err(|e| format!("Failed to update package index: {}", e))?;
+        let mut insert = tx
+            .prepare(
+                "INSERT INTO packages (name, version, description, repo, installed)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .map_err(|e| format!("Failed to update package index: {}", e))?;
+        for pkg in listings {
+            insert
+                .execute(params![
+                    pkg.name,
+                    pkg.version,
+                    pkg.description,
+                    pkg.repo,
+                    pkg.installed
+                ])
+                .map_err(|e| format!("Failed to update package index: {}", e))?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('built_at', ?1)",
+            [now],
+        )
+        .map_err(|e| format!("Failed to update package index: {}", e))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Failed to update package index: {}", e))
+}
+
+/// Whether the index has been built, so a search won't have to build it first.
+pub fn is_built() -> bool {
+    index_path().is_some_and(|path| path.exists())
+}
+
+/// Rebuild the index from the alpm databases.
+pub fn rebuild() -> Result<usize, String> {
+    let listings = pacman::get_package_listings();
+    if listings.is_empty() {
+        return Err("no packages found in the pacman databases".to_string());
+    }
+    let mut db = open_index()?;
+    store(&mut db, &listings, chrono::Local::now().timestamp())?;
+    Ok(listings.len())
+}
+
+/// Rebuild the index after a sync. A failure only costs the next search a rebuild, so
+/// it's reported as a warning.
+pub fn refresh() {
+    if let Err(e) = rebuild() {
+        eprintln!("warning: {}", e);
+    }
+}
+
+fn listing_from_row(row: &rusqlite::Row) -> rusqlite::Result<PackageListing> {
+    Ok(PackageListing {
+        name: row.get(0)?,
+        version: row.get(1)?,
+        description: row.get(2)?,
+        repo: row.get(3)?,
+        installed: row.get(4)?,
+    })
+}
+
+/// `term` with LIKE's wildcards escaped.
+fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// A LIKE pattern matching `term` anywhere.
+fn like_pattern(term: &str) -> String {
+    format!("%{}%", escape_like(term))
+}
+
+/// Packages whose name or description contains every term, exact and prefix name matches
+/// first.
+fn query(db: &Connection, terms: &[String], limit: usize) -> Result<Vec<PackageListing>, String> {
+    let Some(first) = terms.first() else {
+        return Ok(Vec::new());
+    };
+    let conditions: Vec<String> = (0..terms.len())
+        .map(|i| {
+            format!(
+                "(name LIKE ?{n} ESCAPE '\\' OR description LIKE ?{n} ESCAPE '\\')",
+                n = i + 3
+            )
+        })
+        .collect();
+    let sql = format!(
+        "SELECT name, version, description, repo, installed FROM packages
+         WHERE {}
+         ORDER BY lower(name) = lower(?1) DESC, name LIKE ?2 ESCAPE '\\' DESC,
+                  name LIKE ?3 ESCAPE '\\' DESC, name
+         LIMIT {}",
+        conditions.join(" AND "),
+        limit
+    );
+
+    let prefix = format!("{}%", escape_like(first));
+    let values = [first.clone(), prefix]
+        .into_iter()
+        .chain(terms.iter().map(|term| like_pattern(term)));
+
+    let mut stmt = db
+        .prepare(&sql)
+        .map_err(|e| format!("Failed to search package index: {}", e))?;
+    let rows = stmt
+        .query_map(params_from_iter(values), listing_from_row)
+        .map_err(|e| format!("Failed to search package index: {}", e))?;
+    rows.collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to search package index: {}", e))
+}
+
+/// Open the index, rebuilding it first if the databases changed since it was built.
+fn open_current_index() -> Result<Connection, String> {
+    let mut db = open_index()?;
+    if built_at(&db).is_none_or(|built| built < sources_modified("db")) {
+        let listings = pacman::get_package_listings();
+        if !listings.is_empty() {
+            store(&mut db, &listings, chrono::Local::now().timestamp())?;
+        }
+    }
+    Ok(db)
+}
+
+pub fn search(terms: &[String], limit: usize) -> Result<Vec<PackageListing>, String> {
+    query(&open_current_index()?, terms, limit)
+}
+
+/// A search to run again as the terms change, e.g. on every keystroke of a picker, with
+/// the index opened and brought up to date once.
+pub fn searcher(limit: usize) -> Result<impl FnMut(&[String]) -> Vec<PackageListing>, String> {
+    let db = open_current_index()?;
+    Ok(move |terms: &[String]| query(&db, terms, limit).unwrap_or_default())
+}
+
+/// The packages with exactly these names; one name can be in several repos.
+pub fn lookup(names: &[String]) -> Result<Vec<PackageListing>, String> {
+    let db = open_current_index()?;
+    let mut stmt = db
+        .prepare(
+            "SELECT name, version, description, repo, installed FROM packages
+             WHERE name = ?1 ORDER BY repo = 'local'",
+        )
+        .map_err(|e| format!("Failed to search package index: {}", e))?;
+
+    let mut found = Vec::new();
+    for name in names {
+        let rows = stmt
+            .query_map([name], listing_from_row)
+            .map_err(|e| format!("Failed to search package index: {}", e))?;
+        for row in rows {
+            found.push(row.map_err(|e| format!("Failed to search package index: {}", e))?);
+        }
+    }
+    Ok(found)
+}
+
+/// Replace the command table with `providers`, as (command, package) pairs.
+fn store_commands(
+    db: &mut Connection,
+    providers: &[(String, Provider)],
+    now: i64,
+) -> Result<(), String> {
+    let tx = db
+        .transaction()
+        .map_err(|e| format!("Failed to update command index: {}", e))?;
+    {
+        tx.execute("DELETE FROM commands", [])
+            .map_err(|e| format!("Failed to update command index: {}", e))?;
+        let mut insert = tx
+            .prepare(
+                "INSERT INTO commands (command, name, version, repo, path, installed)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )
+            .map_err(|e| format!("Failed to update command index: {}", e))?;
+        for (command, pkg) in providers {
+            insert
+                .execute(params![
+                    command,
+                    pkg.name,
+                    pkg.version,
+                    pkg.repo,
+                    pkg.path,
+                    pkg.installed
+                ])
+                .map_err(|e| format!("Failed to update command index: {}", e))?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('commands_built_at', ?1)",
+            [now],
+        )
+        .map_err(|e| format!("Failed to update command index: {}", e))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Failed to update command index: {}", e))
+}
+
+fn query_commands(db: &Connection, command: &str) -> Result<Vec<Provider>, String> {
+    let mut stmt = db
+        .prepare(
+            "SELECT name, version, repo, path, installed FROM commands
+             WHERE command = ?1 ORDER BY repo, name",
+        )
+        .map_err(|e| format!("Failed to search command index: {}", e))?;
+    let rows = stmt
+        .query_map([command], |row| {
+            Ok(Provider {
+                name: row.get(0)?,
+                version: row.get(1)?,
+                repo: row.get(2)?,
+                path: row.get(3)?,
+                installed: row.get(4)?,
+            })
+        })
+        .map_err(|e| format!("Failed to search command index: {}", e))?;
+    rows.collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to search command index: {}", e))
+}
+
+/// The packages shipping `command`. The commands are read from the files databases the
+/// first time they're needed after a sync, rather than on every lookup.
+pub fn command_providers(command: &str) -> Result<Vec<Provider>, String> {
+    let mut db = open_index()?;
+    if meta(&db, "commands_built_at").is_none_or(|built| built < sources_modified("files")) {
+        let providers = pacman::get_command_providers();
+        store_commands(&mut db, &providers, chrono::Local::now().timestamp())?;
+    }
+    query_commands(&db, command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(name: &str, description: &str, repo: &str) -> PackageListing {
+        PackageListing {
+            name: name.to_string(),
+            version: "1.0-1".to_string(),
+            description: Some(description.to_string()),
+            repo: repo.to_string(),
+            installed: false,
+        }
+    }
+
+    fn terms(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn ranks_name_matches_first() {
+        let mut db = Connection::open_in_memory().unwrap();
+        set_up(&db).unwrap();
+        let listings = [
+            listing("python-pip", "The PyPA recommended tool", "extra"),
+            listing(
+                "bpython",
+                "Fancy interface to the Python interpreter",
+                "extra",
+            ),
+            listing("python", "The Python programming language", "core"),
+            listing("vim", "Vi with python support", "extra"),
+            listing("nano", "Pico editor clone", "core"),
+        ];
+        store(&mut db, &listings, 100).unwrap();
+        assert_eq!(built_at(&db), Some(100));
+
+        let names: Vec<String> = query(&db, &terms(&["python"]), 10)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, ["python", "python-pip", "bpython", "vim"]);
+
+        let both = query(&db, &terms(&["python", "vi"]), 10).unwrap();
+        assert_eq!(both, [listings[3].clone()]);
+    }
+
+    #[test]
+    fn escapes_like_wildcards() {
+        let mut db = Connection::open_in_memory().unwrap();
+        set_up(&db).unwrap();
+        store(
+            &mut db,
+            &[
+                listing("lib32_glibc", "", "multilib"),
+                listing("lib32-glibc", "", "multilib"),
+            ],
+            0,
+        )
+        .unwrap();
+        let found = query(&db, &terms(&["32_"]), 10).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name, "lib32_glibc");
+    }
+
+    #[test]
+    fn looks_up_command_providers() {
+        let mut db = Connection::open_in_memory().unwrap();
+        set_up(&db).unwrap();
+        let provider = |name: &str, repo: &str, path: &str| Provider {
+            name: name.to_string(),
+            version: "1.0-1".to_string(),
+            repo: repo.to_string(),
+            path: path.to_string(),
+            installed: false,
+        };
+        let providers = [
+            ("vim".to_string(), provider("vim", "extra", "/usr/bin/vim")),
+            ("vim".to_string(), provider("gvim", "extra", "/usr/bin/vim")),
+            ("iw".to_string(), provider("iw", "core", "/usr/sbin/iw")),
+        ];
+        store_commands(&mut db, &providers, 100).unwrap();
+        assert_eq!(meta(&db, "commands_built_at"), Some(100));
+
+        let names: Vec<String> = query_commands(&db, "vim")
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, ["gvim", "vim"]);
+        assert_eq!(query_commands(&db, "iw").unwrap()[0].path, "/usr/sbin/iw");
+        assert!(query_commands(&db, "vi").unwrap().is_empty());
+    }
+}
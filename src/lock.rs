@@ -0,0 +1,121 @@
+use crate::paths;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How many guards this process holds on the exclusive lock, so the functions of one
+/// upgrade can each take it without waiting on themselves.
+static EXCLUSIVE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// A held instance lock, released when dropped.
+pub struct InstanceLock {
+    /// None for a guard nested inside the process's own exclusive lock.
+    file: Option<File>,
+    exclusive: bool,
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        if self.exclusive {
+            EXCLUSIVE_DEPTH.fetch_sub(1, Ordering::SeqCst);
+        }
+        // Closing the file releases the flock
+        self.file.take();
+    }
+}
+
+/// A guard that holds no flock: nested inside this process's exclusive lock, or standing
+/// in when the lock file is unusable.
+fn unlocked(exclusive: bool) -> InstanceLock {
+    InstanceLock {
+        file: None,
+        exclusive,
+    }
+}
+
+fn open_lock_file() -> Result<File, String> {
+    let path = paths::lock_file().ok_or("could not determine the runtime directory")?;
+    paths::ensure_parent(&path)?;
+    // flock works on read-only files too, so a lock file another user created still locks
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .or_else(|_| File::open(&path))
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    // It locks either way, but the directory root created must stay writable for the user
+    let _ = paths::return_to_sudo_user(&path);
+    Ok(file)
+}
+
+/// Lock `file`, with flock on Unix and LockFileEx on Windows; returns false when
+/// `nonblocking` and another process holds it.
+fn flock(file: &File, exclusive: bool, nonblocking: bool) -> Result<bool, String> {
+    let result = match (exclusive, nonblocking) {
+        (true, true) => file.try_lock(),
+        (false, true) => file.try_lock_shared(),
+        (true, false) => file.lock().map_err(TryLockError::Error),
+        (false, false) => file.lock_shared().map_err(TryLockError::Error),
+    };
+    match result {
+        Ok(()) => Ok(true),
+        Err(TryLockError::WouldBlock) => Ok(false),
+        Err(TryLockError::Error(e)) => Err(format!("Failed to lock: {}", e)),
+    }
+}
+
+/// Take the lock for anything that changes the system or pacfetch's caches, waiting for
+/// other instances to finish first.
+pub fn exclusive() -> Result<InstanceLock, String> {
+    if EXCLUSIVE_DEPTH.load(Ordering::SeqCst) > 0 {
+        EXCLUSIVE_DEPTH.fetch_add(1, Ordering::SeqCst);
+        return Ok(unlocked(true));
+    }
+
+    let file = open_lock_file()?;
+    if !flock(&file, true, true)? {
+        eprintln!("Waiting for another pacfetch instance to finish...");
+        flock(&file, true, false)?;
+    }
+    EXCLUSIVE_DEPTH.fetch_add(1, Ordering::SeqCst);
+    Ok(InstanceLock {
+        file: Some(file),
+        exclusive: true,
+    })
+}
+
+/// Take the lock for reading stats alongside other readers. Returns None while another
+/// instance holds it exclusively, so the caller can fall back to cached stats.
+pub fn try_shared() -> Option<InstanceLock> {
+    if EXCLUSIVE_DEPTH.load(Ordering::SeqCst) > 0 {
+        return Some(unlocked(false));
+    }
+
+    let Ok(file) = open_lock_file() else {
+        return Some(unlocked(false));
+    };
+    match flock(&file, false, true) {
+        Ok(true) => Some(InstanceLock {
+            file: Some(file),
+            exclusive: false,
+        }),
+        Ok(false) => None,
+        // Without a usable lock, go ahead unlocked rather than not at all
+        Err(_) => Some(unlocked(false)),
+    }
+}
+
+/// Wait for the shared lock, for when there is nothing cached to fall back to.
+pub fn shared() -> InstanceLock {
+    if EXCLUSIVE_DEPTH.load(Ordering::SeqCst) > 0 {
+        return unlocked(false);
+    }
+    let file = open_lock_file()
+        .ok()
+        .filter(|file| flock(file, false, false).unwrap_or(false));
+    InstanceLock {
+        file,
+        exclusive: false,
+    }
+}
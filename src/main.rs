@@ -1,11 +1,47 @@
+// The pacman commands are only built for Linux, which leaves the helpers that only they
+// use unused elsewhere
+#![cfg_attr(not(target_os = "linux"), allow(dead_code))]
+
+#[cfg(target_os = "linux")]
+mod aur;
+#[cfg(target_os = "linux")]
+mod bootstrap;
+mod cache;
+#[cfg(target_os = "linux")]
+mod checks;
 mod config;
+mod crash;
+mod daemon;
+mod distro;
+mod history;
+#[cfg(target_os = "linux")]
+mod hooks;
+#[cfg(target_os = "linux")]
+mod index;
+mod lock;
+mod managers;
+#[cfg(target_os = "linux")]
 mod pacman;
+mod paths;
+mod progress;
+mod prompt;
+mod pty;
+mod push;
+mod report;
+#[cfg(target_os = "linux")]
+mod sandbox;
 mod stats;
+mod storage;
+mod terminal;
 mod ui;
 mod util;
 
-use clap::{CommandFactory, Parser};
-use config::Config;
+use clap::{CommandFactory, Parser, Subcommand};
+use config::{ColorMode, Config};
+use stats::StatId;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use std::time::Instant;
 
 /// Display information about your package manager
 #[derive(Parser)]
@@ -14,17 +50,68 @@ use config::Config;
 #[command(about = "A neofetch style wrapper for pacman's Syu/Sy/Su commands")]
 #[command(after_help = "\
 Commands:
-  -Sy           Sync package databases
-  -Su           Upgrade system 
-  -Syu          Sync databases and upgrade system
+  -Sy                    Sync package databases
+  -Su                    Upgrade system 
+  -Syu                   Sync databases and upgrade system
+  sync [--files]         Sync package databases, or the files databases pacman -F uses
+  stat <id>              Print a single stat, e.g. stat orphaned_packages
+  search <terms>         Search package names and descriptions, --pick to choose
+  foreign                List foreign packages with their AUR status
+  orphans [--pick]       List orphans and what orphaned them, or pick ones to remove
+  info <pkgs>            Version, repo and description of packages
+  provides <command>     Find the package a command comes from in the files databases
+                         --hook bash|zsh|fish prints a command-not-found handler
+  ignore add|remove|list Hold packages back from upgrades, e.g. ignore add linux
+                         --until-version v or --days n releases the hold by itself
+  downgrade [pkgs]       Reinstall older cached versions of packages
+  testing                Updates from testing repos and what they'd be without them
+  optdeps                Missing and no longer needed optional dependencies
+  dedup [--hardlink]     Find packages duplicated across pacman and AUR caches
+  facts [--ini]          Print all stats as Ansible local facts
+  metrics [--format f]   Numeric stats as influx line protocol or zabbix
+  daemon                 Refresh stats periodically and serve /metrics
+  push                   Send stats to the configured push.url
+  prompt [--format f]    Cached update counts for shell prompts and status bars
+  query <stat>           Recorded history, e.g. query cache_size --days 30
+  graph <stat>           Chart a stat's history, e.g. graph cache_size
+  backends               List the supported package managers and which are installed
+  state-dir, cache-dir   Print where pacfetch keeps its state or cache
+  purge-data             Delete pacfetch's history, snapshots and caches
+  about [--stats]        Version and paths, or how pacfetch's own runs have gone
+  setup                  Build the package index and stats snapshot ahead of time
+  bug-report [-o file]   Redacted diagnostics to attach to a GitHub issue
+  check                  Nagios plugin, e.g. check --warn-updates 10 --crit-security 1
+  replay <file>          Replay a session saved by --record through the filter
+  bench [--runs n]       Time each stat's collector on this system
 
 Options:
-  -d, --debug   Debug mode
-  -h, --help    Print help
-  -V, --version Print version")]
+  -t, --tui              Interactive dashboard
+  --stats <ids>          Only show these stats, comma separated
+  --exclude-stats <ids>  Hide these stats
+  --short                One-line summary for scripts and MOTD
+  --accessible           Plain linear output for screen readers
+  --plain                Plain output without colors or spinners
+  --force-tty            Keep terminal output when stdout is not a TTY
+  --no-filter            Show pacman's upgrade output unfiltered
+  --backend <name>       Read stats from this package manager instead of detecting one
+  --all-backends         Stats from every installed package manager, with totals
+  --critical-only        With -Su, only upgrade the upgrade.critical packages
+  --security-only        With -Su, only upgrade packages with an advisory
+  --simulate             With -Su, try the upgrade in a throwaway overlay of / and
+                         report file conflicts and failing hooks
+  --progress-json        Report upgrade progress as JSON lines on stderr
+  --record <file>        Save pacman's raw output to a file for replay
+  --from-file <file>     Package names for info, downgrade and ignore; - reads stdin
+  -d, --debug            Debug mode
+  -h, --help             Print help
+  -V, --version          Print version")]
 #[command(disable_help_flag = true)]
 #[command(disable_version_flag = true)]
+#[command(disable_help_subcommand = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(short = 'S', hide = true)]
     sync_op: bool,
 
@@ -37,6 +124,51 @@ struct Cli {
     #[arg(short, long, hide = true)]
     debug: bool,
 
+    #[arg(short, long, hide = true)]
+    tui: bool,
+
+    #[arg(long, hide = true, value_delimiter = ',')]
+    stats: Vec<String>,
+
+    #[arg(long, hide = true, value_delimiter = ',')]
+    exclude_stats: Vec<String>,
+
+    #[arg(long, hide = true)]
+    short: bool,
+
+    #[arg(long, hide = true)]
+    accessible: bool,
+
+    #[arg(long, hide = true, conflicts_with = "force_tty")]
+    plain: bool,
+
+    #[arg(long, hide = true)]
+    force_tty: bool,
+
+    #[arg(long, hide = true)]
+    progress_json: bool,
+
+    #[arg(long, hide = true)]
+    no_filter: bool,
+
+    #[arg(long, hide = true)]
+    backend: Option<String>,
+
+    #[arg(long, hide = true)]
+    all_backends: bool,
+
+    #[arg(long, hide = true, conflicts_with = "security_only")]
+    critical_only: bool,
+
+    #[arg(long, hide = true)]
+    security_only: bool,
+
+    #[arg(long, hide = true)]
+    simulate: bool,
+
+    #[arg(long, hide = true)]
+    record: Option<std::path::PathBuf>,
+
     #[arg(short = 'h', long = "help", hide = true)]
     help: bool,
 
@@ -44,14 +176,953 @@ struct Cli {
     version: bool,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Sync the package or files databases
+    #[command(hide = true)]
+    Sync {
+        #[arg(long)]
+        files: bool,
+    },
+    /// Print a single stat
+    #[command(hide = true)]
+    Stat { id: String },
+    /// Search the package index
+    #[command(hide = true)]
+    Search {
+        #[arg(required_unless_present = "pick")]
+        terms: Vec<String>,
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        /// Search as you type in a picker and print the chosen names
+        #[arg(long)]
+        pick: bool,
+    },
+    /// List foreign packages with their AUR status
+    #[command(hide = true)]
+    Foreign,
+    /// List orphans with when they appeared
+    #[command(hide = true)]
+    Orphans {
+        /// Pick orphans to remove
+        #[arg(long)]
+        pick: bool,
+    },
+    /// Show package details from the index
+    #[command(hide = true)]
+    Info(PackageArgs),
+    /// Reinstall older cached versions of packages
+    #[command(hide = true)]
+    Downgrade(PackageArgs),
+    /// Find which package ships a command
+    #[command(hide = true)]
+    Provides {
+        #[arg(required_unless_present = "hook")]
+        command: Option<String>,
+        /// Print a command-not-found handler for this shell
+        #[arg(long, value_enum, conflicts_with = "command")]
+        hook: Option<Shell>,
+    },
+    /// Manage the packages held back from upgrades
+    #[command(hide = true)]
+    Ignore {
+        #[command(subcommand)]
+        action: IgnoreAction,
+    },
+    /// Show which pending updates come from testing repos
+    #[command(hide = true)]
+    Testing,
+    /// Audit optional dependencies
+    #[command(hide = true)]
+    Optdeps,
+    /// Find package files duplicated across caches
+    #[command(hide = true)]
+    Dedup {
+        #[arg(long)]
+        hardlink: bool,
+    },
+    /// Print all stats as Ansible local facts
+    #[command(hide = true)]
+    Facts {
+        #[arg(long)]
+        ini: bool,
+    },
+    /// Print numeric stats for time-series databases
+    #[command(hide = true)]
+    Metrics {
+        #[arg(long, value_enum, default_value_t = MetricsFormat::Influx)]
+        format: MetricsFormat,
+    },
+    /// Refresh stats periodically and serve them over HTTP
+    #[command(hide = true)]
+    Daemon,
+    /// Send stats to the configured endpoint
+    #[command(hide = true)]
+    Push,
+    /// Print the cached counts for a shell prompt
+    #[command(hide = true)]
+    Prompt {
+        #[arg(long, default_value = "{updates}")]
+        format: String,
+        /// Print nothing when the cache is older than this many seconds
+        #[arg(long)]
+        max_age: Option<i64>,
+    },
+    /// Print a stat's recorded history
+    #[command(hide = true)]
+    Query(QueryArgs),
+    /// Chart a stat's recorded history
+    #[command(hide = true)]
+    Graph {
+        stat: String,
+        #[arg(long, default_value_t = 90)]
+        days: u32,
+    },
+    /// List the supported package managers
+    #[command(hide = true)]
+    Backends,
+    /// Print the state directory
+    #[command(hide = true)]
+    StateDir,
+    /// Print the cache directory
+    #[command(hide = true)]
+    CacheDir,
+    /// Delete pacfetch's own state, cache and data
+    #[command(hide = true)]
+    PurgeData {
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Build the state pacfetch would otherwise build on demand
+    #[command(hide = true)]
+    Setup,
+    /// Version and paths, or a summary of the local usage history
+    #[command(hide = true)]
+    About {
+        #[arg(long)]
+        stats: bool,
+    },
+    /// Redacted diagnostics for a bug report
+    #[command(hide = true)]
+    BugReport {
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Nagios/Icinga plugin reporting pending updates
+    #[command(hide = true)]
+    Check(CheckThresholds),
+    /// Replay a session saved by --record
+    #[command(hide = true)]
+    Replay { file: std::path::PathBuf },
+    /// Time each stat's collector
+    #[command(hide = true)]
+    Bench {
+        #[arg(long, default_value_t = 5)]
+        runs: u32,
+    },
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum MetricsFormat {
+    /// InfluxDB line protocol, for Telegraf
+    Influx,
+    /// zabbix_sender input
+    Zabbix,
+}
+
+#[derive(clap::Args)]
+struct QueryArgs {
+    /// Stat name, e.g. cache_size or last_update_secs
+    stat: String,
+    /// Only rows from the last N days
+    #[arg(long, default_value_t = 30)]
+    days: u32,
+    /// Collapse rows into one per day, week or month
+    #[arg(long, value_enum)]
+    per: Option<history::Bucket>,
+    /// How rows in one --per bucket are combined
+    #[arg(long, value_enum, default_value_t = history::Aggregate::Max)]
+    agg: history::Aggregate,
+    #[arg(long)]
+    csv: bool,
+}
+
+#[derive(Subcommand)]
+enum IgnoreAction {
+    Add {
+        #[command(flatten)]
+        packages: PackageArgs,
+        /// Hold until the upgrade reaches this version, e.g. a release with a fix
+        #[arg(long)]
+        until_version: Option<String>,
+        /// Hold for this many days
+        #[arg(long)]
+        days: Option<u32>,
+    },
+    Remove(PackageArgs),
+    List,
+}
+
+/// Packages named on the command line, "-" reading more from stdin, or from a file.
+#[derive(clap::Args)]
+struct PackageArgs {
+    packages: Vec<String>,
+    #[arg(long)]
+    from_file: Option<std::path::PathBuf>,
+}
+
+impl PackageArgs {
+    fn names(&self) -> Vec<String> {
+        util::read_package_list(&self.packages, self.from_file.as_deref())
+            .unwrap_or_else(|e| exit_with_error(e))
+    }
+}
+
+/// Pending update counts at which `check` turns WARNING or CRITICAL.
+#[derive(clap::Args)]
+struct CheckThresholds {
+    #[arg(long)]
+    warn_updates: Option<u32>,
+    #[arg(long)]
+    crit_updates: Option<u32>,
+    #[arg(long)]
+    warn_security: Option<u32>,
+    #[arg(long)]
+    crit_security: Option<u32>,
+}
+
 fn print_error_and_help(msg: &str) -> ! {
     eprintln!("error: {}\n", msg);
     let _ = Cli::command().print_help();
     eprintln!();
-    std::process::exit(1);
+    exit(1);
+}
+
+#[cfg(target_os = "linux")]
+fn run_dashboard(config: &Config) {
+    match ui::run_dashboard(config) {
+        Ok(ignored) if !ignored.is_empty() => {
+            println!("To upgrade without the marked packages, run:");
+            println!("  pacman -Syu --ignore {}", ignored.join(","));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn parse_stats(names: &[String]) -> Vec<StatId> {
+    names
+        .iter()
+        .map(|name| {
+            StatId::parse(name.trim())
+                .unwrap_or_else(|| print_error_and_help(&format!("unknown stat '{}'", name)))
+        })
+        .collect()
+}
+
+fn run_short(config: &mut Config) {
+    config.display.stats = vec![
+        StatId::Upgradable,
+        StatId::DownloadSize,
+        StatId::LastUpdate,
+        StatId::MirrorHealth,
+    ];
+
+    let stats = if util::output_mode() == util::OutputMode::Graphics {
+        let spinner = util::create_spinner("Gathering stats");
+        let stats = managers::get_stats(config, false, Some(&spinner));
+        spinner.finish_and_clear();
+        stats
+    } else {
+        managers::get_stats(config, false, None)
+    };
+
+    println!("{}", ui::format_short(&stats));
+}
+
+fn run_stat(config: &mut Config, name: &str) {
+    let Some(stat_id) = StatId::parse(name) else {
+        eprintln!("error: unknown stat '{}'", name);
+        exit(1);
+    };
+    config.display.stats = vec![stat_id];
+
+    let stats = managers::get_stats(config, false, None);
+    match stat_id.format_value(&stats, &config.display.format_of(stat_id)) {
+        Some(value) => println!("{}", value),
+        None => {
+            eprintln!("error: {} is not available", name);
+            exit(1);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn run_foreign() {
+    let packages = pacman::get_foreign_packages();
+    if packages.is_empty() {
+        println!("No foreign packages installed.");
+        return;
+    }
+
+    let names: Vec<String> = packages.iter().map(|p| p.name.clone()).collect();
+    let spinner = util::create_spinner("Querying the AUR");
+    let info = aur::info(&names);
+    spinner.set_message("Checking for needed rebuilds");
+    let rebuilds = pacman::get_rebuild_candidates(&packages, None);
+    spinner.finish_and_clear();
+
+    match info {
+        Ok(info) => ui::display_foreign(&packages, &info, &rebuilds),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn run_orphans() {
+    let now = chrono::Local::now().timestamp();
+    match history::track_orphans(&pacman::get_orphan_names(), now) {
+        Ok(records) => ui::display_orphans(&records, now),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn exit_with_error(e: String) -> ! {
+    eprintln!("error: {}", e);
+    exit(1);
+}
+
+/// When this run started and the command it counts under, for the usage history.
+static RUN: OnceLock<(Instant, String)> = OnceLock::new();
+
+/// Exit, noting the run in the usage history first.
+fn exit(code: i32) -> ! {
+    if let Some((start, command)) = RUN.get() {
+        let now = chrono::Local::now().timestamp();
+        // Only a nicety, so a run never fails over it
+        let _ = history::record_usage(command, start.elapsed().as_secs_f64(), code, now);
+    }
+    std::process::exit(code);
+}
+
+/// The command a run is counted under, e.g. "orphans", "-Syu" or "stats" for a plain run.
+fn usage_label(cli: &Cli) -> String {
+    if let Some(name) = Cli::command()
+        .try_get_matches()
+        .ok()
+        .and_then(|m| m.subcommand_name().map(str::to_string))
+    {
+        return name;
+    }
+    if cli.sync_op {
+        let mut op = String::from("-S");
+        if cli.sync_db {
+            op.push('y');
+        }
+        if cli.upgrade {
+            op.push('u');
+        }
+        op
+    } else if cli.tui {
+        "tui".to_string()
+    } else if cli.short {
+        "short".to_string()
+    } else {
+        "stats".to_string()
+    }
+}
+
+fn run_about(show_stats: bool) {
+    if show_stats {
+        match history::usage_summary() {
+            Ok(usage) => ui::display_usage(&usage, chrono::Local::now().timestamp()),
+            Err(e) => exit_with_error(e),
+        }
+        return;
+    }
+    let path = |p: Option<std::path::PathBuf>| {
+        p.map(|p| p.display().to_string())
+            .unwrap_or_else(|| "unknown".to_string())
+    };
+    println!("pacfetch {}", env!("CARGO_PKG_VERSION"));
+    println!("Config: {}", path(paths::config_file()));
+    println!("State:  {}", path(paths::state_dir()));
+    println!("Cache:  {}", path(paths::cache_dir()));
+}
+
+fn run_bug_report(config: &Config, output: Option<&std::path::Path>) {
+    let report = report::build(config);
+    let Some(path) = output else {
+        print!("{}", report);
+        return;
+    };
+    storage::write_atomic(path, &report)
+        .unwrap_or_else(|e| exit_with_error(format!("Failed to write {}: {}", path.display(), e)));
+    println!(
+        "Wrote {}; review it before attaching it to an issue",
+        path.display()
+    );
+}
+
+/// Simulate the sysupgrade in a sandbox, syncing the real databases first for -Syu. Exits
+/// non-zero when the simulation predicts problems.
+#[cfg(target_os = "linux")]
+fn run_simulate(sync_first: bool, debug: bool) -> ! {
+    if sync_first {
+        pacman::sync_databases().unwrap_or_else(|e| exit_with_error(e));
+    }
+    let problems = sandbox::simulate_upgrade(debug).unwrap_or_else(|e| exit_with_error(e));
+    ui::display_simulation(&problems);
+    exit(if problems.is_empty() { 0 } else { 1 });
+}
+
+#[cfg(target_os = "linux")]
+fn run_search(terms: &[String], limit: usize, pick: bool) {
+    if !pick {
+        let results = index::search(terms, limit).unwrap_or_else(|e| exit_with_error(e));
+        ui::display_search(&results);
+        return;
+    }
+
+    // The index answers each keystroke, so the results follow the query as it's typed
+    let mut search = index::searcher(limit).unwrap_or_else(|e| exit_with_error(e));
+    let chosen = ui::pick_live("Packages>", &terms.join(" "), true, |query| {
+        let terms: Vec<String> = query.split_whitespace().map(str::to_string).collect();
+        search(&terms)
+            .iter()
+            .map(|pkg| {
+                let label = format!(
+                    "{}/{} {}  {}",
+                    pkg.repo,
+                    pkg.name,
+                    pkg.version,
+                    pkg.description.as_deref().unwrap_or("")
+                );
+                ui::PickItem::new(&pkg.name, label)
+            })
+            .collect()
+    })
+    .unwrap_or_else(|e| exit_with_error(e));
+    for name in chosen {
+        println!("{}", name);
+    }
+}
+
+/// Pick orphans to remove, including the possibly wanted ones `-Rns` alone would keep.
+#[cfg(target_os = "linux")]
+fn run_pick_orphans() {
+    let (safe, wanted) = pacman::classify_orphans();
+    let mut items: Vec<ui::PickItem> = safe
+        .iter()
+        .map(|name| ui::PickItem::new(name, name))
+        .collect();
+    items.extend(
+        wanted
+            .iter()
+            .map(|(name, reason)| ui::PickItem::new(name, format!("{} ({})", name, reason))),
+    );
+    if items.is_empty() {
+        println!("No orphaned packages.");
+        return;
+    }
+
+    let chosen = ui::pick("Remove>", &items, true).unwrap_or_else(|e| exit_with_error(e));
+    let names: Vec<String> = chosen.iter().map(|&i| items[i].key.clone()).collect();
+    if names.is_empty() {
+        return;
+    }
+    if let Err(e) = pacman::remove_packages(&names) {
+        exit_with_error(e);
+    }
+}
+
+/// Print what the index knows about `names`, failing if any is unknown.
+#[cfg(target_os = "linux")]
+fn run_info(names: &[String]) -> ! {
+    if names.is_empty() {
+        print_error_and_help("no packages given");
+    }
+    let found = index::lookup(names).unwrap_or_else(|e| exit_with_error(e));
+    ui::display_info(&found);
+
+    let missing: Vec<&String> = names
+        .iter()
+        .filter(|name| !found.iter().any(|pkg| &pkg.name == *name))
+        .collect();
+    for name in &missing {
+        eprintln!("error: package '{}' was not found", name);
+    }
+    exit(if missing.is_empty() { 0 } else { 1 });
+}
+
+#[cfg(target_os = "linux")]
+fn run_provides(command: &str) {
+    if command.contains('/') {
+        print_error_and_help("give a command name, not a path");
+    }
+    let providers = pacman::find_command_providers(command);
+    if providers.is_empty() {
+        eprintln!("No package provides '{}'", command);
+        if let Some(warning) = pacman::files_db_warning() {
+            eprintln!("warning: {}", warning);
+        }
+        exit(1);
+    }
+    ui::display_providers(command, &providers);
+}
+
+/// A shell function that suggests packages for unknown commands, to eval from the shell's
+/// rc file, e.g. `eval "$(pacfetch provides --hook bash)"`.
+fn command_not_found_hook(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => {
+            "command_not_found_handle() {
+    pacfetch provides \"$1\" >&2 2>/dev/null || printf 'bash: %s: command not found\\n' \"$1\" >&2
+    return 127
+}
+"
+        }
+        Shell::Zsh => {
+            "command_not_found_handler() {
+    pacfetch provides \"$1\" >&2 2>/dev/null || printf 'zsh: command not found: %s\\n' \"$1\" >&2
+    return 127
+}
+"
+        }
+        Shell::Fish => {
+            "function fish_command_not_found
+    pacfetch provides $argv[1] >&2 2>/dev/null; or __fish_default_command_not_found_handler $argv
+end
+"
+        }
+    }
+}
+
+/// Edit or print the ignore list that upgrades pass to pacman with `--ignore`.
+#[cfg(target_os = "linux")]
+fn run_ignore(action: &IgnoreAction) {
+    let mut list = history::load_ignore_list();
+    match action {
+        IgnoreAction::List => {
+            if list.packages.is_empty() && list.hold.is_empty() {
+                println!("No packages are ignored.");
+            }
+            for name in &list.packages {
+                println!("{}", name);
+            }
+            for hold in &list.hold {
+                println!("{}  {}", hold.name, ui::describe_hold(hold));
+            }
+            return;
+        }
+        IgnoreAction::Add {
+            packages,
+            until_version,
+            days,
+        } => {
+            let names = packages.names();
+            if names.is_empty() {
+                print_error_and_help("no packages given");
+            }
+            // Patterns are pacman's business; plain names have to exist
+            let plain: Vec<String> = names
+                .iter()
+                .filter(|name| !name.contains(['*', '?']))
+                .cloned()
+                .collect();
+            let found = index::lookup(&plain).unwrap_or_else(|e| exit_with_error(e));
+            if let Some(missing) = plain.iter().find(|n| !found.iter().any(|p| &&p.name == n)) {
+                exit_with_error(format!("target not found: {}", missing));
+            }
+
+            let until = days.map(|d| chrono::Local::now().timestamp() + i64::from(d) * 86400);
+            for name in names {
+                if list.contains(&name) {
+                    continue;
+                }
+                if until_version.is_some() || until.is_some() {
+                    let hold = history::Hold {
+                        name,
+                        until_version: until_version.clone(),
+                        until,
+                    };
+                    println!("Holding {} {}", hold.name, ui::describe_hold(&hold));
+                    list.hold.push(hold);
+                } else {
+                    println!("Ignoring {}", name);
+                    list.packages.push(name);
+                }
+            }
+        }
+        IgnoreAction::Remove(args) => {
+            let names = args.names();
+            if let Some(missing) = names.iter().find(|n| !list.contains(n)) {
+                exit_with_error(format!("{} is not ignored", missing));
+            }
+            list.packages.retain(|name| !names.contains(name));
+            list.hold.retain(|hold| !names.contains(&hold.name));
+            for name in &names {
+                println!("No longer ignoring {}", name);
+            }
+        }
+    }
+    list.packages.sort();
+    if let Err(e) = history::save_ignore_list(&list) {
+        exit_with_error(e);
+    }
+}
+
+/// Let the user pick which cached build of `downgrade` to go back to.
+#[cfg(target_os = "linux")]
+fn pick_version(downgrade: &pacman::Downgrade) -> Option<&cache::CachedPackage> {
+    if let [only] = downgrade.cached.as_slice() {
+        return Some(only);
+    }
+    let items: Vec<ui::PickItem> = downgrade
+        .cached
+        .iter()
+        .map(|c| {
+            let label = format!("{}  ({})", c.version, c.path.display());
+            ui::PickItem::new(&c.version, label)
+        })
+        .collect();
+    let prompt = format!("{} {} ->", downgrade.name, downgrade.installed);
+    let chosen = ui::pick(&prompt, &items, false).unwrap_or_else(|e| exit_with_error(e));
+    chosen.first().map(|&index| &downgrade.cached[index])
+}
+
+/// Go back to cached builds of `packages`: picked for one package (or none, picking the
+/// package too), the previous version of each for a list.
+#[cfg(target_os = "linux")]
+fn run_downgrade(packages: &[String]) {
+    let downgrades = pacman::get_downgrades();
+    let find = |name: &String| {
+        downgrades
+            .iter()
+            .find(|d| &d.name == name)
+            .unwrap_or_else(|| {
+                exit_with_error(format!("no older version of {} in the package cache", name))
+            })
+    };
+
+    let targets: Vec<&cache::CachedPackage> = match packages {
+        [] => {
+            if downgrades.is_empty() {
+                println!("No installed package has an older version in the cache.");
+                return;
+            }
+            let items: Vec<ui::PickItem> = downgrades
+                .iter()
+                .map(|d| ui::PickItem::new(&d.name, format!("{} {}", d.name, d.installed)))
+                .collect();
+            let chosen = ui::pick("Package>", &items, false).unwrap_or_else(|e| exit_with_error(e));
+            chosen
+                .first()
+                .and_then(|&index| pick_version(&downgrades[index]))
+                .into_iter()
+                .collect()
+        }
+        [name] => pick_version(find(name)).into_iter().collect(),
+        names => names.iter().map(|name| &find(name).cached[0]).collect(),
+    };
+    if targets.is_empty() {
+        return;
+    }
+    if let Err(e) = pacman::downgrade(&targets) {
+        exit_with_error(e);
+    }
+}
+
+fn run_dedup(hardlink: bool) {
+    let spinner = util::create_spinner("Comparing cached packages");
+    let duplicates = cache::find_duplicates();
+    spinner.finish_and_clear();
+
+    let reclaimable: u64 = duplicates.iter().map(|d| d.reclaimable()).sum();
+    if reclaimable == 0 {
+        println!("No duplicate packages across caches.");
+        return;
+    }
+
+    println!("Duplicate packages ({})", duplicates.len());
+    for duplicate in duplicates.iter().filter(|d| d.reclaimable() > 0) {
+        println!("  {}", duplicate.file_name);
+        for path in &duplicate.paths {
+            println!("    {}", path.display());
+        }
+    }
+    let mib = |bytes: u64| bytes as f64 / 1048576.0;
+    if !hardlink {
+        println!(
+            "Hardlinking them would free {:.2} MiB; run with --hardlink to do it.",
+            mib(reclaimable)
+        );
+        return;
+    }
+
+    let mut freed = 0;
+    for duplicate in &duplicates {
+        match cache::hardlink(duplicate) {
+            Ok(bytes) => freed += bytes,
+            Err(e) => eprintln!("error: {}", e),
+        }
+    }
+    println!("Freed {:.2} MiB.", mib(freed));
+}
+
+fn run_facts(config: &mut Config, ini: bool) {
+    config.display.stats = StatId::ALL.to_vec();
+    let stats = managers::get_stats(config, false, None);
+    println!("{}", ui::format_facts(&stats, config, ini));
+}
+
+fn run_metrics(config: &mut Config, format: MetricsFormat) {
+    config.display.stats = stats::metric_stats();
+    let stats = managers::get_stats(config, false, None);
+    match format {
+        MetricsFormat::Influx => {
+            let now = chrono::Local::now().timestamp();
+            println!(
+                "{}",
+                ui::format_line_protocol(&stats, &util::hostname(), now)
+            );
+        }
+        MetricsFormat::Zabbix => println!("{}", ui::format_zabbix(&stats)),
+    }
+}
+
+fn run_query(args: &QueryArgs) {
+    let since = chrono::Local::now().timestamp() - i64::from(args.days) * 86400;
+    let rows = match history::query_stats(&args.stat, since, args.per, args.agg) {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    };
+    if rows.is_empty() {
+        eprintln!(
+            "error: no history for '{}' in the last {} days",
+            args.stat, args.days
+        );
+        exit(1);
+    }
+
+    if args.csv {
+        println!("time,{}", args.stat);
+        for (time, value) in &rows {
+            println!("{},{}", time, value);
+        }
+        return;
+    }
+
+    let width = rows.iter().map(|(time, _)| time.len()).max().unwrap_or(0);
+    println!("{:width$}  {}", "time", args.stat);
+    for (time, value) in &rows {
+        println!("{:width$}  {:.2}", time, value);
+    }
+}
+
+fn run_graph(stat: &str, days: u32) {
+    let since = chrono::Local::now().timestamp() - i64::from(days) * 86400;
+    match history::stat_points(stat, since) {
+        Ok(points) if points.len() >= 2 => ui::display_graph(stat, &points),
+        Ok(_) => {
+            eprintln!(
+                "error: not enough history for '{}' in the last {} days",
+                stat, days
+            );
+            exit(1);
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    }
+}
+
+/// Collect each stat on its own `runs` times. The baseline is a run with no stats,
+/// which every other timing includes.
+fn run_bench(config: &mut Config, runs: u32) {
+    let runs = runs.max(1);
+    let time = |config: &mut Config, stats: Vec<StatId>| {
+        config.display.stats = stats;
+        let mut timings: Vec<std::time::Duration> = (0..runs)
+            .map(|_| {
+                let start = std::time::Instant::now();
+                managers::get_stats(config, false, None);
+                start.elapsed()
+            })
+            .collect();
+        timings.sort();
+        timings
+    };
+
+    let spinner = util::create_spinner("Timing the baseline");
+    let baseline = time(config, Vec::new());
+    let mut results = Vec::new();
+    for id in StatId::ALL {
+        spinner.set_message(format!("Timing {}", id.name()));
+        results.push((id, time(config, vec![id])));
+    }
+    spinner.finish_and_clear();
+
+    ui::display_bench(&baseline, &results);
+}
+
+fn print_dir(dir: Option<std::path::PathBuf>) {
+    match dir {
+        Some(dir) => println!("{}", dir.display()),
+        None => {
+            eprintln!("error: could not determine the directory");
+            exit(1);
+        }
+    }
+}
+
+fn run_purge_data(yes: bool) {
+    let dirs = paths::data_dirs();
+    if dirs.is_empty() {
+        println!("Nothing to purge.");
+        return;
+    }
+
+    println!("This deletes:");
+    for dir in &dirs {
+        println!("  {}", dir.display());
+    }
+    if !yes {
+        print!("Proceed? [y/N] ");
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer).is_err()
+            || !answer.trim().eq_ignore_ascii_case("y")
+        {
+            return;
+        }
+    }
+
+    for dir in &dirs {
+        if let Err(e) = std::fs::remove_dir_all(dir) {
+            eprintln!("error: Failed to remove {}: {}", dir.display(), e);
+            exit(1);
+        }
+    }
+}
+
+/// Print the prompt cache through `format`; nothing when it's missing or older than
+/// `max_age` seconds, so a prompt never shows stale counts.
+fn run_prompt(format: &str, max_age: Option<i64>) -> ! {
+    let now = chrono::Local::now().timestamp();
+    let cache = prompt::read().filter(|c| c.is_fresh(max_age, now));
+    match cache {
+        Some(cache) => {
+            println!("{}", cache.render(format, now));
+            exit(0);
+        }
+        None => exit(1),
+    }
+}
+
+/// Nagios plugin output: one status line with perfdata, and the matching exit code.
+fn run_check(config: &mut Config, thresholds: &CheckThresholds) -> ! {
+    const STATES: [&str; 4] = ["OK", "WARNING", "CRITICAL", "UNKNOWN"];
+
+    config.display.stats = vec![StatId::Upgradable, StatId::DownloadSize];
+    let stats = managers::get_stats(config, false, None);
+    let Some(updates) = stats.total_upgradable else {
+        println!("PACFETCH UNKNOWN - pending updates couldn't be counted");
+        exit(3);
+    };
+
+    let level = |value: u32, warn: Option<u32>, crit: Option<u32>| {
+        if crit.is_some_and(|c| value >= c) {
+            2
+        } else if warn.is_some_and(|w| value >= w) {
+            1
+        } else {
+            0
+        }
+    };
+    let threshold = |t: Option<u32>| t.map(|v| v.to_string()).unwrap_or_default();
+
+    let mut state = level(updates, thresholds.warn_updates, thresholds.crit_updates);
+    let mut summary = format!(
+        "{} pending update{}",
+        updates,
+        if updates != 1 { "s" } else { "" }
+    );
+    let mut perfdata = vec![format!(
+        "updates={};{};{};0",
+        updates,
+        threshold(thresholds.warn_updates),
+        threshold(thresholds.crit_updates)
+    )];
+
+    if thresholds.warn_security.is_some() || thresholds.crit_security.is_some() {
+        #[cfg(target_os = "linux")]
+        let vulnerable = pacman::get_vulnerable_upgrades();
+        #[cfg(not(target_os = "linux"))]
+        let vulnerable: Result<Vec<String>, String> = Err("pacman is needed".to_string());
+        match vulnerable {
+            Ok(vulnerable) => {
+                let count = vulnerable.len() as u32;
+                state = state.max(level(
+                    count,
+                    thresholds.warn_security,
+                    thresholds.crit_security,
+                ));
+                summary.push_str(&format!(", {} security", count));
+                if !vulnerable.is_empty() {
+                    summary.push_str(&format!(" ({})", vulnerable.join(", ")));
+                }
+                perfdata.push(format!(
+                    "security={};{};{};0",
+                    count,
+                    threshold(thresholds.warn_security),
+                    threshold(thresholds.crit_security)
+                ));
+            }
+            Err(e) => {
+                state = 3;
+                summary.push_str(&format!(", security unknown: {}", e));
+            }
+        }
+    }
+
+    if let Some(mb) = stats.download_size_mb {
+        perfdata.push(format!("download={:.1}MB;;;0", mb));
+    }
+
+    println!(
+        "PACFETCH {} - {} | {}",
+        STATES[state],
+        summary,
+        perfdata.join(" ")
+    );
+    exit(state as i32);
 }
 
 fn main() {
+    let start = Instant::now();
+    crash::install_hook();
+    terminal::restore_on_signal();
     let cli = match Cli::try_parse() {
         Ok(cli) => cli,
         Err(_) => print_error_and_help("unrecognized flag"),
@@ -60,16 +1131,97 @@ fn main() {
     if cli.help {
         let _ = Cli::command().print_help();
         println!();
-        std::process::exit(0);
+        exit(0);
     }
 
     if cli.version {
         println!("pacfetch {}", env!("CARGO_PKG_VERSION"));
-        std::process::exit(0);
+        exit(0);
+    }
+
+    // Prompts run on every keystroke, so skip the config and pacman.conf entirely
+    if let Some(Command::Prompt { format, max_age }) = &cli.command {
+        run_prompt(format, *max_age);
+    }
+    let _ = RUN.set((start, usage_label(&cli)));
+
+    // Piped or cron output gets plain text unless asked otherwise
+    let output_mode = if cli.plain {
+        util::OutputMode::Plain
+    } else if cli.accessible {
+        util::OutputMode::Accessible
+    } else if !cli.force_tty && !std::io::stdout().is_terminal() {
+        util::OutputMode::Plain
+    } else {
+        util::OutputMode::Graphics
+    };
+    util::set_output_mode(output_mode);
+    util::set_progress_json(cli.progress_json);
+    if let Some(path) = &cli.record {
+        pty::set_record_path(path.clone());
     }
 
     // Load config
-    let config = Config::load();
+    let mut config = Config::load();
+    util::set_symbol_set(config.display.symbols);
+    // crossterm already leaves colour out when NO_COLOR is set
+    match config.display.color {
+        ColorMode::Auto => {}
+        ColorMode::Always => termimad::crossterm::style::force_color_output(true),
+        ColorMode::Never => termimad::crossterm::style::force_color_output(false),
+        #[cfg(target_os = "linux")]
+        ColorMode::Pacman if !pacman::pacman_display().color => {
+            termimad::crossterm::style::force_color_output(false)
+        }
+        ColorMode::Pacman => {}
+    }
+
+    // --stats and --exclude-stats override the configured list for this run
+    if !cli.stats.is_empty() {
+        config.display.stats = parse_stats(&cli.stats);
+    }
+    let excluded = parse_stats(&cli.exclude_stats);
+    config.display.stats.retain(|id| !excluded.contains(id));
+
+    if cli.no_filter {
+        config.filter.enabled = false;
+    }
+    if cli.backend.is_some() {
+        config.backend = cli.backend.clone();
+    }
+    if config.backend.is_some()
+        && let Err(e) = managers::select(config.backend.as_deref())
+    {
+        print_error_and_help(&e);
+    }
+    if let Some(e) = config
+        .sources
+        .iter()
+        .find_map(|name| managers::check_name(name).err())
+    {
+        print_error_and_help(&e);
+    }
+    if (cli.critical_only || cli.security_only) && !(cli.sync_op && cli.upgrade) {
+        print_error_and_help("--critical-only and --security-only need -Su or -Syu");
+    }
+    if cli.simulate && !(cli.sync_op && cli.upgrade) {
+        print_error_and_help("--simulate needs -Su or -Syu");
+    }
+    if cli.critical_only {
+        config.upgrade.scope = config::UpgradeScope::Critical;
+    } else if cli.security_only {
+        config.upgrade.scope = config::UpgradeScope::Security;
+    }
+    let prompts: Result<Vec<regex::Regex>, _> = config
+        .filter
+        .prompts
+        .iter()
+        .map(|p| regex::Regex::new(p))
+        .collect();
+    match prompts {
+        Ok(prompts) => pty::set_prompt_patterns(prompts),
+        Err(e) => print_error_and_help(&format!("invalid prompt pattern: {}", e)),
+    }
 
     let invalid_flag = (cli.sync_op && !cli.sync_db && !cli.upgrade)
         || ((cli.sync_db || cli.upgrade) && !cli.sync_op);
@@ -77,42 +1229,283 @@ fn main() {
         print_error_and_help("unrecognized flag combination");
     }
 
+    if cli.tui {
+        if util::is_accessible() {
+            print_error_and_help("the dashboard is not available in accessible mode");
+        }
+        if util::is_plain() {
+            print_error_and_help("the dashboard needs a terminal; use --force-tty to override");
+        }
+        #[cfg(target_os = "linux")]
+        {
+            run_dashboard(&config);
+            exit(0);
+        }
+        #[cfg(not(target_os = "linux"))]
+        print_error_and_help("the dashboard needs pacman");
+    }
+
+    match &cli.command {
+        Some(Command::Stat { id }) => {
+            run_stat(&mut config, id);
+            exit(0);
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::Search { terms, limit, pick }) => {
+            run_search(terms, *limit, *pick);
+            exit(0);
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::Foreign) => {
+            run_foreign();
+            exit(0);
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::Orphans { pick: false }) => {
+            run_orphans();
+            exit(0);
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::Orphans { pick: true }) => {
+            run_pick_orphans();
+            exit(0);
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::Info(args)) => run_info(&args.names()),
+        #[cfg(target_os = "linux")]
+        Some(Command::Ignore { action }) => {
+            run_ignore(action);
+            exit(0);
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::Downgrade(args)) => {
+            run_downgrade(&args.names());
+            exit(0);
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::Provides { command, hook }) => {
+            match (command, hook) {
+                (_, Some(shell)) => print!("{}", command_not_found_hook(*shell)),
+                (Some(command), None) => run_provides(command),
+                (None, None) => print_error_and_help("no command given"),
+            }
+            exit(0);
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::Sync { files }) => {
+            let result = if *files {
+                pacman::sync_files_databases()
+            } else {
+                pacman::sync_databases()
+            };
+            result.unwrap_or_else(|e| exit_with_error(e));
+            exit(0);
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::Testing) => {
+            ui::display_testing(pacman::get_testing_changes().as_deref());
+            exit(0);
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::Optdeps) => {
+            let (missing, optional_only) = pacman::get_optdep_audit();
+            ui::display_optdeps(&missing, &optional_only);
+            exit(0);
+        }
+        Some(Command::Dedup { hardlink }) => {
+            run_dedup(*hardlink);
+            exit(0);
+        }
+        Some(Command::Facts { ini }) => {
+            run_facts(&mut config, *ini);
+            exit(0);
+        }
+        Some(Command::Metrics { format }) => {
+            run_metrics(&mut config, *format);
+            exit(0);
+        }
+        Some(Command::Daemon) => {
+            if let Err(e) = daemon::run(&config) {
+                eprintln!("error: {}", e);
+                exit(1);
+            }
+            exit(0);
+        }
+        Some(Command::Push) => {
+            let stats = push::gather(&config);
+            if let Err(e) = push::push(&stats, &config) {
+                eprintln!("error: {}", e);
+                exit(1);
+            }
+            exit(0);
+        }
+        Some(Command::Prompt { .. }) => unreachable!("handled before the config loads"),
+        Some(Command::Query(args)) => {
+            run_query(args);
+            exit(0);
+        }
+        Some(Command::Graph { stat, days }) => {
+            run_graph(stat, *days);
+            exit(0);
+        }
+        Some(Command::Backends) => {
+            for (name, installed) in managers::list() {
+                let status = if installed { "installed" } else { "not found" };
+                println!("{:8}  {}", name, status);
+            }
+            exit(0);
+        }
+        Some(Command::StateDir) => {
+            print_dir(paths::state_dir());
+            exit(0);
+        }
+        Some(Command::CacheDir) => {
+            print_dir(paths::cache_dir());
+            exit(0);
+        }
+        Some(Command::PurgeData { yes }) => {
+            run_purge_data(*yes);
+            exit(0);
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::Setup) => {
+            bootstrap::setup(&config);
+            exit(0);
+        }
+        Some(Command::About { stats }) => {
+            run_about(*stats);
+            exit(0);
+        }
+        Some(Command::BugReport { output }) => {
+            run_bug_report(&config, output.as_deref());
+            exit(0);
+        }
+        Some(Command::Check(thresholds)) => run_check(&mut config, thresholds),
+        Some(Command::Bench { runs }) => {
+            run_bench(&mut config, *runs);
+            exit(0);
+        }
+        #[cfg(target_os = "linux")]
+        Some(Command::Replay { file }) => {
+            if let Err(e) = pacman::replay_sessions(&config, file) {
+                eprintln!("error: {}", e);
+                exit(1);
+            }
+            exit(0);
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => print_error_and_help("this command needs pacman"),
+        None => {}
+    }
+
+    if cli.short {
+        run_short(&mut config);
+        exit(0);
+    }
+    if cli.all_backends {
+        let spinner = util::create_spinner("Gathering stats from every backend");
+        let sections = managers::get_all_stats(&config);
+        spinner.finish_and_clear();
+        ui::display_aggregate(&sections, &config);
+        exit(0);
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    if cli.sync_op {
+        print_error_and_help("-S needs pacman");
+    }
     // Handle system upgrade (-Su or -Syu)
+    #[cfg(target_os = "linux")]
+    if cli.sync_op && cli.upgrade && cli.simulate {
+        run_simulate(cli.sync_db, cli.debug);
+    }
+    #[cfg(target_os = "linux")]
     if cli.sync_op && cli.upgrade {
         let sync_first = cli.sync_db;
-        if let Err(e) = pacman::upgrade_system(cli.debug, sync_first) {
+        if let Err(e) = pacman::upgrade_system(&config, cli.debug, sync_first, &[]) {
             eprintln!("error: {}", e);
-            std::process::exit(1);
+            exit(1);
         }
-        std::process::exit(0);
+        exit(0);
     }
 
-    // Get stats
-    let stats = if cli.sync_op && cli.sync_db {
-        if let Err(e) = pacman::sync_databases() {
-            eprintln!("error: {}", e);
-            std::process::exit(1);
-        }
+    #[cfg(target_os = "linux")]
+    if cli.sync_op
+        && cli.sync_db
+        && let Err(e) = pacman::sync_databases()
+    {
+        eprintln!("error: {}", e);
+        exit(1);
+    }
+
+    #[cfg(target_os = "linux")]
+    if !cli.debug {
+        bootstrap::offer(&config);
+    }
+
+    let previous = if config.display.show_deltas {
+        history::load_stats_snapshot()
+    } else {
+        None
+    };
+
+    // Get and show stats
+    let graphics = output_mode == util::OutputMode::Graphics;
+    let stats = if !cli.debug && std::io::stdout().is_terminal() {
+        // Local stats show up first, and mirror and AUR probes fill in as they answer. Each
+        // part is gathered unsaved, so the cache and prompt get the merged whole
+        let (local_config, pending) = managers::spawn_network_stats(&config);
         let spinner = util::create_spinner("Gathering stats");
-        let stats = pacman::get_stats(&config.display.stats, cli.debug, Some(&spinner));
+        let stats = managers::get_stats_unsaved(&local_config, cli.debug, Some(&spinner));
         spinner.finish_and_clear();
+        let stats =
+            ui::display_stats_as_gathered(stats, &config, previous.as_ref(), graphics, pending);
+        let _ = managers::save_stats(&config, &stats);
+        if !graphics {
+            println!();
+        }
         stats
-    } else if cli.debug {
-        println!();
-        pacman::get_stats(&config.display.stats, cli.debug, None)
     } else {
-        let spinner = util::create_spinner("Gathering stats");
-        let stats = pacman::get_stats(&config.display.stats, cli.debug, Some(&spinner));
-        spinner.finish_and_clear();
+        // Debug timings print as each stat is gathered, so they go without a spinner
+        let spinner = (!cli.debug).then(|| util::create_spinner("Gathering stats"));
+        if cli.debug {
+            println!();
+        }
+        let stats = managers::get_stats(&config, cli.debug, spinner.as_ref());
+        if let Some(spinner) = spinner {
+            spinner.finish_and_clear();
+        }
+        if graphics && !cli.debug {
+            if let Err(e) = ui::display_stats_with_graphics(&stats, &config, previous.as_ref()) {
+                eprintln!("error: {}", e);
+            }
+        } else {
+            ui::display_stats(&stats, &config, previous.as_ref());
+            println!();
+        }
         stats
     };
 
-    if cli.debug {
-        ui::display_stats(&stats, &config);
-        println!();
-    } else {
-        if let Err(e) = ui::display_stats_with_graphics(&stats, &config) {
-            eprintln!("error: {}", e);
-        }
+    let now = chrono::Local::now().timestamp();
+    let snapshot = history::StatsSnapshot::capture(&stats, now);
+    if let Err(e) = history::save_stats_snapshot(&snapshot) {
+        eprintln!("warning: {}", e);
+    }
+    if let Err(e) = history::record_stats(&stats, now) {
+        eprintln!("warning: {}", e);
+    }
+    // Each run that counts orphans also notes new ones, so `orphans` can date them
+    #[cfg(target_os = "linux")]
+    if stats.orphaned_packages.is_some()
+        && let Err(e) = history::track_orphans(&pacman::get_orphan_names(), now)
+    {
+        eprintln!("warning: {}", e);
+    }
+
+    if config.push.url.is_some()
+        && let Err(e) = push::push(&stats, &config)
+    {
+        eprintln!("warning: {}", e);
     }
+    exit(0);
 }
@@ -0,0 +1,263 @@
+use chrono::{Local, NaiveDateTime, TimeZone};
+use indicatif::ProgressBar;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+use super::PackageManager;
+use crate::config::Config;
+use crate::stats::{self, ManagerStats, StatId};
+use crate::{cache, util};
+
+const DPKG_STATUS_PATH: &str = "/var/lib/dpkg/status";
+const APT_HISTORY_DIR: &str = "/var/log/apt";
+const APT_ARCHIVES_DIR: &str = "/var/cache/apt/archives";
+
+pub struct Apt;
+
+impl PackageManager for Apt {
+    fn name(&self) -> &'static str {
+        "apt"
+    }
+
+    fn is_available(&self) -> bool {
+        Path::new(DPKG_STATUS_PATH).is_file()
+    }
+
+    fn get_stats(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        let requested = config.display.stats.as_slice();
+        let mut stats = ManagerStats::default();
+
+        if requested.contains(&StatId::Installed) {
+            let start = Instant::now();
+            let status = fs::read_to_string(DPKG_STATUS_PATH).unwrap_or_default();
+            stats.total_installed = Some(count_installed(&status));
+            if debug {
+                eprintln!("Installed count: {:?}", start.elapsed());
+            }
+        }
+
+        if stats::needs_upgrade_stats(requested) {
+            if let Some(pb) = spinner {
+                pb.set_message("Checking upgradable packages");
+            }
+            let start = Instant::now();
+            stats.total_upgradable =
+                apt_output(&["list", "--upgradable"]).map(|out| count_upgradable(&out));
+            // --print-uris only lists what would be fetched, so it needs no lock
+            stats.download_size_mb = Command::new("apt-get")
+                .args(["-qq", "--print-uris", "dist-upgrade"])
+                .output()
+                .ok()
+                .filter(|out| out.status.success())
+                .map(|out| download_bytes(&String::from_utf8_lossy(&out.stdout)))
+                .map(|bytes| bytes as f64 / 1048576.0);
+            if debug {
+                eprintln!("Upgrade sizes + count: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Upgrade sizes: SKIP");
+        }
+
+        if requested.contains(&StatId::LastUpdate) {
+            let start = Instant::now();
+            let logged = history_logs(Path::new(APT_HISTORY_DIR))
+                .iter()
+                .find_map(|path| read_log(path).and_then(|log| last_upgrade_time(&log)));
+            match logged.map(|t| util::seconds_since_logged(t, Local::now().timestamp())) {
+                Some(Ok(secs)) => stats.days_since_last_update = Some(secs),
+                Some(Err(warning)) => stats.last_update_warning = Some(warning),
+                None => {}
+            }
+            if debug {
+                eprintln!("Last update time: {:?}", start.elapsed());
+            }
+        }
+
+        if requested.contains(&StatId::CacheSize) {
+            let start = Instant::now();
+            stats.cache_usage = cache::scan(&[PathBuf::from(APT_ARCHIVES_DIR)]);
+            stats.cache_size_mb = stats
+                .cache_usage
+                .as_ref()
+                .map(|u| u.bytes as f64 / 1048576.0);
+            if debug {
+                eprintln!("Cache size: {:?}", start.elapsed());
+            }
+        }
+
+        stats
+    }
+}
+
+/// `apt`'s stdout; the warning about its CLI being unstable goes to stderr.
+fn apt_output(args: &[&str]) -> Option<String> {
+    let output = Command::new("apt").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Packages dpkg's status file records as installed.
+fn count_installed(status: &str) -> u32 {
+    status
+        .lines()
+        .filter(|line| line.starts_with("Status: ") && line.ends_with(" installed"))
+        .count() as u32
+}
+
+/// Lines of `apt list --upgradable`, e.g. "vim/stable 2:9.0.1378-2 amd64 [upgradable from:
+/// 2:9.0.1378-1]".
+fn count_upgradable(list: &str) -> u32 {
+    list.lines()
+        .filter(|line| line.contains("[upgradable from:"))
+        .count() as u32
+}
+
+/// The total size of the `apt-get --print-uris` lines, each "'uri' file size hash".
+fn download_bytes(uris: &str) -> u64 {
+    uris.lines()
+        .filter(|line| line.starts_with('\''))
+        .filter_map(|line| line.split_whitespace().nth(2)?.parse::<u64>().ok())
+        .sum()
+}
+
+/// Where logrotate put `name`'s history, 0 for the live history.log, 1 for history.log.1,
+/// 2 for history.log.2.gz and so on.
+fn rotation(name: &str) -> Option<u32> {
+    let rest = name.strip_prefix("history.log")?;
+    if rest.is_empty() {
+        return Some(0);
+    }
+    let rest = rest.strip_prefix('.')?;
+    rest.strip_suffix(".gz").unwrap_or(rest).parse().ok()
+}
+
+/// The history logs in `dir`, newest first, so the last upgrade is found in history.log
+/// unless it was rotated away.
+fn history_logs(dir: &Path) -> Vec<PathBuf> {
+    let mut logs: Vec<(u32, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| Some((rotation(entry.file_name().to_str()?)?, entry.path())))
+        .collect();
+    logs.sort();
+    logs.into_iter().map(|(_, path)| path).collect()
+}
+
+/// A history log's contents, unpacking the compressed rotations with gzip.
+fn read_log(path: &Path) -> Option<String> {
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        let out = Command::new("gzip").arg("-dc").arg(path).output().ok()?;
+        out.status
+            .success()
+            .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+    } else {
+        fs::read_to_string(path).ok()
+    }
+}
+
+/// When the last history.log entry that upgraded packages started.
+fn last_upgrade_time(log: &str) -> Option<i64> {
+    let mut start: Option<&str> = None;
+    let mut last = None;
+    for line in log.lines() {
+        if let Some(date) = line.strip_prefix("Start-Date: ") {
+            start = Some(date.trim());
+        } else if line.starts_with("Upgrade: ") {
+            last = start.or(last);
+        }
+    }
+    // e.g. "2024-06-10  09:13:44", with two spaces
+    let time = NaiveDateTime::parse_from_str(last?, "%Y-%m-%d  %H:%M:%S").ok()?;
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .map(|t| t.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_apt_output() {
+        let status = "Package: vim\nStatus: install ok installed\n\n\
+                      Package: nano\nStatus: deinstall ok config-files\n\n\
+                      Package: curl\nStatus: install ok installed\n";
+        assert_eq!(count_installed(status), 2);
+
+        let list = "Listing...\n\
+                    vim/stable 2:9.0.1378-2 amd64 [upgradable from: 2:9.0.1378-1]\n\
+                    curl/stable 7.88.1-10+deb12u6 amd64 [upgradable from: 7.88.1-10+deb12u5]\n";
+        assert_eq!(count_upgradable(list), 2);
+
+        let uris = "'http://deb.debian.org/debian/pool/main/v/vim/vim_9.0.1378-2_amd64.deb' \
+                    vim_2%3a9.0.1378-2_amd64.deb 1567240 SHA256:ab12\n\
+                    'http://deb.debian.org/debian/pool/main/c/curl/curl_7.88.1_amd64.deb' \
+                    curl_7.88.1_amd64.deb 315000 SHA256:cd34\n";
+        assert_eq!(download_bytes(uris), 1882240);
+    }
+
+    #[test]
+    fn finds_last_upgrade_in_history() {
+        let log = "\
+Start-Date: 2024-06-01  10:00:00
+Commandline: apt upgrade
+Upgrade: vim:amd64 (2:9.0.1378-1, 2:9.0.1378-2)
+End-Date: 2024-06-01  10:00:20
+
+Start-Date: 2024-06-05  12:00:00
+Commandline: apt install htop
+Install: htop:amd64 (3.2.2-2)
+End-Date: 2024-06-05  12:00:05
+";
+        let expected = Local
+            .with_ymd_and_hms(2024, 6, 1, 10, 0, 0)
+            .unwrap()
+            .timestamp();
+        assert_eq!(last_upgrade_time(log), Some(expected));
+        assert_eq!(
+            last_upgrade_time("Start-Date: 2024-06-05  12:00:00\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn orders_rotated_history_logs() {
+        let dir = std::env::temp_dir().join(format!("pacfetch-apt-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "history.log.10.gz",
+            "history.log.2.gz",
+            "history.log",
+            "history.log.1",
+            "term.log",
+            "history.log.old",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let names: Vec<_> = history_logs(&dir)
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            names,
+            [
+                "history.log",
+                "history.log.1",
+                "history.log.2.gz",
+                "history.log.10.gz"
+            ]
+        );
+    }
+}
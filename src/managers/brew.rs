@@ -0,0 +1,187 @@
+use indicatif::ProgressBar;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Instant, SystemTime};
+
+use super::PackageManager;
+use crate::cache;
+use crate::config::Config;
+use crate::stats::{self, ManagerStats, StatId};
+use crate::util;
+
+pub struct Brew;
+
+impl PackageManager for Brew {
+    fn name(&self) -> &'static str {
+        "brew"
+    }
+
+    fn is_available(&self) -> bool {
+        util::command_exists("brew")
+    }
+
+    fn get_stats(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        let requested = config.display.stats.as_slice();
+        let mut stats = ManagerStats::default();
+        // brew refuses to run as root
+        if util::is_root() {
+            if debug {
+                eprintln!("Homebrew: SKIP (running as root)");
+            }
+            return stats;
+        }
+
+        if requested.contains(&StatId::Installed) {
+            let start = Instant::now();
+            stats.total_installed = Some(
+                ["--formula", "--cask"]
+                    .iter()
+                    .filter_map(|kind| brew_output(&["list", kind, "-1"]))
+                    .map(|out| out.lines().filter(|l| !l.trim().is_empty()).count() as u32)
+                    .sum(),
+            );
+            if debug {
+                eprintln!("Installed count: {:?}", start.elapsed());
+            }
+        }
+
+        if stats::needs_upgrade_stats(requested) {
+            if let Some(pb) = spinner {
+                pb.set_message("Checking outdated formulae");
+            }
+            let start = Instant::now();
+            stats.total_upgradable =
+                brew_output(&["outdated", "--json=v2"]).and_then(|out| count_outdated(&out));
+            if debug {
+                eprintln!("Outdated count: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Outdated count: SKIP");
+        }
+
+        if requested.contains(&StatId::LastUpdate) {
+            let start = Instant::now();
+            stats.days_since_last_update = last_update_time().and_then(|time| {
+                SystemTime::now()
+                    .duration_since(time)
+                    .ok()
+                    .map(|age| age.as_secs() as i64)
+            });
+            if debug {
+                eprintln!("Last update time: {:?}", start.elapsed());
+            }
+        }
+
+        if requested.contains(&StatId::CacheSize) {
+            let start = Instant::now();
+            if let Some(dir) = brew_path("--cache") {
+                stats.cache_usage = cache::scan(&[dir]);
+                stats.cache_size_mb = stats
+                    .cache_usage
+                    .as_ref()
+                    .map(|u| u.bytes as f64 / 1048576.0);
+            }
+            if debug {
+                eprintln!("Cache size: {:?}", start.elapsed());
+            }
+        }
+
+        if requested.contains(&StatId::StoreSize) {
+            let start = Instant::now();
+            stats.store_size_mb = brew_path("--cellar")
+                .and_then(|cellar| cache::scan(&[cellar]))
+                .map(|usage| usage.bytes as f64 / 1048576.0);
+            if debug {
+                eprintln!("Cellar size: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Cellar size: SKIP");
+        }
+
+        stats
+    }
+}
+
+fn brew_output(args: &[&str]) -> Option<String> {
+    let output = Command::new("brew")
+        .args(args)
+        // Keeps brew from updating itself or asking for analytics first
+        .env("HOMEBREW_NO_AUTO_UPDATE", "1")
+        .env("HOMEBREW_NO_ANALYTICS", "1")
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// One of brew's directories, e.g. `brew --cellar`.
+fn brew_path(flag: &str) -> Option<PathBuf> {
+    let path = brew_output(&[flag])?;
+    let path = path.trim();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+#[derive(Deserialize)]
+struct Outdated {
+    #[serde(default)]
+    formulae: Vec<serde_json::Value>,
+    #[serde(default)]
+    casks: Vec<serde_json::Value>,
+}
+
+/// Formulae and casks in `brew outdated --json=v2`.
+fn count_outdated(json: &str) -> Option<u32> {
+    let outdated: Outdated = serde_json::from_str(json).ok()?;
+    Some((outdated.formulae.len() + outdated.casks.len()) as u32)
+}
+
+/// When `brew update` last ran: recent versions fetch the formula and cask lists from the
+/// API into the cache, older ones fetched the core tap's git repository.
+fn last_update_time() -> Option<SystemTime> {
+    let cache = brew_path("--cache").unwrap_or_default();
+    let repository = brew_path("--repository").unwrap_or_default();
+    [
+        cache.join("api/formula.jws.json"),
+        cache.join("api/cask.jws.json"),
+        repository.join(".git/FETCH_HEAD"),
+    ]
+    .iter()
+    .filter_map(|path| modified(path))
+    .max()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).ok()?.modified().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_outdated_formulae_and_casks() {
+        let json = r#"{
+            "formulae": [
+                {"name": "git", "installed_versions": ["2.45.1"], "current_version": "2.45.2",
+                 "pinned": false, "pinned_version": null},
+                {"name": "node", "installed_versions": ["22.2.0"], "current_version": "22.3.0",
+                 "pinned": false, "pinned_version": null}
+            ],
+            "casks": [
+                {"name": "firefox", "installed_versions": ["126.0.1"], "current_version": "127.0"}
+            ]
+        }"#;
+        assert_eq!(count_outdated(json), Some(3));
+        assert_eq!(count_outdated(r#"{"formulae": []}"#), Some(0));
+        assert_eq!(count_outdated("Error: not json"), None);
+    }
+}
@@ -0,0 +1,212 @@
+use indicatif::ProgressBar;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Instant;
+
+use super::PackageManager;
+use crate::cache;
+use crate::config::Config;
+use crate::stats::{self, ManagerStats, StatId};
+use crate::util;
+
+/// Conda packages in one environment, base unless `conda.environment` names another.
+pub struct Conda;
+
+impl PackageManager for Conda {
+    fn name(&self) -> &'static str {
+        "conda"
+    }
+
+    fn is_available(&self) -> bool {
+        conda_command().is_some()
+    }
+
+    fn get_stats(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        let requested = config.display.stats.as_slice();
+        let mut stats = ManagerStats::default();
+        let Some(conda) = conda_command() else {
+            return stats;
+        };
+        let environment = config.conda.environment.as_deref().unwrap_or("base");
+
+        if requested.contains(&StatId::Installed) {
+            let start = Instant::now();
+            stats.total_installed = Some(
+                command_output(conda, &["list", "--json", "-n", environment])
+                    .and_then(|json| count_listed(&json))
+                    .unwrap_or(0),
+            );
+            if debug {
+                eprintln!("Installed count: {:?}", start.elapsed());
+            }
+        }
+
+        if stats::needs_upgrade_stats(requested) {
+            if let Some(pb) = spinner {
+                pb.set_message(format!(
+                    "Solving the {} environment for updates",
+                    environment
+                ));
+            }
+            let start = Instant::now();
+            let plan = command_output(
+                conda,
+                &["update", "--all", "--dry-run", "--json", "-n", environment],
+            )
+            .and_then(|json| parse_update_plan(&json));
+            if let Some((upgradable, download_bytes)) = plan {
+                stats.total_upgradable = Some(upgradable);
+                stats.download_size_mb = Some(download_bytes as f64 / 1048576.0);
+            }
+            if debug {
+                eprintln!("Update plan: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Update plan: SKIP");
+        }
+
+        if requested.contains(&StatId::CacheSize) {
+            let start = Instant::now();
+            let dirs = command_output(conda, &["info", "--json"])
+                .and_then(|json| parse_pkgs_dirs(&json))
+                .unwrap_or_default();
+            stats.cache_usage = cache::scan(&dirs);
+            stats.cache_size_mb = stats
+                .cache_usage
+                .as_ref()
+                .map(|u| u.bytes as f64 / 1048576.0);
+            if debug {
+                eprintln!("Cache size: {:?}", start.elapsed());
+            }
+        }
+
+        stats
+    }
+}
+
+/// mamba where it's installed, since its solver is much faster, conda otherwise. Both take
+/// the same arguments and print the same JSON.
+fn conda_command() -> Option<&'static str> {
+    ["mamba", "conda"]
+        .into_iter()
+        .find(|conda| util::command_exists(conda))
+}
+
+fn command_output(cmd: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(cmd)
+        .args(args)
+        // Keeps conda from checking for a newer conda on every call
+        .env("CONDA_NOTIFY_OUTDATED_CONDA", "false")
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Packages in `conda list --json`.
+fn count_listed(json: &str) -> Option<u32> {
+    let listed: Vec<serde_json::Value> = serde_json::from_str(json).ok()?;
+    Some(listed.len() as u32)
+}
+
+#[derive(Deserialize, Default)]
+struct UpdatePlan {
+    #[serde(default)]
+    actions: PlanActions,
+    /// Set instead of the actions when the channels couldn't be reached.
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "UPPERCASE")]
+struct PlanActions {
+    #[serde(default)]
+    link: Vec<PlanPackage>,
+    #[serde(default)]
+    unlink: Vec<PlanPackage>,
+    #[serde(default)]
+    fetch: Vec<PlanPackage>,
+}
+
+#[derive(Deserialize)]
+struct PlanPackage {
+    name: String,
+    #[serde(default)]
+    size: u64,
+}
+
+/// The packages `update --all --dry-run --json` would replace with a newer build, and the
+/// bytes it would fetch for them. An environment that's current has no actions at all.
+/// Packages the update only adds, as new dependencies, don't count as upgradable.
+fn parse_update_plan(json: &str) -> Option<(u32, u64)> {
+    let plan: UpdatePlan = serde_json::from_str(json).ok()?;
+    if plan.error.is_some() {
+        return None;
+    }
+    let unlinked: HashSet<&str> = plan
+        .actions
+        .unlink
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect();
+    let upgradable = plan
+        .actions
+        .link
+        .iter()
+        .filter(|p| unlinked.contains(p.name.as_str()))
+        .count() as u32;
+    let download = plan.actions.fetch.iter().map(|p| p.size).sum();
+    Some((upgradable, download))
+}
+
+/// The package caches in `conda info --json`.
+fn parse_pkgs_dirs(json: &str) -> Option<Vec<PathBuf>> {
+    let info: serde_json::Value = serde_json::from_str(json).ok()?;
+    Some(
+        info.get("pkgs_dirs")?
+            .as_array()?
+            .iter()
+            .filter_map(|dir| dir.as_str().map(PathBuf::from))
+            .filter(|dir| dir.is_dir())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_update_plans() {
+        let plan = r#"{"actions": {
+            "FETCH": [{"name": "openssl", "version": "3.3.1", "size": 8358342},
+                      {"name": "libzlib", "version": "1.3.1", "size": 61574}],
+            "LINK": [{"name": "openssl", "version": "3.3.1"},
+                     {"name": "libzlib", "version": "1.3.1"}],
+            "UNLINK": [{"name": "openssl", "version": "3.2.1"}],
+            "PREFIX": "/opt/conda"
+        }, "dry_run": true, "success": true}"#;
+        assert_eq!(parse_update_plan(plan), Some((1, 8419916)));
+
+        let current = r#"{"message": "All requested packages already installed.",
+                          "success": true}"#;
+        assert_eq!(parse_update_plan(current), Some((0, 0)));
+        let offline =
+            r#"{"error": "CondaHTTPError: HTTP 000 CONNECTION FAILED", "success": false}"#;
+        assert_eq!(parse_update_plan(offline), None);
+        assert_eq!(
+            count_listed(r#"[{"name": "python"}, {"name": "pip"}]"#),
+            Some(2)
+        );
+    }
+}
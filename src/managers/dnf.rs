@@ -0,0 +1,356 @@
+use chrono::{Local, NaiveDateTime, TimeZone};
+use indicatif::ProgressBar;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+use super::PackageManager;
+use crate::config::Config;
+use crate::stats::{self, ManagerStats, StatId};
+use crate::{cache, distro, util};
+
+const REPOS_DIR: &str = "/etc/yum.repos.d";
+const OS_RELEASE_PATH: &str = "/etc/os-release";
+/// dnf4 and dnf5 keep their caches in different places.
+const CACHE_DIRS: [&str; 2] = ["/var/cache/dnf", "/var/cache/libdnf5"];
+/// Checked for mirror health in this order: updates changes daily, fedora only at release.
+const MIRROR_REPOS: [&str; 2] = ["updates", "fedora"];
+
+pub struct Dnf;
+
+impl PackageManager for Dnf {
+    fn name(&self) -> &'static str {
+        "dnf"
+    }
+
+    fn is_available(&self) -> bool {
+        util::command_exists("dnf") && util::command_exists("rpm")
+    }
+
+    fn get_stats(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        let requested = config.display.stats.as_slice();
+        let mut stats = ManagerStats::default();
+
+        if requested.contains(&StatId::Installed) {
+            let start = Instant::now();
+            stats.total_installed = Some(
+                command_output("rpm", &["-qa"])
+                    .map(|out| out.lines().count() as u32)
+                    .unwrap_or(0),
+            );
+            if debug {
+                eprintln!("Installed count: {:?}", start.elapsed());
+            }
+        }
+
+        if stats::needs_upgrade_stats(requested) {
+            if let Some(pb) = spinner {
+                pb.set_message("Checking upgradable packages");
+            }
+            let start = Instant::now();
+            let root = util::is_root();
+            // check-update exits 100 when there are updates, so the status isn't checked
+            stats.total_upgradable = Command::new("dnf")
+                .args(query_args(&["check-update", "-q"], root))
+                .output()
+                .ok()
+                .map(|out| count_updates(&String::from_utf8_lossy(&out.stdout)));
+            if stats::needs_download_size(requested)
+                && stats.total_upgradable > Some(0)
+                && let Some(dnf) = preview_command(root, util::command_exists("dnf5"))
+            {
+                stats.download_size_mb = Command::new(dnf)
+                    .args(query_args(&["upgrade", "--assumeno"], root))
+                    .output()
+                    .ok()
+                    .and_then(|out| download_size_mb(&String::from_utf8_lossy(&out.stdout)));
+            }
+            if debug {
+                eprintln!("Upgrade sizes + count: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Upgrade sizes: SKIP");
+        }
+
+        if requested.contains(&StatId::LastUpdate) {
+            let start = Instant::now();
+            let logged =
+                command_output("dnf", &["history", "list"]).and_then(|out| last_upgrade_time(&out));
+            match logged.map(|t| util::seconds_since_logged(t, Local::now().timestamp())) {
+                Some(Ok(secs)) => stats.days_since_last_update = Some(secs),
+                Some(Err(warning)) => stats.last_update_warning = Some(warning),
+                None => {}
+            }
+            if debug {
+                eprintln!("Last update time: {:?}", start.elapsed());
+            }
+        }
+
+        if requested.contains(&StatId::CacheSize) {
+            let start = Instant::now();
+            let dirs: Vec<PathBuf> = CACHE_DIRS.iter().map(PathBuf::from).collect();
+            stats.cache_usage = cache::scan(&dirs);
+            stats.cache_size_mb = stats
+                .cache_usage
+                .as_ref()
+                .map(|u| u.bytes as f64 / 1048576.0);
+            if debug {
+                eprintln!("Cache size: {:?}", start.elapsed());
+            }
+        }
+
+        if stats::needs_mirror_url(requested) {
+            if let Some(pb) = spinner {
+                pb.set_message("Checking mirror last sync");
+            }
+            let start = Instant::now();
+            if let Some((url, age)) = test_mirror_health() {
+                stats.mirror_url = Some(url);
+                stats.mirror_sync_age_hours = age;
+            }
+            if debug {
+                eprintln!("Mirror sync age: {:?}", start.elapsed());
+            }
+        }
+
+        stats
+    }
+}
+
+fn command_output(cmd: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(cmd).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// `args` for a query that only reads metadata. Without root, dnf would refresh the
+/// metadata into the user's own cache first, so the system cache is used as it is.
+fn query_args<'a>(args: &[&'a str], root: bool) -> Vec<&'a str> {
+    let mut args = args.to_vec();
+    if !root {
+        args.push("--cacheonly");
+    }
+    args
+}
+
+/// The dnf that can preview an upgrade: dnf4 refuses `upgrade --assumeno` without root,
+/// while dnf5 shows the transaction before declining it.
+fn preview_command(root: bool, has_dnf5: bool) -> Option<&'static str> {
+    if root {
+        Some("dnf")
+    } else {
+        has_dnf5.then_some("dnf5")
+    }
+}
+
+/// Packages in `dnf check-update` output, one "name.arch version repo" line each; the
+/// obsoletes listed after them are counted with the package replacing them.
+fn count_updates(output: &str) -> u32 {
+    output
+        .lines()
+        .take_while(|line| !line.starts_with("Obsoleting"))
+        .filter(|line| !line.starts_with(' ') && line.split_whitespace().count() == 3)
+        .count() as u32
+}
+
+/// The "Total download size: 123 M" line of `dnf upgrade --assumeno`, in MiB.
+fn download_size_mb(output: &str) -> Option<f64> {
+    let size = output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Total download size:"))?
+        .trim();
+    let (number, unit) = size.split_once(' ').unwrap_or((size, "M"));
+    let number: f64 = number.parse().ok()?;
+    Some(match unit.trim().trim_end_matches("iB") {
+        "k" | "K" => number / 1024.0,
+        "G" => number * 1024.0,
+        _ => number,
+    })
+}
+
+/// When the newest `dnf history list` transaction that upgraded packages ran. Rows are
+/// newest first, e.g. "42 | upgrade | 2024-06-10 09:13 | Upgrade | 45".
+fn last_upgrade_time(history: &str) -> Option<i64> {
+    history.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+        let [_, command, date, actions, ..] = fields.as_slice() else {
+            return None;
+        };
+        let upgraded = actions.contains("Upgrade") || command.contains("upgrade");
+        if !upgraded {
+            return None;
+        }
+        let time = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").ok()?;
+        Local
+            .from_local_datetime(&time)
+            .earliest()
+            .map(|t| t.timestamp())
+    })
+}
+
+/// The `metalink=` URL of an enabled repo in a .repo file.
+fn repo_metalink(contents: &str, repo: &str) -> Option<String> {
+    let mut in_repo = false;
+    let mut metalink = None;
+    let mut enabled = true;
+    for line in contents.lines().map(str::trim) {
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if in_repo {
+                break;
+            }
+            in_repo = section == repo;
+        } else if in_repo && let Some((key, value)) = line.split_once('=') {
+            match key.trim() {
+                "metalink" => metalink = Some(value.trim().to_string()),
+                "enabled" => enabled = value.trim() == "1",
+                _ => {}
+            }
+        }
+    }
+    metalink.filter(|_| enabled)
+}
+
+/// The first mirror a metalink lists for repomd.xml.
+fn metalink_mirror(metalink: &str) -> Option<String> {
+    metalink.lines().find_map(|line| {
+        let line = line.trim();
+        let start = line.strip_prefix("<url ")?.find('>')? + "<url ".len() + 1;
+        let url = line[start..].strip_suffix("</url>")?;
+        url.starts_with("http").then(|| url.to_string())
+    })
+}
+
+/// The `<revision>` of a repomd.xml, which dnf repos set to when it was generated.
+fn repomd_revision(repomd: &str) -> Option<i64> {
+    let start = repomd.find("<revision>")? + "<revision>".len();
+    let end = start + repomd[start..].find("</revision>")?;
+    repomd[start..end].trim().parse().ok()
+}
+
+/// Substitute dnf's $releasever and $basearch in a repo URL.
+fn expand_repo_vars(url: &str) -> String {
+    let os_release = fs::read_to_string(OS_RELEASE_PATH).unwrap_or_default();
+    let release = os_release
+        .lines()
+        .find_map(|line| line.strip_prefix("VERSION_ID="))
+        .map(|v| v.trim_matches('"').to_string())
+        .unwrap_or_default();
+    url.replace("$releasever", &release)
+        .replace("$basearch", &distro::machine_arch())
+}
+
+/// The mirror the active metalink points at and how many hours ago its metadata was
+/// generated, or None for the age when its repomd.xml couldn't be read.
+fn test_mirror_health() -> Option<(String, Option<f64>)> {
+    let contents: String = fs::read_dir(Path::new(REPOS_DIR))
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "repo"))
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let metalink = MIRROR_REPOS
+        .iter()
+        .find_map(|repo| repo_metalink(&contents, repo))?;
+
+    let client = util::http_client()?;
+    let get = |url: &str| -> Option<String> {
+        client
+            .get(url)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.text())
+            .ok()
+    };
+    let repomd_url = metalink_mirror(&get(&expand_repo_vars(&metalink))?)?;
+    let age = get(&repomd_url)
+        .and_then(|repomd| repomd_revision(&repomd))
+        .map(|revision| (Local::now().timestamp() - revision).max(0) as f64 / 3600.0);
+    let mirror = repomd_url
+        .strip_suffix("repodata/repomd.xml")
+        .unwrap_or(&repomd_url)
+        .to_string();
+    Some((mirror, age))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dnf_output() {
+        let check_update = "\n\
+            firefox.x86_64          127.0-1.fc40        updates\n\
+            kernel.x86_64           6.9.4-200.fc40      updates\n\
+            Obsoleting Packages\n\
+            grub2-tools.x86_64      1:2.06-121.fc40     updates\n";
+        assert_eq!(count_updates(check_update), 2);
+
+        assert_eq!(
+            download_size_mb("Transaction Summary\nTotal download size: 1.5 G\n"),
+            Some(1536.0)
+        );
+        assert_eq!(download_size_mb("Total download size: 512 k\n"), Some(0.5));
+
+        let history = "\
+ID     | Command line             | Date and time    | Action(s)      | Altered
+-------------------------------------------------------------------------------
+    43 | install htop             | 2024-06-12 18:30 | Install        |    1
+    42 | upgrade                  | 2024-06-10 09:13 | E, I, U        |   45
+    41 | upgrade --refresh        | 2024-06-01 08:00 | Upgrade        |   12
+";
+        let expected = Local
+            .with_ymd_and_hms(2024, 6, 10, 9, 13, 0)
+            .unwrap()
+            .timestamp();
+        assert_eq!(last_upgrade_time(history), Some(expected));
+    }
+
+    #[test]
+    fn queries_the_system_cache_without_root() {
+        assert_eq!(
+            query_args(&["check-update", "-q"], true),
+            ["check-update", "-q"]
+        );
+        assert_eq!(
+            query_args(&["check-update", "-q"], false),
+            ["check-update", "-q", "--cacheonly"]
+        );
+        assert_eq!(preview_command(true, false), Some("dnf"));
+        assert_eq!(preview_command(false, true), Some("dnf5"));
+        assert_eq!(preview_command(false, false), None);
+    }
+
+    #[test]
+    fn finds_metalink_mirror() {
+        let repos = "[fedora]\nmetalink=https://mirrors.fedoraproject.org/metalink?repo=fedora-$releasever\n\
+                     [updates]\nenabled=0\nmetalink=https://mirrors.fedoraproject.org/metalink?repo=updates\n";
+        assert_eq!(
+            repo_metalink(repos, "fedora").as_deref(),
+            Some("https://mirrors.fedoraproject.org/metalink?repo=fedora-$releasever")
+        );
+        assert_eq!(repo_metalink(repos, "updates"), None);
+
+        let metalink = r#"
+    <resources maxconnections="1">
+      <url protocol="rsync" type="rsync" location="DE" preference="100">rsync://ftp.example.de/fedora/repodata/repomd.xml</url>
+      <url protocol="https" type="https" location="DE" preference="100">https://ftp.example.de/fedora/repodata/repomd.xml</url>
+    </resources>"#;
+        assert_eq!(
+            metalink_mirror(metalink).as_deref(),
+            Some("https://ftp.example.de/fedora/repodata/repomd.xml")
+        );
+        assert_eq!(
+            repomd_revision("<repomd>\n  <revision>1718010824</revision>\n</repomd>"),
+            Some(1718010824)
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use indicatif::ProgressBar;
+use std::process::Command;
+use std::time::Instant;
+
+use super::{PackageManager, PendingPackage};
+use crate::config::Config;
+use crate::stats::{self, ManagerStats, StatId};
+use crate::util;
+
+pub struct Flatpak;
+
+impl PackageManager for Flatpak {
+    fn name(&self) -> &'static str {
+        "flatpak"
+    }
+
+    fn is_available(&self) -> bool {
+        util::command_exists("flatpak")
+    }
+
+    fn get_stats(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        let requested = config.display.stats.as_slice();
+        let mut stats = ManagerStats::default();
+
+        if requested.contains(&StatId::Installed) {
+            let start = Instant::now();
+            stats.total_installed = Some(
+                flatpak_output(&["list", "--columns=application"])
+                    .map(|out| out.lines().filter(|line| !line.trim().is_empty()).count() as u32)
+                    .unwrap_or(0),
+            );
+            if debug {
+                eprintln!("Flatpak installed count: {:?}", start.elapsed());
+            }
+        }
+
+        if stats::needs_upgrade_stats(requested) {
+            if let Some(pb) = spinner {
+                pb.set_message("Checking flatpak updates");
+            }
+            let start = Instant::now();
+            // Lists the updates both the system and user installations would pull
+            if let Some(out) =
+                flatpak_output(&["remote-ls", "--updates", "--columns=download-size"])
+            {
+                let sizes: Vec<&str> = out.lines().filter(|l| !l.trim().is_empty()).collect();
+                stats.total_upgradable = Some(sizes.len() as u32);
+                stats.download_size_mb =
+                    Some(sizes.iter().filter_map(|size| size_mb(size)).sum::<f64>());
+            }
+            if debug {
+                eprintln!("Flatpak updates: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Flatpak updates: SKIP");
+        }
+
+        stats
+    }
+
+    fn pending_updates(&self) -> Vec<PendingPackage> {
+        flatpak_output(&["remote-ls", "--updates", "--columns=application,version"])
+            .map(|out| parse_updates(&out))
+            .unwrap_or_default()
+    }
+
+    fn upgrade_command(&self) -> Option<&'static [&'static str]> {
+        Some(&["flatpak", "update"])
+    }
+}
+
+fn flatpak_output(args: &[&str]) -> Option<String> {
+    let output = Command::new("flatpak").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Apps from `remote-ls --updates`, tab-separated from their version, which runtimes
+/// often leave blank.
+fn parse_updates(output: &str) -> Vec<PendingPackage> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split('\t').map(str::trim);
+            let name = columns.next().filter(|name| !name.is_empty())?;
+            Some(PendingPackage {
+                name: name.to_string(),
+                new_version: columns
+                    .next()
+                    .filter(|version| !version.is_empty())
+                    .map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+/// A flatpak size such as "12.3 MB", which uses decimal units, in MiB.
+fn size_mb(size: &str) -> Option<f64> {
+    let mut parts = size.split_whitespace();
+    let number: f64 = parts.next()?.parse().ok()?;
+    let bytes = match parts.next().unwrap_or("bytes") {
+        "kB" => number * 1e3,
+        "MB" => number * 1e6,
+        "GB" => number * 1e9,
+        _ => number,
+    };
+    Some(bytes / 1048576.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_flatpak_sizes() {
+        // flatpak separates the unit with a no-break space
+        assert_eq!(size_mb("1.0\u{a0}GB"), Some(1e9 / 1048576.0));
+        assert_eq!(size_mb("524.3 kB"), Some(524300.0 / 1048576.0));
+        assert_eq!(size_mb("512 bytes"), Some(512.0 / 1048576.0));
+        assert_eq!(size_mb(""), None);
+    }
+
+    #[test]
+    fn parses_flatpak_updates() {
+        let updates = parse_updates("org.gimp.GIMP\t2.10.38\norg.gnome.Platform\t\n\n");
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].name, "org.gimp.GIMP");
+        assert_eq!(updates[0].new_version.as_deref(), Some("2.10.38"));
+        assert_eq!(updates[1].new_version, None);
+    }
+}
@@ -0,0 +1,453 @@
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+
+use std::sync::mpsc;
+use std::thread;
+
+use crate::config::Config;
+use crate::history;
+use crate::stats::{DataCost, ManagerStats, StatId};
+
+mod apt;
+mod brew;
+mod conda;
+mod dnf;
+mod flatpak;
+mod nix;
+mod npm;
+#[cfg(target_os = "linux")]
+mod pacman;
+mod pip;
+mod winget;
+mod zypper;
+
+/// A package manager pacfetch can gather stats from.
+pub trait PackageManager {
+    /// The name `--backend` and the `backend` config key take, e.g. "pacman".
+    fn name(&self) -> &'static str;
+    /// Whether this manager is installed on the host.
+    fn is_available(&self) -> bool;
+    fn get_stats(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats;
+    /// `get_stats` for one part of a display, leaving whatever the backend saves from a
+    /// run to `save_stats` once the parts are merged.
+    fn get_stats_unsaved(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        self.get_stats(config, debug, spinner)
+    }
+    /// Save what a later run reuses from this one's stats; most backends keep nothing.
+    fn save_stats(&self, _requested: &[StatId], _stats: &ManagerStats) -> Result<(), String> {
+        Ok(())
+    }
+    /// The updates `get_stats` counts, for the dashboard's list; empty for backends that
+    /// only count theirs.
+    fn pending_updates(&self) -> Vec<PendingPackage> {
+        Vec::new()
+    }
+    /// The command that applies the pending updates, run from the dashboard; None when
+    /// it has to be done by hand.
+    fn upgrade_command(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+}
+
+/// An update a backend has pending.
+pub struct PendingPackage {
+    pub name: String,
+    /// The version it goes to, when the backend says.
+    pub new_version: Option<String>,
+}
+
+/// Every backend, in the order detection prefers them.
+fn registry() -> Vec<Box<dyn PackageManager>> {
+    vec![
+        #[cfg(target_os = "linux")]
+        Box::new(pacman::Pacman),
+        Box::new(apt::Apt),
+        Box::new(dnf::Dnf),
+        Box::new(zypper::Zypper),
+        Box::new(brew::Brew),
+        Box::new(winget::Winget),
+        Box::new(nix::Nix),
+        Box::new(flatpak::Flatpak),
+        Box::new(pip::Pip),
+        Box::new(conda::Conda),
+        Box::new(npm::Npm),
+    ]
+}
+
+/// What the other sources stat shows of each source.
+const SOURCE_STATS: [StatId; 3] = [StatId::Installed, StatId::Upgradable, StatId::DownloadSize];
+
+/// Stats that wait on a server, gathered together when they share a probe: the mirror
+/// health check finds the mirror URL anyway.
+const NETWORK_STAT_GROUPS: [&[StatId]; 5] = [
+    &[StatId::MirrorUrl, StatId::MirrorHealth],
+    &[StatId::RepoHealth],
+    // Manjaro's branch lag asks a mirror and Arch
+    &[StatId::Distro],
+    &[StatId::AurUpdates, StatId::AurDownloadSize],
+    &[StatId::OtherSources],
+];
+
+/// Stats still being gathered in the background, handed over a group at a time.
+pub struct PendingStats {
+    pub stats: Vec<StatId>,
+    receiver: mpsc::Receiver<(Vec<StatId>, ManagerStats)>,
+}
+
+impl PendingStats {
+    /// Block until the next group is in; None once they all are.
+    pub fn next(&self) -> Option<(Vec<StatId>, ManagerStats)> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// Stats the grand totals of the all-backends view add up across backends.
+pub const TOTAL_STATS: [StatId; 5] = [
+    StatId::Installed,
+    StatId::Upgradable,
+    StatId::DownloadSize,
+    StatId::CacheSize,
+    StatId::StoreSize,
+];
+
+/// Stats from one of the `sources` shown next to the main backend's.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SourceStats {
+    pub name: String,
+    pub stats: ManagerStats,
+}
+
+/// The backend named by `--backend` or the config, or else the first one installed,
+/// falling back to pacman when none are.
+pub fn select(name: Option<&str>) -> Result<Box<dyn PackageManager>, String> {
+    let mut backends = registry();
+    let Some(name) = name else {
+        let index = backends
+            .iter()
+            .position(|backend| backend.is_available())
+            .unwrap_or(0);
+        return Ok(backends.swap_remove(index));
+    };
+    check_name(name)?;
+    let index = backends
+        .iter()
+        .position(|backend| backend.name() == name)
+        .unwrap_or(0);
+    let backend = backends.swap_remove(index);
+    if !backend.is_available() {
+        return Err(format!("{} is not installed", name));
+    }
+    Ok(backend)
+}
+
+/// An error naming the known backends unless `name` is one of them.
+pub fn check_name(name: &str) -> Result<(), String> {
+    let known: Vec<&str> = registry().iter().map(|backend| backend.name()).collect();
+    if known.contains(&name) {
+        Ok(())
+    } else {
+        Err(format!(
+            "unknown backend '{}', expected one of: {}",
+            name,
+            known.join(", ")
+        ))
+    }
+}
+
+/// Each installed backend's stats, gathered side by side for the all-backends view. Other
+/// sources are left out since every source gets its own section anyway.
+pub fn get_all_stats(config: &Config) -> Vec<SourceStats> {
+    let mut backend_config = config.clone();
+    backend_config
+        .display
+        .stats
+        .retain(|id| *id != StatId::OtherSources);
+    let handles: Vec<_> = registry()
+        .into_iter()
+        .filter(|backend| backend.is_available())
+        .map(|backend| {
+            let name = backend.name();
+            let config = backend_config.clone();
+            thread::spawn(move || SourceStats {
+                name: name.to_string(),
+                stats: select(Some(name))
+                    .map(|backend| backend.get_stats_unsaved(&config, false, None))
+                    .unwrap_or_default(),
+            })
+        })
+        .collect();
+    handles
+        .into_iter()
+        .filter_map(|handle| handle.join().ok())
+        .collect()
+}
+
+/// The `TOTAL_STATS` summed across backends; a count or size is only left out when no
+/// backend reported one.
+pub fn totals(sections: &[SourceStats]) -> ManagerStats {
+    let count = |count: fn(&ManagerStats) -> Option<u32>| {
+        sections
+            .iter()
+            .filter_map(|s| count(&s.stats))
+            .fold(None, |total: Option<u32>, n| Some(total.unwrap_or(0) + n))
+    };
+    let sum = |size: fn(&ManagerStats) -> Option<f64>| {
+        sections
+            .iter()
+            .filter_map(|s| size(&s.stats))
+            .fold(None, |total: Option<f64>, mb| {
+                Some(total.unwrap_or(0.0) + mb)
+            })
+    };
+    ManagerStats {
+        total_installed: count(|stats| stats.total_installed),
+        total_upgradable: count(|stats| stats.total_upgradable),
+        download_size_mb: sum(|stats| stats.download_size_mb),
+        cache_size_mb: sum(|stats| stats.cache_size_mb),
+        store_size_mb: sum(|stats| stats.store_size_mb),
+        ..ManagerStats::default()
+    }
+}
+
+/// Every backend's name, with whether it's installed here.
+pub fn list() -> Vec<(&'static str, bool)> {
+    registry()
+        .iter()
+        .map(|backend| (backend.name(), backend.is_available()))
+        .collect()
+}
+
+/// Gather the configured stats from the selected backend. main checks `config.backend`
+/// at startup, so an unusable one only falls back to pacman here.
+pub fn get_stats(config: &Config, debug: bool, spinner: Option<&ProgressBar>) -> ManagerStats {
+    gather_stats(config, debug, spinner, true)
+}
+
+/// `get_stats` for one part of a display; `save_stats` saves the whole once it's merged.
+pub fn get_stats_unsaved(
+    config: &Config,
+    debug: bool,
+    spinner: Option<&ProgressBar>,
+) -> ManagerStats {
+    gather_stats(config, debug, spinner, false)
+}
+
+/// Save the merged stats of a display gathered in parts with `get_stats_unsaved`.
+pub fn save_stats(config: &Config, stats: &ManagerStats) -> Result<(), String> {
+    selected(config).save_stats(&config.display.stats, stats)
+}
+
+/// The selected backend followed by the configured sources, each once.
+pub fn active(config: &Config) -> Vec<Box<dyn PackageManager>> {
+    let mut backends = vec![selected(config)];
+    for source in config
+        .sources
+        .iter()
+        .filter_map(|name| select(Some(name)).ok())
+    {
+        if !backends
+            .iter()
+            .any(|backend| backend.name() == source.name())
+        {
+            backends.push(source);
+        }
+    }
+    backends
+}
+
+fn selected(config: &Config) -> Box<dyn PackageManager> {
+    select(config.backend.as_deref()).unwrap_or_else(|_| registry().swap_remove(0))
+}
+
+fn gather_stats(
+    config: &Config,
+    debug: bool,
+    spinner: Option<&ProgressBar>,
+    save: bool,
+) -> ManagerStats {
+    let backend = selected(config);
+    let mut stats = if save {
+        backend.get_stats(config, debug, spinner)
+    } else {
+        backend.get_stats_unsaved(config, debug, spinner)
+    };
+    if config.display.stats.contains(&StatId::OtherSources) {
+        stats.other_sources = Some(get_source_stats(config, debug, spinner));
+    }
+    if config.display.stats.contains(&StatId::DataCost)
+        && let Some(per_gb) = config.data.cost_per_gb
+    {
+        let monthly = stats
+            .monthly_download_mb
+            .or_else(|| history::projected_monthly_download_mb(chrono::Local::now().timestamp()));
+        stats.data_cost = Some(DataCost {
+            currency: config.data.currency.clone(),
+            pending: stats.download_size_mb.map(|mb| cost_of(mb, per_gb)),
+            monthly: monthly.map(|mb| cost_of(mb, per_gb)),
+        });
+    }
+    stats
+}
+
+/// Start gathering the requested network stats on their own threads, and return the
+/// config for the local ones along with them, so the local ones can be shown before the
+/// slowest probe answers.
+pub fn spawn_network_stats(config: &Config) -> (Config, PendingStats) {
+    let (local, groups) = split_network_stats(&config.display.stats);
+    let (sender, receiver) = mpsc::channel();
+    let mut pending = Vec::new();
+    for group in groups {
+        let mut group_config = config.clone();
+        group_config.display.stats = group.clone();
+        pending.extend(group.iter().copied());
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let stats = get_stats_unsaved(&group_config, false, None);
+            let _ = sender.send((group, stats));
+        });
+    }
+    let mut local_config = config.clone();
+    local_config.display.stats = local;
+    (
+        local_config,
+        PendingStats {
+            stats: pending,
+            receiver,
+        },
+    )
+}
+
+/// The requested stats that are quick to gather locally, and the network ones grouped by
+/// the probe they share.
+fn split_network_stats(requested: &[StatId]) -> (Vec<StatId>, Vec<Vec<StatId>>) {
+    let groups = NETWORK_STAT_GROUPS
+        .iter()
+        .map(|group| {
+            group
+                .iter()
+                .filter(|id| requested.contains(id))
+                .copied()
+                .collect::<Vec<_>>()
+        })
+        .filter(|group| !group.is_empty())
+        .collect();
+    let local = requested
+        .iter()
+        .filter(|id| !NETWORK_STAT_GROUPS.iter().any(|group| group.contains(id)))
+        .copied()
+        .collect();
+    (local, groups)
+}
+
+/// Fill in the fields a network group gathered; each group's config only asked for its
+/// own stats, so whatever it set came from them.
+pub fn merge_network_stats(into: &mut ManagerStats, from: ManagerStats) {
+    into.mirror_url = into.mirror_url.take().or(from.mirror_url);
+    into.mirror_sync_age_hours = into.mirror_sync_age_hours.or(from.mirror_sync_age_hours);
+    into.clock_skew_secs = into.clock_skew_secs.or(from.clock_skew_secs);
+    into.repo_health = into.repo_health.take().or(from.repo_health);
+    into.distro = into.distro.or(from.distro);
+    into.branch = into.branch.take().or(from.branch);
+    into.branch_lag_hours = into.branch_lag_hours.or(from.branch_lag_hours);
+    into.aur_updates = into.aur_updates.or(from.aur_updates);
+    into.aur_build_eta_secs = into.aur_build_eta_secs.or(from.aur_build_eta_secs);
+    into.aur_download_size_mb = into.aur_download_size_mb.or(from.aur_download_size_mb);
+    into.other_sources = into.other_sources.take().or(from.other_sources);
+}
+
+/// The price of downloading `mb` MiB at `per_gb` a GB.
+fn cost_of(mb: f64, per_gb: f64) -> f64 {
+    mb * 1048576.0 / 1e9 * per_gb
+}
+
+/// Stats from each of the configured `sources` that's installed here; one that isn't is
+/// left out, so a config shared between machines works on all of them.
+fn get_source_stats(
+    config: &Config,
+    debug: bool,
+    spinner: Option<&ProgressBar>,
+) -> Vec<SourceStats> {
+    let mut source_config = config.clone();
+    source_config.display.stats = SOURCE_STATS.to_vec();
+    config
+        .sources
+        .iter()
+        .filter_map(|name| select(Some(name)).ok())
+        .map(|source| SourceStats {
+            name: source.name().to_string(),
+            stats: source.get_stats_unsaved(&source_config, debug, spinner),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prices_downloads_in_decimal_gb() {
+        assert_eq!(cost_of(1e9 / 1048576.0, 2.0), 2.0);
+        assert_eq!(cost_of(0.0, 2.0), 0.0);
+    }
+
+    #[test]
+    fn splits_off_network_stats() {
+        let requested = [
+            StatId::Installed,
+            StatId::MirrorHealth,
+            StatId::AurUpdates,
+            StatId::MirrorUrl,
+            StatId::Distro,
+            StatId::CacheSize,
+        ];
+        let (local, groups) = split_network_stats(&requested);
+        assert_eq!(local, [StatId::Installed, StatId::CacheSize]);
+        assert_eq!(
+            groups,
+            [
+                vec![StatId::MirrorUrl, StatId::MirrorHealth],
+                vec![StatId::Distro],
+                vec![StatId::AurUpdates]
+            ]
+        );
+    }
+
+    #[test]
+    fn totals_sections() {
+        let section = |name: &str, installed, upgradable, download_size_mb| SourceStats {
+            name: name.to_string(),
+            stats: ManagerStats {
+                total_installed: Some(installed),
+                total_upgradable: Some(upgradable),
+                download_size_mb,
+                ..ManagerStats::default()
+            },
+        };
+        let total = totals(&[
+            section("pacman", 1200, 10, Some(150.0)),
+            section("flatpak", 40, 2, None),
+            section("npm", 8, 1, Some(2.5)),
+        ]);
+        assert_eq!(total.total_installed, Some(1248));
+        assert_eq!(total.total_upgradable, Some(13));
+        assert_eq!(total.download_size_mb, Some(152.5));
+        assert_eq!(total.cache_size_mb, None);
+    }
+
+    #[test]
+    fn rejects_unknown_backends() {
+        let error = select(Some("portage")).err().unwrap();
+        assert!(error.contains("unknown backend 'portage'"));
+        assert!(error.contains("pacman"));
+    }
+}
@@ -0,0 +1,234 @@
+use indicatif::ProgressBar;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Instant, SystemTime};
+
+use super::PackageManager;
+use crate::cache;
+use crate::config::Config;
+use crate::stats::{self, ManagerStats, StatId};
+use crate::util;
+
+const NIX_STORE_DIR: &str = "/nix/store";
+const NIX_PROFILES_DIR: &str = "/nix/var/nix/profiles/per-user";
+
+pub struct Nix;
+
+impl PackageManager for Nix {
+    fn name(&self) -> &'static str {
+        "nix"
+    }
+
+    fn is_available(&self) -> bool {
+        util::command_exists("nix-env") && Path::new(NIX_STORE_DIR).is_dir()
+    }
+
+    fn get_stats(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        let requested = config.display.stats.as_slice();
+        let mut stats = ManagerStats::default();
+
+        if requested.contains(&StatId::Installed) {
+            let start = Instant::now();
+            stats.total_installed = Some(profile_package_count().unwrap_or(0));
+            if debug {
+                eprintln!("Installed count: {:?}", start.elapsed());
+            }
+        }
+
+        if stats::needs_upgrade_stats(requested) {
+            if let Some(pb) = spinner {
+                pb.set_message("Checking channel updates");
+            }
+            let start = Instant::now();
+            stats.total_upgradable = command_output("nix-env", &["-q", "--compare-versions"])
+                .map(|out| count_newer_versions(&out));
+            stats.outdated_channels = Some(outdated_channel_count());
+            if debug {
+                eprintln!("Channel updates: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Channel updates: SKIP");
+        }
+
+        if requested.contains(&StatId::LastUpdate) {
+            let start = Instant::now();
+            stats.days_since_last_update = channel_dirs()
+                .iter()
+                .filter_map(|dir| fs::symlink_metadata(dir).ok()?.modified().ok())
+                .max()
+                .and_then(|time| SystemTime::now().duration_since(time).ok())
+                .map(|age| age.as_secs() as i64);
+            if debug {
+                eprintln!("Last update time: {:?}", start.elapsed());
+            }
+        }
+
+        if requested.contains(&StatId::StoreSize) {
+            let start = Instant::now();
+            // Store paths hard-linked by `nix-store --optimise` count once
+            stats.store_size_mb = cache::scan(&[PathBuf::from(NIX_STORE_DIR)])
+                .map(|usage| usage.bytes as f64 / 1048576.0);
+            if debug {
+                eprintln!("Store size: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Store size: SKIP");
+        }
+
+        stats
+    }
+}
+
+fn command_output(cmd: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(cmd).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Packages in the current profile: `nix-env -q` lists a classic one, and a profile
+/// managed with `nix profile` has to be listed with that instead.
+fn profile_package_count() -> Option<u32> {
+    if let Some(out) = command_output("nix-env", &["-q"]) {
+        return Some(out.lines().filter(|l| !l.trim().is_empty()).count() as u32);
+    }
+    let json = command_output(
+        "nix",
+        &[
+            "--extra-experimental-features",
+            "nix-command flakes",
+            "profile",
+            "list",
+            "--json",
+        ],
+    )?;
+    count_profile_elements(&json)
+}
+
+/// Elements of `nix profile list --json`: an array before Nix 2.20, a map by name since.
+fn count_profile_elements(json: &str) -> Option<u32> {
+    let value: serde_json::Value = serde_json::from_str(json).ok()?;
+    match value.get("elements")? {
+        serde_json::Value::Array(elements) => Some(elements.len() as u32),
+        serde_json::Value::Object(elements) => Some(elements.len() as u32),
+        _ => None,
+    }
+}
+
+/// Installed packages `nix-env -qc` marks with "<": the channels as last fetched have a
+/// newer version.
+fn count_newer_versions(output: &str) -> u32 {
+    output
+        .lines()
+        .filter(|line| line.split_whitespace().nth(1) == Some("<"))
+        .count() as u32
+}
+
+/// The profiles holding the user's and root's channels, which NixOS's system channel is
+/// one of.
+fn channel_dirs() -> Vec<PathBuf> {
+    let user = std::env::var("USER").unwrap_or_default();
+    let mut dirs = Vec::new();
+    if let Some(home) = std::env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".local/state/nix/profiles/channels"));
+    }
+    dirs.push(Path::new(NIX_PROFILES_DIR).join(&user).join("channels"));
+    if user != "root" {
+        dirs.push(Path::new(NIX_PROFILES_DIR).join("root/channels"));
+    }
+    dirs
+}
+
+/// The `url name` lines of a .nix-channels file; a channel without a name is named after
+/// the last part of its URL, as nix-channel does.
+fn parse_channels(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let url = fields.next()?.trim_end_matches('/');
+            let name = fields
+                .next()
+                .or_else(|| url.rsplit('/').next())?
+                .to_string();
+            Some((name, url.to_string()))
+        })
+        .collect()
+}
+
+/// Channels whose server has moved past the revision last fetched. Root's channel list is
+/// only readable as root, so other users see just their own.
+fn outdated_channel_count() -> u32 {
+    let home = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    let channels: Vec<(String, String)> =
+        [home.join(".nix-channels"), "/root/.nix-channels".into()]
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .flat_map(|contents| parse_channels(&contents))
+            .collect();
+    let Some(client) = util::http_client() else {
+        return 0;
+    };
+    let dirs = channel_dirs();
+    channels
+        .iter()
+        .filter(|(name, url)| {
+            let local = dirs
+                .iter()
+                .find_map(|dir| fs::read_to_string(dir.join(name).join(".git-revision")).ok());
+            // Every channel on channels.nixos.org publishes the commit it points at
+            let remote = client
+                .get(format!("{}/git-revision", url))
+                .send()
+                .and_then(|r| r.error_for_status())
+                .and_then(|r| r.text())
+                .ok();
+            matches!((local, remote), (Some(local), Some(remote)) if local.trim() != remote.trim())
+        })
+        .count() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_channels_and_profiles() {
+        let channels = "https://nixos.org/channels/nixos-24.05 nixos\n\
+                        https://nixos.org/channels/nixpkgs-unstable/\n";
+        assert_eq!(
+            parse_channels(channels),
+            [
+                (
+                    "nixos".to_string(),
+                    "https://nixos.org/channels/nixos-24.05".to_string()
+                ),
+                (
+                    "nixpkgs-unstable".to_string(),
+                    "https://nixos.org/channels/nixpkgs-unstable".to_string()
+                ),
+            ]
+        );
+
+        let old = r#"{"elements": [{"storePaths": ["/nix/store/abc-hello"]}], "version": 2}"#;
+        let new = r#"{"elements": {"hello": {}, "ripgrep": {}}, "version": 3}"#;
+        assert_eq!(count_profile_elements(old), Some(1));
+        assert_eq!(count_profile_elements(new), Some(2));
+        assert_eq!(count_profile_elements("{}"), None);
+
+        let compared = "acrobat-reader-7.0 - ?\n\
+                        autoconf-2.59      = 2.59\n\
+                        firefox-1.0.4      < 1.0.7\n\
+                        hello-2.12         > 2.10\n";
+        assert_eq!(count_newer_versions(compared), 1);
+    }
+}
@@ -0,0 +1,146 @@
+use indicatif::ProgressBar;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Instant;
+
+use super::PackageManager;
+use crate::cache;
+use crate::config::Config;
+use crate::stats::{self, ManagerStats, StatId};
+use crate::util;
+
+/// Node packages installed with `npm install -g`.
+pub struct Npm;
+
+impl PackageManager for Npm {
+    fn name(&self) -> &'static str {
+        "npm"
+    }
+
+    fn is_available(&self) -> bool {
+        util::command_exists("npm")
+    }
+
+    fn get_stats(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        let requested = config.display.stats.as_slice();
+        let mut stats = ManagerStats::default();
+
+        if requested.contains(&StatId::Installed) {
+            let start = Instant::now();
+            stats.total_installed = Some(
+                npm_output(&["ls", "--global", "--depth=0", "--json"])
+                    .and_then(|json| count_dependencies(&json))
+                    .unwrap_or(0),
+            );
+            if debug {
+                eprintln!("Installed count: {:?}", start.elapsed());
+            }
+        }
+
+        if stats::needs_upgrade_stats(requested) {
+            if let Some(pb) = spinner {
+                pb.set_message("Checking the npm registry for updates");
+            }
+            let start = Instant::now();
+            stats.total_upgradable = npm_output(&["outdated", "--global", "--json"])
+                .and_then(|json| count_outdated(&json));
+            if debug {
+                eprintln!("Outdated count: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Outdated count: SKIP");
+        }
+
+        if requested.contains(&StatId::CacheSize) {
+            let start = Instant::now();
+            // Tarballs live in the content-addressed store under the cache directory
+            let dir = npm_output(&["config", "get", "cache"])
+                .map(|out| PathBuf::from(out.trim()).join("_cacache"))
+                .filter(|dir| dir.is_dir());
+            if let Some(dir) = dir {
+                stats.cache_usage = cache::scan(&[dir]);
+                stats.cache_size_mb = stats
+                    .cache_usage
+                    .as_ref()
+                    .map(|u| u.bytes as f64 / 1048576.0);
+            }
+            if debug {
+                eprintln!("Cache size: {:?}", start.elapsed());
+            }
+        }
+
+        stats
+    }
+}
+
+/// npm's stdout. `outdated` exits 1 whenever something is out of date, so the status is
+/// only used to tell that apart from npm not running at all.
+fn npm_output(args: &[&str]) -> Option<String> {
+    let output = Command::new("npm")
+        .args(args)
+        // Keeps npm from checking for a newer npm on every call
+        .env("NPM_CONFIG_UPDATE_NOTIFIER", "false")
+        .output()
+        .ok()?;
+    matches!(output.status.code(), Some(0 | 1))
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The top-level packages in `npm ls --global --json`.
+fn count_dependencies(json: &str) -> Option<u32> {
+    let value: serde_json::Value = serde_json::from_str(json).ok()?;
+    Some(
+        value
+            .get("dependencies")
+            .and_then(|deps| deps.as_object())
+            .map_or(0, |deps| deps.len() as u32),
+    )
+}
+
+/// Packages in `npm outdated --json`, an object keyed by name that's empty when
+/// everything is current. When npm fails, e.g. offline, it prints `{"error": {...}}`
+/// instead, which isn't a count.
+fn count_outdated(json: &str) -> Option<u32> {
+    if json.trim().is_empty() {
+        return Some(0);
+    }
+    let value: serde_json::Value = serde_json::from_str(json).ok()?;
+    // An outdated package that happens to be called "error" has a current version
+    if value
+        .get("error")
+        .is_some_and(|error| error.get("current").is_none())
+    {
+        return None;
+    }
+    value.as_object().map(|outdated| outdated.len() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_npm_output() {
+        let ls = r#"{"name": "lib", "dependencies": {
+            "npm": {"version": "10.8.1", "overridden": false},
+            "typescript": {"version": "5.4.5", "overridden": false}
+        }}"#;
+        assert_eq!(count_dependencies(ls), Some(2));
+        assert_eq!(count_dependencies(r#"{"name": "lib"}"#), Some(0));
+
+        let outdated = r#"{"typescript": {"current": "5.4.5", "wanted": "5.5.2",
+                           "latest": "5.5.2", "location": "/usr/lib/node_modules/typescript"}}"#;
+        assert_eq!(count_outdated(outdated), Some(1));
+        assert_eq!(count_outdated(""), Some(0));
+        assert_eq!(count_outdated("{}"), Some(0));
+        let offline = r#"{"error": {"code": "ENOTFOUND",
+                          "summary": "request to https://registry.npmjs.org/typescript failed",
+                          "detail": ""}}"#;
+        assert_eq!(count_outdated(offline), None);
+    }
+}
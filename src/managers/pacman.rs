@@ -0,0 +1,41 @@
+use indicatif::ProgressBar;
+use std::path::Path;
+
+use super::PackageManager;
+use crate::config::Config;
+use crate::pacman;
+use crate::stats::{ManagerStats, StatId};
+
+pub struct Pacman;
+
+impl PackageManager for Pacman {
+    fn name(&self) -> &'static str {
+        "pacman"
+    }
+
+    fn is_available(&self) -> bool {
+        Path::new("/var/lib/pacman/local").is_dir()
+    }
+
+    fn get_stats(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        pacman::get_stats(config, debug, spinner)
+    }
+
+    fn get_stats_unsaved(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        pacman::get_stats_unsaved(config, debug, spinner)
+    }
+
+    fn save_stats(&self, requested: &[StatId], stats: &ManagerStats) -> Result<(), String> {
+        pacman::save_stats(requested, stats)
+    }
+}
@@ -0,0 +1,206 @@
+use indicatif::ProgressBar;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Instant;
+
+use super::PackageManager;
+use crate::config::Config;
+use crate::stats::{self, ManagerStats, StatId};
+use crate::{cache, util};
+
+const PYPI_URL: &str = "https://pypi.org/pypi";
+
+/// Python packages installed with pipx, and with `pip install --user`.
+pub struct Pip;
+
+impl PackageManager for Pip {
+    fn name(&self) -> &'static str {
+        "pip"
+    }
+
+    fn is_available(&self) -> bool {
+        util::command_exists("pipx") || pip_command().is_some()
+    }
+
+    fn get_stats(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        let requested = config.display.stats.as_slice();
+        let mut stats = ManagerStats::default();
+        let pipx = pipx_packages();
+        let pip = pip_command();
+
+        if requested.contains(&StatId::Installed) {
+            let start = Instant::now();
+            let user = pip
+                .and_then(|pip| command_output(pip, &["list", "--user", "--format=json"]))
+                .and_then(|json| count_listed(&json))
+                .unwrap_or(0);
+            stats.total_installed = Some(pipx.len() as u32 + user);
+            if debug {
+                eprintln!("Installed count: {:?}", start.elapsed());
+            }
+        }
+
+        if stats::needs_upgrade_stats(requested) {
+            if let Some(pb) = spinner {
+                pb.set_message("Checking PyPI for updates");
+            }
+            let start = Instant::now();
+            let user = pip
+                .and_then(|pip| {
+                    command_output(pip, &["list", "--user", "--outdated", "--format=json"])
+                })
+                .and_then(|json| count_listed(&json))
+                .unwrap_or(0);
+            stats.total_upgradable = Some(outdated_pipx_count(&pipx) + user);
+            if debug {
+                eprintln!("Outdated count: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Outdated count: SKIP");
+        }
+
+        if requested.contains(&StatId::CacheSize) {
+            let start = Instant::now();
+            let dir = pip
+                .and_then(|pip| command_output(pip, &["cache", "dir"]))
+                .map(|out| PathBuf::from(out.trim()))
+                .filter(|dir| dir.is_dir());
+            if let Some(dir) = dir {
+                stats.cache_usage = cache::scan(&[dir]);
+                stats.cache_size_mb = stats
+                    .cache_usage
+                    .as_ref()
+                    .map(|u| u.bytes as f64 / 1048576.0);
+            }
+            if debug {
+                eprintln!("Cache size: {:?}", start.elapsed());
+            }
+        }
+
+        stats
+    }
+}
+
+/// pip as distros name it: pip3 where python 2 was around, plain pip elsewhere.
+fn pip_command() -> Option<&'static str> {
+    ["pip3", "pip"]
+        .into_iter()
+        .find(|pip| util::command_exists(pip))
+}
+
+fn command_output(cmd: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(cmd)
+        .args(args)
+        // Keeps pip from checking for a newer pip on every call
+        .env("PIP_DISABLE_PIP_VERSION_CHECK", "1")
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Entries of `pip list --format=json`, outdated or not.
+fn count_listed(json: &str) -> Option<u32> {
+    let listed: Vec<serde_json::Value> = serde_json::from_str(json).ok()?;
+    Some(listed.len() as u32)
+}
+
+#[derive(Deserialize)]
+struct PipxList {
+    venvs: BTreeMap<String, PipxVenv>,
+}
+
+#[derive(Deserialize)]
+struct PipxVenv {
+    metadata: PipxMetadata,
+}
+
+#[derive(Deserialize)]
+struct PipxMetadata {
+    main_package: PipxPackage,
+}
+
+#[derive(Deserialize)]
+struct PipxPackage {
+    package: String,
+    package_version: String,
+}
+
+/// The (package, version) each pipx app was installed from.
+fn pipx_packages() -> Vec<(String, String)> {
+    command_output("pipx", &["list", "--json"])
+        .and_then(|json| parse_pipx_list(&json))
+        .unwrap_or_default()
+}
+
+fn parse_pipx_list(json: &str) -> Option<Vec<(String, String)>> {
+    let list: PipxList = serde_json::from_str(json).ok()?;
+    Some(
+        list.venvs
+            .into_values()
+            .map(|venv| {
+                let main = venv.metadata.main_package;
+                (main.package, main.package_version)
+            })
+            .collect(),
+    )
+}
+
+/// pipx apps whose package has a newer release on PyPI. pipx has no outdated command of
+/// its own, and asking each venv's pip would start a Python for every app.
+fn outdated_pipx_count(packages: &[(String, String)]) -> u32 {
+    let Some(client) = util::http_client() else {
+        return 0;
+    };
+    packages
+        .iter()
+        .filter(|(name, version)| {
+            // PyPI's info.version is the newest release that isn't a pre-release
+            client
+                .get(format!("{}/{}/json", PYPI_URL, name))
+                .send()
+                .and_then(|r| r.error_for_status())
+                .and_then(|r| r.text())
+                .ok()
+                .and_then(|body| serde_json::from_str::<serde_json::Value>(&body).ok())
+                .and_then(|json| json["info"]["version"].as_str().map(str::to_string))
+                .is_some_and(|latest| latest != *version)
+        })
+        .count() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pip_and_pipx_lists() {
+        let outdated = r#"[{"name": "requests", "version": "2.31.0", "latest_version": "2.32.3",
+                             "latest_filetype": "wheel"}]"#;
+        assert_eq!(count_listed(outdated), Some(1));
+        assert_eq!(count_listed("[]"), Some(0));
+
+        let pipx = r#"{"pipx_spec_version": "0.1", "venvs": {
+            "black": {"metadata": {"main_package": {"package": "black",
+                      "package_version": "24.4.2", "apps": ["black"]}}},
+            "httpie": {"metadata": {"main_package": {"package": "httpie",
+                       "package_version": "3.2.2", "apps": ["http", "https"]}}}
+        }}"#;
+        assert_eq!(
+            parse_pipx_list(pipx),
+            Some(vec![
+                ("black".to_string(), "24.4.2".to_string()),
+                ("httpie".to_string(), "3.2.2".to_string()),
+            ])
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use indicatif::ProgressBar;
+use std::process::Command;
+use std::time::Instant;
+
+use super::PackageManager;
+use crate::config::Config;
+use crate::stats::{self, ManagerStats, StatId};
+use crate::util;
+
+/// The table column a row has to reach to count: winget's footers, such as
+/// "2 upgrades available.", stop short of it.
+const VERSION_COLUMN: usize = 2;
+
+/// Windows apps known to winget, from the Microsoft Store and winget's own repo.
+pub struct Winget;
+
+impl PackageManager for Winget {
+    fn name(&self) -> &'static str {
+        "winget"
+    }
+
+    fn is_available(&self) -> bool {
+        util::command_exists("winget")
+    }
+
+    fn get_stats(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        let requested = config.display.stats.as_slice();
+        let mut stats = ManagerStats::default();
+
+        if requested.contains(&StatId::Installed) {
+            let start = Instant::now();
+            stats.total_installed =
+                Some(winget_output("list").map_or(0, |out| parse_table(&out).len() as u32));
+            if debug {
+                eprintln!("Installed count: {:?}", start.elapsed());
+            }
+        }
+
+        if stats::needs_upgrade_stats(requested) {
+            if let Some(pb) = spinner {
+                pb.set_message("Checking winget sources for updates");
+            }
+            let start = Instant::now();
+            stats.total_upgradable =
+                winget_output("upgrade").map(|out| parse_table(&out).len() as u32);
+            if debug {
+                eprintln!("Upgrade count: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Upgrade count: SKIP");
+        }
+
+        stats
+    }
+}
+
+/// winget's stdout for `list` or `upgrade`. On a first run it would stop to ask about
+/// the sources' terms, so those are accepted up front.
+fn winget_output(command: &str) -> Option<String> {
+    let output = Command::new("winget")
+        .args([
+            command,
+            "--accept-source-agreements",
+            "--disable-interactivity",
+        ])
+        .output()
+        .ok()?;
+    // `upgrade` exits non-zero when there is nothing to upgrade, with the table still valid
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The rows of the first table in winget's output, each split into its columns. winget
+/// aligns columns to the header rather than delimiting them, and its headers are
+/// translated, so columns are found by where the header words start above the dashes.
+fn parse_table(output: &str) -> Vec<Vec<String>> {
+    // Progress spinners are overwritten with \r, leaving only the last write on screen
+    let lines: Vec<&str> = output
+        .lines()
+        .map(|line| line.rsplit('\r').next().unwrap_or(line))
+        .collect();
+    let Some(dashes) = lines
+        .iter()
+        .position(|line| line.len() > 3 && line.trim().chars().all(|c| c == '-'))
+    else {
+        return Vec::new();
+    };
+    let Some(header) = dashes.checked_sub(1).map(|i| lines[i]) else {
+        return Vec::new();
+    };
+    let header: Vec<char> = header.chars().collect();
+    let starts: Vec<usize> = (0..header.len())
+        .filter(|&i| !header[i].is_whitespace() && (i == 0 || header[i - 1].is_whitespace()))
+        .collect();
+    let Some(&reach) = starts.get(VERSION_COLUMN) else {
+        return Vec::new();
+    };
+
+    lines[dashes + 1..]
+        .iter()
+        .map(|line| line.chars().collect::<Vec<char>>())
+        .take_while(|row| row.len() > reach)
+        .map(|row| {
+            starts
+                .iter()
+                .enumerate()
+                .map(|(i, &start)| {
+                    let end = starts
+                        .get(i + 1)
+                        .copied()
+                        .unwrap_or(row.len())
+                        .min(row.len());
+                    row[start.min(end)..end]
+                        .iter()
+                        .collect::<String>()
+                        .trim()
+                        .to_string()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_winget_tables() {
+        let upgrade = "   - \r   \\ \rName           Id             Version Available Source\n\
+                       -------------------------------------------------------------\n\
+                       Git            Git.Git        2.44.0  2.45.1    winget\n\
+                       Microsoft Edge Microsoft.Edge 124.0   125.0     winget\n\
+                       2 upgrades available.\n\
+                       \n\
+                       1 package(s) have version numbers that cannot be determined. Use \
+                       --include-unknown to see all results.\n";
+        let rows = parse_table(upgrade);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[1],
+            [
+                "Microsoft Edge",
+                "Microsoft.Edge",
+                "124.0",
+                "125.0",
+                "winget"
+            ]
+        );
+
+        let list = "Name      Id                  Version\n\
+                    -------------------------------------\n\
+                    Notepad++ Notepad++.Notepad++ 8.6.5\n";
+        assert_eq!(parse_table(list).len(), 1);
+        assert!(parse_table("No installed package found matching input criteria.\n").is_empty());
+    }
+}
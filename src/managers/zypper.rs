@@ -0,0 +1,260 @@
+use chrono::{Local, NaiveDateTime};
+use indicatif::ProgressBar;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Instant, SystemTime};
+
+use super::PackageManager;
+use crate::config::Config;
+use crate::stats::{self, ManagerStats, RepoHealth, StatId};
+use crate::{cache, util};
+
+const ZYPP_HISTORY_PATH: &str = "/var/log/zypp/history";
+const ZYPP_PACKAGES_DIR: &str = "/var/cache/zypp/packages";
+/// Each repo's downloaded metadata, in a directory named after its alias.
+const ZYPP_RAW_DIR: &str = "/var/cache/zypp/raw";
+const OS_RELEASE_PATH: &str = "/etc/os-release";
+
+pub struct Zypper;
+
+impl PackageManager for Zypper {
+    fn name(&self) -> &'static str {
+        "zypper"
+    }
+
+    fn is_available(&self) -> bool {
+        util::command_exists("zypper") && util::command_exists("rpm")
+    }
+
+    fn get_stats(
+        &self,
+        config: &Config,
+        debug: bool,
+        spinner: Option<&ProgressBar>,
+    ) -> ManagerStats {
+        let requested = config.display.stats.as_slice();
+        let mut stats = ManagerStats::default();
+
+        if requested.contains(&StatId::Installed) {
+            let start = Instant::now();
+            stats.total_installed = Some(
+                Command::new("rpm")
+                    .arg("-qa")
+                    .output()
+                    .map(|out| String::from_utf8_lossy(&out.stdout).lines().count() as u32)
+                    .unwrap_or(0),
+            );
+            if debug {
+                eprintln!("Installed count: {:?}", start.elapsed());
+            }
+        }
+
+        if stats::needs_upgrade_stats(requested) {
+            if let Some(pb) = spinner {
+                pb.set_message("Checking upgradable packages");
+            }
+            let start = Instant::now();
+            let upgrade = upgrade_command();
+            stats.total_upgradable =
+                zypper_output(&list_updates_args(upgrade)).map(|out| count_updates(&out));
+            // list-updates has no sizes; a dry run prints the download total, but only as root
+            if stats::needs_download_size(requested)
+                && stats.total_upgradable > Some(0)
+                && util::is_root()
+            {
+                stats.download_size_mb =
+                    zypper_output(&[upgrade, "--dry-run"]).and_then(|out| download_size_mb(&out));
+            }
+            if debug {
+                eprintln!("Upgrade sizes + count: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Upgrade sizes: SKIP");
+        }
+
+        if requested.contains(&StatId::LastUpdate) {
+            let start = Instant::now();
+            let logged = fs::read_to_string(ZYPP_HISTORY_PATH)
+                .ok()
+                .and_then(|log| last_upgrade_time(&log));
+            match logged.map(|t| util::seconds_since_logged(t, Local::now().timestamp())) {
+                Some(Ok(secs)) => stats.days_since_last_update = Some(secs),
+                Some(Err(warning)) => stats.last_update_warning = Some(warning),
+                None => {}
+            }
+            if debug {
+                eprintln!("Last update time: {:?}", start.elapsed());
+            }
+        }
+
+        if requested.contains(&StatId::CacheSize) {
+            let start = Instant::now();
+            stats.cache_usage = cache::scan(&[PathBuf::from(ZYPP_PACKAGES_DIR)]);
+            stats.cache_size_mb = stats
+                .cache_usage
+                .as_ref()
+                .map(|u| u.bytes as f64 / 1048576.0);
+            if debug {
+                eprintln!("Cache size: {:?}", start.elapsed());
+            }
+        }
+
+        if requested.contains(&StatId::RepoHealth) {
+            let start = Instant::now();
+            stats.repo_health = Some(repo_refresh_ages(SystemTime::now()));
+            if debug {
+                eprintln!("Repo refresh ages: {:?}", start.elapsed());
+            }
+        } else if debug {
+            eprintln!("Repo refresh ages: SKIP");
+        }
+
+        stats
+    }
+}
+
+/// zypper's stdout, without refreshing repos first, which only root could do anyway.
+fn zypper_output(args: &[&str]) -> Option<String> {
+    let output = Command::new("zypper")
+        .args(["--non-interactive", "--no-refresh"])
+        .args(args)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Tumbleweed is upgraded with `dup`; Leap's point releases with `update`.
+fn upgrade_command() -> &'static str {
+    let os_release = fs::read_to_string(OS_RELEASE_PATH).unwrap_or_default();
+    let rolling = os_release
+        .lines()
+        .any(|line| line.trim_matches('"').contains("opensuse-tumbleweed"));
+    if rolling { "dup" } else { "update" }
+}
+
+/// `list-updates` only counts what `update` would do; `--all` shows what `dup` would.
+fn list_updates_args(upgrade: &str) -> Vec<&'static str> {
+    if upgrade == "dup" {
+        vec!["list-updates", "--all"]
+    } else {
+        vec!["list-updates"]
+    }
+}
+
+/// Rows of the `zypper list-updates` table, e.g. "v | repo-oss | vim | 9.1.0-1.1 |
+/// 9.1.0-2.1 | x86_64"; the header row has "S" in the status column.
+fn count_updates(table: &str) -> u32 {
+    table
+        .lines()
+        .filter(|line| {
+            let mut columns = line.split('|').map(str::trim);
+            columns.next() == Some("v") && columns.count() >= 5
+        })
+        .count() as u32
+}
+
+/// The "Overall download size: 123.4 MiB." line of a dry run, in MiB.
+fn download_size_mb(output: &str) -> Option<f64> {
+    let mut size = output
+        .lines()
+        .find_map(|line| line.split("Overall download size:").nth(1))?
+        .split_whitespace();
+    let number: f64 = size.next()?.parse().ok()?;
+    Some(match size.next()?.trim_end_matches('.') {
+        "B" => number / 1048576.0,
+        "KiB" => number / 1024.0,
+        "GiB" => number * 1024.0,
+        _ => number,
+    })
+}
+
+/// When the last zypper command that upgraded the system ran, from the history log's
+/// "2024-06-10 09:13:40|command|root@host|'zypper' 'dup'|" lines.
+fn last_upgrade_time(log: &str) -> Option<i64> {
+    let line = log.lines().rev().find(|line| {
+        let fields: Vec<&str> = line.split('|').collect();
+        fields.get(1) == Some(&"command")
+            && fields.get(3).is_some_and(|cmd| {
+                ["'dup'", "'dist-upgrade'", "'up'", "'update'", "'patch'"]
+                    .iter()
+                    .any(|sub| cmd.contains(sub))
+            })
+    })?;
+    let date = line.split('|').next()?;
+    let time = NaiveDateTime::parse_from_str(date.trim(), "%Y-%m-%d %H:%M:%S").ok()?;
+    Some(time.and_local_timezone(Local).earliest()?.timestamp())
+}
+
+/// How long ago each repo's metadata was refreshed, by its repomd.xml in the raw cache.
+fn repo_refresh_ages(now: SystemTime) -> Vec<RepoHealth> {
+    let mut repos: Vec<RepoHealth> = fs::read_dir(Path::new(ZYPP_RAW_DIR))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| {
+            let repomd = entry.path().join("repodata/repomd.xml");
+            let age = fs::metadata(repomd)
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok());
+            RepoHealth {
+                name: entry.file_name().to_string_lossy().to_string(),
+                sync_age_hours: age.map(|age| age.as_secs_f64() / 3600.0),
+            }
+        })
+        .collect();
+    repos.sort_by(|a, b| a.name.cmp(&b.name));
+    repos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_zypper_output() {
+        let table = "\
+Loading repository data...
+Reading installed packages...
+S | Repository | Name | Current Version | Available Version | Arch
+--+------------+------+-----------------+-------------------+-------
+v | repo-oss   | vim  | 9.1.0-1.1       | 9.1.0-2.1         | x86_64
+v | repo-oss   | curl | 8.7.1-1.1       | 8.8.0-1.1         | x86_64
+";
+        assert_eq!(count_updates(table), 2);
+
+        let dry_run = "The following 2 packages are going to be upgraded:\n  curl vim\n\n\
+                       2 packages to upgrade.\n\
+                       Overall download size: 1.5 GiB. Already cached: 0 B. After the \
+                       operation, additional 1.2 MiB will be used.\n";
+        assert_eq!(download_size_mb(dry_run), Some(1536.0));
+        assert_eq!(
+            download_size_mb("Overall download size: 512 KiB. Already cached: 0 B."),
+            Some(0.5)
+        );
+    }
+
+    #[test]
+    fn finds_last_upgrade_in_history() {
+        let log = "\
+2024-06-01 10:00:00|command|root@host|'zypper' 'dup'|
+2024-06-01 10:00:20|install|vim|9.1.0-2.1|x86_64|root@host|repo-oss|abc|
+2024-06-05 12:00:00|command|root@host|'zypper' 'install' 'htop'|
+";
+        let expected = NaiveDateTime::parse_from_str("2024-06-01 10:00:00", "%Y-%m-%d %H:%M:%S")
+            .unwrap()
+            .and_local_timezone(Local)
+            .earliest()
+            .unwrap()
+            .timestamp();
+        assert_eq!(last_upgrade_time(log), Some(expected));
+        assert_eq!(
+            last_upgrade_time("2024-06-05 12:00:00|command|root@host|'zypper' 'in' 'htop'|"),
+            None
+        );
+    }
+}
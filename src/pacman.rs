@@ -1,28 +1,130 @@
-use crate::stats::StatId;
+use crate::cache::{self, CachedPackage};
+use crate::checks;
+use crate::config::{Config, FilterConfig, LastUpdateEvent, MirrorConfig, UpgradeScope};
+use crate::distro::{self, Distro};
+use crate::history;
+use crate::hooks;
+use crate::index;
+use crate::lock;
+use crate::paths;
+use crate::progress::{Phase, ProgressParser};
+use crate::prompt;
+use crate::pty::{self, PtyHandler};
+use crate::stats::{
+    FILES_DB_STALE_DAYS, FilesDbAge, GroupSuggestion, ManagerStats, RepoHealth, StatId,
+    UpgradeBreakdown,
+};
+use crate::storage;
 use crate::util;
 use alpm::Alpm;
-use chrono::{DateTime, FixedOffset, Local};
-use indicatif::{ProgressBar, ProgressStyle};
+use chrono::{DateTime, Local};
+use indicatif::ProgressBar;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+const MIRROR_FALLBACK_LIMIT: usize = 3;
+/// When Arch's repos last changed, which a derivative's branch snapshot is compared with.
+const ARCH_LASTUPDATE_URL: &str = "https://geo.mirror.pkgbuild.com/lastupdate";
+const MIRROR_RANK_LIMIT: usize = 10;
+/// Mirrors that synced longer ago than this rank behind every fresher one.
+const MIRROR_STALE_HOURS: f64 = 24.0;
+/// pacman's cache when pacman.conf sets no CacheDir.
+const PACMAN_CACHE_DIR: &str = "/var/cache/pacman/pkg";
+const PACMAN_CONF_PATH: &str = "/etc/pacman.conf";
+const MIRRORLIST_PATH: &str = "/etc/pacman.d/mirrorlist";
+const SYNC_DB_DIR: &str = "/var/lib/pacman/sync";
+/// Groups and meta packages with fewer members aren't worth suggesting.
+const GROUP_MIN_MEMBERS: usize = 4;
+/// The share of a group's members that must be explicitly installed to suggest it.
+const GROUP_SUGGEST_SHARE: f64 = 0.8;
+/// How much older than its package database a .files database can be and still count as
+/// synced with it.
+const FILES_DB_SLACK: Duration = Duration::from_secs(600);
+/// Longer than any AUR build takes, for telling real build times from reproducible builds'.
+const MAX_BUILD_SECS: i64 = 2 * 86400;
+/// Pending upgrades with at least this many packages, or this large a download, are major.
+const MAJOR_PACKAGE_COUNT: u32 = 50;
+const MAJOR_DOWNLOAD_MB: f64 = 500.0;
+/// Hooks running at least this long are listed after the upgrade.
+const SLOW_HOOK_SECS: f64 = 2.0;
+const BOOTLOADER_PACKAGES: [&str; 4] = ["grub", "refind", "limine", "syslinux"];
 
 // --- Public data structures ---
 
-#[derive(Debug, Default)]
-pub struct ManagerStats {
-    pub total_installed: u32,
-    pub total_upgradable: u32,
-    pub days_since_last_update: Option<i64>,
-    pub download_size_mb: Option<f64>,
-    pub total_installed_size_mb: Option<f64>,
-    pub net_upgrade_size_mb: Option<f64>,
-    pub orphaned_packages: Option<u32>,
-    pub orphaned_size_mb: Option<f64>,
-    pub cache_size_mb: Option<f64>,
-    pub mirror_url: Option<String>,
-    pub mirror_sync_age_hours: Option<f64>,
-    pub pacman_version: Option<String>,
+/// An installed package that isn't in any sync database.
+#[derive(Debug, Clone)]
+pub struct ForeignPackage {
+    pub name: String,
+    pub version: String,
+}
+
+/// A foreign package that should be rebuilt, and what it links against that changed.
+#[derive(Debug, Clone)]
+pub struct RebuildCandidate {
+    pub name: String,
+    pub reason: String,
+}
+
+/// An optional dependency of an installed package that isn't installed.
+#[derive(Debug, Clone)]
+pub struct MissingOptdep {
+    pub package: String,
+    pub optdep: String,
+    pub description: Option<String>,
+}
+
+/// A package installed as a dependency that only optional dependencies still point at.
+#[derive(Debug, Clone)]
+pub struct OptionalOnly {
+    pub name: String,
+    pub optional_for: Vec<String>,
+}
+
+/// A package the pending sysupgrade would install or upgrade.
+#[derive(Debug, Clone)]
+pub struct PendingUpdate {
+    pub name: String,
+    pub old_version: Option<String>,
+    pub new_version: String,
+    pub repo: String,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub download_size: i64,
+    /// The whole package file; `download_size` leaves out what's already in the cache.
+    pub package_size: i64,
+    pub installed_size: i64,
+    pub old_installed_size: Option<i64>,
+    /// Packages newly installed because this one depends on them.
+    pub pulled_in: Vec<String>,
+    /// When the new version was built, which is about when it reached the repos.
+    pub build_date: i64,
+    /// Where the package came from before, when the update is from another repo, e.g. extra
+    /// for an update from extra-testing.
+    pub previous_repo: Option<String>,
+    /// Whether the installed version ships a kernel image.
+    pub kernel: bool,
+}
+
+/// One prepared sysupgrade, read once for everything checked before an upgrade.
+#[derive(Default)]
+struct Sysupgrade {
+    updates: Vec<PendingUpdate>,
+    /// Installed packages the transaction removes, e.g. ones a new package replaces.
+    removals: Vec<String>,
+}
+
+/// A mirror that answered during ranking.
+#[derive(Debug)]
+pub struct MirrorRank {
+    pub url: String,
+    pub latency: Duration,
+    pub sync_age_hours: f64,
+    server: String,
 }
 
 // --- Private data structures ---
@@ -30,9 +132,28 @@ pub struct ManagerStats {
 #[derive(Default)]
 struct UpgradeStats {
     download_size_mb: Option<f64>,
+    download_cached_mb: Option<f64>,
     installed_size_mb: Option<f64>,
     net_upgrade_size_mb: Option<f64>,
-    package_count: u32,
+    package_count: Option<u32>,
+    breakdown: Option<UpgradeBreakdown>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChangeKind {
+    Install,
+    Upgrade,
+    Downgrade,
+}
+
+/// What moving from the installed version `old` to `new` does; a reinstall of the same
+/// version counts as an upgrade.
+fn change_kind(old: Option<&str>, new: &str) -> ChangeKind {
+    match old {
+        None => ChangeKind::Install,
+        Some(old) if alpm::vercmp(new, old).is_lt() => ChangeKind::Downgrade,
+        Some(_) => ChangeKind::Upgrade,
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -68,7 +189,7 @@ impl SyncProgress {
     fn format_state(state: DbSyncState) -> String {
         match state {
             DbSyncState::Syncing(pct) => format!("{}%", pct),
-            DbSyncState::Complete => "✓".to_string(),
+            DbSyncState::Complete => util::symbols().check.to_string(),
         }
     }
 
@@ -89,7 +210,8 @@ impl SyncProgress {
 
         let parts: Vec<&str> = trimmed.split_whitespace().collect();
         if parts.len() >= 2 {
-            let db_name = parts[0];
+            // pacman -Fy names the downloads core.files and so on
+            let db_name = parts[0].trim_end_matches(".files");
             let last = parts[parts.len() - 1];
 
             if let Some(pct_str) = last.strip_suffix('%') {
@@ -112,6 +234,102 @@ impl SyncProgress {
     }
 }
 
+#[derive(Default)]
+struct UpgradeTimings {
+    /// Whether pacman exited successfully, so the transaction went through.
+    succeeded: bool,
+    download: Option<Duration>,
+    install: Option<Duration>,
+    /// Each hook that ran, with how long it took.
+    hooks: Vec<(String, Duration)>,
+}
+
+/// Tracks when pacman enters its download and install phases, and how long each hook runs.
+#[derive(Default)]
+struct PhaseTimer {
+    download_start: Option<Instant>,
+    install_start: Option<Instant>,
+    download: Option<Duration>,
+    in_hooks: bool,
+    current_hook: Option<(String, Instant)>,
+    hooks: Vec<(String, Duration)>,
+}
+
+impl PhaseTimer {
+    fn observe(&mut self, line: &str) {
+        let clean = util::strip_ansi(line);
+        let trimmed = clean.trim();
+
+        if self.download_start.is_none() && clean.contains(":: Retrieving packages") {
+            self.download_start = Some(Instant::now());
+        }
+
+        // The keyring, integrity and signature checks after the downloads aren't download time
+        if self.download.is_none() && is_check_step(trimmed) {
+            self.download = self.download_start.map(|start| start.elapsed());
+        }
+
+        if clean.contains(":: Processing package changes") {
+            self.end_hook();
+            self.in_hooks = false;
+            if self.install_start.is_none() {
+                let now = Instant::now();
+                if self.download.is_none() {
+                    self.download = self.download_start.map(|start| now - start);
+                }
+                self.install_start = Some(now);
+            }
+        }
+
+        if clean.contains("-transaction hooks...") {
+            self.end_hook();
+            self.in_hooks = true;
+            return;
+        }
+
+        // Hook lines look like "(2/5) Updating linux initcpios..."
+        if self.in_hooks
+            && trimmed.starts_with('(')
+            && let Some((_, description)) = trimmed.split_once(") ")
+        {
+            let description = description.trim_end_matches("...").to_string();
+            if self
+                .current_hook
+                .as_ref()
+                .is_none_or(|(d, _)| *d != description)
+            {
+                self.end_hook();
+                self.current_hook = Some((description, Instant::now()));
+            }
+        }
+    }
+
+    fn end_hook(&mut self) {
+        if let Some((description, start)) = self.current_hook.take() {
+            self.hooks.push((description, start.elapsed()));
+        }
+    }
+
+    fn finish(&mut self, succeeded: bool) -> UpgradeTimings {
+        self.end_hook();
+        UpgradeTimings {
+            succeeded,
+            download: self.download,
+            install: self.install_start.map(|start| start.elapsed()),
+            hooks: std::mem::take(&mut self.hooks),
+        }
+    }
+}
+
+/// Whether `line` is one of pacman's transaction checks, e.g. "(1/4) checking keys in keyring".
+fn is_check_step(line: &str) -> bool {
+    let step = match line.strip_prefix('(') {
+        Some(rest) => rest.split_once(") ").map_or("", |(_, step)| step),
+        None => line,
+    };
+    step.starts_with("checking ")
+}
+
 // --- Private helper functions ---
 
 fn get_installed_count() -> u32 {
@@ -120,14 +338,39 @@ fn get_installed_count() -> u32 {
     stdout.lines().count() as u32
 }
 
-fn get_seconds_since_update() -> Option<i64> {
-    let contents = fs::read_to_string("/var/log/pacman.log").expect("Failed to read pacman.log");
+fn get_last_update_time(event: LastUpdateEvent) -> Option<i64> {
+    let contents = fs::read_to_string("/var/log/pacman.log").ok()?;
+    match event {
+        LastUpdateEvent::FullUpgrade => last_upgrade_time(&contents),
+        LastUpdateEvent::AnyTransaction => {
+            last_event_time(&contents, "[ALPM] transaction completed")
+        }
+        LastUpdateEvent::AnySync => {
+            last_event_time(&contents, "[PACMAN] synchronizing package lists")
+        }
+    }
+}
+
+/// When the last pacman.log entry carrying `event`, e.g. "[ALPM] transaction completed",
+/// was written; entries with a timestamp that won't parse are passed over.
+fn last_event_time(log: &str, event: &str) -> Option<i64> {
+    log.lines().rev().find_map(|line| {
+        let (ts, rest) = line.trim().strip_prefix('[')?.split_once(']')?;
+        if rest.trim_start().starts_with(event) {
+            parse_log_timestamp(ts)
+        } else {
+            None
+        }
+    })
+}
 
+/// When the last full system upgrade that completed started, from pacman.log's contents.
+fn last_upgrade_time(log: &str) -> Option<i64> {
     let mut saw_upgrade_start = false;
-    let mut upgrade_start_timestamp: Option<String> = None;
-    let mut last_valid_timestamp: Option<String> = None;
+    let mut upgrade_start_timestamp: Option<&str> = None;
+    let mut last_valid_timestamp: Option<&str> = None;
 
-    for line in contents.lines() {
+    for line in log.lines() {
         let trimmed = line.trim();
 
         let timestamp = trimmed
@@ -138,98 +381,204 @@ fn get_seconds_since_update() -> Option<i64> {
 
         if trimmed.contains("starting full system upgrade") {
             saw_upgrade_start = true;
-            upgrade_start_timestamp = Some(timestamp.to_string());
+            upgrade_start_timestamp = Some(timestamp);
         }
 
         if saw_upgrade_start && trimmed.contains("transaction completed") {
-            last_valid_timestamp = upgrade_start_timestamp.clone();
+            last_valid_timestamp = upgrade_start_timestamp;
             saw_upgrade_start = false;
         }
     }
 
-    if let Some(ts) = last_valid_timestamp {
-        let formatted_date = format!("{}:{}", &ts[..22], &ts[22..]);
-
-        let parsed: DateTime<FixedOffset> = DateTime::parse_from_rfc3339(&formatted_date).unwrap();
+    parse_log_timestamp(last_valid_timestamp?)
+}
 
-        let last_update_local = parsed.with_timezone(&Local);
-        let now = Local::now();
-        let duration = now.signed_duration_since(last_update_local);
-        let seconds = duration.num_seconds().max(0);
+/// The repos pacman.conf configures, or the distro's usual ones without it.
+fn sync_repo_names() -> Vec<String> {
+    let contents = fs::read_to_string(PACMAN_CONF_PATH).unwrap_or_default();
+    let names: Vec<String> = parse_repos(&contents, &pacman_arch(), &MirrorConfig::default())
+        .into_iter()
+        .map(|repo| repo.name)
+        .collect();
+    if !names.is_empty() {
+        return names;
+    }
+    Distro::detect()
+        .default_repos()
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
 
-        return Some(seconds);
+fn register_sync_dbs(alpm: &mut Alpm) {
+    for name in sync_repo_names() {
+        let _ = alpm.register_syncdb_mut(name, alpm::SigLevel::NONE);
     }
+}
 
-    None
+/// How long ago each repo's files database (the one `pacman -F` searches) was synced.
+fn get_files_db_ages(now: i64) -> Vec<FilesDbAge> {
+    sync_repo_names()
+        .into_iter()
+        .map(|repo| {
+            let path = Path::new(SYNC_DB_DIR).join(format!("{}.files", repo));
+            let age_secs = fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|t| now - t.as_secs() as i64);
+            FilesDbAge { repo, age_secs }
+        })
+        .collect()
 }
 
-fn get_upgrade_sizes() -> UpgradeStats {
-    let fail = UpgradeStats::default();
+/// A note to run `sync --files` when a files database is missing or older than
+/// `FILES_DB_STALE_DAYS`, since whatever reads file lists would be working from old ones.
+pub fn files_db_warning() -> Option<String> {
+    let ages = get_files_db_ages(Local::now().timestamp());
+    if ages.iter().any(|db| db.age_secs.is_none()) {
+        return Some("the files databases aren't synced; run pacfetch sync --files".to_string());
+    }
+    let oldest = ages.iter().filter_map(|db| db.age_secs).max()?;
+    (oldest > FILES_DB_STALE_DAYS * 86400).then(|| {
+        format!(
+            "the files databases are {} old; run pacfetch sync --files",
+            util::compact_duration(oldest)
+        )
+    })
+}
 
-    let mut alpm = match Alpm::new("/", "/var/lib/pacman") {
-        Ok(a) => a,
-        Err(_) => return fail,
-    };
+/// Marks that an upgrade pointed out the files databases were never synced.
+const FILES_DB_NOTED_FILE: &str = "files-db-noted";
 
-    let _ = alpm.register_syncdb_mut("core", alpm::SigLevel::NONE);
-    let _ = alpm.register_syncdb_mut("extra", alpm::SigLevel::NONE);
-    let _ = alpm.register_syncdb_mut("multilib", alpm::SigLevel::NONE);
+/// Whether every repo's .files database has been synced at least once.
+fn files_dbs_synced() -> bool {
+    get_files_db_ages(Local::now().timestamp())
+        .iter()
+        .all(|db| db.age_secs.is_some())
+}
 
-    if alpm.trans_init(alpm::TransFlag::NO_LOCK).is_err() {
-        return fail;
+/// True the first time it's called for `marker`, leaving the marker in the sudo user's
+/// state directory so later runs get false.
+fn note_once(marker: &str) -> bool {
+    let Some(path) = paths::user_state_file(marker) else {
+        return false;
+    };
+    if path.exists() {
+        return false;
     }
+    if paths::ensure_parent(&path).is_ok() && storage::write_atomic(&path, "").is_ok() {
+        let _ = paths::return_to_sudo_user(&path);
+    }
+    true
+}
 
-    if alpm.sync_sysupgrade(false).is_err() {
-        let _ = alpm.trans_release();
-        return fail;
+/// Whether every repo's .files database was synced along with its package database or
+/// later, so its file lists are of the versions an upgrade would install. Both take the
+/// mirror's modification time, which can differ a little between the two.
+fn files_dbs_current() -> bool {
+    let modified = |path: PathBuf| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let dir = Path::new(SYNC_DB_DIR);
+    sync_repo_names().iter().all(|repo| {
+        let files = modified(dir.join(format!("{}.files", repo)));
+        let db = modified(dir.join(format!("{}.db", repo)));
+        match (files, db) {
+            (Some(files), Some(db)) => files + FILES_DB_SLACK >= db,
+            _ => false,
+        }
+    })
+}
+
+/// Prepare (but never commit) a sysupgrade transaction and hand it to `f`.
+fn with_sysupgrade<T>(f: impl FnOnce(&Alpm) -> T) -> Option<T> {
+    let mut alpm = Alpm::new("/", "/var/lib/pacman").ok()?;
+    register_sync_dbs(&mut alpm);
+    // Without a cachedir libalpm reports full sizes even for cached or partially downloaded packages
+    for dir in pacman_cache_dirs() {
+        let _ = alpm.add_cachedir(dir.to_string_lossy().as_bytes());
     }
 
-    if alpm.trans_prepare().is_err() {
+    alpm.trans_init(alpm::TransFlag::NO_LOCK).ok()?;
+
+    if alpm.sync_sysupgrade(false).is_err() || alpm.trans_prepare().is_err() {
         let _ = alpm.trans_release();
-        return fail;
+        return None;
     }
 
-    let localdb = alpm.localdb();
+    let result = f(&alpm);
+    let _ = alpm.trans_release();
+    Some(result)
+}
 
-    let mut total_download_size: i64 = 0;
-    let mut total_installed_size: i64 = 0;
-    let mut net_upgrade_size: i64 = 0;
-    let mut package_count: u32 = 0;
+fn get_upgrade_sizes() -> UpgradeStats {
+    with_sysupgrade(|alpm| {
+        let localdb = alpm.localdb();
+
+        let mut total_download_size: i64 = 0;
+        let mut total_package_size: i64 = 0;
+        let mut total_installed_size: i64 = 0;
+        let mut net_upgrade_size: i64 = 0;
+        let mut package_count: u32 = 0;
+        let mut breakdown = UpgradeBreakdown::default();
+
+        for pkg in alpm.trans_add().into_iter() {
+            package_count += 1;
+            total_download_size += pkg.download_size();
+            total_package_size += pkg.size();
+            let new_size = pkg.isize();
+            total_installed_size += new_size;
+
+            let oldpkg = localdb.pkg(pkg.name()).ok();
+            if let Some(oldpkg) = oldpkg {
+                let old_size = oldpkg.isize();
+                net_upgrade_size += new_size - old_size;
+            } else {
+                net_upgrade_size += new_size;
+            }
 
-    for pkg in alpm.trans_add().into_iter() {
-        package_count += 1;
-        total_download_size += pkg.download_size();
-        let new_size = pkg.isize();
-        total_installed_size += new_size;
+            let kind = change_kind(oldpkg.map(|p| p.version().as_str()), pkg.version().as_str());
+            match kind {
+                ChangeKind::Install => &mut breakdown.installs,
+                ChangeKind::Upgrade => &mut breakdown.upgrades,
+                ChangeKind::Downgrade => &mut breakdown.downgrades,
+            }
+            .add(pkg.download_size());
+        }
 
-        if let Ok(oldpkg) = localdb.pkg(pkg.name()) {
-            let old_size = oldpkg.isize();
-            net_upgrade_size += new_size - old_size;
-        } else {
-            net_upgrade_size += new_size;
+        for pkg in alpm.trans_remove().into_iter() {
+            net_upgrade_size -= pkg.isize();
+            breakdown.removals.add(pkg.isize());
         }
-    }
 
-    for pkg in alpm.trans_remove().into_iter() {
-        net_upgrade_size -= pkg.isize();
-    }
+        let download_mib = total_download_size as f64 / 1048576.0;
+        let cached_mib = (total_package_size - total_download_size).max(0) as f64 / 1048576.0;
+        let installed_mib = total_installed_size as f64 / 1048576.0;
+        let mut net_mib = net_upgrade_size as f64 / 1048576.0;
 
-    let _ = alpm.trans_release();
+        if net_mib > -0.01 && net_mib < 0.01 {
+            net_mib = 0.0;
+        }
 
-    let download_mib = total_download_size as f64 / 1048576.0;
-    let installed_mib = total_installed_size as f64 / 1048576.0;
-    let mut net_mib = net_upgrade_size as f64 / 1048576.0;
+        UpgradeStats {
+            download_size_mb: Some(download_mib),
+            download_cached_mb: Some(cached_mib),
+            installed_size_mb: Some(installed_mib),
+            net_upgrade_size_mb: Some(net_mib),
+            package_count: Some(package_count),
+            breakdown: Some(breakdown),
+        }
+    })
+    .unwrap_or_default()
+}
 
-    if net_mib > -0.01 && net_mib < 0.01 {
-        net_mib = 0.0;
-    }
+fn satisfies(pkg: &alpm::Package, name: &str) -> bool {
+    pkg.name() == name || pkg.provides().into_iter().any(|p| p.name() == name)
+}
 
-    UpgradeStats {
-        download_size_mb: Some(download_mib),
-        installed_size_mb: Some(installed_mib),
-        net_upgrade_size_mb: Some(net_mib),
-        package_count,
-    }
+fn is_orphan(pkg: &alpm::Package) -> bool {
+    pkg.reason() == alpm::PackageReason::Depend
+        && pkg.required_by().is_empty()
+        && pkg.optional_for().is_empty()
 }
 
 fn get_orphaned_packages() -> (Option<u32>, Option<f64>) {
@@ -242,334 +591,2488 @@ fn get_orphaned_packages() -> (Option<u32>, Option<f64>) {
     let mut count = 0;
     let mut total_size: i64 = 0;
 
-    for pkg in localdb.pkgs().into_iter() {
-        if pkg.reason() == alpm::PackageReason::Depend {
-            if pkg.required_by().len() == 0 && pkg.optional_for().len() == 0 {
-                count += 1;
-                total_size += pkg.isize();
-            }
-        }
+    for pkg in localdb.pkgs().into_iter().filter(|pkg| is_orphan(pkg)) {
+        count += 1;
+        total_size += pkg.isize();
     }
 
     let size_mb = total_size as f64 / 1048576.0;
     (Some(count), Some(size_mb))
 }
 
-fn get_cache_size() -> Option<f64> {
-    let cache_path = std::path::Path::new("/var/cache/pacman/pkg");
-
-    if let Ok(entries) = std::fs::read_dir(cache_path) {
-        let total_size: u64 = entries
-            .filter_map(|e| e.ok())
-            .filter_map(|e| e.metadata().ok())
-            .filter(|m| m.is_file())
-            .map(|m| m.len())
-            .sum();
+/// Installed packages that no sync database provides, usually built from the AUR.
+pub fn get_foreign_packages() -> Vec<ForeignPackage> {
+    let Ok(mut alpm) = Alpm::new("/", "/var/lib/pacman") else {
+        return Vec::new();
+    };
+    register_sync_dbs(&mut alpm);
+
+    let syncdbs = alpm.syncdbs();
+    alpm.localdb()
+        .pkgs()
+        .into_iter()
+        .filter(|pkg| syncdbs.iter().all(|db| db.pkg(pkg.name()).is_err()))
+        .map(|pkg| ForeignPackage {
+            name: pkg.name().to_string(),
+            version: pkg.version().to_string(),
+        })
+        .collect()
+}
 
-        Some(total_size as f64 / 1048576.0)
-    } else {
-        None
-    }
+/// A package in a sync database, or a foreign one installed from elsewhere.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageListing {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    /// The sync database it comes from, "local" for a foreign package.
+    pub repo: String,
+    pub installed: bool,
 }
 
-fn get_mirror_url() -> Option<String> {
-    let mirrorlist = fs::read_to_string("/etc/pacman.d/mirrorlist").ok()?;
+/// Every package in the sync databases plus the foreign ones, for the search index.
+pub fn get_package_listings() -> Vec<PackageListing> {
+    let Ok(mut alpm) = Alpm::new("/", "/var/lib/pacman") else {
+        return Vec::new();
+    };
+    register_sync_dbs(&mut alpm);
 
-    for line in mirrorlist.lines() {
-        let trimmed = line.trim();
-        if trimmed.starts_with("Server = ") {
-            let url = trimmed.strip_prefix("Server = ")?;
-            let base_url = url.split("/$repo").next()?;
-            return Some(base_url.to_string());
-        }
-    }
-    None
+    let localdb = alpm.localdb();
+    let syncdbs = alpm.syncdbs();
+    let mut listings: Vec<PackageListing> = syncdbs
+        .iter()
+        .flat_map(|db| {
+            db.pkgs().into_iter().map(move |pkg| PackageListing {
+                name: pkg.name().to_string(),
+                version: pkg.version().to_string(),
+                description: pkg.desc().map(str::to_string),
+                repo: db.name().to_string(),
+                installed: localdb.pkg(pkg.name()).is_ok(),
+            })
+        })
+        .collect();
+    listings.extend(
+        localdb
+            .pkgs()
+            .into_iter()
+            .filter(|pkg| syncdbs.iter().all(|db| db.pkg(pkg.name()).is_err()))
+            .map(|pkg| PackageListing {
+                name: pkg.name().to_string(),
+                version: pkg.version().to_string(),
+                description: pkg.desc().map(str::to_string),
+                repo: "local".to_string(),
+                installed: true,
+            }),
+    );
+    listings
 }
 
-fn get_pacman_version() -> Option<String> {
-    let output = Command::new("pacman").arg("--version").output().ok()?;
-    let stdout = String::from_utf8_lossy(&output.stdout);
+/// An installed package with older builds in the cache to go back to.
+pub struct Downgrade {
+    pub name: String,
+    pub installed: String,
+    /// Newest first.
+    pub cached: Vec<CachedPackage>,
+}
 
-    for line in stdout.lines() {
-        if line.contains("Pacman v") && line.contains("libalpm v") {
-            if let Some(version_start) = line.find("Pacman v") {
-                let version_str = &line[version_start..];
-                return Some(version_str.trim().to_string());
-            }
+/// Installed packages that have older cached builds, by name.
+pub fn get_downgrades() -> Vec<Downgrade> {
+    let Ok(alpm) = Alpm::new("/", "/var/lib/pacman") else {
+        return Vec::new();
+    };
+    let localdb = alpm.localdb();
+
+    let mut downgrades: Vec<Downgrade> = Vec::new();
+    let mut cached = cache::cached_packages();
+    cached.sort_by(|a, b| {
+        a.name
+            .cmp(&b.name)
+            .then_with(|| alpm::vercmp(b.version.as_str(), a.version.as_str()))
+    });
+    for package in cached {
+        let Ok(installed) = localdb.pkg(package.name.as_str()) else {
+            continue;
+        };
+        if alpm::vercmp(package.version.as_str(), installed.version().as_str()).is_ge() {
+            continue;
+        }
+        match downgrades.last_mut() {
+            Some(last) if last.name == package.name => last.cached.push(package),
+            _ => downgrades.push(Downgrade {
+                name: package.name.clone(),
+                installed: installed.version().to_string(),
+                cached: vec![package],
+            }),
         }
     }
-    None
+    downgrades
 }
 
-fn check_mirror_sync(mirror_url: &str) -> Option<f64> {
-    let lastsync_url = format!("{}/lastsync", mirror_url);
+/// Sonames a pending upgrade stops providing, mapped to foreign packages that depend on them.
+fn get_pending_soname_breaks(foreign: &[ForeignPackage]) -> Vec<RebuildCandidate> {
+    with_sysupgrade(|alpm| {
+        let localdb = alpm.localdb();
+
+        let mut dropped = HashSet::new();
+        for pkg in alpm.trans_add().into_iter() {
+            let Ok(old) = localdb.pkg(pkg.name()) else {
+                continue;
+            };
+            let new_provides: HashSet<String> =
+                pkg.provides().iter().map(|d| d.to_string()).collect();
+            dropped.extend(
+                old.provides()
+                    .iter()
+                    .filter(|d| d.name().contains(".so"))
+                    .map(|d| d.to_string())
+                    .filter(|d| !new_provides.contains(d)),
+            );
+        }
 
-    let client = reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_secs(5))
-        .build()
-        .ok()?;
+        foreign
+            .iter()
+            .filter_map(|f| {
+                let pkg = localdb.pkg(f.name.as_str()).ok()?;
+                let soname = pkg
+                    .depends()
+                    .iter()
+                    .map(|d| d.to_string())
+                    .find(|d| dropped.contains(d))?;
+                Some(RebuildCandidate {
+                    name: f.name.clone(),
+                    reason: format!("{} is dropped by a pending upgrade", soname),
+                })
+            })
+            .collect()
+    })
+    .unwrap_or_default()
+}
 
-    let response = client.get(&lastsync_url).send().ok()?;
+/// First library one of the package's binaries can no longer resolve, according to ldd.
+fn find_missing_library(pkg: &alpm::Package) -> Option<String> {
+    pkg.files()
+        .files()
+        .iter()
+        .filter_map(|file| std::str::from_utf8(file.name()).ok())
+        .filter(|name| {
+            !name.ends_with('/')
+                && (name.starts_with("usr/bin/")
+                    || (name.starts_with("usr/lib/") && name.contains(".so")))
+        })
+        .find_map(|name| {
+            let output = Command::new("ldd")
+                .arg(format!("/{}", name))
+                .output()
+                .ok()?;
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            stdout
+                .lines()
+                .find(|l| l.contains("not found"))
+                .and_then(|l| l.split_whitespace().next())
+                .map(str::to_string)
+        })
+}
 
-    if !response.status().is_success() {
-        return None;
+/// Whether one of the package's dependencies, a soname or a package, is satisfied by one
+/// of the `upgraded` packages.
+fn depends_on_any(
+    pkg: &alpm::Package,
+    installed: alpm::AlpmList<&alpm::Package>,
+    upgraded: &[String],
+) -> bool {
+    pkg.depends().iter().any(|dep| {
+        installed
+            .find_satisfier(dep.to_string())
+            .is_some_and(|provider| upgraded.iter().any(|name| name == provider.name()))
+    })
+}
+
+/// Foreign packages linking against sonames that changed, either in a pending upgrade
+/// or in one that already happened (found by scanning their binaries with ldd). With
+/// `upgraded` given, only packages depending on one of those are scanned, so checking
+/// after an upgrade doesn't run ldd over every foreign binary.
+pub fn get_rebuild_candidates(
+    foreign: &[ForeignPackage],
+    upgraded: Option<&[String]>,
+) -> Vec<RebuildCandidate> {
+    let mut candidates = get_pending_soname_breaks(foreign);
+
+    if !util::command_exists("ldd") {
+        return candidates;
+    }
+    let Ok(alpm) = Alpm::new("/", "/var/lib/pacman") else {
+        return candidates;
+    };
+    let localdb = alpm.localdb();
+    for f in foreign {
+        if candidates.iter().any(|c| c.name == f.name) {
+            continue;
+        }
+        let Ok(pkg) = localdb.pkg(f.name.as_str()) else {
+            continue;
+        };
+        if upgraded.is_some_and(|upgraded| !depends_on_any(pkg, localdb.pkgs(), upgraded)) {
+            continue;
+        }
+        if let Some(library) = find_missing_library(pkg) {
+            candidates.push(RebuildCandidate {
+                name: f.name.clone(),
+                reason: format!("{} not found", library),
+            });
+        }
     }
+    candidates
+}
 
-    let timestamp_str = response.text().ok()?;
-    let timestamp: i64 = timestamp_str.trim().parse().ok()?;
+pub fn get_orphan_names() -> Vec<String> {
+    let Ok(alpm) = Alpm::new("/", "/var/lib/pacman") else {
+        return Vec::new();
+    };
 
-    let now = Local::now().timestamp();
-    let age_seconds = now - timestamp;
-    let age_hours = age_seconds as f64 / 3600.0;
+    alpm.localdb()
+        .pkgs()
+        .into_iter()
+        .filter(|pkg| is_orphan(pkg))
+        .map(|pkg| pkg.name().to_string())
+        .collect()
+}
+
+/// Pending upgrades that fix a known vulnerability, as reported by arch-audit.
+pub fn get_vulnerable_upgrades() -> Result<Vec<String>, String> {
+    if !util::command_exists("arch-audit") {
+        return Err("arch-audit is not installed".to_string());
+    }
+    // A single --quiet still adds the fixed version, as in "openssl>=3.3.2-1"
+    let output = Command::new("arch-audit")
+        .args(["--upgradable", "--quiet", "--quiet"])
+        .output()
+        .map_err(|e| format!("Failed to run arch-audit: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("arch-audit exited with {}", output.status));
+    }
 
-    Some(age_hours.max(0.0))
+    Ok(parse_audit(&String::from_utf8_lossy(&output.stdout)))
 }
 
-fn filter_upgrade_line(line: &str) -> bool {
-    let clean = util::strip_ansi(line);
-    let trimmed = clean.trim();
+/// The package names in arch-audit's quiet output, cut before any version it adds.
+fn parse_audit(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split(['>', '<', '=', ' ']).next())
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
 
-    if trimmed.is_empty() {
-        return false;
+/// Optional dependencies nothing installed satisfies, and dependencies kept only because
+/// something optionally depends on them.
+pub fn get_optdep_audit() -> (Vec<MissingOptdep>, Vec<OptionalOnly>) {
+    let Ok(alpm) = Alpm::new("/", "/var/lib/pacman") else {
+        return (Vec::new(), Vec::new());
+    };
+    let installed = alpm.localdb().pkgs();
+
+    let mut missing = Vec::new();
+    for pkg in installed {
+        for dep in pkg.optdepends() {
+            if installed.find_satisfier(dep.name()).is_none() {
+                missing.push(MissingOptdep {
+                    package: pkg.name().to_string(),
+                    optdep: dep.name().to_string(),
+                    description: dep.desc().map(str::to_string),
+                });
+            }
+        }
     }
 
-    if trimmed.contains("Total Download Size:")
-        || trimmed.contains("Total Installed Size:")
-        || trimmed.contains("Net Upgrade Size:")
-    {
-        return false;
-    }
+    let optional_only = installed
+        .into_iter()
+        .filter(|pkg| {
+            pkg.reason() == alpm::PackageReason::Depend
+                && pkg.required_by().is_empty()
+                && !pkg.optional_for().is_empty()
+        })
+        .map(|pkg| OptionalOnly {
+            name: pkg.name().to_string(),
+            optional_for: pkg.optional_for().iter().map(|s| s.to_string()).collect(),
+        })
+        .collect();
+
+    (missing, optional_only)
+}
 
-    if trimmed.contains("resolving dependencies")
-        || trimmed.contains("looking for conflicting packages")
-        || trimmed.contains(":: Starting full system upgrade...")
+/// Log timestamps without an offset, in local time: pacman before 5.2 wrote
+/// "2018-06-01 12:00", so logs of long-lived systems start in that format.
+const LOCAL_LOG_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"];
+
+/// Parse a pacman.log timestamp such as "2024-06-01T12:00:00+0200". RFC 3339's
+/// "+02:00" and "Z" are accepted too, as are the offset-less `LOCAL_LOG_FORMATS`, so one
+/// log can mix them.
+fn parse_log_timestamp(ts: &str) -> Option<i64> {
+    if let Ok(time) = DateTime::parse_from_str(ts, "%Y-%m-%dT%H:%M:%S%z")
+        .or_else(|_| DateTime::parse_from_rfc3339(ts))
     {
-        return false;
+        return Some(time.timestamp());
     }
+    let naive = LOCAL_LOG_FORMATS
+        .iter()
+        .find_map(|format| chrono::NaiveDateTime::parse_from_str(ts, format).ok())?;
+    Some(naive.and_local_timezone(Local).earliest()?.timestamp())
+}
 
-    true
+/// Removals and upgrades recorded in pacman.log, read once and queried per orphan.
+#[derive(Debug, Default)]
+pub struct LogChanges {
+    removed: Vec<(i64, String)>,
+    upgraded: Vec<(i64, String)>,
 }
 
-fn should_print(line: &str, filter: bool) -> bool {
-    if filter {
-        filter_upgrade_line(line)
-    } else {
-        true
+impl LogChanges {
+    /// The changes logged at or after `since`; earlier lines are skipped without being
+    /// kept.
+    pub fn read(since: i64) -> Self {
+        fs::read_to_string("/var/log/pacman.log")
+            .map(|contents| Self::parse(&contents, since))
+            .unwrap_or_default()
     }
-}
 
-fn run_pacman_pty(args: &[&str], filter: bool) -> Result<(), String> {
-    use std::io::Write;
+    fn parse(contents: &str, since: i64) -> Self {
+        let mut changes = Self::default();
+        for line in contents.lines() {
+            let Some((ts, rest)) = line.trim_start_matches('[').split_once(']') else {
+                continue;
+            };
+            let Some(action) = rest.trim().strip_prefix("[ALPM] ") else {
+                continue;
+            };
+            let (list, entry) = if let Some(entry) = action.strip_prefix("removed ") {
+                (&mut changes.removed, entry)
+            } else if let Some(entry) = action.strip_prefix("upgraded ") {
+                (&mut changes.upgraded, entry)
+            } else {
+                continue;
+            };
+            let Some(time) = parse_log_timestamp(ts).filter(|&time| time >= since) else {
+                continue;
+            };
+            list.extend(
+                entry
+                    .split_whitespace()
+                    .next()
+                    .map(|name| (time, name.to_string())),
+            );
+        }
+        changes
+    }
 
-    let cmd = format!("pacman {}", args.join(" "));
-    let mut session =
-        expectrl::spawn(&cmd).map_err(|e| format!("Failed to spawn pacman: {}", e))?;
+    /// Packages removed, and failing that upgraded, between two points in time: the
+    /// likely reason a package found orphaned at `until` lost its dependents.
+    pub fn orphan_causes(&self, since: i64, until: i64) -> (Vec<String>, Vec<String>) {
+        let between = |list: &[(i64, String)]| {
+            list.iter()
+                .filter(|(time, _)| (since..=until).contains(time))
+                .map(|(_, name)| name.clone())
+                .collect()
+        };
+        (between(&self.removed), between(&self.upgraded))
+    }
+}
 
-    if let Ok((cols, rows)) = crossterm::terminal::size() {
-        let _ = session.get_process_mut().set_window_size(cols, rows);
+/// The lines of pacman.conf's [options] section as key and value; flags such as Color
+/// have no value.
+fn pacman_conf_options() -> Vec<(String, Option<String>)> {
+    let contents = fs::read_to_string(PACMAN_CONF_PATH).unwrap_or_default();
+    let mut section = "";
+    let mut options = Vec::new();
+    for line in contents.lines() {
+        let line = strip_comment(line);
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name;
+            continue;
+        }
+        if section != "options" || line.is_empty() {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, value)) => {
+                options.push((key.trim().to_string(), Some(value.trim().to_string())))
+            }
+            None => options.push((line.to_string(), None)),
+        }
     }
+    options
+}
 
-    session.set_expect_timeout(Some(std::time::Duration::from_millis(100)));
+/// The display options set in pacman.conf, so pacfetch's output can match pacman's.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PacmanDisplay {
+    pub color: bool,
+    pub candy: bool,
+    pub verbose_pkg_lists: bool,
+}
 
-    let mut stdout = std::io::stdout();
-    let mut line_buffer = String::new();
+pub fn pacman_display() -> PacmanDisplay {
+    let options = pacman_conf_options();
+    let has = |flag: &str| options.iter().any(|(key, _)| key == flag);
+    PacmanDisplay {
+        color: has("Color"),
+        candy: has("ILoveCandy"),
+        verbose_pkg_lists: has("VerbosePkgLists"),
+    }
+}
 
-    loop {
-        match session.is_alive() {
-            Ok(true) => {}
-            Ok(false) => {
-                if !line_buffer.is_empty() && should_print(&line_buffer, filter) {
-                    println!("{}", line_buffer);
-                }
-                return Ok(());
-            }
-            Err(_) => {
-                return Ok(());
-            }
-        }
-
-        let mut buf = [0u8; 1024];
-        match session.try_read(&mut buf) {
-            Ok(0) => continue,
-            Ok(n) => {
-                let chunk = String::from_utf8_lossy(&buf[..n]);
-
-                for ch in chunk.chars() {
-                    if ch == '\n' {
-                        if should_print(&line_buffer, filter) {
-                            println!("{}", line_buffer);
-                        }
-                        line_buffer.clear();
-                    } else if ch == '\r' {
-                        if !line_buffer.is_empty() && should_print(&line_buffer, filter) {
-                            print!("\r{}", line_buffer);
-                            let _ = stdout.flush();
-                        }
-                        line_buffer.clear();
-                    } else {
-                        line_buffer.push(ch);
-
-                        if line_buffer.ends_with("[Y/n] ")
-                            || (line_buffer.contains("::") && line_buffer.ends_with("]: "))
-                        {
-                            if should_print(&line_buffer, filter) {
-                                if line_buffer.contains("Proceed with installation") {
-                                    println!("\n\n");
-                                }
-                                print!("{}", line_buffer);
-                                let _ = stdout.flush();
-                            }
-                            line_buffer.clear();
-
-                            let mut input = String::new();
-                            if std::io::stdin().read_line(&mut input).is_ok() {
-                                let _ = session.send_line(input.trim());
-                            }
-                        }
-                    }
-                }
-            }
-            Err(e) => match e.kind() {
-                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted => {
-                    std::thread::sleep(std::time::Duration::from_millis(10));
-                }
-                _ => break,
-            },
-        }
+/// The CacheDir entries of pacman.conf's [options], or pacman's default cache.
+pub fn pacman_cache_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = pacman_conf_options()
+        .into_iter()
+        .filter(|(key, _)| key == "CacheDir")
+        .filter_map(|(_, value)| value)
+        .flat_map(|value| {
+            value
+                .split_whitespace()
+                .map(PathBuf::from)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    if dirs.is_empty() {
+        dirs.push(PathBuf::from(PACMAN_CACHE_DIR));
     }
+    dirs
+}
 
-    if !line_buffer.is_empty() && should_print(&line_buffer, filter) {
-        println!("{}", line_buffer);
+/// pacman's cache directories followed by the extra ones from the config.
+pub fn cache_dirs(config: &Config) -> Vec<PathBuf> {
+    let mut dirs = pacman_cache_dirs();
+    for dir in &config.cache.extra_dirs {
+        if !dirs.contains(dir) {
+            dirs.push(dir.clone());
+        }
     }
+    dirs
+}
 
-    print!("\x1b[0m");
-    let _ = stdout.flush();
+struct MirrorEntry {
+    /// Server URL up to the `/$repo` placeholder.
+    base_url: String,
+    /// Server value exactly as written in the mirrorlist.
+    server: String,
+    /// The server with `$repo` and `$arch` expanded: where the repo's database lives.
+    url: String,
+}
 
-    Ok(())
+/// A repository section of pacman.conf with its servers, Include files followed.
+struct Repo {
+    name: String,
+    servers: Vec<MirrorEntry>,
 }
 
-fn run_pacman_sync() -> Result<(), String> {
-    if !util::is_root() {
-        return Err("you cannot perform this operation unless you are root.".to_string());
+/// How deep Include files may nest, so an include loop can't hang the stat.
+const INCLUDE_DEPTH: usize = 8;
+/// The comment `write_mirrorlist` puts above the ranked servers. It isn't a `## Country`
+/// header, and the servers under it were already filtered when they were ranked.
+const RANKED_HEADER: &str = "# pacfetch: ranked mirrors";
+
+/// The servers of the official repos, in the order pacman tries them, filtered by the
+/// config. They share a mirrorlist, so core's servers stand for all of them.
+fn get_mirror_entries(config: &MirrorConfig) -> Vec<MirrorEntry> {
+    let contents = fs::read_to_string(PACMAN_CONF_PATH).unwrap_or_default();
+    let mut repos = parse_repos(&contents, &pacman_arch(), config);
+    let official = repos
+        .iter()
+        .position(|r| r.name == "core")
+        .or_else(|| repos.iter().position(|r| !r.servers.is_empty()));
+    if let Some(index) = official {
+        return repos.swap_remove(index).servers;
     }
 
-    let mut session =
-        expectrl::spawn("pacman -Sy").map_err(|e| format!("Failed to spawn pacman: {}", e))?;
+    // Without a readable pacman.conf, fall back to the default mirrorlist
+    let Ok(mirrorlist) = fs::read_to_string(MIRRORLIST_PATH) else {
+        return Vec::new();
+    };
+    parse_mirrorlist(&mirrorlist, "core", &pacman_arch(), config, 0)
+}
 
-    if let Ok((cols, rows)) = crossterm::terminal::size() {
-        let _ = session.get_process_mut().set_window_size(cols, rows);
-    }
+/// The architecture pacman substitutes for `$arch`: pacman.conf's Architecture, or the
+/// machine's when it's unset or "auto".
+fn pacman_arch() -> String {
+    pacman_conf_options()
+        .into_iter()
+        .filter(|(key, _)| key == "Architecture")
+        .filter_map(|(_, value)| value)
+        .flat_map(|value| {
+            value
+                .split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .find(|arch| arch != "auto")
+        .unwrap_or_else(distro::machine_arch)
+}
 
-    session.set_expect_timeout(Some(std::time::Duration::from_millis(100)));
+/// The value of a `key = value` line, if the line sets `key`.
+fn directive<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    line.split_once('=')
+        .filter(|(k, _)| k.trim() == key)
+        .map(|(_, value)| value.trim())
+        .filter(|value| !value.is_empty())
+}
 
-    let mut progress = SyncProgress::new();
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.cyan} Syncing databases: {msg}")
-            .unwrap(),
-    );
-    pb.set_message(progress.format());
-    pb.enable_steady_tick(std::time::Duration::from_millis(80));
+/// The files an Include directive names, with wildcards expanded in sorted order.
+fn include_files(pattern: &str) -> Vec<PathBuf> {
+    match glob::glob(pattern) {
+        Ok(paths) => {
+            let mut files: Vec<PathBuf> = paths.filter_map(Result::ok).collect();
+            files.sort();
+            files
+        }
+        Err(_) => vec![PathBuf::from(pattern)],
+    }
+}
+
+fn mirror_entry(server: &str, repo: &str, arch: &str) -> MirrorEntry {
+    // A server without $repo, e.g. a per-repo override, is the repo's own directory
+    let base_url = match server.split_once("/$repo") {
+        Some((base, _)) => base.to_string(),
+        None => server.replace("$repo", repo),
+    };
+    let url = server.replace("$repo", repo).replace("$arch", arch);
+    MirrorEntry {
+        base_url: base_url
+            .replace("$arch", arch)
+            .trim_end_matches('/')
+            .to_string(),
+        server: server.to_string(),
+        url: url.trim_end_matches('/').to_string(),
+    }
+}
 
-    let mut line_buffer = String::new();
+/// Every repository section of pacman.conf's contents and its servers.
+/// A pacman.conf line without its comment. A `#` starts one at the beginning of the line
+/// or after whitespace, so a Server URL containing one is kept whole.
+fn strip_comment(line: &str) -> &str {
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        if c == '#' && previous.is_whitespace() {
+            return line[..i].trim();
+        }
+        previous = c;
+    }
+    line.trim()
+}
 
-    loop {
-        match session.is_alive() {
-            Ok(true) => {}
-            Ok(false) => {
-                if !line_buffer.is_empty() {
-                    progress.update_from_line(&line_buffer);
-                    pb.set_message(progress.format());
+fn parse_repos(conf: &str, arch: &str, config: &MirrorConfig) -> Vec<Repo> {
+    let mut repos: Vec<Repo> = Vec::new();
+    let mut in_repo = false;
+    for line in conf.lines() {
+        let line = strip_comment(line);
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_repo = name != "options";
+            if in_repo {
+                repos.push(Repo {
+                    name: name.to_string(),
+                    servers: Vec::new(),
+                });
+            }
+            continue;
+        }
+        let Some(repo) = repos.last_mut().filter(|_| in_repo) else {
+            continue;
+        };
+
+        // Servers set in the section itself come before, or instead of, the mirrorlist's
+        if let Some(server) = directive(line, "Server") {
+            let entry = mirror_entry(server, &repo.name, arch);
+            if mirror_allowed(&entry.base_url, None, config) {
+                repo.servers.push(entry);
+            }
+        } else if let Some(pattern) = directive(line, "Include") {
+            for file in include_files(pattern) {
+                if let Ok(contents) = fs::read_to_string(&file) {
+                    let servers = parse_mirrorlist(&contents, &repo.name, arch, config, 1);
+                    repo.servers.extend(servers);
                 }
-                break;
             }
-            Err(_) => break,
         }
+    }
+    repos
+}
 
-        let mut buf = [0u8; 1024];
-        match session.try_read(&mut buf) {
-            Ok(0) => continue,
-            Ok(n) => {
-                let chunk = String::from_utf8_lossy(&buf[..n]);
+/// The servers of a mirrorlist included from `repo`'s section, following nested Includes.
+fn parse_mirrorlist(
+    mirrorlist: &str,
+    repo: &str,
+    arch: &str,
+    config: &MirrorConfig,
+    depth: usize,
+) -> Vec<MirrorEntry> {
+    let mut country: Option<&str> = None;
+    let mut ranked = false;
+    let mut entries = Vec::new();
 
-                for ch in chunk.chars() {
-                    if ch == '\n' || ch == '\r' {
-                        if !line_buffer.is_empty() {
-                            progress.update_from_line(&line_buffer);
-                            pb.set_message(progress.format());
-                        }
-                        line_buffer.clear();
-                    } else {
-                        line_buffer.push(ch);
-                    }
-                }
+    for line in mirrorlist.lines() {
+        let trimmed = line.trim();
+        if trimmed == RANKED_HEADER {
+            ranked = true;
+            country = None;
+            continue;
+        }
+        if let Some(header) = trimmed.strip_prefix("## ") {
+            ranked = false;
+            country = Some(header.trim());
+            continue;
+        }
+        let line = strip_comment(line);
+        if let Some(server) = directive(line, "Server") {
+            let entry = mirror_entry(server, repo, arch);
+            let allowed = if ranked {
+                host_allowed(&entry.base_url, config)
+            } else {
+                mirror_allowed(&entry.base_url, country, config)
+            };
+            if allowed {
+                entries.push(entry);
             }
-            Err(e) => match e.kind() {
-                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted => {
-                    std::thread::sleep(std::time::Duration::from_millis(10));
+        } else if let Some(pattern) = directive(line, "Include")
+            && depth < INCLUDE_DEPTH
+        {
+            for file in include_files(pattern) {
+                if let Ok(contents) = fs::read_to_string(&file) {
+                    entries.extend(parse_mirrorlist(&contents, repo, arch, config, depth + 1));
                 }
-                _ => break,
-            },
+            }
         }
     }
+    entries
+}
 
-    progress.core = DbSyncState::Complete;
-    progress.extra = DbSyncState::Complete;
-    progress.multilib = DbSyncState::Complete;
-    pb.set_message(progress.format());
+pub fn get_mirror_urls(config: &MirrorConfig) -> Vec<String> {
+    get_mirror_entries(config)
+        .into_iter()
+        .map(|entry| entry.base_url)
+        .collect()
+}
 
-    std::thread::sleep(std::time::Duration::from_millis(150));
-    pb.finish_and_clear();
+fn mirror_host(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let authority = without_scheme.split('/').next().unwrap_or("");
+    authority.split(':').next().unwrap_or("")
+}
+
+fn mirror_allowed(url: &str, country: Option<&str>, config: &MirrorConfig) -> bool {
+    if !host_allowed(url, config) {
+        return false;
+    }
+    if config.countries.is_empty() {
+        return true;
+    }
+    country.is_some_and(|c| {
+        config
+            .countries
+            .iter()
+            .any(|want| want.eq_ignore_ascii_case(c))
+    })
+}
+
+/// The config's scheme and domain filters, leaving out the countries.
+fn host_allowed(url: &str, config: &MirrorConfig) -> bool {
+    if config.https_only && !url.starts_with("https://") {
+        return false;
+    }
+
+    let host = mirror_host(url).to_ascii_lowercase();
+    !config.exclude.iter().any(|domain| {
+        let domain = domain.to_ascii_lowercase();
+        host == domain || host.ends_with(&format!(".{}", domain))
+    })
+}
+
+pub fn get_pacman_version() -> Option<String> {
+    let output = Command::new("pacman").arg("--version").output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    for line in stdout.lines() {
+        if line.contains("Pacman v") && line.contains("libalpm v") {
+            if let Some(version_start) = line.find("Pacman v") {
+                let version_str = &line[version_start..];
+                return Some(version_str.trim().to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Hours from `then` to `now`; a time ahead of `now` counts as just now.
+fn age_hours(now: i64, then: i64) -> f64 {
+    ((now - then) as f64 / 3600.0).max(0.0)
+}
+
+/// Hours before `now` the mirror last synced, from its lastsync (or on Manjaro, state) file.
+fn check_mirror_sync(mirror_url: &str, now: i64) -> Option<f64> {
+    Some(age_hours(now, mirror_sync_time(mirror_url)?))
+}
+
+fn mirror_sync_time(mirror_url: &str) -> Option<i64> {
+    remote_sync_time(mirror_url, Distro::detect().sync_file())
+}
+
+/// Files that hold a mirror's sync time; any other file counts by its modification time.
+const SYNC_FILES: [&str; 2] = ["lastsync", "state"];
+
+/// When the file `name` under `url` last changed, by the URL's scheme: local and NFS repos
+/// are read directly and rsync mirrors are listed with rsync, everything else goes over
+/// HTTP.
+fn remote_sync_time(url: &str, name: &str) -> Option<i64> {
+    let file_url = format!("{}/{}", url, name);
+    if let Some(path) = url.strip_prefix("file://") {
+        local_sync_time(Path::new(path), name)
+    } else if url.starts_with("rsync://") {
+        rsync_sync_time(&file_url)
+    } else if SYNC_FILES.contains(&name) {
+        http_sync_file(&file_url, name)
+    } else {
+        http_last_modified(&file_url)
+    }
+}
+
+/// A local file's time; a plain repo directory without the file counts by its own
+/// modification time.
+fn local_sync_time(dir: &Path, name: &str) -> Option<i64> {
+    let path = dir.join(name);
+    if SYNC_FILES.contains(&name)
+        && let Ok(contents) = fs::read_to_string(&path)
+        && let Some(timestamp) = distro::parse_sync_file(name, &contents)
+    {
+        return Some(timestamp);
+    }
+
+    let target = if path.exists() { path.as_path() } else { dir };
+    let modified = fs::metadata(target).ok()?.modified().ok()?;
+    let secs = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some(secs as i64)
+}
+
+/// A file's modification time from an rsync dry run that only lists it.
+fn rsync_sync_time(file_url: &str) -> Option<i64> {
+    if !util::command_exists("rsync") {
+        return None;
+    }
+    let output = Command::new("rsync")
+        .args([
+            "--dry-run",
+            "--list-only",
+            "--no-motd",
+            "--contimeout=5",
+            "--timeout=5",
+        ])
+        .arg(file_url)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // e.g. "-rw-r--r--             11 2024/06/01 12:00:05 lastsync", in local time
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let fields: Vec<&str> = stdout.lines().next()?.split_whitespace().collect();
+    let (date, time) = (fields.get(2)?, fields.get(3)?);
+    let naive =
+        chrono::NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y/%m/%d %H:%M:%S")
+            .ok()?;
+    Some(naive.and_local_timezone(Local).earliest()?.timestamp())
+}
+
+fn http_sync_file(file_url: &str, name: &str) -> Option<i64> {
+    let response = util::http_client()?.get(file_url).send().ok()?;
+
+    if !response.status().is_success() {
+        return None;
+    }
+
+    distro::parse_sync_file(name, &response.text().ok()?)
+}
+
+/// The Last-Modified header of a HEAD request, for repos without the Arch lastsync layout.
+fn http_last_modified(file_url: &str) -> Option<i64> {
+    let response = util::http_client()?.head(file_url).send().ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let header = response
+        .headers()
+        .get(reqwest::header::LAST_MODIFIED)?
+        .to_str()
+        .ok()?;
+    DateTime::parse_from_rfc2822(header)
+        .ok()
+        .map(|t| t.timestamp())
+}
+
+/// Clock differences under this are request latency and the Date header's whole seconds.
+pub const CLOCK_SKEW_TOLERANCE_SECS: i64 = 120;
+
+/// How far the local clock at `local` is ahead of a server's at `server` (negative when
+/// it's behind), or None when they agree within the tolerance.
+fn significant_skew(local: i64, server: i64) -> Option<i64> {
+    let skew = local - server;
+    (skew.abs() > CLOCK_SKEW_TOLERANCE_SECS).then_some(skew)
+}
+
+/// The local clock's skew against `url`'s server, from the Date header of a HEAD request.
+/// It only needs headers, so a slow server gives up sooner than the sync checks do.
+fn clock_skew_secs(url: &str) -> Option<i64> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(2))
+        .build()
+        .ok()?;
+    let response = client.head(url).send().ok()?;
+    let local = Local::now().timestamp();
+    let header = response
+        .headers()
+        .get(reqwest::header::DATE)?
+        .to_str()
+        .ok()?;
+    let server = DateTime::parse_from_rfc2822(header).ok()?.timestamp();
+    significant_skew(local, server)
+}
+
+/// Arch's own repos, whose mirrors the lastsync check covers.
+const OFFICIAL_REPOS: [&str; 8] = [
+    "core",
+    "extra",
+    "multilib",
+    "core-testing",
+    "extra-testing",
+    "multilib-testing",
+    "gnome-unstable",
+    "kde-unstable",
+];
+
+/// Check every custom repo in pacman.conf by its database file: the first server that
+/// has it gives the repo's sync time, None when none answered.
+fn get_repo_sync_times(config: &MirrorConfig) -> Vec<(String, Option<i64>)> {
+    let contents = fs::read_to_string(PACMAN_CONF_PATH).unwrap_or_default();
+    let handles: Vec<_> = parse_repos(&contents, &pacman_arch(), config)
+        .into_iter()
+        .filter(|repo| !OFFICIAL_REPOS.contains(&repo.name.as_str()))
+        .map(|repo| {
+            std::thread::spawn(move || {
+                let db = format!("{}.db", repo.name);
+                let synced = repo
+                    .servers
+                    .iter()
+                    .take(MIRROR_FALLBACK_LIMIT)
+                    .find_map(|server| remote_sync_time(&server.url, &db));
+                (repo.name, synced)
+            })
+        })
+        .collect();
+
+    handles.into_iter().filter_map(|h| h.join().ok()).collect()
+}
+
+/// How far the branch snapshot the mirrors serve is behind Arch's repos: the time between
+/// the snapshot, dated by a mirror's state file, and Arch's last update. Both times come
+/// from servers, so the local clock doesn't come into it.
+fn get_branch_lag_hours(config: &MirrorConfig) -> Option<f64> {
+    let snapshot = get_mirror_urls(config)
+        .iter()
+        .take(MIRROR_FALLBACK_LIMIT)
+        .find_map(|url| remote_sync_time(url, "state"))?;
+    let arch = http_sync_file(ARCH_LASTUPDATE_URL, "lastupdate")?;
+    Some(age_hours(arch, snapshot))
+}
+
+/// Checks candidates in order until one answers, so a dead first mirror doesn't fail the stat.
+/// Gives the mirror and when it synced.
+fn check_mirror_sync_with_fallback(candidates: &[String]) -> Option<(String, i64)> {
+    candidates
+        .iter()
+        .take(MIRROR_FALLBACK_LIMIT)
+        .find_map(|url| mirror_sync_time(url).map(|synced| (url.clone(), synced)))
+}
+
+fn filter_upgrade_line(line: &str) -> bool {
+    let clean = util::strip_ansi(line);
+    let trimmed = clean.trim();
+
+    if trimmed.is_empty() {
+        return false;
+    }
+
+    if trimmed.contains("Total Download Size:")
+        || trimmed.contains("Total Installed Size:")
+        || trimmed.contains("Net Upgrade Size:")
+    {
+        return false;
+    }
+
+    if trimmed.contains("resolving dependencies")
+        || trimmed.contains("looking for conflicting packages")
+        || trimmed.contains(":: Starting full system upgrade...")
+    {
+        return false;
+    }
+
+    true
+}
+
+/// Decides which lines of pacman's output are shown: the configured allow and deny
+/// patterns first, then the built-in rules of `filter_upgrade_line`.
+struct LineFilter {
+    enabled: bool,
+    allow: Vec<Regex>,
+    deny: Vec<Regex>,
+}
+
+impl Default for LineFilter {
+    fn default() -> Self {
+        LineFilter {
+            enabled: true,
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+}
+
+impl LineFilter {
+    /// Shows every line untouched.
+    fn none() -> Self {
+        LineFilter {
+            enabled: false,
+            ..LineFilter::default()
+        }
+    }
+
+    fn from_config(config: &FilterConfig) -> Result<Self, String> {
+        let compile = |patterns: &[String]| -> Result<Vec<Regex>, String> {
+            patterns
+                .iter()
+                .map(|p| {
+                    Regex::new(p).map_err(|e| format!("invalid filter pattern '{}': {}", p, e))
+                })
+                .collect()
+        };
+        Ok(LineFilter {
+            enabled: config.enabled,
+            allow: compile(&config.allow)?,
+            deny: compile(&config.deny)?,
+        })
+    }
+
+    fn shows(&self, line: &str) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let clean = util::strip_ansi(line);
+        if self.allow.iter().any(|re| re.is_match(&clean)) {
+            return true;
+        }
+        if self.deny.iter().any(|re| re.is_match(&clean)) {
+            return false;
+        }
+        filter_upgrade_line(line)
+    }
+}
+
+/// Prints pacman's output as-is (optionally filtered) and answers prompts from stdin.
+struct PlainOutput {
+    filter: LineFilter,
+}
+
+impl PtyHandler for PlainOutput {
+    fn line(&mut self, line: &str) {
+        if self.filter.shows(line) {
+            println!("{}", line);
+        }
+    }
+
+    fn redraw(&mut self, line: &str) {
+        if self.filter.shows(line) {
+            print!("\r{}", line);
+            let _ = std::io::stdout().flush();
+        }
+    }
+
+    fn prompt(&mut self, prompt: &str) -> Option<String> {
+        if self.filter.shows(prompt) {
+            if prompt.contains("Proceed with installation") {
+                println!("\n\n");
+            }
+            print!("{}", prompt);
+            let _ = std::io::stdout().flush();
+        }
+
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input).ok()?;
+        Some(input)
+    }
+}
+
+/// Reports pacman's progress as plain status lines with percentages instead of redrawn bars.
+#[derive(Default)]
+struct AccessibleOutput {
+    filter: LineFilter,
+    parser: ProgressParser,
+    phase: Option<Phase>,
+    item: Option<String>,
+    percent: Option<u32>,
+}
+
+impl AccessibleOutput {
+    /// Returns true when the line was progress and has been reported.
+    fn report(&mut self, line: &str) -> bool {
+        let Some(update) = self.parser.feed(line) else {
+            return false;
+        };
+
+        let mut changed = self.phase != Some(update.phase);
+        if changed {
+            self.phase = Some(update.phase);
+            self.item = None;
+            self.percent = None;
+        }
+        if let Some(item) = update.item
+            && self.item.as_ref() != Some(&item)
+        {
+            self.item = Some(item);
+            changed = true;
+        }
+        if let Some(ratio) = update.ratio {
+            // Report in steps of 10% so redrawn bars don't flood the reader
+            let percent = ((ratio * 10.0).floor() * 10.0) as u32;
+            if self.percent != Some(percent) {
+                self.percent = Some(percent);
+                changed = true;
+            }
+        }
+
+        if changed {
+            let mut status = update.phase.label().to_string();
+            if let Some(percent) = self.percent {
+                status.push_str(&format!(": {}%", percent));
+            }
+            if let Some(item) = &self.item {
+                status.push_str(&format!(", {}", item));
+            }
+            println!("{}", status);
+        }
+        true
+    }
+}
+
+impl PtyHandler for AccessibleOutput {
+    fn line(&mut self, line: &str) {
+        if !self.report(line) && self.filter.shows(line) {
+            println!("{}", util::strip_ansi(line).trim_end());
+        }
+    }
+
+    fn redraw(&mut self, line: &str) {
+        self.report(line);
+    }
+
+    fn prompt(&mut self, prompt: &str) -> Option<String> {
+        print!("{}", util::strip_ansi(prompt));
+        let _ = std::io::stdout().flush();
+
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input).ok()?;
+        Some(input)
+    }
+}
+
+/// Wraps another handler to time pacman's phases for the upgrade history.
+struct Timed<'a> {
+    timer: PhaseTimer,
+    inner: &'a mut dyn PtyHandler,
+}
+
+impl PtyHandler for Timed<'_> {
+    fn line(&mut self, line: &str) {
+        self.timer.observe(line);
+        self.inner.line(line);
+    }
+
+    fn redraw(&mut self, line: &str) {
+        self.timer.observe(line);
+        self.inner.redraw(line);
+    }
+
+    fn prompt(&mut self, prompt: &str) -> Option<String> {
+        self.inner.prompt(prompt)
+    }
+
+    fn idle(&mut self) -> Option<String> {
+        self.inner.idle()
+    }
+}
+
+/// Wraps another handler to also report progress as JSON lines on stderr
+/// (`--progress-json`), leaving the human output on stdout untouched.
+struct JsonEvents<'a> {
+    parser: ProgressParser,
+    last: Option<(Phase, Option<String>, Option<u32>)>,
+    inner: &'a mut dyn PtyHandler,
+}
+
+impl JsonEvents<'_> {
+    fn observe(&mut self, line: &str) {
+        let Some(update) = self.parser.feed(line) else {
+            return;
+        };
+        let percent = update.ratio.map(|r| (r * 100.0).round() as u32);
+        let current = (update.phase, update.item, percent);
+        if self.last.as_ref() == Some(&current) {
+            return;
+        }
+
+        let (phase, item, percent) = &current;
+        let event = serde_json::json!({
+            "event": "progress",
+            "phase": phase,
+            "package": item,
+            "percent": percent,
+        });
+        eprintln!("{}", event);
+        self.last = Some(current);
+    }
+}
+
+impl PtyHandler for JsonEvents<'_> {
+    fn line(&mut self, line: &str) {
+        self.observe(line);
+        self.inner.line(line);
+    }
+
+    fn redraw(&mut self, line: &str) {
+        self.observe(line);
+        self.inner.redraw(line);
+    }
+
+    fn prompt(&mut self, prompt: &str) -> Option<String> {
+        self.inner.prompt(prompt)
+    }
+
+    fn idle(&mut self) -> Option<String> {
+        self.inner.idle()
+    }
+}
+
+/// Shows a recorded session the way the plain upgrade output would, echoing prompts
+/// instead of answering them.
+struct ReplayOutput {
+    plain: PlainOutput,
+}
+
+impl PtyHandler for ReplayOutput {
+    fn line(&mut self, line: &str) {
+        self.plain.line(line);
+    }
+
+    fn redraw(&mut self, line: &str) {
+        self.plain.redraw(line);
+    }
+
+    fn prompt(&mut self, prompt: &str) -> Option<String> {
+        if self.plain.filter.shows(prompt) {
+            println!("{}", prompt);
+        }
+        None
+    }
+}
+
+/// Replay the sessions saved by `--record` through the configured output filter.
+pub fn replay_sessions(config: &Config, path: &Path) -> Result<(), String> {
+    let mut output = ReplayOutput {
+        plain: PlainOutput {
+            filter: LineFilter::from_config(&config.filter)?,
+        },
+    };
+    for recording in pty::load_recordings(path)? {
+        println!(":: {}", recording.command);
+        pty::replay(&recording, &mut output);
+    }
+    Ok(())
+}
+
+/// Run a command with inherited stdio, leaving prompts and output to the command itself.
+pub fn run_direct(cmd: &str, args: &[&str]) -> Result<(), String> {
+    let status = Command::new(cmd)
+        .args(args)
+        .status()
+        .map_err(|e| format!("Failed to run {}: {}", cmd, e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}", cmd, status))
+    }
+}
+
+fn run_pacman_pty(args: &[&str], handler: &mut dyn PtyHandler) -> Result<UpgradeTimings, String> {
+    // Progress events need pacman's output, so they keep the pty even in plain mode
+    if util::is_plain() && !util::progress_json() {
+        return run_direct("pacman", args).map(|_| UpgradeTimings::default());
+    }
+
+    let mut events;
+    let handler: &mut dyn PtyHandler = if util::progress_json() {
+        events = JsonEvents {
+            parser: ProgressParser::default(),
+            last: None,
+            inner: handler,
+        };
+        &mut events
+    } else {
+        handler
+    };
+    let mut timed = Timed {
+        timer: PhaseTimer::default(),
+        inner: handler,
+    };
+    let result = pty::run(&format!("pacman {}", args.join(" ")), &mut timed);
+
+    print!("\x1b[0m");
+    let _ = std::io::stdout().flush();
+    if util::progress_json() {
+        let event = serde_json::json!({ "event": "done", "success": result == Ok(true) });
+        eprintln!("{}", event);
+    }
+
+    result.map(|success| timed.timer.finish(success))
+}
+
+/// Feeds `pacman -Sy` output into the per-database progress spinner.
+struct SyncOutput {
+    progress: SyncProgress,
+    pb: ProgressBar,
+}
+
+impl PtyHandler for SyncOutput {
+    fn line(&mut self, line: &str) {
+        if !line.is_empty() {
+            self.progress.update_from_line(line);
+            self.pb.set_message(self.progress.format());
+        }
+    }
+
+    fn redraw(&mut self, line: &str) {
+        self.line(line);
+    }
+
+    fn prompt(&mut self, _prompt: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Sync the package databases, or with `files` the files databases `pacman -F` uses.
+fn run_pacman_sync(files: bool) -> Result<(), String> {
+    if !util::is_root() {
+        return Err("you cannot perform this operation unless you are root.".to_string());
+    }
+
+    let (flag, what, done) = if files {
+        ("-Fy", "file databases", "File databases synced")
+    } else {
+        ("-Sy", "databases", "Databases synced")
+    };
+    if util::is_plain() {
+        return run_direct("pacman", &[flag]);
+    }
+
+    let command = format!("pacman {}", flag);
+    if util::is_accessible() {
+        println!("Syncing {}...", what);
+        pty::run(&command, &mut AccessibleOutput::default())?;
+        println!("{}", done);
+        return Ok(());
+    }
+
+    let progress = SyncProgress::new();
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(util::spinner_style(&format!(
+        "{{spinner:.cyan}} Syncing {}: {{msg}}",
+        what
+    )));
+    pb.set_message(progress.format());
+    pb.enable_steady_tick(std::time::Duration::from_millis(80));
+
+    let mut output = SyncOutput { progress, pb };
+    pty::run(&command, &mut output)?;
+
+    let SyncOutput { mut progress, pb } = output;
+    progress.core = DbSyncState::Complete;
+    progress.extra = DbSyncState::Complete;
+    progress.multilib = DbSyncState::Complete;
+    pb.set_message(progress.format());
+
+    std::thread::sleep(std::time::Duration::from_millis(150));
+    pb.finish_and_clear();
+
+    Ok(())
+}
+
+// --- Public API ---
+
+pub fn sync_databases() -> Result<(), String> {
+    let _lock = lock::exclusive()?;
+    run_pacman_sync(false)?;
+    index::refresh();
+    Ok(())
+}
+
+pub fn sync_files_databases() -> Result<(), String> {
+    let _lock = lock::exclusive()?;
+    run_pacman_sync(true)
+}
+
+/// Split updates into ones built at least `min_age_days` ago and ones too fresh to install
+/// yet under `upgrade.min_age_days`.
+pub fn split_by_age(
+    updates: Vec<PendingUpdate>,
+    min_age_days: u32,
+    now: i64,
+) -> (Vec<PendingUpdate>, Vec<PendingUpdate>) {
+    let cutoff = now - i64::from(min_age_days) * 86400;
+    updates.into_iter().partition(|u| u.build_date <= cutoff)
+}
+
+/// The repo `update`'s package is pinned to under `upgrade.pin`, when the update comes
+/// from a different one.
+fn pin_conflict<'a>(update: &PendingUpdate, pins: &'a HashMap<String, String>) -> Option<&'a str> {
+    pins.get(&update.name)
+        .map(String::as_str)
+        .filter(|repo| *repo != update.repo)
+}
+
+/// Drop the holds whose condition `updates` or the clock now meets, then return what the
+/// ignore list still holds back.
+fn release_holds(updates: &[PendingUpdate], now: i64) -> Vec<String> {
+    let mut list = history::load_ignore_list();
+    let (released, kept): (Vec<_>, Vec<_>) = list.hold.into_iter().partition(|hold| {
+        let pending = updates.iter().find(|u| u.name == hold.name);
+        hold.is_released(pending.map(|u| u.new_version.as_str()), now)
+    });
+    list.hold = kept;
+
+    if !released.is_empty() {
+        for hold in &released {
+            println!("Released the hold on {}", hold.name);
+        }
+        if let Err(e) = history::save_ignore_list(&list) {
+            eprintln!("warning: {}", e);
+        }
+    }
+    list.names()
+}
+
+pub fn upgrade_system(
+    config: &Config,
+    debug: bool,
+    sync_first: bool,
+    ignore: &[String],
+) -> Result<(), String> {
+    if !util::is_root() {
+        return Err("you cannot perform this operation unless you are root.".to_string());
+    }
+    let _lock = lock::exclusive()?;
+
+    let mut config = config.clone();
+    if !config.display.stats.contains(&StatId::UpgradeEta) {
+        config.display.stats.push(StatId::UpgradeEta);
+    }
+
+    if sync_first {
+        run_pacman_sync(false)?;
+        index::refresh();
+    }
+    let spinner = if debug {
+        None
+    } else {
+        Some(util::create_spinner("Gathering stats"))
+    };
+    if !config.display.stats.contains(&StatId::PendingHooks) {
+        config.display.stats.push(StatId::PendingHooks);
+    }
+    // Also what the rebuild check after the upgrade looks at
+    let foreign = get_foreign_packages();
+    if !config.display.stats.contains(&StatId::AurUpdates) && !foreign.is_empty() {
+        config.display.stats.push(StatId::AurUpdates);
+    }
+    if let Some(template) = &config.display.upgrade_template {
+        for stat_id in crate::stats::template_stats(template) {
+            if !config.display.stats.contains(&stat_id) {
+                config.display.stats.push(stat_id);
+            }
+        }
+    }
+    let stats = get_stats(&config, debug, spinner.as_ref());
+    // The one transaction every check below works from
+    let Sysupgrade { updates, removals } = read_sysupgrade();
+    if let Some(s) = spinner {
+        s.finish_and_clear();
+    }
+
+    // Packages held back with `pacfetch ignore` on top of the ones given for this run
+    let mut ignore = ignore.to_vec();
+    for name in release_holds(&updates, Local::now().timestamp()) {
+        if !ignore.contains(&name) {
+            ignore.push(name);
+        }
+    }
+    let (updates, too_fresh) = match config.upgrade.min_age_days {
+        Some(days) => split_by_age(updates, days, Local::now().timestamp()),
+        None => (updates, Vec::new()),
+    };
+    ignore.extend(too_fresh.iter().map(|u| u.name.clone()));
+    for update in &updates {
+        if let Some(pinned) = pin_conflict(update, &config.upgrade.pin) {
+            println!(
+                "Holding back {}: pinned to {}, but the update is from {}",
+                update.name, pinned, update.repo
+            );
+            ignore.push(update.name.clone());
+        }
+    }
+
+    // --critical-only and --security-only hold back everything they don't pick
+    let selected = match config.upgrade.scope {
+        UpgradeScope::All => None,
+        UpgradeScope::Critical => Some(
+            updates
+                .iter()
+                .filter(|u| {
+                    config
+                        .upgrade
+                        .critical
+                        .iter()
+                        .any(|pattern| util::glob_match(pattern, &u.name))
+                })
+                .map(|u| u.name.clone())
+                .collect::<Vec<_>>(),
+        ),
+        UpgradeScope::Security => Some(get_vulnerable_upgrades()?),
+    };
+    if let Some(selected) = &selected {
+        if !updates.iter().any(|u| selected.contains(&u.name)) {
+            println!("No matching updates to install.");
+            return Ok(());
+        }
+        ignore.extend(
+            updates
+                .iter()
+                .filter(|u| !selected.contains(&u.name))
+                .map(|u| u.name.clone()),
+        );
+    }
+    let is_ignored = |name: &str| ignore.iter().any(|pattern| util::glob_match(pattern, name));
+    let pending: Vec<PendingUpdate> = updates
+        .into_iter()
+        .filter(|u| !is_ignored(&u.name))
+        .collect();
+
+    let display = pacman_display();
+    if let Some(template) = &config.display.upgrade_template {
+        crate::ui::display_upgrade_template(
+            template,
+            &stats,
+            &config,
+            &pending,
+            display.verbose_pkg_lists,
+        );
+    } else if debug || !matches!(util::output_mode(), util::OutputMode::Graphics) {
+        crate::ui::display_stats(&stats, &config, None);
+        println!();
+    } else {
+        if let Err(e) = crate::ui::display_stats_with_graphics(&stats, &config, None) {
+            eprintln!("error: {}", e);
+            crate::ui::display_stats(&stats, &config, None);
+            println!();
+        }
+    }
+    if !too_fresh.is_empty() {
+        crate::ui::display_too_fresh(&too_fresh, Local::now().timestamp());
+    }
+
+    // Caught here, the upgrade stops before downloading rather than at pacman's own check.
+    // Never having synced them is a choice, so that's pointed out on one upgrade only
+    if let Some(warning) = files_db_warning()
+        && (files_dbs_synced() || note_once(FILES_DB_NOTED_FILE))
+    {
+        eprintln!("warning: {}", warning);
+    }
+    let conflicts = get_file_conflicts(&pending, &removals);
+    if !conflicts.is_empty() {
+        crate::ui::display_file_conflicts(&conflicts);
+        if files_dbs_current() {
+            return Err("the upgrade would stop on these file conflicts".to_string());
+        }
+        // File lists older than the packages may name files the new versions don't ship
+        eprintln!(
+            "warning: the files databases are older than the package databases, so these \
+             may be out of date; run pacfetch sync --files"
+        );
+    }
+
+    // Taken before pacman downloads anything, so only what was cached already counts
+    let cache_savings = CacheSavings::of(&pending);
+    let boot_packages = boot_packages(&pending);
+    if !boot_packages.is_empty() {
+        checks::check_boot_ready(&boot_packages)?;
+    }
+
+    let ignore_list = ignore.join(",");
+    let mut args = vec!["-Su"];
+    if !ignore.is_empty() {
+        args.extend(["--ignore", ignore_list.as_str()]);
+    }
+
+    let mut plain = PlainOutput {
+        filter: LineFilter::from_config(&config.filter)?,
+    };
+    let timings = if debug || util::is_plain() {
+        run_pacman_pty(&args, &mut plain)?
+    } else if util::is_accessible() {
+        let mut accessible = AccessibleOutput {
+            filter: LineFilter::from_config(&config.filter)?,
+            ..AccessibleOutput::default()
+        };
+        run_pacman_pty(&args, &mut accessible)?
+    } else {
+        let mut screen = crate::ui::UpgradeScreen::new(&mut plain, sync_first, display.candy);
+        let timings = run_pacman_pty(&args, &mut screen);
+        screen.finish();
+        timings?
+    };
+
+    // A failed transaction's timings say nothing about how long upgrades take
+    if timings.succeeded
+        && let Some(install) = timings.install
+    {
+        let record = history::UpgradeRecord {
+            timestamp: Local::now().timestamp(),
+            download_mb: stats.download_size_mb.unwrap_or(0.0),
+            installed_mb: stats.total_installed_size_mb.unwrap_or(0.0),
+            download_secs: timings.download.map(|d| d.as_secs_f64()).unwrap_or(0.0),
+            install_secs: install.as_secs_f64(),
+            cached_mb: cache_savings.bytes as f64 / 1048576.0,
+        };
+        if let Err(e) = history::record_upgrade(record) {
+            eprintln!("warning: {}", e);
+        }
+        if cache_savings.bytes > 0 {
+            crate::ui::display_cache_savings(&cache_savings, history::total_cache_savings());
+        }
+    }
+
+    let slow_hooks: Vec<String> = timings
+        .hooks
+        .iter()
+        .filter(|(_, elapsed)| elapsed.as_secs_f64() >= SLOW_HOOK_SECS)
+        .map(|(description, elapsed)| format!("{} ({:.1}s)", description, elapsed.as_secs_f64()))
+        .collect();
+    if !slow_hooks.is_empty() {
+        println!("Slow hooks:");
+        for hook in &slow_hooks {
+            println!("  {}", hook);
+        }
+    }
+
+    // The checks below look at what changed, so they wait on pacman having changed something
+    let upgraded = applied_updates(&pending);
+    if upgraded.is_empty() {
+        return Ok(());
+    }
+
+    if pending.iter().any(|u| u.kernel) {
+        checks::report("Kernel modules", &checks::check_kernel_modules());
+        checks::report("Boot", &checks::check_boot_entries());
+    }
+
+    let rebuilds = get_rebuild_candidates(&foreign, Some(&upgraded));
+    if !rebuilds.is_empty() {
+        println!("Rebuild recommended:");
+        for candidate in &rebuilds {
+            println!("  {} ({})", candidate.name, candidate.reason);
+        }
+    }
+
+    checks::offer_service_restarts(&checks::find_stale_processes());
+    checks::advise(
+        "Session restart advised:",
+        &checks::session_advice(&upgraded),
+    );
+
+    Ok(())
+}
+
+/// The pending updates that are now installed. pacman's exit status doesn't make it
+/// through the pty, and a failed or declined transaction leaves the old versions.
+fn applied_updates(pending: &[PendingUpdate]) -> Vec<String> {
+    let Ok(alpm) = Alpm::new("/", "/var/lib/pacman") else {
+        return Vec::new();
+    };
+    let localdb = alpm.localdb();
+    pending
+        .iter()
+        .filter(|u| {
+            localdb
+                .pkg(u.name.as_str())
+                .is_ok_and(|pkg| pkg.version().as_str() == u.new_version)
+        })
+        .map(|u| u.name.clone())
+        .collect()
+}
+
+/// Builds in the AUR helpers' caches that aren't `recorded` yet. makepkg stamps a package
+/// with the time its build started, and writing the package file is the last thing it
+/// does, so the file's mtime is when it finished.
+fn get_finished_builds(recorded: &[history::BuildRecord]) -> Vec<history::BuildRecord> {
+    let Ok(alpm) = Alpm::new("/", "/var/lib/pacman") else {
+        return Vec::new();
+    };
+    cache::built_packages()
+        .into_iter()
+        .filter_map(|package| {
+            let finished = fs::metadata(&package.path)
+                .and_then(|m| m.modified())
+                .ok()?
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()?
+                .as_secs() as i64;
+            if recorded.iter().any(|r| {
+                r.package == package.name && r.version == package.version && r.timestamp == finished
+            }) {
+                return None;
+            }
+            let started = alpm
+                .pkg_load(
+                    package.path.to_string_lossy().as_bytes(),
+                    false,
+                    alpm::SigLevel::NONE,
+                )
+                .ok()?
+                .build_date();
+            Some(history::BuildRecord {
+                timestamp: finished,
+                package: package.name,
+                version: package.version,
+                build_secs: build_secs(started, finished)?,
+            })
+        })
+        .collect()
+}
+
+/// Seconds from a package's build date to its file being written. Packages built with a
+/// fixed SOURCE_DATE_EPOCH carry an older date, which doesn't time anything.
+fn build_secs(started: i64, finished: i64) -> Option<f64> {
+    let secs = finished - started;
+    (secs > 0 && secs <= MAX_BUILD_SECS).then_some(secs as f64)
+}
+
+/// Why an orphan might still be wanted: something optionally depends on it through a
+/// provide, it ships a running service, or one of its programs is running.
+fn orphan_wanted_reason(
+    alpm: &Alpm,
+    pkg: &alpm::Package,
+    services: &[String],
+    commands: &[String],
+) -> Option<String> {
+    let optional_for: Vec<&str> = alpm
+        .localdb()
+        .pkgs()
+        .into_iter()
+        .filter(|other| {
+            other
+                .optdepends()
+                .into_iter()
+                .any(|dep| satisfies(pkg, dep.name()))
+        })
+        .map(|other| other.name())
+        .collect();
+    if !optional_for.is_empty() {
+        return Some(format!("optional for {}", optional_for.join(", ")));
+    }
+
+    let files: Vec<&str> = pkg
+        .files()
+        .files()
+        .iter()
+        .filter_map(|f| std::str::from_utf8(f.name()).ok())
+        .collect();
+    if let Some(service) = services
+        .iter()
+        .find(|s| files.contains(&format!("usr/lib/systemd/system/{}", s).as_str()))
+    {
+        return Some(format!("provides running {}", service));
+    }
+    // comm is cut to 15 characters, so compare against the truncated binary name
+    let running = files
+        .iter()
+        .filter_map(|f| f.strip_prefix("usr/bin/"))
+        .filter(|bin| !bin.is_empty())
+        .find(|bin| {
+            let short: String = bin.chars().take(15).collect();
+            commands.contains(&short)
+        });
+    running.map(|bin| format!("{} is running", bin))
+}
+
+/// Split orphans into ones that are safe to remove and possibly wanted ones with a reason.
+pub fn classify_orphans() -> (Vec<String>, Vec<(String, String)>) {
+    let Ok(alpm) = Alpm::new("/", "/var/lib/pacman") else {
+        return (Vec::new(), Vec::new());
+    };
+    let services = checks::running_services();
+    let commands = checks::running_commands();
+
+    let mut safe = Vec::new();
+    let mut wanted = Vec::new();
+    for pkg in alpm
+        .localdb()
+        .pkgs()
+        .into_iter()
+        .filter(|pkg| is_orphan(pkg))
+    {
+        match orphan_wanted_reason(&alpm, pkg, &services, &commands) {
+            Some(reason) => wanted.push((pkg.name().to_string(), reason)),
+            None => safe.push(pkg.name().to_string()),
+        }
+    }
+    (safe, wanted)
+}
+
+/// Remove the orphans that are safe to remove, letting pacman list them and ask for
+/// confirmation. Possibly wanted ones are listed and kept.
+pub fn remove_orphans() -> Result<(), String> {
+    if !util::is_root() {
+        return Err("you cannot perform this operation unless you are root.".to_string());
+    }
+    let _lock = lock::exclusive()?;
+
+    let (names, wanted) = classify_orphans();
+    if !wanted.is_empty() {
+        println!("Keeping possibly wanted orphans:");
+        for (name, reason) in &wanted {
+            println!("  {} ({})", name, reason);
+        }
+        println!("Remove them yourself with `pacman -Rns <name>` if they aren't needed.");
+    }
+    if names.is_empty() {
+        if wanted.is_empty() {
+            println!("No orphaned packages to remove.");
+        } else {
+            println!("No orphaned packages are safe to remove.");
+        }
+        return Ok(());
+    }
+    remove_packages(&names)
+}
+
+/// Remove packages and the dependencies only they needed, e.g. the orphans picked from
+/// `pacfetch orphans --pick`.
+pub fn remove_packages(names: &[String]) -> Result<(), String> {
+    if !util::is_root() {
+        return Err("you cannot perform this operation unless you are root.".to_string());
+    }
+    let _lock = lock::exclusive()?;
+
+    let mut args = vec!["-Rns"];
+    args.extend(names.iter().map(String::as_str));
+    run_pacman_pty(
+        &args,
+        &mut PlainOutput {
+            filter: LineFilter::none(),
+        },
+    )
+    .map(|_| ())
+}
+
+/// Install cached package files in one transaction, going back to those versions.
+pub fn downgrade(packages: &[&CachedPackage]) -> Result<(), String> {
+    if !util::is_root() {
+        return Err("you cannot perform this operation unless you are root.".to_string());
+    }
+    let _lock = lock::exclusive()?;
+
+    let paths: Vec<String> = packages
+        .iter()
+        .map(|package| package.path.to_string_lossy().into_owned())
+        .collect();
+    let mut args = vec!["-U"];
+    args.extend(paths.iter().map(String::as_str));
+    run_pacman_pty(
+        &args,
+        &mut PlainOutput {
+            filter: LineFilter::none(),
+        },
+    )
+    .map(|_| ())
+}
+
+/// Trim the package cache with paccache when available, otherwise `pacman -Sc`.
+pub fn clean_cache(config: &Config) -> Result<(), String> {
+    if !util::is_root() {
+        return Err("you cannot perform this operation unless you are root.".to_string());
+    }
+    let _lock = lock::exclusive()?;
+
+    let dirs: Vec<String> = cache_dirs(config)
+        .iter()
+        .map(|d| d.to_string_lossy().into_owned())
+        .collect();
+    let (cmd, mut args) = if util::command_exists("paccache") {
+        ("paccache", vec!["-r"])
+    } else {
+        ("pacman", vec!["-Sc"])
+    };
+    for dir in &dirs {
+        args.extend(["--cachedir", dir.as_str()]);
+    }
+    if util::is_plain() {
+        return run_direct(cmd, &args);
+    }
+    let succeeded = pty::run(
+        &format!("{} {}", cmd, args.join(" ")),
+        &mut PlainOutput {
+            filter: LineFilter::none(),
+        },
+    )?;
+    if succeeded {
+        Ok(())
+    } else {
+        Err(format!("{} failed", cmd))
+    }
+}
+
+/// Rank the allowed mirrors by freshness, then response time. Only the mirrorlist's own
+/// servers are ranked, since that's the file the order is saved to.
+pub fn rank_mirrors(config: &MirrorConfig) -> Vec<MirrorRank> {
+    // Mirrors are only compared with each other, so a skewed clock doesn't matter here
+    let now = Local::now().timestamp();
+    let mirrorlist = fs::read_to_string(MIRRORLIST_PATH).unwrap_or_default();
+    // At the deepest level, Includes of other files aren't followed
+    let entries = parse_mirrorlist(&mirrorlist, "core", &pacman_arch(), config, INCLUDE_DEPTH);
+    let handles: Vec<_> = entries
+        .into_iter()
+        .take(MIRROR_RANK_LIMIT)
+        .map(|entry| {
+            std::thread::spawn(move || {
+                let start = Instant::now();
+                check_mirror_sync(&entry.base_url, now).map(|age| MirrorRank {
+                    url: entry.base_url,
+                    latency: start.elapsed(),
+                    sync_age_hours: age,
+                    server: entry.server,
+                })
+            })
+        })
+        .collect();
+
+    let mut ranked: Vec<MirrorRank> = handles
+        .into_iter()
+        .filter_map(|h| h.join().ok().flatten())
+        .collect();
+    ranked.sort_by_key(|m| (m.sync_age_hours > MIRROR_STALE_HOURS, m.latency));
+    ranked
+}
+
+/// Put the ranked mirrors at the top of the mirrorlist, commenting out the old entries.
+/// The previous file is kept as mirrorlist.bak.
+pub fn write_mirrorlist(ranked: &[MirrorRank]) -> Result<(), String> {
+    if !util::is_root() {
+        return Err("you cannot perform this operation unless you are root.".to_string());
+    }
+
+    let previous = fs::read_to_string(MIRRORLIST_PATH)
+        .map_err(|e| format!("Failed to read {}: {}", MIRRORLIST_PATH, e))?;
+    storage::write_atomic(Path::new(&format!("{}.bak", MIRRORLIST_PATH)), &previous)
+        .map_err(|e| format!("Failed to back up mirrorlist: {}", e))?;
+
+    let servers: Vec<&str> = ranked.iter().map(|m| m.server.as_str()).collect();
+    storage::write_atomic(
+        Path::new(MIRRORLIST_PATH),
+        ranked_mirrorlist(&previous, &servers),
+    )
+    .map_err(|e| format!("Failed to write mirrorlist: {}", e))
+}
+
+/// `previous` with `servers` on top under `RANKED_HEADER`, in place of an earlier ranking,
+/// and every other active server commented out.
+fn ranked_mirrorlist(previous: &str, servers: &[&str]) -> String {
+    let mut contents = format!("{}\n", RANKED_HEADER);
+    for server in servers {
+        contents.push_str(&format!("Server = {}\n", server));
+    }
+    contents.push('\n');
+
+    let mut lines = previous.lines().peekable();
+    if lines
+        .peek()
+        .is_some_and(|line| line.trim() == RANKED_HEADER)
+    {
+        // The last ranking runs up to the blank line written after it
+        lines
+            .by_ref()
+            .take_while(|line| !line.trim().is_empty())
+            .count();
+    }
+    for line in lines {
+        if directive(line.trim(), "Server").is_some() {
+            contents.push('#');
+        }
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    contents
+}
+
+fn is_kernel_package(pkg: &alpm::Package) -> bool {
+    pkg.files().files().iter().any(|f| {
+        std::str::from_utf8(f.name())
+            .is_ok_and(|name| name.starts_with("usr/lib/modules/") && name.ends_with("/vmlinuz"))
+    })
+}
+
+/// What the package cache spares the pending upgrade: how many of its packages are
+/// already downloaded, and the bytes those and any partial downloads save.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheSavings {
+    pub packages: u32,
+    pub bytes: i64,
+}
+
+impl CacheSavings {
+    /// The savings on the updates about to be installed.
+    pub fn of(pending: &[PendingUpdate]) -> CacheSavings {
+        let mut savings = CacheSavings::default();
+        for update in pending {
+            if update.download_size == 0 {
+                savings.packages += 1;
+            }
+            savings.bytes += (update.package_size - update.download_size).max(0);
+        }
+        savings
+    }
+}
+
+/// Kernels and boot loaders among the updates, which all write to /boot or the ESP.
+fn boot_packages(updates: &[PendingUpdate]) -> Vec<String> {
+    updates
+        .iter()
+        .filter(|u| u.kernel || BOOTLOADER_PACKAGES.contains(&u.name.as_str()))
+        .map(|u| u.name.clone())
+        .collect()
+}
+
+/// Hooks the pending upgrade will fire, matched against package names and the files
+/// of the installed versions (new packages' file lists aren't known before download).
+fn get_pending_hooks() -> Option<Vec<String>> {
+    let hooks = hooks::load_hooks();
+    with_sysupgrade(|alpm| {
+        let localdb = alpm.localdb();
+        let packages: Vec<(String, Vec<String>)> = alpm
+            .trans_add()
+            .into_iter()
+            .map(|pkg| {
+                let files = localdb
+                    .pkg(pkg.name())
+                    .map(|old| {
+                        old.files()
+                            .files()
+                            .iter()
+                            .filter_map(|f| std::str::from_utf8(f.name()).ok())
+                            .map(str::to_string)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                (pkg.name().to_string(), files)
+            })
+            .collect();
+
+        let mut pending: Vec<&hooks::Hook> = hooks
+            .iter()
+            .filter(|hook| {
+                packages
+                    .iter()
+                    .any(|(name, files)| hook.triggered_by(name, files))
+            })
+            .collect();
+        // In the order pacman runs them
+        pending.sort_by_key(|hook| hook.when == hooks::HookWhen::PostTransaction);
+        pending
+            .iter()
+            .map(|hook| hook.label().to_string())
+            .collect()
+    })
+}
+
+/// Reasons the pending `updates` are a major upgrade: kernel or desktop changes, many
+/// packages, or a large download. Relies on the upgrade stats already being in `stats`.
+fn major_upgrade_reasons(stats: &ManagerStats, updates: &[PendingUpdate]) -> Vec<String> {
+    let mut reasons = Vec::new();
+
+    if updates.iter().any(|u| u.kernel) {
+        reasons.push("kernel".to_string());
+    }
+    let session: Vec<&str> = updates
+        .iter()
+        .map(|u| u.name.as_str())
+        .filter(|name| checks::is_session_package(name))
+        .collect();
+    if !session.is_empty() {
+        reasons.push(session.join(", "));
+    }
+    if let Some(count) = stats.total_upgradable
+        && count >= MAJOR_PACKAGE_COUNT
+    {
+        reasons.push(format!("{} packages", count));
+    }
+    if let Some(mb) = stats.download_size_mb
+        && mb >= MAJOR_DOWNLOAD_MB
+    {
+        reasons.push(format!("{:.0} MiB download", mb));
+    }
 
-    Ok(())
+    reasons
 }
 
-// --- Public API ---
+pub fn get_pending_updates() -> Vec<PendingUpdate> {
+    read_sysupgrade().updates
+}
 
-pub fn sync_databases() -> Result<(), String> {
-    run_pacman_sync()
+fn read_sysupgrade() -> Sysupgrade {
+    with_sysupgrade(|alpm| {
+        let localdb = alpm.localdb();
+        let adds: Vec<&alpm::Package> = alpm.trans_add().into_iter().collect();
+        let new_installs: Vec<&alpm::Package> = adds
+            .iter()
+            .copied()
+            .filter(|pkg| localdb.pkg(pkg.name()).is_err())
+            .collect();
+
+        let mut updates: Vec<PendingUpdate> = adds
+            .iter()
+            .map(|pkg| {
+                let old = localdb.pkg(pkg.name()).ok();
+                let pulled_in = new_installs
+                    .iter()
+                    .filter(|dep_pkg| dep_pkg.name() != pkg.name())
+                    .filter(|dep_pkg| {
+                        pkg.depends()
+                            .into_iter()
+                            .any(|d| satisfies(dep_pkg, d.name()))
+                    })
+                    .map(|dep_pkg| dep_pkg.name().to_string())
+                    .collect();
+
+                let repo = pkg.db().map(|db| db.name().to_string()).unwrap_or_default();
+                let previous_repo = old.and_then(|old| {
+                    let carried: Vec<(String, String, i64)> = alpm
+                        .syncdbs()
+                        .iter()
+                        .filter_map(|db| {
+                            let synced = db.pkg(pkg.name()).ok()?;
+                            Some((
+                                db.name().to_string(),
+                                synced.version().to_string(),
+                                synced.build_date(),
+                            ))
+                        })
+                        .collect();
+                    previous_repo(&repo, old.version().as_str(), old.build_date(), &carried)
+                });
+
+                PendingUpdate {
+                    name: pkg.name().to_string(),
+                    old_version: old.map(|o| o.version().to_string()),
+                    new_version: pkg.version().to_string(),
+                    repo,
+                    description: pkg.desc().map(str::to_string),
+                    url: pkg.url().map(str::to_string),
+                    download_size: pkg.download_size(),
+                    package_size: pkg.size(),
+                    installed_size: pkg.isize(),
+                    old_installed_size: old.map(|o| o.isize()),
+                    pulled_in,
+                    build_date: pkg.build_date(),
+                    previous_repo,
+                    kernel: old.is_some_and(is_kernel_package),
+                }
+            })
+            .collect();
+
+        updates.sort_by(|a, b| a.name.cmp(&b.name));
+        Sysupgrade {
+            updates,
+            removals: alpm
+                .trans_remove()
+                .into_iter()
+                .map(|pkg| pkg.name().to_string())
+                .collect(),
+        }
+    })
+    .unwrap_or_default()
 }
 
-pub fn upgrade_system(debug: bool, sync_first: bool) -> Result<(), String> {
-    if !util::is_root() {
-        return Err("you cannot perform this operation unless you are root.".to_string());
+/// The repo the installed build came from, out of the (repo, version, build date) of each
+/// sync db carrying the package: the local db doesn't record it, so it's the other repo
+/// with the very build that's installed. None when no repo has that build any more, or
+/// the update is from the repo that does.
+fn previous_repo(
+    repo: &str,
+    version: &str,
+    build_date: i64,
+    carried: &[(String, String, i64)],
+) -> Option<String> {
+    carried
+        .iter()
+        .find(|(_, v, built)| v == version && *built == build_date)
+        .map(|(name, _, _)| name.clone())
+        .filter(|name| name != repo)
+}
+
+/// Whether `repo` is one of Arch's testing or staging-for-testing repos, e.g.
+/// core-testing or kde-unstable.
+fn is_testing_repo(repo: &str) -> bool {
+    repo == "testing" || repo.ends_with("-testing") || repo.ends_with("-unstable")
+}
+
+/// A pending update that comes from a testing repo.
+#[derive(Debug, Clone)]
+pub struct TestingChange {
+    pub name: String,
+    pub installed: Option<String>,
+    pub testing_version: String,
+    pub repo: String,
+    /// What the stable repos would upgrade to instead; None when they have nothing newer
+    /// than what's installed.
+    pub stable_version: Option<String>,
+}
+
+/// The version the stable repos would install in place of a testing update: `stable` when
+/// it's newer than `installed`, or when the package isn't installed yet.
+fn stable_alternative(installed: Option<&str>, stable: Option<&str>) -> Option<String> {
+    let stable = stable?;
+    match installed {
+        Some(installed) if alpm::vercmp(stable, installed).is_le() => None,
+        _ => Some(stable.to_string()),
     }
+}
 
-    let config = crate::config::Config::load();
+/// The pending updates from testing repos and what each would be without them, or None
+/// when no testing repo is enabled.
+pub fn get_testing_changes() -> Option<Vec<TestingChange>> {
+    with_sysupgrade(|alpm| {
+        if !alpm.syncdbs().iter().any(|db| is_testing_repo(db.name())) {
+            return None;
+        }
+        let localdb = alpm.localdb();
+        let mut changes: Vec<TestingChange> = alpm
+            .trans_add()
+            .into_iter()
+            .filter_map(|pkg| {
+                let repo = pkg.db()?.name().to_string();
+                if !is_testing_repo(&repo) {
+                    return None;
+                }
+                let installed = localdb
+                    .pkg(pkg.name())
+                    .ok()
+                    .map(|o| o.version().to_string());
+                let stable = alpm
+                    .syncdbs()
+                    .iter()
+                    .filter(|db| !is_testing_repo(db.name()))
+                    .find_map(|db| db.pkg(pkg.name()).ok())
+                    .map(|p| p.version().to_string());
+                Some(TestingChange {
+                    name: pkg.name().to_string(),
+                    stable_version: stable_alternative(installed.as_deref(), stable.as_deref()),
+                    installed,
+                    testing_version: pkg.version().to_string(),
+                    repo,
+                })
+            })
+            .collect();
+        changes.sort_by(|a, b| a.name.cmp(&b.name));
+        Some(changes)
+    })
+    .flatten()
+}
 
-    if sync_first {
-        run_pacman_sync()?;
+/// A file a pending update would install over one already on disk.
+#[derive(Debug, Clone)]
+pub struct FileConflict {
+    pub package: String,
+    pub path: String,
+    /// The installed package that owns the file, None for one no package owns.
+    pub owner: Option<String>,
+}
+
+/// Whether a file on disk owned by `owner` stops a package from installing it: unowned
+/// files do, and so do other packages' unless the transaction takes the file away from them.
+fn blocks_install(owner: Option<&str>, package: &str, releasing: &HashSet<&str>) -> bool {
+    match owner {
+        Some(owner) => owner != package && !releasing.contains(owner),
+        None => true,
     }
-    let spinner = if debug {
-        None
-    } else {
-        Some(util::create_spinner("Gathering stats"))
+}
+
+/// The conflicts pacman's file check would stop the `pending` updates on, found from the
+/// .files databases before anything is downloaded; `removals` are the packages the same
+/// transaction takes away. Empty when the .files databases haven't been synced.
+fn get_file_conflicts(pending: &[PendingUpdate], removals: &[String]) -> Vec<FileConflict> {
+    let Ok(mut alpm) = Alpm::new("/", "/var/lib/pacman") else {
+        return Vec::new();
     };
-    let stats = get_stats(&config.display.stats, debug, spinner.as_ref());
-    if let Some(s) = spinner {
-        s.finish_and_clear();
+    alpm.set_dbext(".files");
+    register_sync_dbs(&mut alpm);
+    let localdb = alpm.localdb();
+    // The new versions, with their file lists, from the repos the updates come from
+    let syncdbs = alpm.syncdbs();
+    let adds: Vec<&alpm::Package> = pending
+        .iter()
+        .filter_map(|u| {
+            let db = syncdbs.iter().find(|db| db.name() == u.repo)?;
+            db.pkg(u.name.as_str()).ok()
+        })
+        .collect();
+    let removed: Vec<&str> = removals.iter().map(String::as_str).collect();
+    let owners: HashMap<&str, &str> = localdb
+        .pkgs()
+        .into_iter()
+        .flat_map(|other| {
+            other
+                .files()
+                .files()
+                .iter()
+                .filter_map(move |f| Some((std::str::from_utf8(f.name()).ok()?, other.name())))
+        })
+        .collect();
+
+    let mut conflicts = Vec::new();
+    for pkg in &adds {
+        let old = localdb.pkg(pkg.name()).ok();
+        for file in pkg.files().files() {
+            let Ok(name) = std::str::from_utf8(file.name()) else {
+                continue;
+            };
+            if name.ends_with('/') || old.is_some_and(|o| o.files().contains(name).is_some()) {
+                continue;
+            }
+            let path = format!("/{}", name);
+            if fs::symlink_metadata(&path).is_err() {
+                continue;
+            }
+
+            let owner = owners.get(name).copied();
+            // Upgraded packages whose new version no longer ships the file hand it over
+            let mut releasing: HashSet<&str> = removed.iter().copied().collect();
+            releasing.extend(
+                adds.iter()
+                    .filter(|other| other.files().contains(name).is_none())
+                    .map(|other| other.name()),
+            );
+            if blocks_install(owner, pkg.name(), &releasing) {
+                conflicts.push(FileConflict {
+                    package: pkg.name().to_string(),
+                    path,
+                    owner: owner.map(str::to_string),
+                });
+            }
+        }
     }
+    conflicts
+}
 
-    if debug {
-        crate::ui::display_stats(&stats, &config);
-        println!();
-    } else {
-        if let Err(e) = crate::ui::display_stats_with_graphics(&stats, &config) {
-            eprintln!("error: {}", e);
-            crate::ui::display_stats(&stats, &config);
-            println!();
+/// Directories a command is looked up in, as they appear in file lists.
+const BIN_DIRS: [&str; 4] = ["usr/bin/", "usr/sbin/", "bin/", "sbin/"];
+
+/// A package whose files include a command.
+#[derive(Debug, Clone)]
+pub struct Provider {
+    pub name: String,
+    pub version: String,
+    pub repo: String,
+    pub path: String,
+    pub installed: bool,
+}
+
+/// The command a file list entry is, e.g. "vim" for usr/bin/vim.
+fn command_name(file: &str) -> Option<&str> {
+    BIN_DIRS
+        .iter()
+        .find_map(|dir| file.strip_prefix(dir))
+        .filter(|name| !name.is_empty() && !name.contains('/'))
+}
+
+/// The packages in the files databases that ship `command`, for `provides`. They're looked
+/// up in the package index, which reads the files databases once after each sync; when it
+/// can't be opened, the databases are read directly.
+pub fn find_command_providers(command: &str) -> Vec<Provider> {
+    match index::command_providers(command) {
+        Ok(providers) => providers,
+        Err(_) => get_command_providers()
+            .into_iter()
+            .filter(|(name, _)| name == command)
+            .map(|(_, provider)| provider)
+            .collect(),
+    }
+}
+
+/// Every command in the files databases with the package that ships it.
+pub fn get_command_providers() -> Vec<(String, Provider)> {
+    let Ok(mut alpm) = Alpm::new("/", "/var/lib/pacman") else {
+        return Vec::new();
+    };
+    alpm.set_dbext(".files");
+    register_sync_dbs(&mut alpm);
+
+    let localdb = alpm.localdb();
+    let mut providers = Vec::new();
+    for db in alpm.syncdbs() {
+        for pkg in db.pkgs() {
+            for file in pkg.files().files() {
+                let Some(name) = std::str::from_utf8(file.name()).ok() else {
+                    continue;
+                };
+                if let Some(command) = command_name(name) {
+                    providers.push((
+                        command.to_string(),
+                        Provider {
+                            name: pkg.name().to_string(),
+                            version: pkg.version().to_string(),
+                            repo: db.name().to_string(),
+                            path: format!("/{}", name),
+                            installed: localdb.pkg(pkg.name()).is_ok(),
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    providers
+}
+
+/// Suggest `name` when at least `GROUP_SUGGEST_SHARE` of its members, but not all of
+/// them, are explicitly installed.
+fn suggest_group(
+    name: &str,
+    meta: bool,
+    members: &[&str],
+    explicit: &HashSet<&str>,
+) -> Option<GroupSuggestion> {
+    if members.len() < GROUP_MIN_MEMBERS {
+        return None;
+    }
+    let count = members.iter().filter(|m| explicit.contains(*m)).count();
+    let share = count as f64 / members.len() as f64;
+    (share >= GROUP_SUGGEST_SHARE && (meta || count < members.len())).then(|| GroupSuggestion {
+        name: name.to_string(),
+        meta,
+        explicit: count,
+        total: members.len(),
+    })
+}
+
+/// Groups and meta packages the user has mostly installed by hand, so that installing the
+/// meta package, or the rest of the group, keeps the set complete as it changes.
+fn get_group_suggestions() -> Vec<GroupSuggestion> {
+    let Ok(mut alpm) = Alpm::new("/", "/var/lib/pacman") else {
+        return Vec::new();
+    };
+    register_sync_dbs(&mut alpm);
+    let localdb = alpm.localdb();
+    let explicit: HashSet<&str> = localdb
+        .pkgs()
+        .into_iter()
+        .filter(|pkg| pkg.reason() == alpm::PackageReason::Explicit)
+        .map(|pkg| pkg.name())
+        .collect();
+
+    let mut suggestions = Vec::new();
+    for db in alpm.syncdbs() {
+        for group in db.groups().into_iter().flatten() {
+            let members: Vec<&str> = group.packages().into_iter().map(|p| p.name()).collect();
+            suggestions.extend(suggest_group(group.name(), false, &members, &explicit));
+        }
+        for pkg in db.pkgs() {
+            if !pkg.name().ends_with("-meta") || localdb.pkg(pkg.name()).is_ok() {
+                continue;
+            }
+            let members: Vec<&str> = pkg.depends().into_iter().map(|d| d.name()).collect();
+            suggestions.extend(suggest_group(pkg.name(), true, &members, &explicit));
         }
     }
+    suggestions.sort_by(|a, b| a.name.cmp(&b.name));
+    suggestions.dedup_by(|a, b| a.name == b.name);
+    suggestions
+}
+
+/// Gather the configured stats and save them for the next instance and the prompt. While
+/// another instance holds the lock for an upgrade, the stats it last cached are returned
+/// instead of fighting over the databases, as long as they cover every requested stat.
+pub fn get_stats(config: &Config, debug: bool, spinner: Option<&ProgressBar>) -> ManagerStats {
+    let (stats, fresh) = gather_stats(config, debug, spinner);
+    if fresh
+        && let Err(e) = save_stats(&config.display.stats, &stats)
+        && debug
+    {
+        eprintln!("warning: {}", e);
+    }
+    stats
+}
+
+/// `get_stats` without saving, for one part of a display gathered on its own. Saving a
+/// part would narrow the cache to its stats, so the whole is saved once it's merged.
+pub fn get_stats_unsaved(
+    config: &Config,
+    debug: bool,
+    spinner: Option<&ProgressBar>,
+) -> ManagerStats {
+    gather_stats(config, debug, spinner).0
+}
+
+/// Cache `stats` for instances that find the lock taken, and update the prompt's counts.
+pub fn save_stats(requested: &[StatId], stats: &ManagerStats) -> Result<(), String> {
+    history::save_stats_cache(requested, stats)
+        .and_then(|_| prompt::update(requested, stats, chrono::Local::now().timestamp()))
+}
 
-    run_pacman_pty(&["-Su"], true)
+/// The stats, and whether they were just collected rather than read from the cache.
+fn gather_stats(
+    config: &Config,
+    debug: bool,
+    spinner: Option<&ProgressBar>,
+) -> (ManagerStats, bool) {
+    let _lock = match lock::try_shared() {
+        Some(lock) => lock,
+        None => {
+            if let Some(stats) = history::load_stats_cache(&config.display.stats) {
+                if debug {
+                    eprintln!("Another instance holds the lock, using cached stats");
+                }
+                return (stats, false);
+            }
+            lock::shared()
+        }
+    };
+    (collect_stats(config, debug, spinner), true)
 }
 
-pub fn get_stats(requested: &[StatId], debug: bool, spinner: Option<&ProgressBar>) -> ManagerStats {
+fn collect_stats(config: &Config, debug: bool, spinner: Option<&ProgressBar>) -> ManagerStats {
     use crate::stats::{
-        needs_mirror_health, needs_mirror_url, needs_orphan_stats, needs_upgrade_stats,
+        needs_aur_stats, needs_hook_stats, needs_mirror_health, needs_mirror_url,
+        needs_orphan_stats, needs_upgrade_kind, needs_upgrade_stats,
     };
 
+    let requested = config.display.stats.as_slice();
     let total_start = Instant::now();
     let mut stats = ManagerStats::default();
 
@@ -578,15 +3081,79 @@ pub fn get_stats(requested: &[StatId], debug: bool, spinner: Option<&ProgressBar
         let upgrade_stats = get_upgrade_sizes();
         stats.total_upgradable = upgrade_stats.package_count;
         stats.download_size_mb = upgrade_stats.download_size_mb;
+        stats.download_cached_mb = upgrade_stats.download_cached_mb;
         stats.total_installed_size_mb = upgrade_stats.installed_size_mb;
         stats.net_upgrade_size_mb = upgrade_stats.net_upgrade_size_mb;
+        stats.upgrade_breakdown = upgrade_stats.breakdown;
         if debug {
             eprintln!("Upgrade sizes + count: {:?}", start.elapsed());
         }
+
+        if requested.contains(&StatId::UpgradeEta) && stats.total_upgradable > Some(0) {
+            stats.upgrade_eta_secs = history::estimate_upgrade_secs(
+                stats.download_size_mb.unwrap_or(0.0),
+                stats.total_installed_size_mb.unwrap_or(0.0),
+            );
+        }
     } else if debug {
         eprintln!("Upgrade sizes: SKIP");
     }
 
+    if needs_upgrade_kind(requested) {
+        let start = Instant::now();
+        stats.major_upgrade_reasons = Some(major_upgrade_reasons(&stats, &get_pending_updates()));
+        if debug {
+            eprintln!("Upgrade kind: {:?}", start.elapsed());
+        }
+    } else if debug {
+        eprintln!("Upgrade kind: SKIP");
+    }
+
+    let foreign = (needs_aur_stats(requested) || requested.contains(&StatId::ForeignPackages))
+        .then(get_foreign_packages)
+        .unwrap_or_default();
+    if needs_aur_stats(requested) {
+        let start = Instant::now();
+        if let Ok(names) = crate::aur::pending_updates(&foreign) {
+            stats.aur_updates = Some(names.len() as u32);
+            // Builds since the last run are timed first, so the estimate counts them
+            if let Err(e) = history::record_builds(get_finished_builds)
+                && debug
+            {
+                eprintln!("warning: {}", e);
+            }
+            stats.aur_build_eta_secs = history::estimate_build_secs(&names);
+            if requested.contains(&StatId::AurDownloadSize) {
+                stats.aur_download_size_mb = crate::aur::download_size_mb(&names).ok();
+            }
+        }
+        if debug {
+            eprintln!("AUR updates: {:?}", start.elapsed());
+        }
+    } else if debug {
+        eprintln!("AUR updates: SKIP");
+    }
+
+    if requested.contains(&StatId::ForeignPackages) {
+        let start = Instant::now();
+        stats.foreign_packages = Some(foreign.len() as u32);
+        if debug {
+            eprintln!("Foreign packages: {:?}", start.elapsed());
+        }
+    } else if debug {
+        eprintln!("Foreign packages: SKIP");
+    }
+
+    if needs_hook_stats(requested) {
+        let start = Instant::now();
+        stats.pending_hooks = get_pending_hooks();
+        if debug {
+            eprintln!("Pending hooks: {:?}", start.elapsed());
+        }
+    } else if debug {
+        eprintln!("Pending hooks: SKIP");
+    }
+
     if needs_orphan_stats(requested) {
         let start = Instant::now();
         let (orphaned_count, orphaned_size) = get_orphaned_packages();
@@ -599,21 +3166,34 @@ pub fn get_stats(requested: &[StatId], debug: bool, spinner: Option<&ProgressBar
         eprintln!("Orphaned packages: SKIP");
     }
 
+    // Remote sync times are compared with the local clock, so it's checked against a mirror
+    // while the sync checks run
+    let skew_handle = if needs_mirror_health(requested) || requested.contains(&StatId::RepoHealth) {
+        let url = get_mirror_urls(&config.mirror)
+            .into_iter()
+            .find(|url| url.starts_with("http"));
+        Some((
+            std::thread::spawn(move || url.and_then(|url| clock_skew_secs(&url))),
+            Instant::now(),
+        ))
+    } else {
+        if debug {
+            eprintln!("Clock skew: SKIP");
+        }
+        None
+    };
+
     let sync_handle = if needs_mirror_url(requested) {
         let start = Instant::now();
-        stats.mirror_url = get_mirror_url();
+        let candidates = get_mirror_urls(&config.mirror);
+        stats.mirror_url = candidates.first().cloned();
         if debug {
             eprintln!("Mirror URL: {:?}", start.elapsed());
         }
 
         if needs_mirror_health(requested) {
             let sync_start = Instant::now();
-            let mirror_url_clone = stats.mirror_url.clone();
-            let handle = std::thread::spawn(move || {
-                mirror_url_clone
-                    .as_ref()
-                    .and_then(|url| check_mirror_sync(url))
-            });
+            let handle = std::thread::spawn(move || check_mirror_sync_with_fallback(&candidates));
             Some((handle, sync_start))
         } else {
             if debug {
@@ -629,27 +3209,57 @@ pub fn get_stats(requested: &[StatId], debug: bool, spinner: Option<&ProgressBar
         None
     };
 
+    let repo_handle = if requested.contains(&StatId::RepoHealth) {
+        let mirror = config.mirror.clone();
+        Some((
+            std::thread::spawn(move || get_repo_sync_times(&mirror)),
+            Instant::now(),
+        ))
+    } else {
+        if debug {
+            eprintln!("Custom repos: SKIP");
+        }
+        None
+    };
+
     if requested.contains(&StatId::Installed) {
         let start = Instant::now();
-        stats.total_installed = get_installed_count();
+        stats.total_installed = Some(get_installed_count());
         if debug {
             eprintln!("Installed count: {:?}", start.elapsed());
         }
     }
 
-    if requested.contains(&StatId::LastUpdate) {
+    let last_logged = if requested.contains(&StatId::LastUpdate) {
         let start = Instant::now();
-        stats.days_since_last_update = get_seconds_since_update();
+        let logged = get_last_update_time(config.display.last_update);
         if debug {
             eprintln!("Last update time: {:?}", start.elapsed());
         }
-    }
+        logged
+    } else {
+        None
+    };
 
     if requested.contains(&StatId::CacheSize) {
         let start = Instant::now();
-        stats.cache_size_mb = get_cache_size();
+        stats.cache_usage = cache::scan(&cache_dirs(config));
+        stats.cache_size_mb = stats
+            .cache_usage
+            .as_ref()
+            .map(|u| u.bytes as f64 / 1048576.0);
         if debug {
             eprintln!("Cache size: {:?}", start.elapsed());
+            if let Some(usage) = &stats.cache_usage {
+                for (ext, (files, bytes)) in &usage.by_extension {
+                    eprintln!(
+                        "  {}: {} files, {:.2} MiB",
+                        ext,
+                        files,
+                        *bytes as f64 / 1048576.0
+                    );
+                }
+            }
         }
     }
 
@@ -659,19 +3269,784 @@ pub fn get_stats(requested: &[StatId], debug: bool, spinner: Option<&ProgressBar
         eprintln!("Pacman version: {:?}", start.elapsed());
     }
 
+    if let Some((handle, skew_start)) = skew_handle {
+        stats.clock_skew_secs = handle.join().ok().flatten();
+        if debug {
+            eprintln!("Clock skew: {:?}", skew_start.elapsed());
+        }
+    }
+    // The local time the server clocks give, for the sync ages and the last update
+    let now = Local::now().timestamp() - stats.clock_skew_secs.unwrap_or(0);
+
+    match last_logged.map(|t| util::seconds_since_logged(t, now)) {
+        Some(Ok(secs)) => stats.days_since_last_update = Some(secs),
+        Some(Err(warning)) => stats.last_update_warning = Some(warning),
+        None => {}
+    }
+
     if let Some((handle, sync_start)) = sync_handle {
         if let Some(pb) = spinner {
             pb.set_message("Checking mirror last sync");
         }
-        stats.mirror_sync_age_hours = handle.join().ok().flatten();
+        if let Some((url, synced)) = handle.join().ok().flatten() {
+            stats.mirror_url = Some(url);
+            stats.mirror_sync_age_hours = Some(age_hours(now, synced));
+        }
         if debug {
             eprintln!("Mirror sync age: {:?}", sync_start.elapsed());
         }
     }
 
+    if requested.contains(&StatId::Distro) {
+        let start = Instant::now();
+        let distro = Distro::detect();
+        stats.distro = Some(distro);
+        if distro == Distro::Manjaro {
+            stats.branch = Some(distro::manjaro_branch());
+            stats.branch_lag_hours = get_branch_lag_hours(&config.mirror);
+        }
+        if debug {
+            eprintln!("Distro: {:?}", start.elapsed());
+        }
+    }
+
+    if requested.contains(&StatId::FilesDb) {
+        let start = Instant::now();
+        stats.files_db_ages = Some(get_files_db_ages(Local::now().timestamp()));
+        if debug {
+            eprintln!("Files databases: {:?}", start.elapsed());
+        }
+    } else if debug {
+        eprintln!("Files databases: SKIP");
+    }
+
+    if requested.contains(&StatId::GroupSuggestions) {
+        let start = Instant::now();
+        stats.group_suggestions = Some(get_group_suggestions());
+        if debug {
+            eprintln!("Group suggestions: {:?}", start.elapsed());
+        }
+    } else if debug {
+        eprintln!("Group suggestions: SKIP");
+    }
+
+    if requested.contains(&StatId::MonthlyData) {
+        let start = Instant::now();
+        stats.monthly_download_mb =
+            history::projected_monthly_download_mb(Local::now().timestamp());
+        if debug {
+            eprintln!("Monthly data: {:?}", start.elapsed());
+        }
+    } else if debug {
+        eprintln!("Monthly data: SKIP");
+    }
+
+    if requested.contains(&StatId::TestingUpdates) {
+        let start = Instant::now();
+        stats.testing_updates = get_testing_changes().map(|changes| changes.len() as u32);
+        if debug {
+            eprintln!("Testing updates: {:?}", start.elapsed());
+        }
+    } else if debug {
+        eprintln!("Testing updates: SKIP");
+    }
+
+    if let Some((handle, repo_start)) = repo_handle {
+        if let Some(pb) = spinner {
+            pb.set_message("Checking custom repos");
+        }
+        stats.repo_health = handle.join().ok().map(|repos| {
+            repos
+                .into_iter()
+                .map(|(name, synced)| RepoHealth {
+                    name,
+                    sync_age_hours: synced.map(|t| age_hours(now, t)),
+                })
+                .collect()
+        });
+        if debug {
+            eprintln!("Custom repos: {:?}", repo_start.elapsed());
+        }
+    }
+
     if debug {
         eprintln!("TOTAL: {:?}\n", total_start.elapsed());
     }
 
     stats
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Keeps the lines the default filter would show.
+    #[derive(Default)]
+    struct Shown {
+        filter: LineFilter,
+        lines: Vec<String>,
+    }
+
+    impl PtyHandler for Shown {
+        fn line(&mut self, line: &str) {
+            if self.filter.shows(line) {
+                self.lines.push(line.to_string());
+            }
+        }
+
+        fn redraw(&mut self, line: &str) {
+            self.line(line);
+        }
+
+        fn prompt(&mut self, _prompt: &str) -> Option<String> {
+            Some("y".to_string())
+        }
+    }
+
+    #[test]
+    fn splits_updates_by_age() {
+        let update = |name: &str, build_date| PendingUpdate {
+            name: name.to_string(),
+            old_version: None,
+            new_version: "1.0-1".to_string(),
+            repo: "extra".to_string(),
+            description: None,
+            url: None,
+            download_size: 0,
+            package_size: 0,
+            installed_size: 0,
+            old_installed_size: None,
+            pulled_in: Vec::new(),
+            build_date,
+            previous_repo: None,
+            kernel: false,
+        };
+        let now = 30 * 86400;
+        let updates = vec![
+            update("old", now - 10 * 86400),
+            update("fresh", now - 86400),
+            update("exactly", now - 7 * 86400),
+        ];
+        let (matured, too_fresh) = split_by_age(updates, 7, now);
+        let names = |updates: &[PendingUpdate]| -> Vec<String> {
+            updates.iter().map(|u| u.name.clone()).collect()
+        };
+        assert_eq!(names(&matured), ["old", "exactly"]);
+        assert_eq!(names(&too_fresh), ["fresh"]);
+    }
+
+    #[test]
+    fn counts_what_the_cache_saves() {
+        let update = |download_size, package_size| PendingUpdate {
+            name: "mesa".to_string(),
+            old_version: None,
+            new_version: "1.0-1".to_string(),
+            repo: "extra".to_string(),
+            description: None,
+            url: None,
+            download_size,
+            package_size,
+            installed_size: 0,
+            old_installed_size: None,
+            pulled_in: Vec::new(),
+            build_date: 0,
+            previous_repo: None,
+            kernel: false,
+        };
+        // Cached, a partial download, and one still to fetch in full
+        let pending = [update(0, 300), update(40, 100), update(500, 500)];
+        assert_eq!(
+            CacheSavings::of(&pending),
+            CacheSavings {
+                packages: 1,
+                bytes: 360
+            }
+        );
+        assert_eq!(CacheSavings::of(&[]), CacheSavings::default());
+    }
+
+    #[test]
+    fn finds_major_upgrades_and_boot_packages() {
+        let update = |name: &str, kernel| PendingUpdate {
+            name: name.to_string(),
+            old_version: Some("1.0-1".to_string()),
+            new_version: "1.1-1".to_string(),
+            repo: "core".to_string(),
+            description: None,
+            url: None,
+            download_size: 0,
+            package_size: 0,
+            installed_size: 0,
+            old_installed_size: None,
+            pulled_in: Vec::new(),
+            build_date: 0,
+            previous_repo: None,
+            kernel,
+        };
+        let updates = [
+            update("grub", false),
+            update("linux", true),
+            update("mesa", false),
+            update("ripgrep", false),
+        ];
+        let stats = ManagerStats {
+            total_upgradable: Some(60),
+            download_size_mb: Some(120.0),
+            ..ManagerStats::default()
+        };
+        assert_eq!(
+            major_upgrade_reasons(&stats, &updates),
+            ["kernel", "mesa", "60 packages"]
+        );
+        assert_eq!(boot_packages(&updates), ["grub", "linux"]);
+
+        let quick = [update("ripgrep", false)];
+        assert!(major_upgrade_reasons(&ManagerStats::default(), &quick).is_empty());
+        assert!(boot_packages(&quick).is_empty());
+    }
+
+    #[test]
+    fn holds_back_pinned_packages_from_other_repos() {
+        let mut update = PendingUpdate {
+            name: "linux".to_string(),
+            old_version: Some("6.9.6.arch1-1".to_string()),
+            new_version: "6.9.7.arch1-1".to_string(),
+            repo: "core-testing".to_string(),
+            description: None,
+            url: None,
+            download_size: 0,
+            package_size: 0,
+            installed_size: 0,
+            old_installed_size: None,
+            pulled_in: Vec::new(),
+            build_date: 0,
+            previous_repo: Some("core".to_string()),
+            kernel: true,
+        };
+        let pins = HashMap::from([("linux".to_string(), "core".to_string())]);
+        assert_eq!(pin_conflict(&update, &pins), Some("core"));
+        update.repo = "core".to_string();
+        assert_eq!(pin_conflict(&update, &pins), None);
+        update.name = "mesa".to_string();
+        update.repo = "extra-testing".to_string();
+        assert_eq!(pin_conflict(&update, &pins), None);
+    }
+
+    #[test]
+    fn parses_log_timestamps_without_offsets() {
+        use chrono::TimeZone;
+        assert_eq!(
+            parse_log_timestamp("2024-06-01T12:00:00+02:00"),
+            parse_log_timestamp("2024-06-01T12:00:00+0200")
+        );
+        assert_eq!(
+            parse_log_timestamp("2024-06-01T10:00:00Z"),
+            parse_log_timestamp("2024-06-01T12:00:00+0200")
+        );
+        let local = Local
+            .with_ymd_and_hms(2024, 6, 1, 12, 0, 0)
+            .unwrap()
+            .timestamp();
+        assert_eq!(parse_log_timestamp("2024-06-01T12:00:00"), Some(local));
+        assert_eq!(parse_log_timestamp("2024-06-01 12:00"), Some(local));
+        assert_eq!(parse_log_timestamp("2024-06-01 12"), None);
+
+        // An old-format upgrade counts when the newer entries aren't upgrades
+        let log = "\
+[2018-05-30 09:00] [PACMAN] synchronizing package lists
+[2024-06-01 12:00] [PACMAN] starting full system upgrade
+[2024-06-01 12:05] [ALPM] transaction completed
+[2024-06-02T08:00:00+0200] [ALPM] installed htop (3.3.0-1)
+";
+        assert_eq!(last_upgrade_time(log), Some(local));
+
+        assert_eq!(util::seconds_since_logged(100, 160), Ok(60));
+        assert!(
+            util::seconds_since_logged(7300, 100)
+                .unwrap_err()
+                .contains("2h in the future")
+        );
+    }
+
+    #[test]
+    fn dates_the_configured_last_update_event() {
+        let log = "\
+[2024-06-01T12:00:00+0000] [PACMAN] synchronizing package lists
+[2024-06-01T12:00:05+0000] [PACMAN] starting full system upgrade
+[2024-06-01T12:01:00+0000] [ALPM] transaction completed
+[2024-06-03T08:00:00+0000] [PACMAN] Running 'pacman -S htop'
+[2024-06-03T08:00:10+0000] [ALPM] transaction completed
+[2024-06-04T09:00:00+0000] [PACMAN] synchronizing package lists
+";
+        let at = |ts: &str| parse_log_timestamp(ts);
+        assert_eq!(last_upgrade_time(log), at("2024-06-01T12:00:05+0000"));
+        assert_eq!(
+            last_event_time(log, "[ALPM] transaction completed"),
+            at("2024-06-03T08:00:10+0000")
+        );
+        assert_eq!(
+            last_event_time(log, "[PACMAN] synchronizing package lists"),
+            at("2024-06-04T09:00:00+0000")
+        );
+        assert_eq!(last_event_time(log, "[ALPM] installed"), None);
+    }
+
+    #[test]
+    fn finds_orphan_causes_in_one_log_pass() {
+        let log = "\
+[2024-06-01T12:00:00+0000] [ALPM] removed python-old (1.0-1)
+[2024-06-02T12:00:00+0000] [ALPM] upgraded python (3.11.9-1 -> 3.12.4-1)
+[2024-06-02T12:00:01+0000] [ALPM] removed pyqt5-sip (12.13.0-1)
+[2024-06-03T12:00:00+0000] [ALPM] upgraded glibc (2.39-1 -> 2.40-1)
+";
+        let at = |ts: &str| parse_log_timestamp(ts).unwrap();
+        let changes = LogChanges::parse(log, at("2024-06-02T00:00:00+0000"));
+        assert_eq!(
+            changes.orphan_causes(
+                at("2024-06-02T00:00:00+0000"),
+                at("2024-06-02T13:00:00+0000")
+            ),
+            (vec!["pyqt5-sip".to_string()], vec!["python".to_string()])
+        );
+        assert_eq!(
+            changes.orphan_causes(
+                at("2024-06-03T00:00:00+0000"),
+                at("2024-06-04T00:00:00+0000")
+            ),
+            (Vec::new(), vec!["glibc".to_string()])
+        );
+        // Lines before the earliest check aren't kept
+        assert_eq!(
+            changes.orphan_causes(0, at("2024-06-01T13:00:00+0000")),
+            (Vec::new(), Vec::new())
+        );
+    }
+
+    #[test]
+    fn finds_the_repo_of_the_installed_build() {
+        let carried = |repos: &[(&str, &str, i64)]| {
+            repos
+                .iter()
+                .map(|(repo, version, built)| (repo.to_string(), version.to_string(), *built))
+                .collect::<Vec<_>>()
+        };
+        let testing = carried(&[("core", "6.9.3-1", 100), ("core-testing", "6.9.4-1", 200)]);
+        assert_eq!(
+            previous_repo("core-testing", "6.9.3-1", 100, &testing),
+            Some("core".to_string())
+        );
+        // Both repos moved on, so nothing says where the installed build came from
+        let moved = carried(&[("extra", "2.0-1", 300), ("custom", "1.5-1", 250)]);
+        assert_eq!(previous_repo("extra", "1.0-1", 100, &moved), None);
+        // A rebuild of the same version elsewhere isn't the installed build
+        let rebuilt = carried(&[("extra", "1.1-1", 300), ("chaotic-aur", "1.0-1", 150)]);
+        assert_eq!(previous_repo("extra", "1.0-1", 100, &rebuilt), None);
+        let same = carried(&[("extra", "1.0-1", 100)]);
+        assert_eq!(previous_repo("extra", "1.0-1", 100, &same), None);
+    }
+
+    #[test]
+    fn classifies_pending_changes() {
+        assert_eq!(change_kind(None, "1.0-1"), ChangeKind::Install);
+        assert_eq!(change_kind(Some("1.0-1"), "1.1-1"), ChangeKind::Upgrade);
+        assert_eq!(change_kind(Some("1.1-1"), "1.0-1"), ChangeKind::Downgrade);
+        assert_eq!(change_kind(Some("1.0-1"), "1.0-1"), ChangeKind::Upgrade);
+    }
+
+    #[test]
+    fn ignores_clock_skew_within_tolerance() {
+        assert_eq!(significant_skew(1_000_060, 1_000_000), None);
+        assert_eq!(significant_skew(1_003_600, 1_000_000), Some(3600));
+        assert_eq!(significant_skew(1_000_000, 1_000_600), Some(-600));
+        // A sync time ahead of a skewed clock doesn't come out negative
+        assert_eq!(age_hours(1_000_000, 1_003_600), 0.0);
+        assert_eq!(age_hours(1_007_200 - 3600, 1_000_000), 1.0);
+    }
+
+    #[test]
+    fn finds_stable_alternative_to_testing() {
+        assert!(is_testing_repo("core-testing"));
+        assert!(is_testing_repo("kde-unstable"));
+        assert!(!is_testing_repo("extra"));
+        assert_eq!(
+            stable_alternative(Some("6.9.6-1"), Some("6.9.7-1")),
+            Some("6.9.7-1".to_string())
+        );
+        assert_eq!(stable_alternative(Some("6.9.7-1"), Some("6.9.7-1")), None);
+        assert_eq!(
+            stable_alternative(None, Some("1.0-1")),
+            Some("1.0-1".to_string())
+        );
+        assert_eq!(stable_alternative(None, None), None);
+    }
+
+    #[test]
+    fn blocks_files_nothing_hands_over() {
+        let releasing = HashSet::from(["python-foo-legacy"]);
+        assert!(blocks_install(None, "python-foo", &releasing));
+        assert!(blocks_install(Some("vim"), "python-foo", &releasing));
+        assert!(!blocks_install(
+            Some("python-foo"),
+            "python-foo",
+            &releasing
+        ));
+        assert!(!blocks_install(
+            Some("python-foo-legacy"),
+            "python-foo",
+            &releasing
+        ));
+    }
+
+    #[test]
+    fn matches_commands_in_bin_dirs() {
+        assert_eq!(command_name("usr/bin/vim"), Some("vim"));
+        assert_eq!(command_name("usr/sbin/iw"), Some("iw"));
+        assert_eq!(command_name("usr/share/vim/vim"), None);
+        assert_eq!(command_name("usr/bin/"), None);
+        assert_eq!(command_name("usr/bin/site-packages/pip"), None);
+    }
+
+    #[test]
+    fn suggests_mostly_installed_groups() {
+        let members = ["kate", "dolphin", "konsole", "okular", "gwenview"];
+        let explicit = HashSet::from(["kate", "dolphin", "konsole", "okular"]);
+        assert_eq!(
+            suggest_group("kde-applications", false, &members, &explicit),
+            Some(GroupSuggestion {
+                name: "kde-applications".to_string(),
+                meta: false,
+                explicit: 4,
+                total: 5,
+            })
+        );
+        assert_eq!(suggest_group("kde", false, &members[..3], &explicit), None);
+
+        let all = HashSet::from(members);
+        assert_eq!(
+            suggest_group("kde-applications", false, &members, &all),
+            None
+        );
+        assert!(suggest_group("kde-applications-meta", true, &members, &all).is_some());
+        let few = HashSet::from(["kate", "dolphin"]);
+        assert_eq!(
+            suggest_group("kde-applications", false, &members, &few),
+            None
+        );
+    }
+
+    #[test]
+    fn filters_replayed_upgrade() {
+        let recordings: Vec<pty::Recording> =
+            serde_json::from_str(include_str!("../tests/fixtures/upgrade_session.json")).unwrap();
+        let mut shown = Shown::default();
+        pty::replay(&recordings[0], &mut shown);
+
+        assert!(
+            !shown
+                .lines
+                .iter()
+                .any(|l| l.contains("Total Download Size"))
+        );
+        assert!(
+            !shown
+                .lines
+                .iter()
+                .any(|l| l.contains("resolving dependencies"))
+        );
+        assert!(
+            shown
+                .lines
+                .iter()
+                .any(|l| l.contains("(1/2) upgrading linux"))
+        );
+        assert!(shown.lines.iter().all(|l| !l.trim().is_empty()));
+    }
+
+    /// A pacman.log timestamp with its expected Unix time.
+    fn log_timestamp() -> impl Strategy<Value = (String, i64)> {
+        (
+            1990..2100i32,
+            1..=12u32,
+            1..=28u32,
+            0..24u32,
+            0..60u32,
+            0..60u32,
+            -12..=14i32,
+            prop::sample::select(vec![0, 30, 45]),
+        )
+            .prop_map(|(year, month, day, hour, minute, second, off_h, off_m)| {
+                let offset_secs = off_h * 3600 + off_h.signum() * off_m * 60;
+                let sign = if off_h < 0 { '-' } else { '+' };
+                let text = format!(
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}{:02}{:02}",
+                    year,
+                    month,
+                    day,
+                    hour,
+                    minute,
+                    second,
+                    sign,
+                    off_h.abs(),
+                    if off_h == 0 { 0 } else { off_m }
+                );
+                let time = chrono::NaiveDate::from_ymd_opt(year, month, day)
+                    .and_then(|d| d.and_hms_opt(hour, minute, second))
+                    .unwrap()
+                    .and_utc()
+                    .timestamp()
+                    - i64::from(offset_secs);
+                (text, time)
+            })
+    }
+
+    fn log_line() -> impl Strategy<Value = String> {
+        prop_oneof![
+            ".*",
+            log_timestamp()
+                .prop_map(|(ts, _)| format!("[{}] [PACMAN] starting full system upgrade", ts)),
+            log_timestamp().prop_map(|(ts, _)| format!("[{}] [ALPM] transaction completed", ts)),
+            Just("[2019-01-01 12:00] starting full system upgrade".to_string()),
+            Just("[] transaction completed".to_string()),
+            "\\[[0-9T:+-]{0,30}\\] .{0,40}",
+        ]
+    }
+
+    fn mirrorlist_line() -> impl Strategy<Value = String> {
+        prop_oneof![
+            ".*",
+            "# .{0,40}",
+            "## [A-Za-z ]{0,20}",
+            Just("Include = /etc/pacman.d/mirrorlist-extra".to_string()),
+            Just(String::new()),
+            server_line(),
+        ]
+    }
+
+    fn server_line() -> impl Strategy<Value = String> {
+        (
+            prop::sample::select(vec!["Server = ", "Server=", "  Server =  "]),
+            prop::sample::select(vec!["https", "http", "rsync"]),
+            "[a-z]{1,10}(\\.[a-z]{1,10}){1,2}",
+            prop::sample::select(vec![
+                "/$repo/os/$arch",
+                "/archlinux/$repo/os/$arch",
+                "/$arch/$repo",
+            ]),
+        )
+            .prop_map(|(key, scheme, host, path)| format!("{}{}://{}{}", key, scheme, host, path))
+    }
+
+    #[test]
+    fn reads_repo_servers_from_pacman_conf() {
+        let conf = "\
+[options]
+Architecture = auto
+Server = https://ignored.example/$repo
+
+[core]
+Server = https://first.example/archlinux/$repo/os/$arch
+Include = /nonexistent/mirrorlist
+
+[custom]
+Server = file:///srv/repo/$arch # local build
+#Server = https://disabled.example/$repo
+Server = file:///srv/build#2/$arch	# a comment after a tab
+";
+        let repos = parse_repos(conf, "x86_64", &MirrorConfig::default());
+
+        assert_eq!(repos.len(), 2);
+        assert_eq!(repos[0].name, "core");
+        assert_eq!(repos[0].servers.len(), 1);
+        assert_eq!(
+            repos[0].servers[0].base_url,
+            "https://first.example/archlinux"
+        );
+        assert_eq!(repos[1].servers[0].base_url, "file:///srv/repo/x86_64");
+        assert_eq!(repos[1].servers.len(), 2);
+        assert_eq!(repos[1].servers[1].base_url, "file:///srv/build#2/x86_64");
+    }
+
+    #[test]
+    fn filters_mirrors_by_domain_scheme_and_country() {
+        assert_eq!(
+            mirror_host("https://Mirror.Example:8443/arch"),
+            "Mirror.Example"
+        );
+        assert_eq!(mirror_host("mirror.example/arch"), "mirror.example");
+
+        let config: MirrorConfig = toml::from_str(
+            "exclude = [\"bad.example\"]\nhttps_only = true\ncountries = [\"germany\"]",
+        )
+        .unwrap();
+        assert!(mirror_allowed(
+            "https://de.example/arch",
+            Some("Germany"),
+            &config
+        ));
+        assert!(!mirror_allowed(
+            "http://de.example/arch",
+            Some("Germany"),
+            &config
+        ));
+        assert!(!mirror_allowed(
+            "https://BAD.example/arch",
+            Some("Germany"),
+            &config
+        ));
+        assert!(!mirror_allowed(
+            "https://de.bad.example/arch",
+            Some("Germany"),
+            &config
+        ));
+        assert!(mirror_allowed(
+            "https://notbad.example/arch",
+            Some("Germany"),
+            &config
+        ));
+        assert!(!mirror_allowed(
+            "https://fr.example/arch",
+            Some("France"),
+            &config
+        ));
+        assert!(!mirror_allowed("https://de.example/arch", None, &config));
+
+        let list = "\
+## Germany
+Server = https://de.example/archlinux/$repo/os/$arch
+Server = http://plain.example/$repo/os/$arch
+## France
+Server = https://fr.example/$repo/os/$arch
+";
+        let urls: Vec<String> = parse_mirrorlist(list, "core", "x86_64", &config, 0)
+            .into_iter()
+            .map(|entry| entry.base_url)
+            .collect();
+        assert_eq!(urls, ["https://de.example/archlinux"]);
+        let all = parse_mirrorlist(list, "core", "x86_64", &MirrorConfig::default(), 0);
+        assert_eq!(all.len(), 3);
+    }
+
+    #[test]
+    fn reranks_mirrorlists_in_place() {
+        let original = "\
+## Germany
+Server = https://de.example/archlinux/$repo/os/$arch
+Server=https://slow.example/$repo/os/$arch
+## France
+  Server  = https://fr.example/$repo/os/$arch
+";
+        let config = MirrorConfig {
+            countries: vec!["Germany".to_string(), "France".to_string()],
+            ..MirrorConfig::default()
+        };
+        let servers = |list: &str| -> Vec<String> {
+            parse_mirrorlist(list, "core", "x86_64", &config, INCLUDE_DEPTH)
+                .into_iter()
+                .map(|entry| entry.server)
+                .collect()
+        };
+        assert_eq!(servers(original).len(), 3);
+
+        let fr = "https://fr.example/$repo/os/$arch";
+        let de = "https://de.example/archlinux/$repo/os/$arch";
+        let first = ranked_mirrorlist(original, &[fr, de]);
+        assert_eq!(servers(&first), [fr, de]);
+
+        let second = ranked_mirrorlist(&first, &[de]);
+        assert_eq!(servers(&second), [de]);
+        assert_eq!(second.matches(RANKED_HEADER).count(), 1);
+        assert!(second.contains("#Server=https://slow.example"));
+        assert!(second.ends_with("## France\n#  Server  = https://fr.example/$repo/os/$arch\n"));
+    }
+
+    #[test]
+    fn reads_arch_audit_package_names() {
+        let versions = "openssl>=3.3.2-1\nlib32-expat>=2.6.3-1\n\n";
+        assert_eq!(parse_audit(versions), ["openssl", "lib32-expat"]);
+        assert_eq!(
+            parse_audit("openssl\nlib32-expat\n"),
+            ["openssl", "lib32-expat"]
+        );
+        assert!(parse_audit("").is_empty());
+    }
+
+    #[test]
+    fn stops_the_download_timer_at_the_checks() {
+        assert!(is_check_step("(1/4) checking keys in keyring"));
+        assert!(is_check_step("checking package integrity..."));
+        assert!(!is_check_step("(2/4) upgrading vim"));
+        assert!(!is_check_step("vim-9.1-1-x86_64 downloading..."));
+
+        let mut timer = PhaseTimer::default();
+        timer.observe(":: Retrieving packages...");
+        timer.observe(" vim-9.1-1-x86_64 downloading...");
+        assert!(timer.download.is_none());
+        timer.observe("(1/4) checking keys in keyring");
+        let download = timer.download;
+        assert!(download.is_some());
+        timer.observe("(2/4) checking package integrity");
+        timer.observe(":: Processing package changes...");
+        assert_eq!(timer.download, download);
+        assert!(timer.install_start.is_some());
+    }
+
+    #[test]
+    fn times_builds_from_their_build_date() {
+        assert_eq!(build_secs(1718000000, 1718000750), Some(750.0));
+        // A reproducible build dated to its last commit
+        assert_eq!(build_secs(1700000000, 1718000000), None);
+        assert_eq!(build_secs(1718000000, 1717999990), None);
+    }
+
+    proptest! {
+        #[test]
+        fn parses_generated_log_timestamps((text, time) in log_timestamp()) {
+            prop_assert_eq!(parse_log_timestamp(&text), Some(time));
+        }
+
+        #[test]
+        fn log_timestamp_never_panics(text in ".*") {
+            parse_log_timestamp(&text);
+        }
+
+        #[test]
+        fn last_upgrade_never_panics(lines in prop::collection::vec(log_line(), 0..20)) {
+            last_upgrade_time(&lines.join("\n"));
+        }
+
+        #[test]
+        fn finds_completed_upgrade(
+            (start, time) in log_timestamp(),
+            (end, _) in log_timestamp(),
+            noise in prop::collection::vec(".*", 0..5),
+        ) {
+            let mut lines: Vec<String> = noise
+                .into_iter()
+                .filter(|l| !l.contains("starting full system upgrade") && !l.contains("transaction completed"))
+                .collect();
+            lines.push(format!("[{}] [PACMAN] starting full system upgrade", start));
+            lines.push(format!("[{}] [ALPM] transaction completed", end));
+            prop_assert_eq!(last_upgrade_time(&lines.join("\n")), Some(time));
+        }
+
+        #[test]
+        fn mirrorlist_never_panics(lines in prop::collection::vec(mirrorlist_line(), 0..20)) {
+            parse_mirrorlist(&lines.join("\n"), "core", "x86_64", &MirrorConfig::default(), 0);
+        }
+
+        #[test]
+        fn reads_every_server_line(
+            servers in prop::collection::vec(server_line(), 0..10),
+            comments in prop::collection::vec("#.{0,40}", 0..10),
+        ) {
+            let mut lines = comments;
+            lines.extend(servers.iter().cloned());
+            let entries =
+                parse_mirrorlist(&lines.join("\n"), "core", "aarch64", &MirrorConfig::default(), 0);
+            prop_assert_eq!(entries.len(), servers.len());
+            prop_assert!(entries.iter().all(|e| !e.base_url.contains("$repo")));
+            prop_assert!(entries.iter().all(|e| !e.base_url.contains('$')));
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::util;
+
+/// Directory name used under each XDG base directory.
+const APP_DIR: &str = "pacfetch";
+
+/// The config file (~/.config/pacfetch.toml).
+pub fn config_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|p| p.join("pacfetch.toml"))
+}
+
+/// History and snapshots (~/.local/state/pacfetch).
+pub fn state_dir() -> Option<PathBuf> {
+    dirs::state_dir().map(|p| p.join(APP_DIR))
+}
+
+/// Regenerable data such as downloaded indexes (~/.cache/pacfetch).
+pub fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|p| p.join(APP_DIR))
+}
+
+/// Data worth keeping that isn't state (~/.local/share/pacfetch).
+pub fn data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|p| p.join(APP_DIR))
+}
+
+/// Per-session files such as the instance lock (/run/user/<uid>/pacfetch). Under sudo
+/// this is the invoking user's, so a root upgrade and the user's status bar share it;
+/// what root creates there goes back to them with `return_to_sudo_user`.
+pub fn runtime_dir() -> Option<PathBuf> {
+    let sudo_uid = std::env::var("SUDO_UID").ok();
+    if let Some(dir) = sudo_runtime_dir(sudo_uid.as_deref(), |dir| dir.is_dir()) {
+        return Some(dir.join(APP_DIR));
+    }
+    dirs::runtime_dir()
+        .map(|p| p.join(APP_DIR))
+        .or_else(|| Some(std::env::temp_dir().join(APP_DIR)))
+}
+
+/// The sudo user's /run/user/<uid>, when the session has one.
+fn sudo_runtime_dir(sudo_uid: Option<&str>, exists: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+    let uid: u32 = sudo_uid?.parse().ok()?;
+    let dir = PathBuf::from(format!("/run/user/{}", uid));
+    exists(&dir).then_some(dir)
+}
+
+/// The lock held while pacfetch reads stats or changes the system.
+pub fn lock_file() -> Option<PathBuf> {
+    runtime_dir().map(|p| p.join("pacfetch.lock"))
+}
+
+/// The fixed-layout stats `pacfetch prompt` reads.
+pub fn prompt_file() -> Option<PathBuf> {
+    runtime_dir().map(|p| p.join("prompt.bin"))
+}
+
+/// A file in the state directory, e.g. `state_file("upgrades.toml")`.
+pub fn state_file(name: &str) -> Option<PathBuf> {
+    state_dir().map(|p| p.join(name))
+}
+
+/// A state file of the user pacfetch is acting for: under sudo the invoking user's, so the
+/// ignore list `sudo pacfetch -Syu` applies is the one they edited rather than root's.
+pub fn user_state_file(name: &str) -> Option<PathBuf> {
+    user_state_dir(util::sudo_user_home().as_deref(), state_dir()).map(|p| p.join(name))
+}
+
+/// The state directory under `sudo_home`, where the invoking user's XDG_STATE_HOME isn't
+/// known so the default is assumed, or else `own`.
+fn user_state_dir(sudo_home: Option<&Path>, own: Option<PathBuf>) -> Option<PathBuf> {
+    match sudo_home {
+        Some(home) => Some(home.join(".local/state").join(APP_DIR)),
+        None => own,
+    }
+}
+
+/// A cache file of the user pacfetch is acting for: under sudo the invoking user's, so the
+/// package index `sudo pacfetch -Syu` refreshes is the one their searches read.
+pub fn user_cache_file(name: &str) -> Option<PathBuf> {
+    match util::sudo_user_home() {
+        Some(home) => Some(home.join(".cache").join(APP_DIR).join(name)),
+        None => cache_file(name),
+    }
+}
+
+/// Give a file root wrote into the sudo user's home or runtime directory back to them,
+/// along with the directories created for it, so they can still change it without sudo.
+pub fn return_to_sudo_user(path: &Path) -> Result<(), String> {
+    #[cfg(unix)]
+    if util::is_root()
+        && let (Ok(sudo_uid), Ok(gid)) = (std::env::var("SUDO_UID"), std::env::var("SUDO_GID"))
+    {
+        use std::os::unix::fs::MetadataExt;
+        let (Ok(uid), Ok(gid)) = (sudo_uid.parse(), gid.parse()) else {
+            return Ok(());
+        };
+        let Some(root) = [
+            util::sudo_user_home(),
+            sudo_runtime_dir(Some(&sudo_uid), |dir| dir.is_dir()),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|root| path.starts_with(root)) else {
+            return Ok(());
+        };
+        for owned in path
+            .ancestors()
+            .take_while(|p| p.starts_with(&root) && *p != root)
+        {
+            if fs::metadata(owned).is_ok_and(|meta| meta.uid() == 0) {
+                std::os::unix::fs::chown(owned, Some(uid), Some(gid))
+                    .map_err(|e| format!("Failed to chown {}: {}", owned.display(), e))?;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// A file in the cache directory, e.g. `cache_file("stats.json")`.
+pub fn cache_file(name: &str) -> Option<PathBuf> {
+    cache_dir().map(|p| p.join(name))
+}
+
+/// Create the directory a file is about to be written to.
+pub fn ensure_parent(path: &Path) -> Result<(), String> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e)),
+        None => Ok(()),
+    }
+}
+
+/// pacfetch's own state, cache and data directories that exist; the config is left alone.
+pub fn data_dirs() -> Vec<PathBuf> {
+    [state_dir(), cache_dir(), data_dir()]
+        .into_iter()
+        .flatten()
+        .filter(|dir| dir.exists())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_the_sudo_users_state_dir() {
+        let own = Some(PathBuf::from("/root/.local/state/pacfetch"));
+        assert_eq!(
+            user_state_dir(Some(Path::new("/home/me")), own.clone()),
+            Some(PathBuf::from("/home/me/.local/state/pacfetch"))
+        );
+        assert_eq!(user_state_dir(None, own.clone()), own);
+    }
+
+    #[test]
+    fn resolves_the_sudo_users_runtime_dir() {
+        assert_eq!(
+            sudo_runtime_dir(Some("1000"), |_| true),
+            Some(PathBuf::from("/run/user/1000"))
+        );
+        assert_eq!(sudo_runtime_dir(Some("1000"), |_| false), None);
+        assert_eq!(sudo_runtime_dir(Some("../0"), |_| true), None);
+        assert_eq!(sudo_runtime_dir(None, |_| true), None);
+    }
+}
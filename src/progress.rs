@@ -0,0 +1,172 @@
+use serde::Serialize;
+
+use crate::util;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Sync,
+    Download,
+    Keyring,
+    Install,
+    Hooks,
+}
+
+pub const PHASES: [Phase; 5] = [
+    Phase::Sync,
+    Phase::Download,
+    Phase::Keyring,
+    Phase::Install,
+    Phase::Hooks,
+];
+
+impl Phase {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Phase::Sync => "Sync databases",
+            Phase::Download => "Download",
+            Phase::Keyring => "Keyring check",
+            Phase::Install => "Install",
+            Phase::Hooks => "Hooks",
+        }
+    }
+}
+
+/// Progress within a phase, as reported by one line of pacman output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressUpdate {
+    pub phase: Phase,
+    /// Completed fraction of the phase, when the line reports one.
+    pub ratio: Option<f64>,
+    /// Package, hook or step the line is about.
+    pub item: Option<String>,
+}
+
+/// Steps pacman runs between downloading and installing, in order.
+const CHECK_STEPS: [&str; 5] = [
+    "checking keys in keyring",
+    "checking package integrity",
+    "loading package files",
+    "checking for file conflicts",
+    "checking available disk space",
+];
+
+/// Follows pacman's transaction output and maps each line onto a phase.
+#[derive(Default)]
+pub struct ProgressParser {
+    phase: Option<Phase>,
+    pre_hooks: bool,
+    saw_download_total: bool,
+}
+
+impl ProgressParser {
+    pub fn feed(&mut self, line: &str) -> Option<ProgressUpdate> {
+        let clean = util::strip_ansi(line);
+        let trimmed = clean.trim();
+
+        if trimmed.starts_with(":: Retrieving packages") {
+            return self.enter(Phase::Download, None);
+        }
+        if trimmed.starts_with(":: Running pre-transaction hooks") {
+            self.pre_hooks = true;
+            return self.enter(Phase::Install, Some("pre-transaction hooks"));
+        }
+        if trimmed.starts_with(":: Processing package changes") {
+            self.pre_hooks = false;
+            return self.enter(Phase::Install, None);
+        }
+        if trimmed.starts_with(":: Running post-transaction hooks") {
+            return self.enter(Phase::Hooks, None);
+        }
+
+        if let Some(step) = CHECK_STEPS.iter().position(|s| trimmed.contains(s)) {
+            self.phase = Some(Phase::Keyring);
+            let step_ratio = counter_ratio(trimmed).unwrap_or(0.0);
+            return Some(ProgressUpdate {
+                phase: Phase::Keyring,
+                ratio: Some((step as f64 + step_ratio) / CHECK_STEPS.len() as f64),
+                item: Some(CHECK_STEPS[step].to_string()),
+            });
+        }
+
+        match self.phase? {
+            Phase::Download => self.download_line(trimmed),
+            Phase::Install if !self.pre_hooks => {
+                let (action, name) = install_action(trimmed)?;
+                Some(ProgressUpdate {
+                    phase: Phase::Install,
+                    ratio: counter_ratio(trimmed),
+                    item: Some(format!("{} {}", action, name)),
+                })
+            }
+            Phase::Hooks => {
+                let ratio = counter_ratio(trimmed)?;
+                let hook = trimmed.split_once(") ").map(|(_, rest)| rest)?;
+                Some(ProgressUpdate {
+                    phase: Phase::Hooks,
+                    ratio: Some(ratio),
+                    item: Some(hook.trim_end_matches("...").to_string()),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn enter(&mut self, phase: Phase, item: Option<&str>) -> Option<ProgressUpdate> {
+        self.phase = Some(phase);
+        Some(ProgressUpdate {
+            phase,
+            ratio: Some(0.0),
+            item: item.map(str::to_string),
+        })
+    }
+
+    fn download_line(&mut self, line: &str) -> Option<ProgressUpdate> {
+        let percent = line
+            .split_whitespace()
+            .last()?
+            .strip_suffix('%')?
+            .parse::<f64>()
+            .ok()?;
+        let ratio = Some((percent / 100.0).clamp(0.0, 1.0));
+
+        if line.starts_with("Total") {
+            self.saw_download_total = true;
+            return Some(ProgressUpdate {
+                phase: Phase::Download,
+                ratio,
+                item: None,
+            });
+        }
+
+        // With several packages only the "Total" line tracks the phase as a whole
+        let name = line.split_whitespace().next()?.to_string();
+        Some(ProgressUpdate {
+            phase: Phase::Download,
+            ratio: if self.saw_download_total { None } else { ratio },
+            item: Some(name),
+        })
+    }
+}
+
+/// Parses pacman's "(3/12)" counters into a completed fraction.
+fn counter_ratio(line: &str) -> Option<f64> {
+    let inner = line.strip_prefix('(')?.split(')').next()?;
+    let (done, total) = inner.split_once('/')?;
+    let done: f64 = done.trim().parse().ok()?;
+    let total: f64 = total.trim().parse().ok()?;
+    (total > 0.0).then(|| (done / total).clamp(0.0, 1.0))
+}
+
+/// Splits "(3/12) upgrading linux  [###] 100%" into ("upgrading", "linux").
+fn install_action(line: &str) -> Option<(&str, &str)> {
+    let rest = line.split_once(") ")?.1;
+    let mut words = rest.split_whitespace();
+    let action = words.next()?;
+    let name = words.next()?;
+    matches!(
+        action,
+        "installing" | "upgrading" | "downgrading" | "reinstalling" | "removing"
+    )
+    .then_some((action, name))
+}
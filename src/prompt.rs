@@ -0,0 +1,243 @@
+use std::fs;
+use std::path::Path;
+
+use crate::stats::{ManagerStats, StatId};
+use crate::{paths, storage};
+
+/// Identifies the file and its layout version.
+const MAGIC: [u8; 4] = *b"PFP1";
+const LEN: usize = 32;
+/// Stored for a count that hasn't been collected yet.
+const UNKNOWN: u32 = u32::MAX;
+
+/// What `pacfetch prompt` shows, kept in a fixed 32-byte little-endian file in the
+/// runtime directory so a shell prompt can read it without touching pacman:
+///
+/// | bytes  | field                        |
+/// |--------|------------------------------|
+/// | 0..4   | magic `PFP1`                 |
+/// | 4..12  | written at, unix seconds i64 |
+/// | 12..16 | upgradable u32               |
+/// | 16..20 | AUR updates u32              |
+/// | 20..24 | orphaned packages u32        |
+/// | 24..28 | days since last update u32   |
+/// | 28..32 | reserved                     |
+///
+/// Counts are `u32::MAX` until a run collects them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PromptCache {
+    pub written_at: i64,
+    pub upgradable: Option<u32>,
+    pub aur_updates: Option<u32>,
+    pub orphaned_packages: Option<u32>,
+    pub days_since_last_update: Option<u32>,
+}
+
+impl PromptCache {
+    fn empty() -> PromptCache {
+        PromptCache {
+            written_at: 0,
+            upgradable: None,
+            aur_updates: None,
+            orphaned_packages: None,
+            days_since_last_update: None,
+        }
+    }
+
+    fn encode(&self) -> [u8; LEN] {
+        let mut bytes = [0u8; LEN];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..12].copy_from_slice(&self.written_at.to_le_bytes());
+        let counts = [
+            self.upgradable,
+            self.aur_updates,
+            self.orphaned_packages,
+            self.days_since_last_update,
+        ];
+        for (i, count) in counts.iter().enumerate() {
+            let at = 12 + i * 4;
+            bytes[at..at + 4].copy_from_slice(&count.unwrap_or(UNKNOWN).to_le_bytes());
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<PromptCache> {
+        if bytes.len() != LEN || bytes[0..4] != MAGIC {
+            return None;
+        }
+        let count = |at: usize| {
+            let value = u32::from_le_bytes(bytes[at..at + 4].try_into().ok()?);
+            (value != UNKNOWN).then_some(value)
+        };
+        Some(PromptCache {
+            written_at: i64::from_le_bytes(bytes[4..12].try_into().ok()?),
+            upgradable: count(12),
+            aur_updates: count(16),
+            orphaned_packages: count(20),
+            days_since_last_update: count(24),
+        })
+    }
+
+    /// Take the counts of the `requested` stats, leaving the rest as they were.
+    fn record(&mut self, requested: &[StatId], stats: &ManagerStats, now: i64) {
+        self.written_at = now;
+        if requested.contains(&StatId::Upgradable) {
+            self.upgradable = stats.total_upgradable;
+        }
+        if requested.contains(&StatId::AurUpdates) {
+            self.aur_updates = stats.aur_updates;
+        }
+        if requested.contains(&StatId::OrphanedPackages) {
+            self.orphaned_packages = stats.orphaned_packages;
+        }
+        if requested.contains(&StatId::LastUpdate) {
+            // The stat holds the time since in seconds, despite its name
+            self.days_since_last_update = stats
+                .days_since_last_update
+                .and_then(|secs| u32::try_from(secs / 86400).ok());
+        }
+    }
+
+    /// Whether the cache was written within `max_age` seconds, if there's a limit.
+    pub fn is_fresh(&self, max_age: Option<i64>, now: i64) -> bool {
+        max_age.is_none_or(|max| now - self.written_at <= max)
+    }
+
+    /// Fill a `prompt --format` template: {updates}, {aur}, {orphans}, {days} and {age}
+    /// (the cache's age in minutes). Counts not collected yet show as "?".
+    pub fn render(&self, format: &str, now: i64) -> String {
+        let show = |count: Option<u32>| count.map_or("?".to_string(), |n| n.to_string());
+        let age_mins = (now - self.written_at).max(0) / 60;
+        format
+            .replace("{updates}", &show(self.upgradable))
+            .replace("{aur}", &show(self.aur_updates))
+            .replace("{orphans}", &show(self.orphaned_packages))
+            .replace("{days}", &show(self.days_since_last_update))
+            .replace("{age}", &age_mins.to_string())
+    }
+}
+
+/// The prompt cache, if a run has written one.
+pub fn read() -> Option<PromptCache> {
+    read_file(&paths::prompt_file()?)
+}
+
+/// A missing, truncated or foreign file reads as no cache.
+fn read_file(path: &Path) -> Option<PromptCache> {
+    PromptCache::decode(&fs::read(path).ok()?)
+}
+
+/// Update the prompt cache with the stats a run just collected. Counts it didn't collect
+/// keep their earlier value, so a run showing only some stats doesn't blank the rest.
+pub fn update(requested: &[StatId], stats: &ManagerStats, now: i64) -> Result<(), String> {
+    let path = paths::prompt_file().ok_or("could not determine the runtime directory")?;
+    paths::ensure_parent(&path)?;
+
+    let mut cache = read().unwrap_or_else(PromptCache::empty);
+    cache.record(requested, stats, now);
+
+    // Written aside and renamed so a prompt never reads half a file
+    storage::write_atomic(&path, cache.encode())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    paths::return_to_sudo_user(&path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_the_layout() {
+        let cache = PromptCache {
+            written_at: 1718010824,
+            upgradable: Some(12),
+            aur_updates: None,
+            orphaned_packages: Some(0),
+            days_since_last_update: Some(3),
+        };
+        let bytes = cache.encode();
+        assert_eq!(&bytes[0..4], b"PFP1");
+        assert_eq!(&bytes[12..16], &12u32.to_le_bytes());
+        assert_eq!(PromptCache::decode(&bytes), Some(cache));
+        assert_eq!(PromptCache::decode(&bytes[..16]), None);
+    }
+
+    #[test]
+    fn renders_format() {
+        let cache = PromptCache {
+            written_at: 1000,
+            upgradable: Some(12),
+            ..PromptCache::empty()
+        };
+        assert_eq!(
+            cache.render("{updates} ({aur} aur, {age}m)", 1000 + 180),
+            "12 (? aur, 3m)"
+        );
+    }
+
+    #[test]
+    fn renders_every_segment() {
+        let cache = PromptCache {
+            written_at: 1000,
+            upgradable: Some(0),
+            aur_updates: Some(2),
+            orphaned_packages: None,
+            days_since_last_update: Some(14),
+        };
+        assert_eq!(
+            cache.render(
+                "{updates}/{aur}/{orphans}/{days}/{age} {other}",
+                1000 + 3599
+            ),
+            "0/2/?/14/59 {other}"
+        );
+        // A clock set back since the write doesn't make the age negative
+        assert_eq!(cache.render("{age}", 400), "0");
+    }
+
+    #[test]
+    fn reads_stale_and_corrupt_caches() {
+        let dir = std::env::temp_dir().join(format!("pacfetch-prompt-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("prompt.bin");
+
+        assert_eq!(read_file(&path), None);
+        let cache = PromptCache {
+            written_at: 1000,
+            upgradable: Some(5),
+            ..PromptCache::empty()
+        };
+        storage::write_atomic(&path, cache.encode()).unwrap();
+        let read = read_file(&path).unwrap();
+        assert_eq!(read, cache);
+        assert!(read.is_fresh(Some(600), 1600));
+        assert!(!read.is_fresh(Some(600), 1601));
+        assert!(read.is_fresh(None, i64::MAX));
+
+        let mut bytes = cache.encode();
+        bytes[0..4].copy_from_slice(b"PFP0");
+        fs::write(&path, bytes).unwrap();
+        assert_eq!(read_file(&path), None);
+        fs::write(&path, &cache.encode()[..20]).unwrap();
+        assert_eq!(read_file(&path), None);
+        fs::write(&path, [cache.encode(), [0; LEN]].concat()).unwrap();
+        assert_eq!(read_file(&path), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn records_whole_days_since_the_last_update() {
+        let stats = ManagerStats {
+            total_upgradable: Some(4),
+            days_since_last_update: Some(3 * 86400 + 7200),
+            orphaned_packages: Some(2),
+            ..ManagerStats::default()
+        };
+        let mut cache = PromptCache::empty();
+        cache.record(&[StatId::Upgradable, StatId::LastUpdate], &stats, 500);
+        assert_eq!(cache.days_since_last_update, Some(3));
+        assert_eq!(cache.upgradable, Some(4));
+        assert_eq!(cache.orphaned_packages, None);
+        assert_eq!(cache.written_at, 500);
+    }
+}
@@ -0,0 +1,377 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::storage;
+use crate::terminal::TerminalGuard;
+
+/// How long a partial line has to sit unanswered before it's taken for a prompt.
+const PROMPT_IDLE: Duration = Duration::from_millis(500);
+
+static PROMPT_PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+
+/// Extra regexes that mark a partial line as a prompt. Only the first call has an effect.
+pub fn set_prompt_patterns(patterns: Vec<Regex>) {
+    let _ = PROMPT_PATTERNS.set(patterns);
+}
+
+/// Receives the output of a command running in a pseudo-terminal.
+pub trait PtyHandler {
+    /// A line terminated by `\n`.
+    fn line(&mut self, line: &str);
+
+    /// A line terminated by `\r`, which the command is about to redraw (e.g. a progress bar).
+    fn redraw(&mut self, line: &str);
+
+    /// The command is waiting on a prompt. Returns the answer to send, if any.
+    fn prompt(&mut self, prompt: &str) -> Option<String>;
+
+    /// Called while waiting for output. Returns raw input to forward to the command, if any.
+    fn idle(&mut self) -> Option<String> {
+        None
+    }
+}
+
+/// Yes/no choices in any language, e.g. "[Y/n] " or "[J/n] ".
+fn choice_prompt() -> &'static Regex {
+    static CHOICE: OnceLock<Regex> = OnceLock::new();
+    CHOICE.get_or_init(|| Regex::new(r"\[\p{L}/\p{L}\] $").expect("valid prompt regex"))
+}
+
+fn is_prompt(line: &str) -> bool {
+    let clean = crate::util::strip_ansi(line);
+    choice_prompt().is_match(&clean)
+        || (clean.contains("::") && clean.ends_with("]: "))
+        || PROMPT_PATTERNS
+            .get()
+            .is_some_and(|patterns| patterns.iter().any(|re| re.is_match(&clean)))
+}
+
+/// A partial line the command has gone quiet on, ending like a question rather than
+/// like a progress bar: most likely a prompt the patterns don't know, e.g. a translation.
+fn looks_like_waiting(line: &str) -> bool {
+    let clean = crate::util::strip_ansi(line);
+    let trimmed = clean.trim_end();
+    !trimmed.is_empty() && trimmed.ends_with([':', '?', ']', '>'])
+}
+
+/// Splits a command's output into lines, redraws and prompts for a handler.
+#[derive(Default)]
+struct Splitter {
+    line_buffer: String,
+    /// A `\r` was just seen: a redraw, unless the `\n` of a pty line ending follows.
+    carriage_return: bool,
+}
+
+impl Splitter {
+    /// Feed one chunk of output. Returns the answers to prompts it completed, in order.
+    fn feed(&mut self, chunk: &str, handler: &mut dyn PtyHandler) -> Vec<String> {
+        let mut answers = Vec::new();
+        for ch in chunk.chars() {
+            if std::mem::take(&mut self.carriage_return) && ch != '\n' {
+                if !self.line_buffer.is_empty() {
+                    handler.redraw(&self.line_buffer);
+                }
+                self.line_buffer.clear();
+            }
+
+            if ch == '\n' {
+                handler.line(&self.line_buffer);
+                self.line_buffer.clear();
+            } else if ch == '\r' {
+                self.carriage_return = true;
+            } else {
+                self.line_buffer.push(ch);
+
+                if is_prompt(&self.line_buffer) {
+                    answers.extend(handler.prompt(&self.line_buffer));
+                    self.line_buffer.clear();
+                }
+            }
+        }
+        answers
+    }
+
+    /// The command has been quiet for `quiet`: take the partial line if it looks like a
+    /// question it's waiting on.
+    fn take_idle_prompt(&mut self, quiet: Duration) -> Option<String> {
+        if quiet < PROMPT_IDLE || self.carriage_return || !looks_like_waiting(&self.line_buffer) {
+            return None;
+        }
+        Some(std::mem::take(&mut self.line_buffer))
+    }
+
+    fn finish(&mut self, handler: &mut dyn PtyHandler) {
+        if self.line_buffer.is_empty() {
+            return;
+        }
+        if std::mem::take(&mut self.carriage_return) {
+            handler.redraw(&self.line_buffer);
+        } else {
+            handler.line(&self.line_buffer);
+        }
+        self.line_buffer.clear();
+    }
+}
+
+/// One chunk of output, `at_ms` after the command started.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    pub at_ms: u64,
+    pub data: String,
+}
+
+/// A command's output as it arrived, for replaying it through the handlers later.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Recording {
+    pub command: String,
+    pub chunks: Vec<Chunk>,
+}
+
+static RECORD_PATH: OnceLock<PathBuf> = OnceLock::new();
+static RECORDINGS: Mutex<Vec<Recording>> = Mutex::new(Vec::new());
+
+/// Record every session of this run to `path` (`--record`). Only the first call has an effect.
+pub fn set_record_path(path: PathBuf) {
+    let _ = RECORD_PATH.set(path);
+}
+
+/// Add a finished session to the recording file, which holds all sessions of the run.
+fn save_recording(recording: Recording) {
+    let Some(path) = RECORD_PATH.get() else {
+        return;
+    };
+    let Ok(mut recordings) = RECORDINGS.lock() else {
+        return;
+    };
+    recordings.push(recording);
+
+    let result = serde_json::to_string_pretty(&*recordings)
+        .map_err(|e| e.to_string())
+        .and_then(|json| storage::write_atomic(path, json).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        eprintln!(
+            "warning: Failed to write recording {}: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Read the sessions saved by `--record`.
+pub fn load_recordings(path: &Path) -> Result<Vec<Recording>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&contents).map_err(|e| format!("Invalid recording: {}", e))
+}
+
+/// Feed a recorded session through `handler` as if the command were running, gaps between
+/// chunks included. Returns the answers the handler gave to prompts.
+pub fn replay(recording: &Recording, handler: &mut dyn PtyHandler) -> Vec<String> {
+    let mut splitter = Splitter::default();
+    let mut answers = Vec::new();
+    let mut last_at = 0;
+    for chunk in &recording.chunks {
+        let gap = Duration::from_millis(chunk.at_ms.saturating_sub(last_at));
+        if let Some(prompt) = splitter.take_idle_prompt(gap) {
+            answers.extend(handler.prompt(&prompt));
+        }
+        answers.extend(splitter.feed(&chunk.data, handler));
+        last_at = chunk.at_ms;
+    }
+    if let Some(prompt) = splitter.take_idle_prompt(PROMPT_IDLE) {
+        answers.extend(handler.prompt(&prompt));
+    }
+    splitter.finish(handler);
+    answers
+}
+
+/// Whether `session`'s command is still running: None while it is, then whether it exited
+/// successfully.
+#[cfg(unix)]
+fn exit_status(session: &expectrl::Session) -> Option<bool> {
+    match session.get_process().status() {
+        Ok(expectrl::WaitStatus::StillAlive) => None,
+        Ok(expectrl::WaitStatus::Exited(_, code)) => Some(code == 0),
+        _ => Some(false),
+    }
+}
+
+#[cfg(windows)]
+fn exit_status(session: &expectrl::Session) -> Option<bool> {
+    let process = session.get_process();
+    if process.is_alive() {
+        return None;
+    }
+    Some(process.wait(Some(0)).is_ok_and(|code| code == 0))
+}
+
+/// Run `cmd` in a pseudo-terminal sized like ours, feeding its output to `handler`.
+/// Gives whether the command exited successfully.
+pub fn run(cmd: &str, handler: &mut dyn PtyHandler) -> Result<bool, String> {
+    let mut session =
+        expectrl::spawn(cmd).map_err(|e| format!("Failed to spawn {}: {}", cmd, e))?;
+    // The command's colors and hidden cursor reach our terminal, and outlive it if it dies
+    let _output = TerminalGuard::output();
+
+    #[cfg(unix)]
+    if let Ok((cols, rows)) = crossterm::terminal::size() {
+        let _ = session.get_process_mut().set_window_size(cols, rows);
+    }
+
+    session.set_expect_timeout(Some(Duration::from_millis(100)));
+
+    let mut splitter = Splitter::default();
+    let started = Instant::now();
+    let mut last_output = Instant::now();
+    let mut recording = RECORD_PATH.get().map(|_| Recording {
+        command: cmd.to_string(),
+        chunks: Vec::new(),
+    });
+
+    let success = loop {
+        if let Some(success) = exit_status(&session) {
+            break success;
+        }
+
+        let mut buf = [0u8; 1024];
+        match session.try_read(&mut buf) {
+            Ok(0) => continue,
+            Ok(n) => {
+                last_output = Instant::now();
+                let chunk = String::from_utf8_lossy(&buf[..n]);
+                if let Some(recording) = &mut recording {
+                    recording.chunks.push(Chunk {
+                        at_ms: started.elapsed().as_millis() as u64,
+                        data: chunk.to_string(),
+                    });
+                }
+
+                for answer in splitter.feed(&chunk, handler) {
+                    let _ = session.send_line(answer.trim());
+                }
+            }
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted => {
+                    if let Some(prompt) = splitter.take_idle_prompt(last_output.elapsed()) {
+                        if let Some(answer) = handler.prompt(&prompt) {
+                            let _ = session.send_line(answer.trim());
+                        }
+                        continue;
+                    }
+                    if let Some(input) = handler.idle() {
+                        let _ = session.send(input);
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                _ => break exit_status(&session).unwrap_or(false),
+            },
+        }
+    };
+
+    splitter.finish(handler);
+    if let Some(recording) = recording {
+        save_recording(recording);
+    }
+
+    Ok(success)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects what the splitter hands out, answering every prompt with "y".
+    #[derive(Default)]
+    struct Events {
+        lines: Vec<String>,
+        redraws: Vec<String>,
+        prompts: Vec<String>,
+    }
+
+    impl PtyHandler for Events {
+        fn line(&mut self, line: &str) {
+            self.lines.push(line.to_string());
+        }
+
+        fn redraw(&mut self, line: &str) {
+            self.redraws.push(line.to_string());
+        }
+
+        fn prompt(&mut self, prompt: &str) -> Option<String> {
+            self.prompts.push(prompt.to_string());
+            Some("y".to_string())
+        }
+    }
+
+    fn recording(chunks: &[(u64, &str)]) -> Recording {
+        Recording {
+            command: "pacman -Su".to_string(),
+            chunks: chunks
+                .iter()
+                .map(|(at_ms, data)| Chunk {
+                    at_ms: *at_ms,
+                    data: data.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn replays_fixture_session() {
+        let recordings: Vec<Recording> =
+            serde_json::from_str(include_str!("../tests/fixtures/upgrade_session.json")).unwrap();
+        let mut events = Events::default();
+        let answers = replay(&recordings[0], &mut events);
+
+        assert_eq!(answers, ["y"]);
+        assert_eq!(events.prompts, [":: Proceed with installation? [Y/n] "]);
+        assert!(events.lines.iter().any(|l| l.contains("upgrading linux")));
+        assert!(events.redraws.iter().any(|l| l.contains("50%")));
+    }
+
+    #[test]
+    fn splits_lines_across_chunks() {
+        let mut events = Events::default();
+        replay(
+            &recording(&[(0, "resolving depen"), (5, "dencies...\n")]),
+            &mut events,
+        );
+        assert_eq!(events.lines, ["resolving dependencies..."]);
+    }
+
+    #[test]
+    fn detects_localized_choice() {
+        let mut events = Events::default();
+        let answers = replay(
+            &recording(&[(0, ":: Installation fortsetzen? [J/n] ")]),
+            &mut events,
+        );
+        assert_eq!(answers, ["y"]);
+        assert_eq!(events.prompts.len(), 1);
+    }
+
+    #[test]
+    fn takes_quiet_question_for_prompt() {
+        let mut events = Events::default();
+        replay(
+            &recording(&[(0, ":: Remplacer foo par bar ? "), (2000, "\n")]),
+            &mut events,
+        );
+        assert_eq!(events.prompts, [":: Remplacer foo par bar ? "]);
+    }
+
+    #[test]
+    fn leaves_stalled_progress_alone() {
+        let mut events = Events::default();
+        replay(
+            &recording(&[(0, " linux-6.9  [####-----]  45%"), (2000, "\r")]),
+            &mut events,
+        );
+        assert!(events.prompts.is_empty());
+        assert_eq!(events.redraws.len(), 1);
+    }
+}
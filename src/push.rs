@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use crate::config::{Config, PushConfig};
+use crate::stats::{self, ManagerStats};
+use crate::{managers, ui, util};
+
+/// The stats a push sends. `pacfetch push` and the daemon both gather them here, so a
+/// gateway gets the same metric set whichever of them sent it.
+pub fn gather(config: &Config) -> ManagerStats {
+    let mut config = config.clone();
+    config.display.stats = stats::metric_stats();
+    managers::get_stats(&config, false, None)
+}
+
+/// POST the stats as JSON to `push.url`, tagged with this host and the current time.
+pub fn push(stats: &ManagerStats, config: &Config) -> Result<(), String> {
+    let PushConfig { url, token } = &config.push;
+    let Some(url) = url else {
+        return Err("push.url is not set in the config".to_string());
+    };
+    // The token travels with every request, so don't send it in the clear
+    if !url.starts_with("https://") {
+        return Err(format!("push.url must use https: {}", url));
+    }
+
+    let payload = serde_json::json!({
+        "host": util::hostname(),
+        "timestamp": chrono::Local::now().timestamp(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "stats": ui::stats_json(stats, config),
+    });
+
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let mut request = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(payload.to_string());
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+
+    let response = request
+        .send()
+        .map_err(|e| format!("Failed to push stats to {}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("{} rejected the stats: {}", url, response.status()));
+    }
+    Ok(())
+}
@@ -0,0 +1,212 @@
+use std::fmt::Write as _;
+use std::fs;
+
+use crate::config::Config;
+use crate::distro::{self, Distro};
+#[cfg(target_os = "linux")]
+use crate::pacman;
+use crate::{crash, history, managers, paths, ui};
+
+const PACMAN_LOG_PATH: &str = "/var/log/pacman.log";
+/// How much of pacman.log goes in, enough to show the last transaction or two.
+const LOG_LINES: usize = 30;
+/// Crash logs included, newest first; older ones rarely matter to the issue at hand.
+const CRASH_LOGS: usize = 2;
+#[cfg(target_os = "linux")]
+const MIRROR_LINES: usize = 5;
+/// Config keys whose values are left out entirely rather than just having URLs redacted.
+const SECRET_KEYS: [&str; 4] = ["token", "password", "secret", "auth"];
+
+/// A markdown diagnostics report to paste into a GitHub issue. Hostnames, secrets and the
+/// home directory are redacted, so it says how pacfetch is set up without saying where.
+pub fn build(config: &Config) -> String {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let mut out = String::from("## pacfetch bug report\n\n");
+
+    out.push_str("### System\n\n");
+    let _ = writeln!(out, "- pacfetch: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(out, "- Distro: {}", Distro::detect().name());
+    let kernel = fs::read_to_string("/proc/sys/kernel/osrelease").unwrap_or_default();
+    let _ = writeln!(
+        out,
+        "- Kernel: {} ({})",
+        kernel.trim(),
+        distro::machine_arch()
+    );
+    #[cfg(target_os = "linux")]
+    if let Some(version) = pacman::get_pacman_version() {
+        let _ = writeln!(out, "- Pacman: {}", version);
+    }
+    let backends: Vec<&str> = managers::list()
+        .into_iter()
+        .filter(|(_, installed)| *installed)
+        .map(|(name, _)| name)
+        .collect();
+    let _ = writeln!(out, "- Backends: {}", backends.join(", "));
+    let _ = writeln!(
+        out,
+        "- Selected backend: {}",
+        config.backend.as_deref().unwrap_or("detected")
+    );
+
+    out.push_str("\n### Config\n\n");
+    match paths::config_file().and_then(|path| fs::read_to_string(path).ok()) {
+        Some(contents) => code_block(&mut out, "toml", &redact_config(&contents, &home)),
+        None => out.push_str("No config file; using the defaults.\n"),
+    }
+
+    #[cfg(target_os = "linux")]
+    write_mirrors(&mut out, config);
+
+    out.push_str("\n### Recent pacman.log\n\n");
+    match fs::read_to_string(PACMAN_LOG_PATH) {
+        Ok(log) => code_block(&mut out, "", &redact_home(&tail(&log, LOG_LINES), &home)),
+        Err(e) => {
+            let _ = writeln!(out, "Couldn't read {}: {}", PACMAN_LOG_PATH, e);
+        }
+    }
+
+    out.push_str("\n### Crash logs\n\n");
+    let crashes: Vec<String> = crash::logs()
+        .iter()
+        .take(CRASH_LOGS)
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .collect();
+    if crashes.is_empty() {
+        out.push_str("None recorded.\n");
+    }
+    for log in crashes {
+        code_block(&mut out, "", &redact_home(&log, &home));
+    }
+
+    out.push_str("\n### Timings\n\n");
+    match history::usage_summary() {
+        Ok(usage) => {
+            code_block(
+                &mut out,
+                "",
+                &ui::format_usage(&usage, chrono::Local::now().timestamp()),
+            );
+        }
+        Err(e) => {
+            let _ = writeln!(out, "Couldn't read the usage history: {}", e);
+        }
+    }
+    out
+}
+
+#[cfg(target_os = "linux")]
+fn write_mirrors(out: &mut String, config: &Config) {
+    out.push_str("\n### Mirrors\n\n");
+    let mirrors: Vec<String> = pacman::get_mirror_urls(&config.mirror)
+        .iter()
+        .take(MIRROR_LINES)
+        .map(|url| redact_url(url))
+        .collect();
+    if mirrors.is_empty() {
+        out.push_str("No mirrors found.\n");
+    } else {
+        code_block(out, "", &mirrors.join("\n"));
+    }
+}
+
+fn code_block(out: &mut String, lang: &str, body: &str) {
+    let _ = writeln!(out, "```{}\n{}\n```", lang, body.trim_end());
+}
+
+/// The last `n` lines of `text`.
+fn tail(text: &str, n: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    lines[lines.len().saturating_sub(n)..].join("\n")
+}
+
+/// The config with secret values blanked and every URL's host hidden.
+fn redact_config(contents: &str, home: &str) -> String {
+    contents
+        .lines()
+        .map(|line| {
+            if let Some((key, _)) = line.split_once('=') {
+                let name = key.trim().to_ascii_lowercase();
+                if SECRET_KEYS.iter().any(|secret| name.contains(secret)) {
+                    return format!("{}= \"<redacted>\"", key);
+                }
+            }
+            redact_home(&redact_urls(line), home)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Every http(s) URL in `line` with its host, and any credentials, redacted.
+fn redact_urls(line: &str) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(start) = ["https://", "http://"]
+        .iter()
+        .filter_map(|scheme| rest.find(scheme))
+        .min()
+    {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+            .map_or(rest.len(), |len| start + len);
+        out.push_str(&redact_url(&rest[start..end]));
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A URL with its host replaced, keeping the scheme and path, which are what tell one
+/// mirror layout from another. The query and fragment go too, since tokens end up there.
+fn redact_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let rest = rest.find(['?', '#']).map_or(rest, |i| &rest[..i]);
+    let path = rest.find('/').map_or("", |i| &rest[i..]);
+    format!("{}://<redacted>{}", scheme, path)
+}
+
+fn redact_home(text: &str, home: &str) -> String {
+    if home.len() > 1 {
+        text.replace(home, "~")
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_hosts_secrets_and_home() {
+        assert_eq!(
+            redact_url("https://user:pw@mirror.example.org/archlinux/$repo/os/$arch"),
+            "https://<redacted>/archlinux/$repo/os/$arch"
+        );
+        assert_eq!(
+            redact_url("https://mirror.example.org/repo/os?token=abc123#x86_64"),
+            "https://<redacted>/repo/os"
+        );
+        assert_eq!(
+            redact_url("http://mirror.example.org?key=a/b"),
+            "http://<redacted>"
+        );
+        let config = "[push]\n\
+                      url = \"https://metrics.example.org:8086/write\"\n\
+                      auth_token = \"abc123\"\n\
+                      [cache]\n\
+                      dirs = [\"/home/me/.cache/paru\"]\n";
+        assert_eq!(
+            redact_config(config, "/home/me"),
+            "[push]\n\
+             url = \"https://<redacted>/write\"\n\
+             auth_token = \"<redacted>\"\n\
+             [cache]\n\
+             dirs = [\"~/.cache/paru\"]"
+        );
+        assert_eq!(tail("a\nb\nc\n", 2), "b\nc");
+    }
+}
@@ -0,0 +1,210 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use crate::{lock, util};
+
+/// Where the sandbox's tmpfs is mounted.
+const SANDBOX_PARENT: &str = "/var/tmp";
+/// The host filesystems hooks and scriptlets expect inside the sandbox root.
+const BIND_MOUNTS: [&str; 3] = ["dev", "proc", "sys"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemKind {
+    FileConflict,
+    HookFailure,
+    Error,
+}
+
+/// Something the simulated upgrade ran into that the real one would too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub message: String,
+}
+
+/// What the hook or scriptlet line `line` of pacman's output names, e.g. "(2/5) Arming
+/// ConditionNeedsUpdate..." or "upgrading linux...".
+fn step_name(line: &str) -> Option<&str> {
+    let line = match line.strip_prefix('(') {
+        Some(rest) => rest.split_once(") ")?.1,
+        None => line,
+    };
+    let step = line.trim().trim_end_matches("...").trim();
+    (!step.is_empty()).then_some(step)
+}
+
+/// Pick the predicted problems out of a pacman run's output: files another package or the
+/// filesystem already owns, hooks and scriptlets that failed, and any other error.
+fn parse_problems(output: &str) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut last_step: Option<&str> = None;
+    for line in output.lines().map(str::trim) {
+        if let Some((package, rest)) = line.split_once(": ")
+            && (rest.ends_with(" exists in filesystem") || rest.contains(" exists in both "))
+        {
+            problems.push(Problem {
+                kind: ProblemKind::FileConflict,
+                message: format!("{}: {}", package, rest),
+            });
+        } else if let Some(error) = line.strip_prefix("error: ") {
+            if error == "command failed to execute correctly" {
+                problems.push(Problem {
+                    kind: ProblemKind::HookFailure,
+                    message: format!("{} failed", last_step.unwrap_or("a hook")),
+                });
+            } else if !error.starts_with("failed to commit transaction") {
+                problems.push(Problem {
+                    kind: ProblemKind::Error,
+                    message: error.to_string(),
+                });
+            }
+        } else if !line.is_empty() && !line.starts_with("::") && !line.contains(": ") {
+            last_step = step_name(line).or(last_step);
+        }
+    }
+    problems
+}
+
+/// Exit status the setup script uses when the sandbox couldn't be built, as opposed to
+/// pacman failing inside it.
+const SETUP_FAILED: i32 = 125;
+
+/// Builds the sandbox and runs pacman in it, as `sh -c SCRIPT sh DIR`. It runs in a mount
+/// namespace of its own with private propagation, so none of its mounts reach the host's
+/// and all of them go away with the namespace when pacman exits. The host filesystems are
+/// bound one level deep and read-only: their submounts such as /dev/pts and cgroups stay
+/// out, and hooks can read /sys and /proc but not change them.
+const SETUP_SCRIPT: &str = r#"
+fail() { echo "$1" >&2; exit 125; }
+mount --make-rprivate / || fail "could not make / private"
+mount -t tmpfs pacfetch-simulate "$1" || fail "could not mount the tmpfs"
+mkdir "$1/upper" "$1/work" "$1/root" || fail "could not set up the tmpfs"
+mount -t overlay overlay -o "lowerdir=/,upperdir=$1/upper,workdir=$1/work" "$1/root" \
+    || fail "could not mount the overlay"
+for name in BIND_MOUNTS; do
+    mount --bind "/$name" "$1/root/$name" || fail "could not bind /$name"
+    mount -o remount,bind,ro "$1/root/$name" || fail "could not make /$name read-only"
+done
+exec pacman --sysroot "$1/root" -Su --noconfirm --noprogressbar
+"#;
+
+/// A copy-on-write view of `/` for a trial upgrade: an overlay whose upper layer lives on
+/// a tmpfs, so files pacman writes never reach the real system. It isn't a security
+/// boundary, since hooks still run as root in the host's process tree and can write to
+/// device nodes. The directory the tmpfs is mounted on is removed when dropped.
+struct Sandbox {
+    dir: PathBuf,
+}
+
+impl Sandbox {
+    fn create() -> Result<Sandbox, String> {
+        let dir =
+            Path::new(SANDBOX_PARENT).join(format!("pacfetch-simulate-{}", std::process::id()));
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        Ok(Sandbox { dir })
+    }
+
+    /// Run the sysupgrade in the sandbox, in its own mount namespace.
+    fn run_upgrade(&self) -> Result<Output, String> {
+        let script = SETUP_SCRIPT.replace("BIND_MOUNTS", &BIND_MOUNTS.join(" "));
+        let output = Command::new("unshare")
+            .args([
+                "--mount",
+                "--propagation",
+                "private",
+                "sh",
+                "-c",
+                &script,
+                "sh",
+            ])
+            .arg(&self.dir)
+            .env("LC_ALL", "C")
+            .output()
+            .map_err(|e| format!("Failed to run unshare: {}", e))?;
+        if output.status.code() == Some(SETUP_FAILED) {
+            return Err(format!(
+                "Failed to set up the sandbox: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(output)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        // The mounts were only ever in the namespace, which is gone once pacman exits
+        let _ = fs::remove_dir(&self.dir);
+    }
+}
+
+/// Run the pending sysupgrade inside a sandbox and report what went wrong in it. pacman
+/// runs with `--sysroot`, so it uses the sandbox's copy of the databases, cache and hooks;
+/// whatever it downloads is thrown away with the sandbox. Ignore, hold and pin settings
+/// aren't applied, so this covers every pending update.
+pub fn simulate_upgrade(debug: bool) -> Result<Vec<Problem>, String> {
+    if !util::is_root() {
+        return Err("you cannot perform this operation unless you are root.".to_string());
+    }
+    // An upgrade changing / underneath the overlay would leave the sandbox inconsistent
+    let _lock = lock::exclusive()?;
+
+    let spinner = util::create_spinner("Simulating upgrade");
+    let output = Sandbox::create().and_then(|sandbox| sandbox.run_upgrade());
+    spinner.finish_and_clear();
+    let output = output?;
+
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    if debug {
+        eprintln!("{}", text);
+    }
+    let mut problems = parse_problems(&text);
+    if problems.is_empty() && !output.status.success() {
+        problems.push(Problem {
+            kind: ProblemKind::Error,
+            message: format!("pacman exited with {}", output.status),
+        });
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_conflicts_and_failed_hooks() {
+        let output = "\
+:: Proceed with installation? [Y/n]
+checking for file conflicts...
+error: failed to commit transaction (conflicting files)
+python-foo: /usr/lib/python3.12/foo/__init__.py exists in filesystem
+Errors occurred, no packages were upgraded.
+:: Running post-transaction hooks...
+(1/2) Arming ConditionNeedsUpdate...
+(2/2) Updating linux initcpios...
+error: command failed to execute correctly
+";
+        assert_eq!(
+            parse_problems(output),
+            [
+                Problem {
+                    kind: ProblemKind::FileConflict,
+                    message: "python-foo: /usr/lib/python3.12/foo/__init__.py exists in filesystem"
+                        .to_string(),
+                },
+                Problem {
+                    kind: ProblemKind::HookFailure,
+                    message: "Updating linux initcpios failed".to_string(),
+                },
+            ]
+        );
+        assert!(parse_problems("upgrading vim...\n").is_empty());
+    }
+}
@@ -1,9 +1,132 @@
-use serde::Deserialize;
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Serialize};
 
-use crate::pacman::ManagerStats;
+use crate::cache::CacheUsage;
+use crate::config::StatFormat;
+use crate::distro::Distro;
+use crate::history::StatsSnapshot;
+use crate::managers::SourceStats;
 use crate::util;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+/// Files databases older than this are reported as stale.
+pub const FILES_DB_STALE_DAYS: i64 = 14;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ManagerStats {
+    /// The counts, None when they weren't asked for or couldn't be gathered.
+    pub total_installed: Option<u32>,
+    pub total_upgradable: Option<u32>,
+    /// Nix channels whose server is past the revision last fetched; their package
+    /// updates aren't known, so they aren't in `total_upgradable`.
+    pub outdated_channels: Option<u32>,
+    /// `total_upgradable` split into installs, upgrades and downgrades, plus the removals
+    /// replacements bring along.
+    pub upgrade_breakdown: Option<UpgradeBreakdown>,
+    pub days_since_last_update: Option<i64>,
+    pub download_size_mb: Option<f64>,
+    pub download_cached_mb: Option<f64>,
+    pub total_installed_size_mb: Option<f64>,
+    pub net_upgrade_size_mb: Option<f64>,
+    pub orphaned_packages: Option<u32>,
+    pub orphaned_size_mb: Option<f64>,
+    pub cache_size_mb: Option<f64>,
+    pub cache_usage: Option<CacheUsage>,
+    pub mirror_url: Option<String>,
+    pub mirror_sync_age_hours: Option<f64>,
+    pub pacman_version: Option<String>,
+    pub upgrade_eta_secs: Option<f64>,
+    /// Descriptions of the hooks the pending upgrade will run.
+    pub pending_hooks: Option<Vec<String>>,
+    pub aur_updates: Option<u32>,
+    pub aur_build_eta_secs: Option<f64>,
+    /// Packages installed from outside the sync repos, mostly from the AUR.
+    pub foreign_packages: Option<u32>,
+    /// MiB the sources of the pending AUR updates would download.
+    pub aur_download_size_mb: Option<f64>,
+    /// Why the pending upgrade counts as major; empty when it's a quick one.
+    pub major_upgrade_reasons: Option<Vec<String>>,
+    pub repo_health: Option<Vec<RepoHealth>>,
+    pub distro: Option<Distro>,
+    /// The Manjaro branch in use and how far its snapshot is behind Arch's repos.
+    pub branch: Option<String>,
+    pub branch_lag_hours: Option<f64>,
+    /// Pending updates from testing repos; None when none are enabled.
+    pub testing_updates: Option<u32>,
+    pub files_db_ages: Option<Vec<FilesDbAge>>,
+    pub group_suggestions: Option<Vec<GroupSuggestion>>,
+    /// How far the local clock is ahead of a mirror's, when it's off by more than the
+    /// tolerance; the sync ages above are already corrected for it.
+    pub clock_skew_secs: Option<i64>,
+    /// Why the last update time from the log can't be trusted; the time is left unset.
+    pub last_update_warning: Option<String>,
+    pub other_sources: Option<Vec<SourceStats>>,
+    /// MiB a month of updates downloads, projected from past upgrades.
+    pub monthly_download_mb: Option<f64>,
+    pub data_cost: Option<DataCost>,
+    /// What the installed packages take up where the manager keeps them, e.g. Homebrew's
+    /// Cellar.
+    pub store_size_mb: Option<f64>,
+}
+
+/// What the pending download and a month of updates cost at the configured price.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataCost {
+    pub currency: String,
+    pub pending: Option<f64>,
+    pub monthly: Option<f64>,
+}
+
+/// How many packages of one kind the pending transaction changes, with their download
+/// size, or for removals the installed size they free.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChangeCount {
+    pub count: u32,
+    pub size_mb: f64,
+}
+
+impl ChangeCount {
+    pub fn add(&mut self, bytes: i64) {
+        self.count += 1;
+        self.size_mb += bytes as f64 / 1048576.0;
+    }
+}
+
+/// The pending transaction split by what it does to each package; a replacement shows up
+/// as an install plus a removal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct UpgradeBreakdown {
+    pub installs: ChangeCount,
+    pub upgrades: ChangeCount,
+    pub downgrades: ChangeCount,
+    pub removals: ChangeCount,
+}
+
+/// A group or meta package most of whose members were installed one by one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupSuggestion {
+    pub name: String,
+    /// Whether it's a meta package to install rather than a group.
+    pub meta: bool,
+    pub explicit: usize,
+    pub total: usize,
+}
+
+/// How long ago a repo's files database was synced; None when it never was.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilesDbAge {
+    pub repo: String,
+    pub age_secs: Option<i64>,
+}
+
+/// A repo that isn't one of Arch's own, with how long ago its database changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoHealth {
+    pub name: String,
+    /// None when none of its servers answered.
+    pub sync_age_hours: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum StatId {
     Installed,
@@ -16,9 +139,74 @@ pub enum StatId {
     CacheSize,
     MirrorUrl,
     MirrorHealth,
+    UpgradeEta,
+    PendingHooks,
+    AurUpdates,
+    UpgradeKind,
+    RepoHealth,
+    Distro,
+    TestingUpdates,
+    FilesDb,
+    GroupSuggestions,
+    OtherSources,
+    MonthlyData,
+    DataCost,
+    StoreSize,
+    ForeignPackages,
+    AurDownloadSize,
+    UpgradeBreakdown,
 }
 
 impl StatId {
+    pub const ALL: [StatId; 26] = [
+        StatId::Installed,
+        StatId::Upgradable,
+        StatId::LastUpdate,
+        StatId::DownloadSize,
+        StatId::InstalledSize,
+        StatId::NetUpgradeSize,
+        StatId::OrphanedPackages,
+        StatId::CacheSize,
+        StatId::MirrorUrl,
+        StatId::MirrorHealth,
+        StatId::UpgradeEta,
+        StatId::PendingHooks,
+        StatId::AurUpdates,
+        StatId::UpgradeKind,
+        StatId::RepoHealth,
+        StatId::Distro,
+        StatId::TestingUpdates,
+        StatId::FilesDb,
+        StatId::GroupSuggestions,
+        StatId::OtherSources,
+        StatId::MonthlyData,
+        StatId::DataCost,
+        StatId::StoreSize,
+        StatId::ForeignPackages,
+        StatId::AurDownloadSize,
+        StatId::UpgradeBreakdown,
+    ];
+
+    /// The stat's config name, e.g. "orphaned_packages".
+    pub fn name(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default()
+    }
+
+    /// The `{name}` placeholder this stat fills in an upgrade template.
+    pub fn placeholder(&self) -> String {
+        format!("{{{}}}", self.name())
+    }
+
+    /// Parse a stat by its config name, e.g. "orphaned_packages".
+    pub fn parse(name: &str) -> Option<StatId> {
+        let deserializer: serde::de::value::StrDeserializer<serde::de::value::Error> =
+            name.into_deserializer();
+        StatId::deserialize(deserializer).ok()
+    }
+
     pub fn label(&self) -> &'static str {
         match self {
             StatId::Installed => "Installed",
@@ -31,17 +219,124 @@ impl StatId {
             StatId::CacheSize => "Package Cache",
             StatId::MirrorUrl => "Mirror URL",
             StatId::MirrorHealth => "Mirror Health",
+            StatId::UpgradeEta => "Estimated Upgrade Time",
+            StatId::PendingHooks => "Pending Hooks",
+            StatId::AurUpdates => "AUR Updates",
+            StatId::UpgradeKind => "Recommendation",
+            StatId::RepoHealth => "Custom Repos",
+            StatId::Distro => "Distribution",
+            StatId::TestingUpdates => "Testing Updates",
+            StatId::FilesDb => "Files Database",
+            StatId::GroupSuggestions => "Group Suggestions",
+            StatId::OtherSources => "Other Sources",
+            StatId::MonthlyData => "Monthly Data",
+            StatId::DataCost => "Data Cost",
+            StatId::StoreSize => "Package Store",
+            StatId::ForeignPackages => "Foreign Packages",
+            StatId::AurDownloadSize => "AUR Download Size",
+            StatId::UpgradeBreakdown => "Upgrade Breakdown",
+        }
+    }
+
+    /// The stat as a number, for stats that can be compared between runs.
+    pub fn numeric_value(&self, stats: &ManagerStats) -> Option<f64> {
+        match self {
+            StatId::Installed => stats.total_installed.map(|c| c as f64),
+            StatId::Upgradable => stats.total_upgradable.map(|c| c as f64),
+            StatId::DownloadSize => stats.download_size_mb,
+            StatId::InstalledSize => stats.total_installed_size_mb,
+            StatId::NetUpgradeSize => stats.net_upgrade_size_mb,
+            StatId::OrphanedPackages => stats.orphaned_packages.map(|c| c as f64),
+            StatId::CacheSize => stats.cache_size_mb,
+            StatId::MonthlyData => stats.monthly_download_mb,
+            StatId::StoreSize => stats.store_size_mb,
+            StatId::ForeignPackages => stats.foreign_packages.map(|c| c as f64),
+            StatId::AurDownloadSize => stats.aur_download_size_mb,
+            StatId::TestingUpdates => stats.testing_updates.map(|c| c as f64),
+            _ => None,
+        }
+    }
+
+    /// Whether a rise in this stat is something the user would want to act on.
+    pub fn higher_is_worse(&self) -> bool {
+        !matches!(self, StatId::Installed)
+    }
+
+    /// Whether `numeric_value` is a count of packages rather than a size in MiB.
+    pub fn is_count(&self) -> bool {
+        matches!(
+            self,
+            StatId::Installed
+                | StatId::Upgradable
+                | StatId::OrphanedPackages
+                | StatId::ForeignPackages
+                | StatId::TestingUpdates
+        )
+    }
+
+    /// Format a change in `numeric_value`, e.g. "+5" or "-12.30 MiB".
+    pub fn format_delta(&self, delta: f64) -> String {
+        let sign = if delta < 0.0 { "-" } else { "+" };
+        if self.is_count() {
+            format!("{}{}", sign, delta.abs().round())
+        } else {
+            format!("{}{:.2} MiB", sign, delta.abs())
+        }
+    }
+
+    /// `format_value` for a run that shows deltas: the cached share of the download size
+    /// is given as its change since `previous`, when it moved.
+    pub fn format_value_since(
+        &self,
+        stats: &ManagerStats,
+        format: &StatFormat,
+        previous: Option<&StatsSnapshot>,
+    ) -> Option<String> {
+        if *self == StatId::DownloadSize
+            && let Some(size) = stats.download_size_mb
+            && let Some(change) = previous.and_then(|p| p.cached_delta(stats))
+        {
+            return Some(format!(
+                "{:.2} MiB ({} cached)",
+                size,
+                self.format_delta(change)
+            ));
         }
+        self.format_value(stats, format)
     }
 
-    pub fn format_value(&self, stats: &ManagerStats) -> Option<String> {
+    pub fn format_value(&self, stats: &ManagerStats, format: &StatFormat) -> Option<String> {
         match self {
-            StatId::Installed => Some(stats.total_installed.to_string()),
-            StatId::Upgradable => Some(stats.total_upgradable.to_string()),
-            StatId::LastUpdate => stats
-                .days_since_last_update
-                .map(|s| util::normalize_duration(s)),
-            StatId::DownloadSize => stats.download_size_mb.map(|s| format!("{:.2} MiB", s)),
+            StatId::Installed => stats.total_installed.map(|c| c.to_string()),
+            StatId::Upgradable => stats
+                .total_upgradable
+                .map(|c| match stats.outdated_channels {
+                    Some(1) => format!("{} (1 channel to update)", c),
+                    Some(channels) if channels > 1 => {
+                        format!("{} ({} channels to update)", c, channels)
+                    }
+                    _ => c.to_string(),
+                }),
+            StatId::LastUpdate => match &stats.last_update_warning {
+                Some(warning) => Some(format!("Unknown ({})", warning)),
+                None => stats.days_since_last_update.map(|s| {
+                    if format.absolute {
+                        util::format_time(-s, format.date_format.as_deref())
+                    } else {
+                        util::format_duration(s, format.duration)
+                    }
+                }),
+            },
+            StatId::DownloadSize => {
+                stats
+                    .download_size_mb
+                    .map(|s| match stats.download_cached_mb {
+                        Some(cached) if cached >= 0.01 => {
+                            format!("{:.2} MiB ({:.2} MiB cached)", s, cached)
+                        }
+                        _ => format!("{:.2} MiB", s),
+                    })
+            }
             StatId::InstalledSize => stats.total_installed_size_mb.map(|s| format!("{:.2} MiB", s)),
             StatId::NetUpgradeSize => stats.net_upgrade_size_mb.map(|s| format!("{:.2} MiB", s)),
             StatId::OrphanedPackages => {
@@ -59,15 +354,168 @@ impl StatId {
                     None
                 }
             }
-            StatId::CacheSize => stats.cache_size_mb.map(|s| format!("{:.2} MiB", s)),
+            StatId::CacheSize => stats.cache_size_mb.map(|s| match &stats.cache_usage {
+                Some(usage) => format!(
+                    "{:.2} MiB ({} files{})",
+                    s,
+                    usage.files,
+                    describe_extensions(usage)
+                ),
+                None => format!("{:.2} MiB", s),
+            }),
             StatId::MirrorUrl => stats.mirror_url.clone(),
-            StatId::MirrorHealth => {
-                match (&stats.mirror_url, stats.mirror_sync_age_hours) {
-                    (Some(_), Some(age)) => Some(format!("OK (last sync {:.1} hours)", age)),
-                    (Some(_), None) => Some("Err - could not check sync status".to_string()),
-                    (None, _) => Some("Err - no mirror found".to_string()),
+            StatId::MirrorHealth => match (&stats.mirror_url, stats.mirror_sync_age_hours) {
+                (Some(_), Some(age)) => Some(format!(
+                    "OK (last sync {:.1} hours){}",
+                    age,
+                    clock_note(stats)
+                )),
+                (Some(_), None) => Some("Err - could not check sync status".to_string()),
+                (None, _) => Some("Err - no mirror found".to_string()),
+            },
+            StatId::UpgradeEta => stats.upgrade_eta_secs.map(|s| {
+                let secs = s.round() as i64;
+                if format.absolute {
+                    format!(
+                        "~{}",
+                        util::format_time(secs, format.date_format.as_deref())
+                    )
+                } else {
+                    format!("~{}", util::format_duration(secs, format.duration))
                 }
-            }
+            }),
+            StatId::PendingHooks => stats.pending_hooks.as_ref().map(|hooks| {
+                if hooks.is_empty() {
+                    "0".to_string()
+                } else {
+                    format!("{} ({})", hooks.len(), hooks.join(", "))
+                }
+            }),
+            StatId::AurUpdates => stats.aur_updates.map(|count| {
+                let rebuilds = format!("{} rebuild{}", count, if count != 1 { "s" } else { "" });
+                match stats.aur_build_eta_secs {
+                    Some(secs) if count > 0 => format!(
+                        "{}, est. {}",
+                        rebuilds,
+                        util::compact_duration(secs.round() as i64)
+                    ),
+                    _ => rebuilds,
+                }
+            }),
+            StatId::UpgradeKind => match &stats.major_upgrade_reasons {
+                _ if stats.total_upgradable == Some(0) => Some("Up to date".to_string()),
+                Some(reasons) if reasons.is_empty() => {
+                    Some("Quick upgrade, fine to run now".to_string())
+                }
+                Some(reasons) => Some(format!(
+                    "Major upgrade ({}), schedule it when you can reboot",
+                    reasons.join("; ")
+                )),
+                None => None,
+            },
+            StatId::RepoHealth => stats.repo_health.as_ref().map(|repos| {
+                if repos.is_empty() {
+                    return "None configured".to_string();
+                }
+                repos
+                    .iter()
+                    .map(|repo| match repo.sync_age_hours {
+                        Some(age) => format!("{} OK ({:.1} hours)", repo.name, age),
+                        None => format!("{} Err", repo.name),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+                    + &clock_note(stats)
+            }),
+            StatId::Distro => stats.distro.map(|distro| match &stats.branch {
+                Some(branch) => match stats.branch_lag_hours {
+                    Some(hours) => format!(
+                        "{} ({} branch, {} behind Arch)",
+                        distro.name(),
+                        branch,
+                        util::compact_duration((hours * 3600.0) as i64)
+                    ),
+                    None => format!("{} ({} branch)", distro.name(), branch),
+                },
+                None => distro.name().to_string(),
+            }),
+            StatId::TestingUpdates => stats.testing_updates.map(|n| n.to_string()),
+            StatId::MonthlyData => stats
+                .monthly_download_mb
+                .map(|mb| format!("~{:.2} MiB/month ({:.2} MiB/week)", mb, mb * 7.0 / 30.0)),
+            StatId::StoreSize => stats.store_size_mb.map(|s| format!("{:.2} MiB", s)),
+            StatId::ForeignPackages => stats.foreign_packages.map(|n| n.to_string()),
+            StatId::AurDownloadSize => stats.aur_download_size_mb.map(|s| format!("{:.2} MiB", s)),
+            StatId::UpgradeBreakdown => stats.upgrade_breakdown.as_ref().map(describe_breakdown),
+            StatId::DataCost => stats.data_cost.as_ref().map(|cost| {
+                let price = |amount: f64| format!("~{}{:.2}", cost.currency, amount);
+                let pending = cost
+                    .pending
+                    .map(|p| format!("{} for pending updates", price(p)));
+                let monthly = cost.monthly.map(|m| format!("{}/month", price(m)));
+                match (pending, monthly) {
+                    (Some(p), Some(m)) => format!("{}, {}", p, m),
+                    (Some(one), None) | (None, Some(one)) => one,
+                    (None, None) => "Unknown".to_string(),
+                }
+            }),
+            StatId::OtherSources => stats.other_sources.as_ref().map(|sources| {
+                if sources.is_empty() {
+                    return "None configured".to_string();
+                }
+                sources
+                    .iter()
+                    .map(|source| {
+                        let upgradable = source.stats.total_upgradable.unwrap_or(0);
+                        let updates = match source.stats.download_size_mb {
+                            Some(mb) if upgradable > 0 => {
+                                format!("{} updates ({:.2} MiB)", upgradable, mb)
+                            }
+                            _ => format!("{} updates", upgradable),
+                        };
+                        format!(
+                            "{} {} installed, {}",
+                            source.name,
+                            source.stats.total_installed.unwrap_or(0),
+                            updates
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            }),
+            StatId::GroupSuggestions => stats.group_suggestions.as_ref().map(|groups| {
+                if groups.is_empty() {
+                    return "None".to_string();
+                }
+                groups
+                    .iter()
+                    .map(|g| {
+                        let kind = if g.meta { "install" } else { "complete" };
+                        format!("{} {} ({}/{} installed)", kind, g.name, g.explicit, g.total)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }),
+            StatId::FilesDb => stats.files_db_ages.as_ref().and_then(|dbs| {
+                let missing: Vec<&str> = dbs
+                    .iter()
+                    .filter(|db| db.age_secs.is_none())
+                    .map(|db| db.repo.as_str())
+                    .collect();
+                if !missing.is_empty() {
+                    return Some(format!(
+                        "Not synced for {} (pacfetch sync --files)",
+                        missing.join(", ")
+                    ));
+                }
+                let oldest = dbs.iter().filter_map(|db| db.age_secs).max()?;
+                let age = util::compact_duration(oldest);
+                Some(if oldest > FILES_DB_STALE_DAYS * 86400 {
+                    format!("Stale, {} old (pacfetch sync --files)", age)
+                } else {
+                    format!("{} old", age)
+                })
+            }),
         }
     }
 }
@@ -84,10 +532,63 @@ pub fn default_stats() -> Vec<StatId> {
         StatId::CacheSize,
         StatId::MirrorUrl,
         StatId::MirrorHealth,
+        StatId::UpgradeKind,
+    ]
+}
+
+/// Stats gathered for the time-series outputs.
+pub fn metric_stats() -> Vec<StatId> {
+    vec![
+        StatId::Installed,
+        StatId::Upgradable,
+        StatId::LastUpdate,
+        StatId::DownloadSize,
+        StatId::InstalledSize,
+        StatId::NetUpgradeSize,
+        StatId::OrphanedPackages,
+        StatId::CacheSize,
+        StatId::MirrorHealth,
     ]
 }
 
+/// Every numeric stat as a (name, value) pair for time-series outputs; sizes are in MiB.
+pub fn numeric_metrics(stats: &ManagerStats) -> Vec<(String, f64)> {
+    let mut metrics: Vec<(String, f64)> = StatId::ALL
+        .into_iter()
+        .filter_map(|id| id.numeric_value(stats).map(|value| (id.name(), value)))
+        .collect();
+
+    if let Some(secs) = stats.days_since_last_update {
+        metrics.push(("last_update_secs".to_string(), secs as f64));
+    }
+    if let Some(usage) = &stats.cache_usage {
+        metrics.push(("cache_files".to_string(), usage.files as f64));
+        for (ext, (_, bytes)) in &usage.by_extension {
+            let name = format!(
+                "cache_{}_size",
+                ext.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+            );
+            metrics.push((name, *bytes as f64 / 1048576.0));
+        }
+    }
+    if let Some(hours) = stats.mirror_sync_age_hours {
+        metrics.push(("mirror_sync_age_hours".to_string(), hours));
+    }
+    if let Some(secs) = stats.upgrade_eta_secs {
+        metrics.push(("upgrade_eta_secs".to_string(), secs));
+    }
+    if let Some(hours) = stats.branch_lag_hours {
+        metrics.push(("branch_lag_hours".to_string(), hours));
+    }
+    metrics
+}
+
 // --- stat fetch request helpers ---
+/// Whether the download size is needed, for its own stat or to price it.
+pub fn needs_download_size(requested: &[StatId]) -> bool {
+    requested.contains(&StatId::DownloadSize) || requested.contains(&StatId::DataCost)
+}
+
 pub fn needs_upgrade_stats(requested: &[StatId]) -> bool {
     requested.iter().any(|s| {
         matches!(
@@ -96,14 +597,110 @@ pub fn needs_upgrade_stats(requested: &[StatId]) -> bool {
                 | StatId::DownloadSize
                 | StatId::InstalledSize
                 | StatId::NetUpgradeSize
+                | StatId::UpgradeEta
+                | StatId::UpgradeKind
+                | StatId::DataCost
+                | StatId::UpgradeBreakdown
         )
     })
 }
 
+/// Stats referenced by `{name}` placeholders in a template.
+pub fn template_stats(template: &str) -> Vec<StatId> {
+    StatId::ALL
+        .into_iter()
+        .filter(|id| template.contains(&id.placeholder()))
+        .collect()
+}
+
+pub fn needs_upgrade_kind(requested: &[StatId]) -> bool {
+    requested.contains(&StatId::UpgradeKind)
+}
+
+pub fn needs_aur_stats(requested: &[StatId]) -> bool {
+    requested.contains(&StatId::AurUpdates) || requested.contains(&StatId::AurDownloadSize)
+}
+
+pub fn needs_hook_stats(requested: &[StatId]) -> bool {
+    requested.contains(&StatId::PendingHooks)
+}
+
 pub fn needs_orphan_stats(requested: &[StatId]) -> bool {
     requested.contains(&StatId::OrphanedPackages)
 }
 
+/// The largest kinds of file in a cache, e.g. ": pkg.tar.zst 2047.54 MiB, sig 0.46 MiB".
+fn describe_extensions(usage: &CacheUsage) -> String {
+    let mut extensions: Vec<_> = usage.by_extension.iter().collect();
+    extensions.sort_by_key(|(_, (_, bytes))| std::cmp::Reverse(*bytes));
+    let parts: Vec<String> = extensions
+        .into_iter()
+        .take(3)
+        .map(|(ext, (_, bytes))| format!("{} {:.2} MiB", ext, *bytes as f64 / 1048576.0))
+        .collect();
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", parts.join(", "))
+    }
+}
+
+/// e.g. "9 upgrades 120.50 MiB, 2 new 4.00 MiB, 1 removal frees 3.20 MiB"
+fn describe_breakdown(breakdown: &UpgradeBreakdown) -> String {
+    let plural = |n: u32| if n != 1 { "s" } else { "" };
+    let mut parts = Vec::new();
+    let UpgradeBreakdown {
+        installs,
+        upgrades,
+        downgrades,
+        removals,
+    } = breakdown;
+    if upgrades.count > 0 {
+        parts.push(format!(
+            "{} upgrade{} {:.2} MiB",
+            upgrades.count,
+            plural(upgrades.count),
+            upgrades.size_mb
+        ));
+    }
+    if installs.count > 0 {
+        parts.push(format!(
+            "{} new {:.2} MiB",
+            installs.count, installs.size_mb
+        ));
+    }
+    if downgrades.count > 0 {
+        parts.push(format!(
+            "{} downgrade{} {:.2} MiB",
+            downgrades.count,
+            plural(downgrades.count),
+            downgrades.size_mb
+        ));
+    }
+    if removals.count > 0 {
+        parts.push(format!(
+            "{} removal{} frees {:.2} MiB",
+            removals.count,
+            plural(removals.count),
+            removals.size_mb
+        ));
+    }
+    parts.join(", ")
+}
+
+/// Marks sync ages measured on a skewed clock, e.g. " (clock 2h ahead)"; empty when the
+/// clock is fine or wasn't checked.
+pub fn clock_note(stats: &ManagerStats) -> String {
+    match stats.clock_skew_secs {
+        Some(skew) => format!(
+            " (clock {} {})",
+            util::compact_duration(skew.abs()),
+            if skew > 0 { "ahead" } else { "behind" }
+        ),
+        None => String::new(),
+    }
+}
+
 pub fn needs_mirror_health(requested: &[StatId]) -> bool {
     requested.contains(&StatId::MirrorHealth)
 }
@@ -0,0 +1,243 @@
+use rusqlite::Connection;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The top-level key a state file keeps its layout version under. Files from before
+/// versioning have none and count as version 1.
+const VERSION_KEY: &str = "version";
+
+/// One step of a schema's upgrade, editing the parsed file in place.
+pub type Migration = fn(&mut toml::Table) -> Result<(), String>;
+
+/// The layout of one state file: its current version, and the steps bringing an older
+/// file up to it, the first upgrading version 1 to 2.
+pub struct Schema {
+    pub version: i64,
+    pub migrations: &'static [Migration],
+}
+
+/// Read a TOML state file, first migrating it in place if an older pacfetch wrote it.
+/// The old file is kept as e.g. upgrades.toml.v1.bak. A file that won't migrate is
+/// returned as it is, for the caller to make what it can of.
+pub fn read_toml(path: &Path, schema: &Schema) -> Option<String> {
+    let contents = fs::read_to_string(path).ok()?;
+    match migrate_toml(&contents, schema) {
+        Ok(Some((from, migrated))) => {
+            let backup = backup_path(path, from);
+            let saved =
+                write_atomic(&backup, &contents).and_then(|_| write_atomic(path, &migrated));
+            if let Err(e) = saved {
+                eprintln!("warning: Failed to migrate {}: {}", path.display(), e);
+            }
+            Some(migrated)
+        }
+        Ok(None) => Some(contents),
+        Err(e) => {
+            eprintln!("warning: Failed to migrate {}: {}", path.display(), e);
+            Some(contents)
+        }
+    }
+}
+
+/// Write a TOML state file stamped with the schema's version. A file a newer pacfetch
+/// wrote is left alone, since this one would drop whatever it added.
+pub fn write_toml(path: &Path, schema: &Schema, contents: &str) -> Result<(), String> {
+    if let Some(found) = fs::read_to_string(path)
+        .ok()
+        .and_then(|existing| file_version(&existing))
+        && found > schema.version
+    {
+        return Err(format!(
+            "{} is from a newer pacfetch (version {}, this one knows {})",
+            path.display(),
+            found,
+            schema.version
+        ));
+    }
+    let stamped = format!("{} = {}\n{}", VERSION_KEY, schema.version, contents);
+    write_atomic(path, stamped).map_err(|e| e.to_string())
+}
+
+fn file_version(contents: &str) -> Option<i64> {
+    let table: toml::Table = toml::from_str(contents).ok()?;
+    Some(
+        table
+            .get(VERSION_KEY)
+            .and_then(|v| v.as_integer())
+            .unwrap_or(1),
+    )
+}
+
+/// `contents` brought up to the schema's version, with the version it started at; None
+/// when it's already current or newer.
+fn migrate_toml(contents: &str, schema: &Schema) -> Result<Option<(i64, String)>, String> {
+    let mut table: toml::Table = toml::from_str(contents).map_err(|e| e.to_string())?;
+    let from = table
+        .get(VERSION_KEY)
+        .and_then(|v| v.as_integer())
+        .unwrap_or(1);
+    if from >= schema.version {
+        return Ok(None);
+    }
+    for migration in schema.migrations.iter().skip((from - 1).max(0) as usize) {
+        migration(&mut table)?;
+    }
+    table.insert(VERSION_KEY.to_string(), schema.version.into());
+    let migrated = toml::to_string(&table).map_err(|e| e.to_string())?;
+    Ok(Some((from, migrated)))
+}
+
+/// Bring a database up to the last of `migrations` by its `user_version`, each statement
+/// batch upgrading from the version at its index. Runs in one transaction, after copying
+/// a database that already has tables to e.g. stats.db.v1.bak.
+pub fn migrate_db(db: &mut Connection, path: &Path, migrations: &[&str]) -> Result<(), String> {
+    let version: i64 = db
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let target = migrations.len() as i64;
+    if version >= target {
+        return Ok(());
+    }
+    let tables: i64 = db
+        .query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if tables > 0 {
+        let backup = backup_path(path, version);
+        let _ = fs::remove_file(&backup);
+        db.execute("VACUUM INTO ?1", [backup.to_string_lossy()])
+            .map_err(|e| format!("Failed to back up {}: {}", path.display(), e))?;
+    }
+    let tx = db.transaction().map_err(|e| e.to_string())?;
+    for migration in &migrations[version as usize..] {
+        tx.execute_batch(migration).map_err(|e| e.to_string())?;
+    }
+    tx.pragma_update(None, "user_version", target)
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// Where a file is kept before migrating from `version`, e.g. upgrades.toml.v1.bak.
+fn backup_path(path: &Path, version: i64) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}.v{}.bak", name, version))
+}
+
+/// Replace `path` with `contents` so that a crash or a full disk leaves either the old file
+/// or the new one, never a mix: the contents go to a temporary file beside it, which is
+/// synced and renamed over it, and on unix the directory is synced so the rename sticks.
+/// An existing file's permissions carry over on unix.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let partial = partial_path(path);
+    let result = write_synced(&partial, contents.as_ref(), path).and_then(|_| {
+        fs::rename(&partial, path)?;
+        sync_parent(path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// A hidden sibling named after the file, this process and the call, so two instances or
+/// two threads writing the same file don't share one.
+fn partial_path(path: &Path) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+fn write_synced(partial: &Path, contents: &[u8], path: &Path) -> io::Result<()> {
+    let mut file = File::create(partial)?;
+    file.write_all(contents)?;
+    // On Windows the only permission is the read-only flag, which would keep the partial
+    // from being removed when the rename fails
+    #[cfg(unix)]
+    if let Ok(meta) = fs::metadata(path) {
+        file.set_permissions(meta.permissions())?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    file.sync_all()
+}
+
+/// Windows can't flush a directory handle opened for reading, so the rename is left to
+/// the filesystem there.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_files_without_leaving_partials() {
+        let dir = std::env::temp_dir().join(format!("pacfetch-storage-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("stats.toml");
+
+        write_atomic(&path, "first").unwrap();
+        write_atomic(&path, "second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        let entries: Vec<_> = fs::read_dir(&dir).unwrap().flatten().collect();
+        assert_eq!(entries.len(), 1);
+        assert_ne!(partial_path(&path), partial_path(&path));
+
+        assert!(write_atomic(&dir.join("missing/stats.toml"), "third").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn migrates_old_files_in_order() {
+        fn rename_total(table: &mut toml::Table) -> Result<(), String> {
+            let total = table.remove("total").ok_or("no total")?;
+            table.insert("count".to_string(), total);
+            Ok(())
+        }
+        fn add_unit(table: &mut toml::Table) -> Result<(), String> {
+            table.insert("unit".to_string(), "MiB".into());
+            Ok(())
+        }
+        let schema = Schema {
+            version: 3,
+            migrations: &[rename_total, add_unit],
+        };
+
+        let (from, migrated) = migrate_toml("total = 5\n", &schema).unwrap().unwrap();
+        assert_eq!(from, 1);
+        let table: toml::Table = toml::from_str(&migrated).unwrap();
+        assert_eq!(table["count"].as_integer(), Some(5));
+        assert_eq!(table["unit"].as_str(), Some("MiB"));
+        assert_eq!(table["version"].as_integer(), Some(3));
+
+        let (from, _) = migrate_toml("version = 2\ncount = 5\n", &schema)
+            .unwrap()
+            .unwrap();
+        assert_eq!(from, 2);
+        assert!(migrate_toml("version = 3\n", &schema).unwrap().is_none());
+        assert!(migrate_toml("version = 4\n", &schema).unwrap().is_none());
+        assert!(migrate_toml("version = 1\n", &schema).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::style::ResetColor;
+use crossterm::terminal::{
+    EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+};
+use crossterm::{cursor, execute};
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The modes some guard currently has the terminal in, for restoring it from a panic or
+/// a signal, where the guards' drops don't run. Leaving the alternate screen when it
+/// isn't shown would move the cursor, so only what was entered is undone.
+static RAW: AtomicBool = AtomicBool::new(false);
+static ALTERNATE: AtomicBool = AtomicBool::new(false);
+static MOUSE: AtomicBool = AtomicBool::new(false);
+
+/// Terminal modes held for an interactive session: raw mode, the alternate screen and
+/// mouse capture, whichever were entered. Dropping it leaves them and resets colors and
+/// the cursor too, so an error, early return or panic can't leave the shell broken.
+pub struct TerminalGuard {
+    writer: Box<dyn Write>,
+    raw: bool,
+    alternate: bool,
+    mouse: bool,
+    suspended: bool,
+}
+
+impl TerminalGuard {
+    fn new(writer: impl Write + 'static) -> TerminalGuard {
+        TerminalGuard {
+            writer: Box::new(writer),
+            raw: false,
+            alternate: false,
+            mouse: false,
+            suspended: false,
+        }
+    }
+
+    /// No modes, for output passed through from a command, such as pacman hiding the
+    /// cursor for its progress bars. None when stdout isn't a terminal.
+    pub fn output() -> Option<TerminalGuard> {
+        io::stdout()
+            .is_terminal()
+            .then(|| TerminalGuard::new(io::stdout()))
+    }
+
+    /// Raw mode on the main screen, for an inline viewport.
+    pub fn raw() -> io::Result<TerminalGuard> {
+        let mut guard = TerminalGuard::new(io::stdout());
+        guard.raw = true;
+        guard.enter()?;
+        Ok(guard)
+    }
+
+    /// Raw mode on the alternate screen drawn through `writer`, with the mouse captured if
+    /// asked. Whatever was entered before a failure is left again as the guard drops.
+    pub fn fullscreen(writer: impl Write + 'static, mouse: bool) -> io::Result<TerminalGuard> {
+        let mut guard = TerminalGuard::new(writer);
+        guard.raw = true;
+        guard.alternate = true;
+        guard.mouse = mouse;
+        guard.enter()?;
+        Ok(guard)
+    }
+
+    /// Hand the plain terminal back for a while, e.g. to run pacman from the dashboard.
+    pub fn suspend(&mut self) -> io::Result<()> {
+        self.suspended = true;
+        self.leave()
+    }
+
+    /// Enter the modes again after `suspend`.
+    pub fn resume(&mut self) -> io::Result<()> {
+        self.suspended = false;
+        self.enter()
+    }
+
+    // Each flag is set before its mode is entered, so a half-entered one is still undone
+    fn enter(&mut self) -> io::Result<()> {
+        if self.alternate {
+            ALTERNATE.store(true, Ordering::SeqCst);
+            execute!(self.writer, EnterAlternateScreen)?;
+        }
+        if self.raw {
+            RAW.store(true, Ordering::SeqCst);
+            enable_raw_mode()?;
+        }
+        if self.mouse {
+            MOUSE.store(true, Ordering::SeqCst);
+            execute!(self.writer, EnableMouseCapture)?;
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        if self.mouse && MOUSE.swap(false, Ordering::SeqCst) {
+            result = result.and(execute!(self.writer, DisableMouseCapture));
+        }
+        if self.raw && RAW.swap(false, Ordering::SeqCst) {
+            result = result.and(disable_raw_mode());
+        }
+        if self.alternate && ALTERNATE.swap(false, Ordering::SeqCst) {
+            result = result.and(execute!(self.writer, LeaveAlternateScreen));
+        }
+        result.and(execute!(self.writer, ResetColor, cursor::Show))
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        if !self.suspended {
+            let _ = self.leave();
+        }
+    }
+}
+
+/// Undo whatever modes a guard left the terminal in and reset colors and the cursor,
+/// for when its drop won't run. Nothing is written when stdout isn't a terminal.
+pub fn restore() {
+    if RAW.swap(false, Ordering::SeqCst) {
+        let _ = disable_raw_mode();
+    }
+    let mut stdout = io::stdout();
+    if !stdout.is_terminal() {
+        return;
+    }
+    if MOUSE.swap(false, Ordering::SeqCst) {
+        let _ = execute!(stdout, DisableMouseCapture);
+    }
+    if ALTERNATE.swap(false, Ordering::SeqCst) {
+        let _ = execute!(stdout, LeaveAlternateScreen);
+    }
+    let _ = execute!(stdout, ResetColor, cursor::Show);
+}
+
+#[cfg(unix)]
+static SAVED_TERMIOS: std::sync::OnceLock<libc::termios> = std::sync::OnceLock::new();
+
+/// Restore the terminal when Ctrl-C, a closed terminal or kill ends pacfetch, then die of
+/// the signal as before. Raw mode is undone by putting back the settings from startup.
+#[cfg(unix)]
+pub fn restore_on_signal() {
+    if io::stdin().is_terminal() {
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } == 0 {
+            let _ = SAVED_TERMIOS.set(termios);
+        }
+    }
+    let handler = on_signal as extern "C" fn(libc::c_int);
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        unsafe {
+            libc::signal(signal, handler as libc::sighandler_t);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn restore_on_signal() {}
+
+/// Only async-signal-safe calls are allowed here, so the escapes are written directly and
+/// the modes read from the atomics rather than going through crossterm.
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    fn write(bytes: &[u8]) {
+        unsafe {
+            libc::write(libc::STDOUT_FILENO, bytes.as_ptr().cast(), bytes.len());
+        }
+    }
+    unsafe {
+        if libc::isatty(libc::STDOUT_FILENO) == 1 {
+            if MOUSE.load(Ordering::SeqCst) {
+                write(b"\x1b[?1006l\x1b[?1015l\x1b[?1003l\x1b[?1002l\x1b[?1000l");
+            }
+            if ALTERNATE.load(Ordering::SeqCst) {
+                write(b"\x1b[?1049l");
+            }
+            write(b"\x1b[0m\x1b[?25h");
+        }
+        if RAW.load(Ordering::SeqCst)
+            && let Some(termios) = SAVED_TERMIOS.get()
+        {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios);
+        }
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
@@ -18,3 +18,16 @@ pub const PACMAN_ART: [&str; 16] = [
     " ⠀⠀⠀⠀⠀⠀⠀⠉⠻⢿⣿⣿⣿⣿⣿⣿⣿⣿⣿⣿⣿⣿⣿⠿⠛⠉⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀  ",
     " ⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠉⠙⠛⠛⠛⠛⠛⠛⠋⠉⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀  ",
 ];
+
+pub const PACMAN_ART_ASCII: [&str; 10] = [
+    "        .--------.        ",
+    "     .-'          '-.     ",
+    "   .'     (@)       .'    ",
+    "  /              .-'      ",
+    " ;            .-'    o  o ",
+    " ;            '-.         ",
+    "  \\              '-.      ",
+    "   '.               '.    ",
+    "     '-.          .-'     ",
+    "        '--------'        ",
+];
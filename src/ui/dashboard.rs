@@ -0,0 +1,958 @@
+use crate::config::Config;
+use crate::history::{self, IgnoreList};
+use crate::managers::{self, PackageManager, PendingPackage, SourceStats};
+use crate::pacman::{self, PendingUpdate};
+use crate::stats::{ManagerStats, StatId};
+use crate::terminal::TerminalGuard;
+use crate::{aur, util};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, MouseButton, MouseEvent, MouseEventKind,
+};
+use indicatif::ProgressBar;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout, Position, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame, Terminal};
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+
+const PAGE_SIZE: usize = 10;
+/// What the AUR tab shows of pacman's stats.
+const AUR_STATS: [StatId; 3] = [
+    StatId::ForeignPackages,
+    StatId::AurUpdates,
+    StatId::AurDownloadSize,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    Upgrade,
+    Sync,
+    Orphans,
+    CleanCache,
+    RankMirrors,
+}
+
+impl Action {
+    fn label(&self) -> &'static str {
+        match self {
+            Action::Upgrade => "Upgrade",
+            Action::Sync => "Sync",
+            Action::Orphans => "Orphan removal",
+            Action::CleanCache => "Cache clean",
+            Action::RankMirrors => "Mirror ranking",
+        }
+    }
+
+    /// Ranking only reads; writing the mirrorlist asks for root separately.
+    fn needs_root(&self) -> bool {
+        !matches!(self, Action::RankMirrors)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Action(Action),
+    Refresh,
+    Quit,
+}
+
+/// Footer buttons, clickable when mouse capture is on.
+const BUTTONS: [(char, &str, Command); 7] = [
+    ('u', "Upgrade", Command::Action(Action::Upgrade)),
+    ('s', "Sync", Command::Action(Action::Sync)),
+    ('o', "Orphans", Command::Action(Action::Orphans)),
+    ('c', "Clean cache", Command::Action(Action::CleanCache)),
+    ('m', "Rank mirrors", Command::Action(Action::RankMirrors)),
+    ('r', "Refresh", Command::Refresh),
+    ('q', "Quit", Command::Quit),
+];
+
+impl Command {
+    fn from_key(code: KeyCode) -> Option<Command> {
+        if code == KeyCode::Esc {
+            return Some(Command::Quit);
+        }
+        let KeyCode::Char(c) = code else {
+            return None;
+        };
+        BUTTONS
+            .iter()
+            .find(|(key, _, _)| *key == c)
+            .map(|(_, _, command)| *command)
+    }
+}
+
+/// A dashboard tab: one of the active backends, the AUR updates of pacman's foreign
+/// packages, or everything at once.
+enum Tab {
+    Backend {
+        manager: Box<dyn PackageManager>,
+        /// Where its stats are in `Dashboard::sections`.
+        section: usize,
+        /// What it has pending; pacman's richer list is `Dashboard::updates` instead.
+        packages: Vec<PendingPackage>,
+    },
+    Aur {
+        /// pacman's section, whose stats count the foreign packages.
+        section: usize,
+        packages: Vec<PendingPackage>,
+    },
+    All,
+}
+
+impl Tab {
+    fn title(&self) -> &str {
+        match self {
+            Tab::Backend { manager, .. } => manager.name(),
+            Tab::Aur { .. } => "AUR",
+            Tab::All => "All",
+        }
+    }
+
+    fn is_pacman(&self) -> bool {
+        matches!(self, Tab::Backend { manager, .. } if manager.name() == "pacman")
+    }
+
+    fn actions(&self) -> &'static [Action] {
+        match self {
+            Tab::Backend { .. } if self.is_pacman() => &[
+                Action::Upgrade,
+                Action::Sync,
+                Action::Orphans,
+                Action::CleanCache,
+                Action::RankMirrors,
+            ],
+            Tab::Backend { manager, .. } if manager.upgrade_command().is_some() => {
+                &[Action::Upgrade]
+            }
+            Tab::Aur { .. } if aur::helper().is_some() => &[Action::Upgrade],
+            _ => &[],
+        }
+    }
+}
+
+/// Interactive view of the stats and the pending update list, with a tab per active
+/// backend when there's more than one.
+struct Dashboard<'a> {
+    config: &'a Config,
+    /// Each active backend's stats, in tab order.
+    sections: Vec<SourceStats>,
+    /// The sections added up, for the All tab.
+    totals: ManagerStats,
+    tabs: Vec<Tab>,
+    tab: usize,
+    updates: Vec<PendingUpdate>,
+    /// Indices into the tab's rows matching the search, in display order.
+    visible: Vec<usize>,
+    list_state: ListState,
+    search: String,
+    searching: bool,
+    ignored: BTreeSet<String>,
+    /// What `pacfetch ignore` holds back, shown next to the updates it affects.
+    held: IgnoreList,
+    /// Result of the last action, and whether it failed.
+    status: Option<(String, bool)>,
+    details_scroll: u16,
+    // Screen areas from the last render, for mouse hit-testing
+    list_area: Rect,
+    details_area: Rect,
+    button_areas: Vec<(Rect, Command)>,
+    tab_areas: Vec<(Rect, usize)>,
+}
+
+/// Run the dashboard until the user quits. Returns the packages marked to ignore.
+pub fn run_dashboard(config: &Config) -> io::Result<Vec<String>> {
+    let spinner = util::create_spinner("Gathering stats");
+    let mut dashboard = Dashboard {
+        config,
+        sections: Vec::new(),
+        totals: ManagerStats::default(),
+        tabs: Vec::new(),
+        tab: 0,
+        updates: Vec::new(),
+        visible: Vec::new(),
+        list_state: ListState::default(),
+        search: String::new(),
+        searching: false,
+        ignored: BTreeSet::new(),
+        held: history::load_ignore_list(),
+        status: None,
+        details_scroll: 0,
+        list_area: Rect::default(),
+        details_area: Rect::default(),
+        button_areas: Vec::new(),
+        tab_areas: Vec::new(),
+    };
+    dashboard.gather(Some(&spinner));
+    dashboard.refilter();
+    spinner.finish_and_clear();
+
+    let mut screen = TerminalGuard::fullscreen(io::stdout(), config.tui.mouse)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    dashboard.run(&mut terminal, &mut screen)?;
+    Ok(dashboard.ignored.into_iter().collect())
+}
+
+impl Dashboard<'_> {
+    /// Gather every active backend's stats and pending updates into tabs, the AUR's after
+    /// the backends when pacman has foreign packages, and All last when there's more than
+    /// one tab.
+    fn gather(&mut self, spinner: Option<&ProgressBar>) {
+        self.sections.clear();
+        self.tabs.clear();
+        let mut aur = None;
+        for (i, manager) in managers::active(self.config).into_iter().enumerate() {
+            // The selected backend's stats are gathered and saved like a plain run's
+            let stats = if i == 0 {
+                managers::get_stats(self.config, false, spinner)
+            } else {
+                manager.get_stats_unsaved(self.config, false, spinner)
+            };
+            if let Some(pb) = spinner {
+                pb.set_message(format!("Resolving pending {} updates", manager.name()));
+            }
+            let mut packages = Vec::new();
+            if manager.name() == "pacman" {
+                self.updates = pacman::get_pending_updates();
+                let foreign = pacman::get_foreign_packages();
+                if !foreign.is_empty() {
+                    let names = aur::pending_updates(&foreign).unwrap_or_default();
+                    aur = Some(Tab::Aur {
+                        section: i,
+                        packages: names
+                            .into_iter()
+                            .map(|name| PendingPackage {
+                                name,
+                                new_version: None,
+                            })
+                            .collect(),
+                    });
+                }
+            } else {
+                packages = manager.pending_updates();
+            }
+            self.sections.push(SourceStats {
+                name: manager.name().to_string(),
+                stats,
+            });
+            self.tabs.push(Tab::Backend {
+                manager,
+                section: i,
+                packages,
+            });
+        }
+        self.tabs.extend(aur);
+        if self.tabs.len() > 1 {
+            self.tabs.push(Tab::All);
+        }
+        self.totals = managers::totals(&self.sections);
+        self.tab = self.tab.min(self.tabs.len() - 1);
+    }
+
+    fn current(&self) -> &Tab {
+        &self.tabs[self.tab]
+    }
+
+    fn stats(&self) -> &ManagerStats {
+        match self.current() {
+            Tab::Backend { section, .. } | Tab::Aur { section, .. } => {
+                &self.sections[*section].stats
+            }
+            Tab::All => &self.totals,
+        }
+    }
+
+    fn stat_ids(&self) -> Vec<StatId> {
+        let requested = &self.config.display.stats;
+        match self.current() {
+            Tab::Backend { .. } => requested.clone(),
+            Tab::Aur { .. } => AUR_STATS.to_vec(),
+            Tab::All => requested
+                .iter()
+                .filter(|id| managers::TOTAL_STATS.contains(id))
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// The tab's pending list as names and versions; the All tab puts each under its tab.
+    fn rows(&self) -> Vec<(String, Option<String>)> {
+        let rows_of = |tab: &Tab| -> Vec<(String, Option<String>)> {
+            match tab {
+                Tab::Backend { .. } if tab.is_pacman() => self
+                    .updates
+                    .iter()
+                    .map(|u| (u.name.clone(), Some(u.new_version.clone())))
+                    .collect(),
+                Tab::Backend { packages, .. } | Tab::Aur { packages, .. } => packages
+                    .iter()
+                    .map(|p| (p.name.clone(), p.new_version.clone()))
+                    .collect(),
+                Tab::All => Vec::new(),
+            }
+        };
+        match self.current() {
+            Tab::All => self
+                .tabs
+                .iter()
+                .flat_map(|tab| {
+                    rows_of(tab)
+                        .into_iter()
+                        .map(move |(name, version)| (format!("{}/{}", tab.title(), name), version))
+                })
+                .collect(),
+            tab => rows_of(tab),
+        }
+    }
+
+    fn switch_tab(&mut self, delta: isize) {
+        let count = self.tabs.len() as isize;
+        self.select_tab((self.tab as isize + delta).rem_euclid(count) as usize);
+    }
+
+    fn select_tab(&mut self, tab: usize) {
+        self.tab = tab;
+        self.search.clear();
+        self.details_scroll = 0;
+        self.refilter();
+    }
+
+    /// Whether the current tab has a button for `command`.
+    fn offers(&self, command: Command) -> bool {
+        match command {
+            Command::Action(action) => self.current().actions().contains(&action),
+            _ => true,
+        }
+    }
+
+    fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        screen: &mut TerminalGuard,
+    ) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.render(frame))?;
+
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                Event::Mouse(mouse) => {
+                    if let Some(command) = self.handle_mouse(mouse)
+                        && self.execute(terminal, screen, command)?
+                    {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            if self.searching {
+                match key.code {
+                    KeyCode::Esc => {
+                        self.searching = false;
+                        self.search.clear();
+                        self.refilter();
+                    }
+                    KeyCode::Enter => self.searching = false,
+                    KeyCode::Backspace => {
+                        self.search.pop();
+                        self.refilter();
+                    }
+                    KeyCode::Char(c) => {
+                        self.search.push(c);
+                        self.refilter();
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            if let Some(command) = Command::from_key(key.code).filter(|c| self.offers(*c)) {
+                if self.execute(terminal, screen, command)? {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::PageDown => self.move_selection(PAGE_SIZE as isize),
+                KeyCode::PageUp => self.move_selection(-(PAGE_SIZE as isize)),
+                KeyCode::Home | KeyCode::Char('g') => self.move_selection(isize::MIN),
+                KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX),
+                KeyCode::Tab => self.switch_tab(1),
+                KeyCode::BackTab => self.switch_tab(-1),
+                KeyCode::Char('/') => self.searching = true,
+                KeyCode::Char(' ') => self.toggle_ignored(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Run a command; returns true when the dashboard should close.
+    fn execute(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        screen: &mut TerminalGuard,
+        command: Command,
+    ) -> io::Result<bool> {
+        match command {
+            Command::Quit => return Ok(true),
+            Command::Action(action) => self.perform(terminal, screen, action)?,
+            Command::Refresh => {
+                self.status = Some(("Refreshing...".to_string(), false));
+                terminal.draw(|frame| self.render(frame))?;
+                self.refresh();
+                self.status = Some(("Refreshed".to_string(), false));
+            }
+        }
+        Ok(false)
+    }
+
+    fn handle_mouse(&mut self, mouse: MouseEvent) -> Option<Command> {
+        let pos = Position::new(mouse.column, mouse.row);
+
+        match mouse.kind {
+            MouseEventKind::ScrollDown if self.list_area.contains(pos) => self.move_selection(1),
+            MouseEventKind::ScrollUp if self.list_area.contains(pos) => self.move_selection(-1),
+            MouseEventKind::ScrollDown if self.details_area.contains(pos) => {
+                self.details_scroll = self.details_scroll.saturating_add(1);
+            }
+            MouseEventKind::ScrollUp if self.details_area.contains(pos) => {
+                self.details_scroll = self.details_scroll.saturating_sub(1);
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some((_, command)) = self.button_areas.iter().find(|(a, _)| a.contains(pos))
+                {
+                    return Some(*command);
+                }
+                if let Some(&(_, tab)) = self.tab_areas.iter().find(|(a, _)| a.contains(pos)) {
+                    self.select_tab(tab);
+                    return None;
+                }
+                if self.list_area.contains(pos) {
+                    // Skip the top border
+                    let row = pos.y.checked_sub(self.list_area.y + 1)? as usize;
+                    let index = self.list_state.offset() + row;
+                    if index < self.visible.len() {
+                        self.list_state.select(Some(index));
+                        self.details_scroll = 0;
+                    }
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Leave the TUI so the action can talk to the user directly, then come back and refresh.
+    fn perform(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        screen: &mut TerminalGuard,
+        action: Action,
+    ) -> io::Result<()> {
+        let pacman_tab = self.current().is_pacman();
+        if pacman_tab && action.needs_root() && !util::is_root() {
+            self.status = Some((format!("{} needs root", action.label()), true));
+            return Ok(());
+        }
+        // makepkg refuses to build as root, and the helpers with it
+        if matches!(self.current(), Tab::Aur { .. }) && util::is_root() {
+            self.status = Some(("AUR helpers can't run as root".to_string(), true));
+            return Ok(());
+        }
+
+        screen.suspend()?;
+
+        let ignored: Vec<String> = self.ignored.iter().cloned().collect();
+        let result = match (self.current(), action) {
+            (Tab::Backend { manager, .. }, Action::Upgrade) if !pacman_tab => {
+                match manager.upgrade_command() {
+                    Some([cmd, args @ ..]) => pacman::run_direct(cmd, args),
+                    _ => Err(format!("{} has no upgrade command", manager.name())),
+                }
+            }
+            (Tab::Aur { .. }, Action::Upgrade) => match aur::helper() {
+                Some(helper) => pacman::run_direct(helper, &["-Sua"]),
+                None => Err("No AUR helper is installed".to_string()),
+            },
+            (_, Action::Upgrade) => pacman::upgrade_system(self.config, false, false, &ignored),
+            (_, Action::Sync) => pacman::sync_databases(),
+            (_, Action::Orphans) => pacman::remove_orphans(),
+            (_, Action::CleanCache) => pacman::clean_cache(self.config),
+            (_, Action::RankMirrors) => rank_mirrors(self.config),
+        };
+        if let Err(e) = &result {
+            eprintln!("error: {}", e);
+        }
+        print!("\nPress Enter to return to the dashboard");
+        let _ = io::stdout().flush();
+        let _ = io::stdin().read_line(&mut String::new());
+
+        screen.resume()?;
+        terminal.clear()?;
+
+        self.status = Some(match result {
+            Ok(()) => (format!("{} finished", action.label()), false),
+            Err(e) => (format!("{} failed: {}", action.label(), e), true),
+        });
+        terminal.draw(|frame| self.render(frame))?;
+        self.refresh();
+        Ok(())
+    }
+
+    /// Gather everything again, staying on the same tab even if the tabs changed.
+    fn refresh(&mut self) {
+        let title = self.current().title().to_string();
+        self.gather(None);
+        if let Some(tab) = self.tabs.iter().position(|tab| tab.title() == title) {
+            self.tab = tab;
+        }
+        self.held = history::load_ignore_list();
+        let names: BTreeSet<&str> = self.updates.iter().map(|u| u.name.as_str()).collect();
+        self.ignored.retain(|name| names.contains(name.as_str()));
+        self.refilter();
+    }
+
+    fn refilter(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .rows()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (name, _))| util::fuzzy_score(&self.search, name).map(|s| (s, i)))
+            .collect();
+        if !self.search.is_empty() {
+            scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        }
+
+        self.visible = scored.into_iter().map(|(_, i)| i).collect();
+        self.list_state.select(if self.visible.is_empty() {
+            None
+        } else {
+            Some(0)
+        });
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let last = self.visible.len() as isize - 1;
+        let next = current.saturating_add(delta).clamp(0, last);
+        self.list_state.select(Some(next as usize));
+        self.details_scroll = 0;
+    }
+
+    /// Whether `upgrade.min_age_days` leaves this update for a later upgrade.
+    fn too_fresh(&self, update: &PendingUpdate) -> bool {
+        self.config.upgrade.min_age_days.is_some_and(|days| {
+            chrono::Local::now().timestamp() - update.build_date < i64::from(days) * 86400
+        })
+    }
+
+    /// The selected update, on the pacman tab.
+    fn selected(&self) -> Option<&PendingUpdate> {
+        if !self.current().is_pacman() {
+            return None;
+        }
+        let index = *self.visible.get(self.list_state.selected()?)?;
+        self.updates.get(index)
+    }
+
+    fn toggle_ignored(&mut self) {
+        let Some(name) = self.selected().map(|u| u.name.clone()) else {
+            return;
+        };
+        if !self.ignored.remove(&name) {
+            self.ignored.insert(name);
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let stat_lines: Vec<Line> = self
+            .stat_ids()
+            .iter()
+            .filter_map(|id| {
+                id.format_value(self.stats(), &self.config.display.format_of(*id))
+                    .map(|value| {
+                        Line::from(vec![
+                            format!("{}: ", id.label()).bold().yellow(),
+                            value.into(),
+                        ])
+                    })
+            })
+            .collect();
+
+        let tab_bar = u16::from(self.tabs.len() > 1);
+        let rows = Layout::vertical([
+            Constraint::Length(tab_bar),
+            Constraint::Length(stat_lines.len() as u16 + 2),
+            Constraint::Min(5),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .split(frame.area());
+        self.render_tabs(frame, rows[0]);
+        let rows = &rows[1..];
+
+        let title = match (self.current(), &self.stats().pacman_version) {
+            (Tab::Backend { .. }, Some(version)) if self.current().is_pacman() => {
+                format!(" {} ", version)
+            }
+            (tab, _) => format!(" {} ", tab.title()),
+        };
+        frame.render_widget(
+            Paragraph::new(stat_lines).block(super::block().title(title.bold().yellow())),
+            rows[0],
+        );
+
+        let columns = Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)])
+            .split(rows[1]);
+        self.list_area = columns[0];
+        self.details_area = columns[1];
+        self.render_list(frame, columns[0]);
+        self.render_details(frame, columns[1]);
+
+        self.render_buttons(frame, rows[2]);
+
+        let footer = if self.searching {
+            Line::from(vec![
+                "/".yellow(),
+                self.search.clone().into(),
+                util::symbols().cursor.into(),
+            ])
+        } else if let Some((message, failed)) = &self.status {
+            if *failed {
+                Line::from(message.clone().red())
+            } else {
+                Line::from(message.clone().green())
+            }
+        } else {
+            let symbols = util::symbols();
+            let mut hint = format!(
+                "{} scroll {sep} / search",
+                symbols.up_down,
+                sep = symbols.separator
+            );
+            if self.current().is_pacman() {
+                hint.push_str(&format!(" {} space ignore on upgrade", symbols.separator));
+            }
+            if self.tabs.len() > 1 {
+                hint.push_str(&format!(" {} tab switch view", symbols.separator));
+            }
+            Line::from(hint.dark_gray())
+        };
+        frame.render_widget(Paragraph::new(footer), rows[3]);
+    }
+
+    fn render_tabs(&mut self, frame: &mut Frame, area: Rect) {
+        self.tab_areas.clear();
+        if area.height == 0 {
+            return;
+        }
+        let mut spans = Vec::new();
+        let mut x = area.x;
+
+        for (i, tab) in self.tabs.iter().enumerate() {
+            let text = format!(" {} ", tab.title());
+            let width = text.len() as u16;
+            if x + width > area.right() {
+                break;
+            }
+
+            self.tab_areas.push((Rect::new(x, area.y, width, 1), i));
+            spans.push(if i == self.tab {
+                text.black().on_yellow()
+            } else {
+                text.dark_gray()
+            });
+            spans.push(" ".into());
+            x += width + 1;
+        }
+
+        frame.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+
+    fn render_buttons(&mut self, frame: &mut Frame, area: Rect) {
+        self.button_areas.clear();
+        let mut spans = Vec::new();
+        let mut x = area.x;
+
+        let offered: Vec<_> = BUTTONS
+            .into_iter()
+            .filter(|(.., command)| self.offers(*command))
+            .collect();
+        for (key, label, command) in offered {
+            let key_text = format!(" {} ", key);
+            let label_text = format!(" {} ", label);
+            let width = (key_text.len() + label_text.len()) as u16;
+            if x + width > area.right() {
+                break;
+            }
+
+            self.button_areas
+                .push((Rect::new(x, area.y, width, 1), command));
+            spans.push(key_text.black().on_yellow());
+            spans.push(label_text.into());
+            spans.push(" ".into());
+            x += width + 1;
+        }
+
+        frame.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+
+    fn render_list(&mut self, frame: &mut Frame, area: Rect) {
+        if !self.current().is_pacman() {
+            return self.render_rows(frame, area);
+        }
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let update = &self.updates[i];
+                let ignored = self.ignored.contains(&update.name);
+                let held = self.held.contains(&update.name) || self.too_fresh(update);
+                let version = match &update.old_version {
+                    Some(old) => {
+                        format!("{} {} {}", old, util::symbols().arrow, update.new_version)
+                    }
+                    None => format!("new {}", update.new_version),
+                };
+                let name_style = if ignored || held {
+                    Style::new()
+                        .fg(Color::DarkGray)
+                        .add_modifier(Modifier::CROSSED_OUT)
+                } else {
+                    Style::new().bold()
+                };
+                let repo_switch = match &update.previous_repo {
+                    Some(previous) => {
+                        format!(" {} {} {}", previous, util::symbols().arrow, update.repo)
+                    }
+                    None => String::new(),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw(if ignored {
+                        "[i] "
+                    } else if self.too_fresh(update) {
+                        "[f] "
+                    } else if held {
+                        "[h] "
+                    } else {
+                        "    "
+                    }),
+                    Span::styled(update.name.clone(), name_style),
+                    Span::raw(" "),
+                    version.dark_gray(),
+                    repo_switch.yellow(),
+                ]))
+            })
+            .collect();
+
+        let held = self
+            .updates
+            .iter()
+            .filter(|u| self.held.contains(&u.name) || self.too_fresh(u))
+            .count();
+        let count = if held > 0 {
+            format!("{}, {} held", self.updates.len(), held)
+        } else {
+            self.updates.len().to_string()
+        };
+        let title = if self.search.is_empty() {
+            format!(" Pending updates ({}) ", count)
+        } else {
+            format!(
+                " Pending updates ({}/{} matching \"{}\") ",
+                self.visible.len(),
+                self.updates.len(),
+                self.search
+            )
+        };
+
+        let list = List::new(items)
+            .block(super::block().title(title.bold()))
+            .highlight_style(Style::new().bg(Color::DarkGray))
+            .highlight_symbol(format!("{} ", util::symbols().pointer));
+        frame.render_stateful_widget(list, area, &mut self.list_state);
+    }
+
+    /// The list for tabs other than pacman's, which only know names and versions.
+    fn render_rows(&mut self, frame: &mut Frame, area: Rect) {
+        let rows = self.rows();
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let (name, version) = &rows[i];
+                ListItem::new(Line::from(vec![
+                    Span::raw("    "),
+                    Span::styled(name.clone(), Style::new().bold()),
+                    Span::raw(" "),
+                    version.clone().unwrap_or_default().dark_gray(),
+                ]))
+            })
+            .collect();
+
+        let title = if self.search.is_empty() {
+            format!(" Pending updates ({}) ", rows.len())
+        } else {
+            format!(
+                " Pending updates ({}/{} matching \"{}\") ",
+                self.visible.len(),
+                rows.len(),
+                self.search
+            )
+        };
+
+        let list = List::new(items)
+            .block(super::block().title(title.bold()))
+            .highlight_style(Style::new().bg(Color::DarkGray))
+            .highlight_symbol(format!("{} ", util::symbols().pointer));
+        frame.render_stateful_widget(list, area, &mut self.list_state);
+    }
+
+    fn render_details(&self, frame: &mut Frame, area: Rect) {
+        let block = super::block().title(" Details ".bold());
+        if !self.current().is_pacman() {
+            let rows = self.rows();
+            let Some((name, version)) = self
+                .list_state
+                .selected()
+                .and_then(|i| self.visible.get(i))
+                .map(|&i| &rows[i])
+            else {
+                frame.render_widget(Paragraph::new("No pending updates").block(block), area);
+                return;
+            };
+            let mut lines = vec![Line::from(name.clone().bold())];
+            if let Some(version) = version {
+                lines.push(Line::from(vec![
+                    "Version: ".bold().yellow(),
+                    version.clone().into(),
+                ]));
+            }
+            frame.render_widget(Paragraph::new(lines).block(block), area);
+            return;
+        }
+        let Some(update) = self.selected() else {
+            frame.render_widget(Paragraph::new("No pending updates").block(block), area);
+            return;
+        };
+
+        let field = |label: &str, value: String| {
+            Line::from(vec![format!("{}: ", label).bold().yellow(), value.into()])
+        };
+        let mib = |bytes: i64| format!("{:.2} MiB", bytes as f64 / 1048576.0);
+
+        let installed = match update.old_installed_size {
+            Some(old) => format!(
+                "{} ({:+.2} MiB)",
+                mib(update.installed_size),
+                (update.installed_size - old) as f64 / 1048576.0
+            ),
+            None => mib(update.installed_size),
+        };
+        let pulled_in = if update.pulled_in.is_empty() {
+            "nothing new".to_string()
+        } else {
+            update.pulled_in.join(", ")
+        };
+
+        let mut lines = vec![
+            Line::from(update.name.clone().bold()),
+            field(
+                "Version",
+                match &update.old_version {
+                    Some(old) => {
+                        format!("{} {} {}", old, util::symbols().arrow, update.new_version)
+                    }
+                    None => format!("{} (new install)", update.new_version),
+                },
+            ),
+            field(
+                "Repository",
+                match &update.previous_repo {
+                    Some(previous) => format!("{} (was {})", update.repo, previous),
+                    None => update.repo.clone(),
+                },
+            ),
+            field("Download Size", mib(update.download_size)),
+            field("Installed Size", installed),
+            field("URL", update.url.clone().unwrap_or_else(|| "-".to_string())),
+            field("Pulls In", pulled_in),
+        ];
+        if self.ignored.contains(&update.name) {
+            lines.push(Line::from("Ignored on upgrade".dark_gray()));
+        }
+        if let Some(hold) = self.held.hold.iter().find(|h| h.name == update.name) {
+            let text = format!("Held {}", super::describe_hold(hold));
+            lines.push(Line::from(text.dark_gray()));
+        } else if self.held.packages.contains(&update.name) {
+            lines.push(Line::from("Held by pacfetch ignore".dark_gray()));
+        }
+        if self.too_fresh(update) {
+            let age = chrono::Local::now().timestamp() - update.build_date;
+            let text = format!(
+                "Too fresh: built {} ago, installed after {} days",
+                util::compact_duration(age),
+                self.config.upgrade.min_age_days.unwrap_or(0)
+            );
+            lines.push(Line::from(text.dark_gray()));
+        }
+        if let Some(desc) = &update.description {
+            lines.push(Line::default());
+            lines.push(Line::from(desc.clone()));
+        }
+
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(block)
+                .wrap(Wrap { trim: false })
+                .scroll((self.details_scroll, 0)),
+            area,
+        );
+    }
+}
+
+/// Rank mirrors on the plain terminal and offer to save the new order.
+fn rank_mirrors(config: &Config) -> Result<(), String> {
+    let spinner = util::create_spinner("Ranking mirrors");
+    let ranked = pacman::rank_mirrors(&config.mirror);
+    spinner.finish_and_clear();
+
+    if ranked.is_empty() {
+        return Err("no mirror answered".to_string());
+    }
+
+    for (i, mirror) in ranked.iter().enumerate() {
+        println!(
+            "{:>2}. {}  {} ms, last sync {:.1} hours ago",
+            i + 1,
+            mirror.url,
+            mirror.latency.as_millis(),
+            mirror.sync_age_hours
+        );
+    }
+
+    if !util::is_root() {
+        println!("\nRun as root to save this order to the mirrorlist.");
+        return Ok(());
+    }
+
+    print!("\nWrite this order to the mirrorlist? [y/N] ");
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    let _ = io::stdin().read_line(&mut answer);
+    if answer.trim().eq_ignore_ascii_case("y") {
+        pacman::write_mirrorlist(&ranked)?;
+        println!("Saved; the previous mirrorlist is in mirrorlist.bak");
+    }
+    Ok(())
+}
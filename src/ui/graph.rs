@@ -0,0 +1,89 @@
+use crate::config::SymbolSet;
+use crate::util;
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::symbols::Marker;
+use ratatui::widgets::{Axis, Chart, Dataset, GraphType, Widget};
+
+const GRAPH_HEIGHT: u16 = 20;
+const DEFAULT_WIDTH: u16 = 80;
+
+fn axis_date(timestamp: f64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+/// Swap the chart's box drawing and braille for ASCII when the console can't show them.
+fn to_ascii(line: &str) -> String {
+    line.chars()
+        .map(|c| match c {
+            '│' => '|',
+            '─' => '-',
+            '└' | '┌' | '┐' | '┘' => '+',
+            '\u{2800}' => ' ',
+            c if c.is_ascii() => c,
+            _ => '*',
+        })
+        .collect()
+}
+
+/// Print a line chart of a stat's recorded values, with dates along the bottom.
+pub fn display_graph(stat: &str, points: &[(i64, f64)]) {
+    let data: Vec<(f64, f64)> = points.iter().map(|(t, v)| (*t as f64, *v)).collect();
+    let (x_min, x_max) = data.iter().fold((f64::MAX, f64::MIN), |(lo, hi), (x, _)| {
+        (lo.min(*x), hi.max(*x))
+    });
+    let (y_min, y_max) = data.iter().fold((f64::MAX, f64::MIN), |(lo, hi), (_, y)| {
+        (lo.min(*y), hi.max(*y))
+    });
+    // A flat line still needs some room above and below it
+    let (y_min, y_max) = if (y_max - y_min).abs() < f64::EPSILON {
+        (y_min - 1.0, y_max + 1.0)
+    } else {
+        (y_min, y_max)
+    };
+
+    let marker = match util::symbol_set() {
+        SymbolSet::Unicode => Marker::Braille,
+        SymbolSet::Ascii => Marker::Dot,
+    };
+    let dataset = Dataset::default()
+        .name(stat.to_string())
+        .marker(marker)
+        .graph_type(GraphType::Line)
+        .data(&data);
+    let chart = Chart::new(vec![dataset])
+        .x_axis(Axis::default().bounds([x_min, x_max]).labels([
+            axis_date(x_min),
+            axis_date((x_min + x_max) / 2.0),
+            axis_date(x_max),
+        ]))
+        .y_axis(Axis::default().bounds([y_min, y_max]).labels([
+            format!("{:.1}", y_min),
+            format!("{:.1}", (y_min + y_max) / 2.0),
+            format!("{:.1}", y_max),
+        ]))
+        .legend_position(None);
+
+    let width = util::terminal_width()
+        .map(|w| w as u16)
+        .unwrap_or(DEFAULT_WIDTH);
+    let area = Rect::new(0, 0, width, GRAPH_HEIGHT);
+    let mut buffer = Buffer::empty(area);
+    chart.render(area, &mut buffer);
+
+    println!("{}", stat);
+    for y in 0..area.height {
+        let line: String = (0..area.width).map(|x| buffer[(x, y)].symbol()).collect();
+        let line = match util::symbol_set() {
+            SymbolSet::Unicode => line,
+            SymbolSet::Ascii => to_ascii(&line),
+        };
+        println!("{}", line.trim_end());
+    }
+}
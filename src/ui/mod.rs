@@ -1,31 +1,958 @@
 mod ascii;
+#[cfg(target_os = "linux")]
+mod dashboard;
+mod graph;
+mod picker;
+mod upgrade;
 
-use crate::config::Config;
-use crate::pacman::ManagerStats;
-use crate::stats::StatId;
+#[cfg(target_os = "linux")]
+use crate::aur::AurInfo;
+use crate::config::{Config, SymbolSet};
+#[cfg(target_os = "linux")]
+use crate::history::OrphanRecord;
+use crate::history::{CommandUsage, Hold, StatsSnapshot};
+use crate::managers::{self, PendingStats, SourceStats};
+#[cfg(target_os = "linux")]
+use crate::pacman::{
+    self, CacheSavings, FileConflict, ForeignPackage, MissingOptdep, OptionalOnly, PackageListing,
+    PendingUpdate, Provider, RebuildCandidate, TestingChange,
+};
+#[cfg(target_os = "linux")]
+use crate::sandbox::{Problem, ProblemKind};
+use crate::stats::{self, ManagerStats, StatId};
+use crate::util;
+use ratatui::symbols::border;
+use ratatui::widgets::Block;
 use std::io;
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use termimad::MadSkin;
 use termimad::crossterm::style::{Color::*, Stylize};
 
-pub fn display_stats(stats: &ManagerStats, config: &Config) {
+#[cfg(target_os = "linux")]
+pub use dashboard::run_dashboard;
+pub use graph::display_graph;
+#[cfg(target_os = "linux")]
+pub use picker::{PickItem, pick, pick_live};
+#[cfg(target_os = "linux")]
+pub use upgrade::UpgradeScreen;
+
+const ASCII_BORDER: border::Set = border::Set {
+    top_left: "+",
+    top_right: "+",
+    bottom_left: "+",
+    bottom_right: "+",
+    vertical_left: "|",
+    vertical_right: "|",
+    horizontal_top: "-",
+    horizontal_bottom: "-",
+};
+
+/// A bordered block drawn with the configured symbol set.
+fn block<'a>() -> Block<'a> {
+    match util::symbol_set() {
+        SymbolSet::Unicode => Block::bordered(),
+        SymbolSet::Ascii => Block::bordered().border_set(ASCII_BORDER),
+    }
+}
+
+/// Below this many columns values are left alone rather than squeezed further.
+const MIN_VALUE_WIDTH: usize = 16;
+
+/// Fit a stat value into `width` columns: URLs lose their middle, anything else wraps.
+fn fit_value(stat_id: StatId, value: &str, width: Option<usize>) -> Vec<String> {
+    let Some(width) = width.map(|w| w.max(MIN_VALUE_WIDTH)) else {
+        return vec![value.to_string()];
+    };
+    if value.chars().count() <= width || value.contains('\x1b') {
+        return vec![value.to_string()];
+    }
+
+    if stat_id == StatId::MirrorUrl {
+        vec![util::truncate_middle(value, width)]
+    } else {
+        util::wrap_list(value, width)
+    }
+}
+
+/// Colored arrow and amount for a stat that changed since the previous run.
+fn format_delta_colored(stat_id: StatId, delta: f64) -> String {
+    let symbols = util::symbols();
+    let arrow = if delta > 0.0 {
+        symbols.up
+    } else {
+        symbols.down
+    };
+    let text = format!(
+        "{}{}",
+        arrow,
+        stat_id.format_delta(delta).trim_start_matches(['+', '-'])
+    );
+
+    let color = match (stat_id.higher_is_worse(), delta > 0.0) {
+        (false, _) => Cyan,
+        (true, true) => Red,
+        (true, false) => Green,
+    };
+    format!("{}", text.with(color))
+}
+
+fn format_date(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// The updates `upgrade.min_age_days` keeps back this time, with how long they've been out.
+#[cfg(target_os = "linux")]
+pub fn display_too_fresh(updates: &[PendingUpdate], now: i64) {
+    println!("Too fresh to install yet ({})", updates.len());
+    let width = updates.iter().map(|u| u.name.len()).max().unwrap_or(0);
+    for update in updates {
+        println!(
+            "  {:width$}  {}  built {} ago",
+            update.name,
+            update.new_version,
+            util::compact_duration(now - update.build_date)
+        );
+    }
+    println!();
+}
+
+/// The pending updates from testing repos and what the upgrade would be without them.
+#[cfg(target_os = "linux")]
+pub fn display_testing(changes: Option<&[TestingChange]>) {
+    let Some(changes) = changes else {
+        println!("No testing repos are enabled.");
+        return;
+    };
+    if changes.is_empty() {
+        println!("No pending updates come from testing repos.");
+        return;
+    }
+
+    println!("Updates from testing repos ({})", changes.len());
+    let name_width = changes.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    let version_width = changes
+        .iter()
+        .map(|c| c.testing_version.len() + c.repo.len() + 3)
+        .max()
+        .unwrap_or(0);
+    for change in changes {
+        let testing = format!("{} ({})", change.testing_version, change.repo);
+        let without = match (&change.stable_version, &change.installed) {
+            (Some(stable), _) => format!("{} {}", util::symbols().arrow, stable),
+            (None, Some(installed)) => format!("stays at {}", installed),
+            (None, None) => "not installed".to_string(),
+        };
+        println!(
+            "  {:name_width$}  {:version_width$}  without testing: {}",
+            change.name, testing, without
+        );
+    }
+
+    let held = changes
+        .iter()
+        .filter(|c| c.stable_version.is_none())
+        .count();
+    println!();
+    println!(
+        "Disabling testing would skip {} of these and take the rest from the stable repos.",
+        held
+    );
+}
+
+/// What a sandboxed upgrade ran into, grouped by kind.
+#[cfg(target_os = "linux")]
+pub fn display_simulation(problems: &[Problem]) {
+    if problems.is_empty() {
+        println!("The simulated upgrade completed without problems.");
+        return;
+    }
+
+    println!(
+        "The simulated upgrade ran into {} problem(s)",
+        problems.len()
+    );
+    let groups = [
+        (ProblemKind::FileConflict, "File conflicts"),
+        (ProblemKind::HookFailure, "Failed hooks and scriptlets"),
+        (ProblemKind::Error, "Errors"),
+    ];
+    for (kind, heading) in groups {
+        let messages: Vec<&str> = problems
+            .iter()
+            .filter(|p| p.kind == kind)
+            .map(|p| p.message.as_str())
+            .collect();
+        if messages.is_empty() {
+            continue;
+        }
+        println!("{}", heading);
+        for message in messages {
+            println!("  {}", message);
+        }
+    }
+}
+
+/// Files the pending upgrade would install over, with who owns them now.
+#[cfg(target_os = "linux")]
+pub fn display_file_conflicts(conflicts: &[FileConflict]) {
+    println!("File conflicts ({})", conflicts.len());
+    let width = conflicts.iter().map(|c| c.package.len()).max().unwrap_or(0);
+    for conflict in conflicts {
+        let owner = match &conflict.owner {
+            Some(owner) => format!("owned by {}", owner),
+            None => "not owned by any package".to_string(),
+        };
+        println!(
+            "  {:width$}  {}  ({})",
+            conflict.package, conflict.path, owner
+        );
+    }
+    println!();
+}
+
+/// The post-upgrade note on what the cache saved, with the total over the recorded upgrades.
+#[cfg(target_os = "linux")]
+pub fn display_cache_savings(savings: &CacheSavings, total: (f64, usize)) {
+    println!("{}", format_cache_savings(savings, total));
+}
+
+#[cfg(target_os = "linux")]
+fn format_cache_savings(savings: &CacheSavings, (total_mb, upgrades): (f64, usize)) -> String {
+    let packages = match savings.packages {
+        0 => String::new(),
+        n => format!(
+            " ({} package{} already downloaded)",
+            n,
+            if n != 1 { "s" } else { "" }
+        ),
+    };
+    format!(
+        "Saved {:.2} MiB via cache{}; {:.2} MiB over the last {} upgrade{}",
+        savings.bytes as f64 / 1048576.0,
+        packages,
+        total_mb,
+        upgrades,
+        if upgrades != 1 { "s" } else { "" }
+    )
+}
+
+/// When a hold ends, e.g. "until 1:24.1.2-1 or 2024-06-20".
+pub fn describe_hold(hold: &Hold) -> String {
+    let conditions: Vec<String> = [hold.until_version.clone(), hold.until.map(format_date)]
+        .into_iter()
+        .flatten()
+        .collect();
+    format!("until {}", conditions.join(" or "))
+}
+
+/// List foreign packages with their AUR state: version, votes, and risk flags.
+#[cfg(target_os = "linux")]
+pub fn display_foreign(
+    packages: &[ForeignPackage],
+    aur: &[AurInfo],
+    rebuilds: &[RebuildCandidate],
+) {
+    let color = util::output_mode() == util::OutputMode::Graphics;
+    let paint = |text: String, c| {
+        if color {
+            format!("{}", text.with(c))
+        } else {
+            text
+        }
+    };
+
+    println!("Foreign packages ({})", packages.len());
+    let name_width = packages.iter().map(|p| p.name.len()).max().unwrap_or(0);
+    let version_width = packages.iter().map(|p| p.version.len()).max().unwrap_or(0);
+
+    for pkg in packages {
+        let mut line = format!("  {:name_width$}  {:version_width$}", pkg.name, pkg.version);
+
+        let rebuild = rebuilds.iter().find(|r| r.name == pkg.name).map(|r| {
+            let flag = format!("[rebuild recommended: {}]", r.reason);
+            format!("  {}", paint(flag, Magenta))
+        });
+
+        let Some(info) = aur.iter().find(|i| i.name == pkg.name) else {
+            line.push_str(&format!("  {}", paint("not in AUR".to_string(), Red)));
+            line.push_str(&rebuild.unwrap_or_default());
+            println!("{}", line);
+            continue;
+        };
+
+        if info.version != pkg.version {
+            line.push_str(&format!(
+                "  {} {}",
+                util::symbols().arrow,
+                paint(info.version.clone(), Green)
+            ));
+        }
+        line.push_str(&format!(
+            "  votes {}  popularity {:.2}  modified {}",
+            info.num_votes,
+            info.popularity,
+            format_date(info.last_modified)
+        ));
+        if let Some(flagged) = info.out_of_date {
+            let flag = format!("[out-of-date since {}]", format_date(flagged));
+            line.push_str(&format!("  {}", paint(flag, Yellow)));
+        }
+        if info.maintainer.is_none() {
+            line.push_str(&format!("  {}", paint("[orphaned]".to_string(), Red)));
+        }
+        line.push_str(&rebuild.unwrap_or_default());
+        println!("{}", line);
+    }
+}
+
+/// Orphans noticed within this many seconds are listed as new.
+const NEW_ORPHAN_SECS: i64 = 7 * 86400;
+/// Packages named as the likely cause of a new orphan before the rest are counted.
+const MAX_ORPHAN_CAUSES: usize = 3;
+
+fn name_list(names: &[String]) -> String {
+    if names.len() <= MAX_ORPHAN_CAUSES {
+        return names.join(", ");
+    }
+    format!(
+        "{} and {} more",
+        names[..MAX_ORPHAN_CAUSES].join(", "),
+        names.len() - MAX_ORPHAN_CAUSES
+    )
+}
+
+pub fn display_usage(usage: &[CommandUsage], now: i64) {
+    print!("{}", format_usage(usage, now));
+}
+
+/// A table of pacfetch's own runs per command, from the local usage history.
+pub fn format_usage(usage: &[CommandUsage], now: i64) -> String {
+    if usage.is_empty() {
+        return "No runs recorded yet.\n".to_string();
+    }
+    let width = usage
+        .iter()
+        .map(|u| u.command.len())
+        .max()
+        .unwrap_or(0)
+        .max("Command".len());
+    let mut out = format!(
+        "{:width$}  {:>5}  {:>6}  {:>7}  {:>7}  Last run\n",
+        "Command", "Runs", "Failed", "Avg", "Max"
+    );
+    for u in usage {
+        out.push_str(&format!(
+            "{:width$}  {:>5}  {:>6}  {:>6.1}s  {:>6.1}s  {} ago\n",
+            u.command,
+            u.runs,
+            u.failures,
+            u.avg_secs,
+            u.max_secs,
+            util::compact_duration((now - u.last_run).max(0))
+        ));
+    }
+    let runs: u32 = usage.iter().map(|u| u.runs).sum();
+    let failures: u32 = usage.iter().map(|u| u.failures).sum();
+    out.push_str(&format!(
+        "\n{} runs, {} failed. Recorded on this machine only.\n",
+        runs, failures
+    ));
+    out
+}
+
+/// List orphans, newest first, with what most likely orphaned the ones from the last week.
+#[cfg(target_os = "linux")]
+pub fn display_orphans(records: &[OrphanRecord], now: i64) {
+    if records.is_empty() {
+        println!("No orphaned packages.");
+        return;
+    }
+
+    let mut records = records.to_vec();
+    records.sort_by(|a, b| b.first_seen.cmp(&a.first_seen).then(a.name.cmp(&b.name)));
+    let (new, old): (Vec<_>, Vec<_>) = records
+        .iter()
+        .partition(|r| r.previous_check.is_some() && now - r.first_seen <= NEW_ORPHAN_SECS);
+
+    let width = records.iter().map(|r| r.name.len()).max().unwrap_or(0);
+    if !new.is_empty() {
+        println!("New orphans since last week ({})", new.len());
+        let since = |record: &OrphanRecord| record.previous_check.unwrap_or(record.first_seen);
+        let log = pacman::LogChanges::read(new.iter().map(|r| since(r)).min().unwrap_or(now));
+        for record in &new {
+            let cause = match log.orphan_causes(since(record), record.first_seen) {
+                (removed, _) if !removed.is_empty() => {
+                    format!("after removing {}", name_list(&removed))
+                }
+                (_, upgraded) if !upgraded.is_empty() => {
+                    format!("after upgrading {}", name_list(&upgraded))
+                }
+                _ => "cause unknown".to_string(),
+            };
+            println!(
+                "  {:width$}  {}  {}",
+                record.name,
+                format_date(record.first_seen),
+                cause
+            );
+        }
+    }
+    if !old.is_empty() {
+        if !new.is_empty() {
+            println!();
+        }
+        println!("Orphans ({})", old.len());
+        for record in &old {
+            println!(
+                "  {:width$}  seen since {}",
+                record.name,
+                format_date(record.first_seen)
+            );
+        }
+    }
+}
+
+/// Collectors slower than this are worth caching or running in parallel.
+const SLOW_COLLECTOR: Duration = Duration::from_millis(100);
+
+/// Print `bench` timings, slowest collector first. `timings` are sorted ascending.
+pub fn display_bench(baseline: &[Duration], results: &[(StatId, Vec<Duration>)]) {
+    let median = |timings: &[Duration]| timings.get(timings.len() / 2).copied().unwrap_or_default();
+    let ms = |d: Duration| format!("{:.1} ms", d.as_secs_f64() * 1000.0);
+    let base = median(baseline);
+
+    let mut rows: Vec<(String, Duration, Duration, Duration)> = results
+        .iter()
+        .map(|(id, timings)| {
+            let own = median(timings).saturating_sub(base);
+            let min = timings.first().copied().unwrap_or_default();
+            let max = timings.last().copied().unwrap_or_default();
+            (id.name(), own, min, max)
+        })
+        .collect();
+    rows.sort_by_key(|r| std::cmp::Reverse(r.1));
+
+    let width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0).max(8);
+    println!(
+        "{:width$}  {:>10}  {:>10}  {:>10}",
+        "Stat", "Own", "Min", "Max"
+    );
+    println!("{:width$}  {:>10}", "baseline", ms(base));
+    for (name, own, min, max) in &rows {
+        println!(
+            "{:width$}  {:>10}  {:>10}  {:>10}",
+            name,
+            ms(*own),
+            ms(*min),
+            ms(*max)
+        );
+    }
+
+    let slow: Vec<&str> = rows
+        .iter()
+        .filter(|r| r.1 >= SLOW_COLLECTOR)
+        .map(|r| r.0.as_str())
+        .collect();
+    println!();
+    println!(
+        "Own is the median over {} runs minus the baseline.",
+        baseline.len()
+    );
+    if slow.is_empty() {
+        println!("No collector takes longer than {}.", ms(SLOW_COLLECTOR));
+    } else {
+        println!("Worth caching or running in parallel: {}", slow.join(", "));
+    }
+}
+
+/// Search results in `pacman -Ss` style: repo/name version, then the description.
+#[cfg(target_os = "linux")]
+pub fn display_search(results: &[PackageListing]) {
+    if results.is_empty() {
+        println!("No packages found.");
+        return;
+    }
+    let color = util::output_mode() == util::OutputMode::Graphics;
+    for pkg in results {
+        let installed = if pkg.installed { " [installed]" } else { "" };
+        if color {
+            println!(
+                "{}{} {}{}",
+                format!("{}/", pkg.repo).with(Magenta),
+                pkg.name.as_str().bold(),
+                pkg.version.as_str().with(Green),
+                installed.with(Cyan)
+            );
+        } else {
+            println!("{}/{} {}{}", pkg.repo, pkg.name, pkg.version, installed);
+        }
+        if let Some(description) = &pkg.description {
+            println!("    {}", description);
+        }
+    }
+}
+
+/// Package details in `pacman -Si` style, one block per package.
+#[cfg(target_os = "linux")]
+pub fn display_info(packages: &[PackageListing]) {
+    for (i, pkg) in packages.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("Name        : {}", pkg.name);
+        println!("Version     : {}", pkg.version);
+        println!("Repository  : {}", pkg.repo);
+        println!("Installed   : {}", if pkg.installed { "Yes" } else { "No" });
+        println!(
+            "Description : {}",
+            pkg.description.as_deref().unwrap_or("None")
+        );
+    }
+}
+
+/// The packages that ship a command, installed ones marked.
+#[cfg(target_os = "linux")]
+pub fn display_providers(command: &str, providers: &[Provider]) {
+    println!("'{}' is provided by:", command);
+    for provider in providers {
+        let installed = if provider.installed {
+            " [installed]"
+        } else {
+            ""
+        };
+        println!(
+            "  {}/{} {}  ({}){}",
+            provider.repo, provider.name, provider.version, provider.path, installed
+        );
+    }
+}
+
+/// List missing optional dependencies per package, then dependencies that only
+/// optional dependencies still keep installed.
+#[cfg(target_os = "linux")]
+pub fn display_optdeps(missing: &[MissingOptdep], optional_only: &[OptionalOnly]) {
+    if missing.is_empty() && optional_only.is_empty() {
+        println!("All optional dependencies are installed and wanted.");
+        return;
+    }
+
+    if !missing.is_empty() {
+        println!("Missing optional dependencies");
+        let width = missing.iter().map(|m| m.optdep.len()).max().unwrap_or(0);
+        let mut current: Option<&str> = None;
+        for optdep in missing {
+            if current != Some(optdep.package.as_str()) {
+                println!("  {}", optdep.package);
+                current = Some(&optdep.package);
+            }
+            match &optdep.description {
+                Some(desc) => println!("    {:width$}  {}", optdep.optdep, desc),
+                None => println!("    {}", optdep.optdep),
+            }
+        }
+    }
+
+    if !optional_only.is_empty() {
+        if !missing.is_empty() {
+            println!();
+        }
+        println!("Installed only as optional dependencies");
+        let width = optional_only
+            .iter()
+            .map(|o| o.name.len())
+            .max()
+            .unwrap_or(0);
+        for pkg in optional_only {
+            println!(
+                "  {:width$}  optional for {}",
+                pkg.name,
+                pkg.optional_for.join(", ")
+            );
+        }
+    }
+}
+
+/// Single summary line, e.g. "12 updates (184 MiB) · last update 3d ago · mirror OK".
+pub fn format_short(stats: &ManagerStats) -> String {
+    let mut parts = Vec::new();
+
+    if let Some(upgradable) = stats.total_upgradable {
+        parts.push(match (upgradable, stats.download_size_mb) {
+            (0, _) => "up to date".to_string(),
+            (n, Some(mb)) => format!(
+                "{} update{} ({:.0} MiB)",
+                n,
+                if n != 1 { "s" } else { "" },
+                mb
+            ),
+            (n, None) => format!("{} update{}", n, if n != 1 { "s" } else { "" }),
+        });
+    }
+
+    if let Some(secs) = stats.days_since_last_update {
+        parts.push(format!("last update {} ago", util::compact_duration(secs)));
+    }
+
+    parts.push(match (&stats.mirror_url, stats.mirror_sync_age_hours) {
+        (Some(_), Some(_)) => "mirror OK".to_string(),
+        (Some(_), None) => "mirror Err".to_string(),
+        (None, _) => "no mirror".to_string(),
+    });
+
+    parts.join(&format!(" {} ", util::symbols().separator))
+}
+
+/// Render the configured pre-upgrade template: `{stat_name}` placeholders take the stat's
+/// value and `{pending}` becomes a list of the pending upgrades, with repo and download
+/// size when `verbose`.
+#[cfg(target_os = "linux")]
+pub fn display_upgrade_template(
+    template: &str,
+    stats: &ManagerStats,
+    config: &Config,
+    pending: &[PendingUpdate],
+    verbose: bool,
+) {
+    let text = format_upgrade_template(template, stats, config, pending, verbose);
+    let skin = if util::output_mode() == util::OutputMode::Graphics {
+        MadSkin::default()
+    } else {
+        MadSkin::no_style()
+    };
+    skin.print_text(&text);
+    println!();
+}
+
+/// The pre-upgrade template with its placeholders filled in, as markdown.
+#[cfg(target_os = "linux")]
+pub fn format_upgrade_template(
+    template: &str,
+    stats: &ManagerStats,
+    config: &Config,
+    pending: &[PendingUpdate],
+    verbose: bool,
+) -> String {
+    let mut text = template.to_string();
+    for stat_id in stats::template_stats(template) {
+        let value = stat_id
+            .format_value(stats, &config.display.format_of(stat_id))
+            .unwrap_or_else(|| "-".to_string());
+        text = text.replace(&stat_id.placeholder(), &value);
+    }
+
+    let arrow = util::symbols().arrow;
+    let pending_list: Vec<String> = pending
+        .iter()
+        .map(|u| {
+            let mut line = match &u.old_version {
+                Some(old) => format!("* {} {} {} {}", u.name, old, arrow, u.new_version),
+                None => format!("* {} {} (new)", u.name, u.new_version),
+            };
+            if let Some(previous) = &u.previous_repo {
+                line.push_str(&format!(" [{} {} {}]", previous, arrow, u.repo));
+            }
+            // Like pacman's VerbosePkgLists: where it comes from and what it costs
+            if verbose {
+                line.push_str(&format!(
+                    " ({}, {:.2} MiB)",
+                    u.repo,
+                    u.download_size as f64 / 1048576.0
+                ));
+            }
+            line
+        })
+        .collect();
+    text.replace("{pending}", &pending_list.join("\n"))
+}
+
+/// All stats as a flat name -> value map. Numeric stats are plain numbers (sizes in MiB)
+/// so they can be compared by whatever consumes them.
+pub fn stats_json(
+    stats: &ManagerStats,
+    config: &Config,
+) -> serde_json::Map<String, serde_json::Value> {
+    StatId::ALL
+        .into_iter()
+        .map(|id| {
+            let value = match id.numeric_value(stats) {
+                Some(n) if id.is_count() => serde_json::json!(n as u64),
+                Some(n) => serde_json::json!(n),
+                None => match id.format_value(stats, &config.display.format_of(id)) {
+                    Some(text) => serde_json::Value::String(util::strip_ansi(&text)),
+                    None => serde_json::Value::Null,
+                },
+            };
+            (id.name(), value)
+        })
+        .collect()
+}
+
+/// All stats as Ansible local facts: a flat JSON object, or an INI `[pacfetch]` section.
+pub fn format_facts(stats: &ManagerStats, config: &Config, ini: bool) -> String {
+    let facts = stats_json(stats, config);
+
+    if !ini {
+        return serde_json::Value::Object(facts).to_string();
+    }
+
+    let mut lines = vec!["[pacfetch]".to_string()];
+    for (key, value) in &facts {
+        let value = match value {
+            serde_json::Value::String(text) => text.clone(),
+            serde_json::Value::Null => String::new(),
+            other => other.to_string(),
+        };
+        lines.push(format!("{}={}", key, value));
+    }
+    lines.join("\n")
+}
+
+/// Numeric stats as one InfluxDB line protocol point, e.g. for Telegraf's exec input.
+pub fn format_line_protocol(stats: &ManagerStats, host: &str, timestamp: i64) -> String {
+    let host = host.replace([' ', ','], "_");
+    let fields: Vec<String> = stats::numeric_metrics(stats)
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    format!(
+        "pacfetch,host={} {} {}",
+        host,
+        fields.join(","),
+        timestamp * 1_000_000_000
+    )
+}
+
+/// Numeric stats as OpenMetrics gauges, for Prometheus to scrape.
+pub fn format_openmetrics(stats: &ManagerStats) -> String {
+    let mut text = String::new();
+    for (name, value) in stats::numeric_metrics(stats) {
+        text.push_str(&format!("# TYPE pacfetch_{} gauge\n", name));
+        text.push_str(&format!("pacfetch_{} {}\n", name, value));
+    }
+    text.push_str("# EOF\n");
+    text
+}
+
+/// Numeric stats as zabbix_sender input; "-" uses the host from the agent config.
+pub fn format_zabbix(stats: &ManagerStats) -> String {
+    stats::numeric_metrics(stats)
+        .into_iter()
+        .map(|(name, value)| format!("- pacfetch.{} {}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Shown for a stat whose probe hasn't answered yet.
+const WAITING: &str = "checking...";
+
+pub fn display_stats(stats: &ManagerStats, config: &Config, previous: Option<&StatsSnapshot>) {
+    print!(
+        "{}",
+        format_stats(stats, config, previous, util::terminal_width())
+    );
+}
+
+pub fn display_aggregate(sections: &[SourceStats], config: &Config) {
+    print!(
+        "{}",
+        format_aggregate(sections, config, util::terminal_width())
+    );
+}
+
+/// Each backend's stats under its own heading, then what they add up to when there's more
+/// than one.
+pub fn format_aggregate(
+    sections: &[SourceStats],
+    config: &Config,
+    term_width: Option<usize>,
+) -> String {
+    let mut out = Vec::new();
+    let mut push_section = |title: &str, stats: &ManagerStats, ids: &[StatId]| {
+        if !out.is_empty() {
+            out.push(String::new());
+        }
+        out.push(title.to_string());
+        out.push("-".repeat(title.len()));
+        for stat_id in ids {
+            let Some(value) = stat_id.format_value(stats, &config.display.format_of(*stat_id))
+            else {
+                continue;
+            };
+            let indent = stat_id.label().len() + 2;
+            let width = term_width.map(|w| w.saturating_sub(indent));
+            let mut lines = fit_value(*stat_id, &value, width).into_iter();
+            out.push(format!(
+                "{}: {}",
+                stat_id.label(),
+                lines.next().unwrap_or_default()
+            ));
+            out.extend(lines.map(|line| format!("{:indent$}{}", "", line)));
+        }
+    };
+
+    for section in sections {
+        push_section(&section.name, &section.stats, &config.display.stats);
+    }
+    if sections.len() > 1 {
+        let ids: Vec<StatId> = config
+            .display
+            .stats
+            .iter()
+            .filter(|id| managers::TOTAL_STATS.contains(id))
+            .copied()
+            .collect();
+        push_section("Total", &managers::totals(sections), &ids);
+    }
+    if out.is_empty() {
+        out.push("No supported package managers found.".to_string());
+    }
+    out.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// Show the local stats in `stats` right away, then fill in each of the `pending` ones
+/// in place as its probe answers, and return the stats with all of them in.
+pub fn display_stats_as_gathered(
+    mut stats: ManagerStats,
+    config: &Config,
+    previous: Option<&StatsSnapshot>,
+    graphics: bool,
+    pending: PendingStats,
+) -> ManagerStats {
+    let render = |stats: &ManagerStats, waiting: &[StatId]| {
+        let width = util::terminal_width();
+        if graphics {
+            render_stats_with_graphics(stats, config, previous, waiting, width)
+        } else {
+            render_stats(stats, config, previous, waiting, width)
+        }
+    };
+    let mut waiting = pending.stats.clone();
+    let mut shown = render(&stats, &waiting);
+    // Lines scrolled off the top can't be redrawn, so a display that doesn't fit waits
+    let rows = crossterm::terminal::size().map_or(0, |(_, rows)| rows as usize);
+    let redraw = shown.matches('\n').count() < rows;
+    if redraw {
+        print!("{}", shown);
+        let _ = io::Write::flush(&mut io::stdout());
+    }
+    while let Some((ids, gathered)) = pending.next() {
+        managers::merge_network_stats(&mut stats, gathered);
+        waiting.retain(|id| !ids.contains(id));
+        if redraw {
+            shown = redraw_stats(&shown, &render(&stats, &waiting));
+        }
+    }
+    // A probe that died never reports back; show its stat as missing
+    if redraw {
+        redraw_stats(&shown, &render(&stats, &[]));
+    } else {
+        print!("{}", render(&stats, &[]));
+    }
+    stats
+}
+
+/// Replace `shown`, the display last printed, with `next`.
+fn redraw_stats(shown: &str, next: &str) -> String {
+    let mut stdout = io::stdout();
+    let _ = crossterm::execute!(
+        stdout,
+        crossterm::cursor::MoveUp(shown.matches('\n').count() as u16),
+        crossterm::terminal::Clear(crossterm::terminal::ClearType::FromCursorDown)
+    );
+    print!("{}", next);
+    let _ = io::Write::flush(&mut stdout);
+    next.to_string()
+}
+
+/// The plain stats listing, with values wrapped to fit `term_width` columns.
+pub fn format_stats(
+    stats: &ManagerStats,
+    config: &Config,
+    previous: Option<&StatsSnapshot>,
+    term_width: Option<usize>,
+) -> String {
+    render_stats(stats, config, previous, &[], term_width)
+}
+
+/// The plain listing, with a placeholder for each stat still `waiting` on its probe.
+fn render_stats(
+    stats: &ManagerStats,
+    config: &Config,
+    previous: Option<&StatsSnapshot>,
+    waiting: &[StatId],
+    term_width: Option<usize>,
+) -> String {
+    let mut out = Vec::new();
+
     // Header
     if let Some(version) = &stats.pacman_version {
         let dashes = "-".repeat(version.len());
-        println!("{}", version);
-        println!("{}", dashes);
+        out.push(version.clone());
+        out.push(dashes);
     } else {
-        println!("----- pacfetch -----");
+        out.push("----- pacfetch -----".to_string());
     }
 
     // stats
     for stat_id in &config.display.stats {
-        if let Some(value) = stat_id.format_value(stats) {
-            println!("{}: {}", stat_id.label(), value);
+        if waiting.contains(stat_id) {
+            out.push(format!("{}: {}", stat_id.label(), WAITING));
+        } else if let Some(value) =
+            stat_id.format_value_since(stats, &config.display.format_of(*stat_id), previous)
+        {
+            let indent = stat_id.label().len() + 2;
+            let width = term_width.map(|w| w.saturating_sub(indent));
+            let mut lines = fit_value(*stat_id, &value, width).into_iter();
+            let mut first = lines.next().unwrap_or_default();
+            if let Some(delta) = previous.and_then(|p| p.delta(*stat_id, stats)) {
+                first.push_str(&format!(" ({})", stat_id.format_delta(delta)));
+            }
+            out.push(format!("{}: {}", stat_id.label(), first));
+            for line in lines {
+                out.push(format!("{:indent$}{}", "", line));
+            }
         }
     }
+
+    out.iter().map(|line| format!("{}\n", line)).collect()
 }
 
-pub fn display_stats_with_graphics(stats: &ManagerStats, config: &Config) -> io::Result<()> {
-    let ascii_art = &ascii::PACMAN_ART;
+pub fn display_stats_with_graphics(
+    stats: &ManagerStats,
+    config: &Config,
+    previous: Option<&StatsSnapshot>,
+) -> io::Result<()> {
+    print!(
+        "{}",
+        format_stats_with_graphics(stats, config, previous, util::terminal_width())
+    );
+    Ok(())
+}
+
+/// The stats next to the pacman art, with colors and the palette rows.
+pub fn format_stats_with_graphics(
+    stats: &ManagerStats,
+    config: &Config,
+    previous: Option<&StatsSnapshot>,
+    term_width: Option<usize>,
+) -> String {
+    render_stats_with_graphics(stats, config, previous, &[], term_width)
+}
+
+fn render_stats_with_graphics(
+    stats: &ManagerStats,
+    config: &Config,
+    previous: Option<&StatsSnapshot>,
+    waiting: &[StatId],
+    term_width: Option<usize>,
+) -> String {
+    let ascii_art: &[&str] = match util::symbol_set() {
+        SymbolSet::Unicode => &ascii::PACMAN_ART,
+        SymbolSet::Ascii => &ascii::PACMAN_ART_ASCII,
+    };
 
     // Build stat lines from config
     let mut stats_lines = vec![];
@@ -36,19 +963,55 @@ pub fn display_stats_with_graphics(stats: &ManagerStats, config: &Config) -> io:
         stats_lines.push(dashes);
     }
 
+    // Room left for a stat value once the art and label are printed
+    let art_width = ascii_art
+        .iter()
+        .map(|l| l.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 1;
+
     // Add stats
     for stat_id in &config.display.stats {
-        let value = stat_id.format_value(stats).unwrap_or_else(|| "-".to_string());
+        if waiting.contains(stat_id) {
+            stats_lines.push(format!(
+                "{}: {}",
+                stat_id.label().bold().with(Yellow),
+                WAITING.dim()
+            ));
+            continue;
+        }
+        let value = stat_id
+            .format_value_since(stats, &config.display.format_of(*stat_id), previous)
+            .unwrap_or_else(|| "-".to_string());
         let formatted_value = if *stat_id == StatId::MirrorHealth {
             match (&stats.mirror_url, stats.mirror_sync_age_hours) {
-                (Some(_), Some(age)) => format!("{} (last sync {:.1} hours)", "OK".green(), age),
+                (Some(_), Some(age)) => format!(
+                    "{} (last sync {:.1} hours){}",
+                    "OK".green(),
+                    age,
+                    stats::clock_note(stats)
+                ),
                 (Some(_), None) => format!("{} - could not check sync status", "Err".red()),
                 (None, _) => format!("{} - no mirror found", "Err".red()),
             }
         } else {
             value
         };
-        stats_lines.push(format!("{}: {}", stat_id.label().bold().with(Yellow), formatted_value));
+        let indent = stat_id.label().len() + 2;
+        let width = term_width.map(|w| w.saturating_sub(art_width + indent));
+        let mut lines = fit_value(*stat_id, &formatted_value, width).into_iter();
+        let mut first = lines.next().unwrap_or_default();
+        if let Some(delta) = previous.and_then(|p| p.delta(*stat_id, stats)) {
+            first.push(' ');
+            first.push_str(&format_delta_colored(*stat_id, delta));
+        }
+        stats_lines.push(format!(
+            "{}: {}",
+            stat_id.label().bold().with(Yellow),
+            first
+        ));
+        stats_lines.extend(lines.map(|line| format!("{:indent$}{}", "", line)));
     }
 
     stats_lines.push(String::new());
@@ -70,14 +1033,312 @@ pub fn display_stats_with_graphics(stats: &ManagerStats, config: &Config) -> io:
     stats_lines.push(color_row_1);
     stats_lines.push(color_row_2);
 
-    println!();
+    let mut out = String::from("\n");
     let max_lines = ascii_art.len().max(stats_lines.len());
     for i in 0..max_lines {
-        let art_line = ascii_art.get(i).copied().unwrap_or("                       ");
+        let art_line = ascii_art.get(i).copied().unwrap_or("");
+        let art_line = format!("{:width$}", art_line, width = art_width - 1);
         let stat_line = stats_lines.get(i).map(|s| s.as_str()).unwrap_or("");
-        println!("{} {}", art_line.cyan(), stat_line);
+        out.push_str(&format!("{} {}\n", art_line.cyan(), stat_line));
     }
 
-    println!();
-    Ok(())
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheUsage;
+    use crate::distro::Distro;
+    use crate::managers::SourceStats;
+    use crate::stats::{
+        ChangeCount, DataCost, FilesDbAge, GroupSuggestion, RepoHealth, UpgradeBreakdown,
+    };
+
+    fn fake_stats() -> ManagerStats {
+        ManagerStats {
+            total_installed: Some(1234),
+            total_upgradable: Some(12),
+            outdated_channels: None,
+            upgrade_breakdown: Some(UpgradeBreakdown {
+                installs: ChangeCount {
+                    count: 1,
+                    size_mb: 4.5,
+                },
+                upgrades: ChangeCount {
+                    count: 11,
+                    size_mb: 180.0,
+                },
+                downgrades: ChangeCount::default(),
+                removals: ChangeCount {
+                    count: 1,
+                    size_mb: 3.25,
+                },
+            }),
+            days_since_last_update: Some(3 * 86400 + 3600),
+            download_size_mb: Some(184.5),
+            download_cached_mb: Some(20.0),
+            total_installed_size_mb: Some(512.25),
+            net_upgrade_size_mb: Some(12.75),
+            orphaned_packages: Some(3),
+            orphaned_size_mb: Some(45.5),
+            cache_size_mb: Some(2048.0),
+            cache_usage: Some(CacheUsage {
+                bytes: 2_147_483_648,
+                files: 310,
+                by_extension: [
+                    ("pkg.tar.zst".to_string(), (300, 2_147_000_000)),
+                    ("sig".to_string(), (10, 483_648)),
+                ]
+                .into(),
+            }),
+            mirror_url: Some("https://geo.mirror.pkgbuild.com/".to_string()),
+            mirror_sync_age_hours: Some(1.5),
+            pacman_version: Some("Pacman v7.0.0 - libalpm v15.0.0".to_string()),
+            upgrade_eta_secs: Some(95.0),
+            pending_hooks: Some(vec![
+                "Updating linux initcpios".to_string(),
+                "Arming ConditionNeedsUpdate".to_string(),
+            ]),
+            aur_updates: Some(2),
+            aur_build_eta_secs: Some(600.0),
+            major_upgrade_reasons: Some(vec!["kernel upgrade".to_string()]),
+            repo_health: Some(vec![
+                RepoHealth {
+                    name: "chaotic-aur".to_string(),
+                    sync_age_hours: Some(2.25),
+                },
+                RepoHealth {
+                    name: "homelab".to_string(),
+                    sync_age_hours: None,
+                },
+            ]),
+            distro: Some(Distro::Manjaro),
+            branch: Some("stable".to_string()),
+            branch_lag_hours: Some(216.0),
+            testing_updates: Some(1),
+            files_db_ages: Some(vec![
+                FilesDbAge {
+                    repo: "core".to_string(),
+                    age_secs: Some(2 * 86400),
+                },
+                FilesDbAge {
+                    repo: "extra".to_string(),
+                    age_secs: Some(20 * 86400),
+                },
+            ]),
+            group_suggestions: Some(vec![GroupSuggestion {
+                name: "xfce4".to_string(),
+                meta: false,
+                explicit: 13,
+                total: 15,
+            }]),
+            clock_skew_secs: None,
+            last_update_warning: None,
+            other_sources: Some(vec![SourceStats {
+                name: "flatpak".to_string(),
+                stats: ManagerStats {
+                    total_installed: Some(45),
+                    total_upgradable: Some(3),
+                    download_size_mb: Some(120.0),
+                    ..Default::default()
+                },
+            }]),
+            monthly_download_mb: Some(850.0),
+            data_cost: Some(DataCost {
+                currency: "$".to_string(),
+                pending: Some(1.93),
+                monthly: Some(8.91),
+            }),
+            store_size_mb: Some(8192.0),
+            foreign_packages: Some(14),
+            aur_download_size_mb: Some(56.5),
+        }
+    }
+
+    fn fake_config() -> Config {
+        let mut config = Config::default();
+        config.display.stats = StatId::ALL.to_vec();
+        config
+    }
+
+    #[cfg(target_os = "linux")]
+    fn fake_pending() -> Vec<PendingUpdate> {
+        vec![PendingUpdate {
+            name: "linux".to_string(),
+            old_version: Some("6.9.6.arch1-1".to_string()),
+            new_version: "6.9.7.arch1-1".to_string(),
+            repo: "core".to_string(),
+            description: None,
+            url: None,
+            download_size: 150 * 1048576,
+            package_size: 150 * 1048576,
+            installed_size: 190 * 1048576,
+            old_installed_size: Some(189 * 1048576),
+            pulled_in: Vec::new(),
+            build_date: 1718000000,
+            previous_repo: None,
+            kernel: true,
+        }]
+    }
+
+    #[test]
+    fn text_output() {
+        insta::assert_snapshot!(format_stats(&fake_stats(), &fake_config(), None, Some(80)));
+    }
+
+    /// The snapshots hold the escapes, which crossterm leaves out when NO_COLOR is set.
+    fn force_color() {
+        termimad::crossterm::style::force_color_output(true);
+    }
+
+    #[test]
+    fn graphics_output() {
+        force_color();
+        insta::assert_snapshot!(format_stats_with_graphics(
+            &fake_stats(),
+            &fake_config(),
+            None,
+            Some(120)
+        ));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn template_markdown() {
+        force_color();
+        let template = "# {upgradable} updates\n\nDownload: **{download_size}**\n\n{pending}";
+        let text = format_upgrade_template(
+            template,
+            &fake_stats(),
+            &fake_config(),
+            &fake_pending(),
+            true,
+        );
+        insta::assert_snapshot!(MadSkin::default().text(&text, Some(80)).to_string());
+    }
+
+    #[test]
+    fn aggregate_output() {
+        let mut config = Config::default();
+        config.display.stats = vec![
+            StatId::Installed,
+            StatId::Upgradable,
+            StatId::DownloadSize,
+            StatId::AurUpdates,
+        ];
+        let flatpak = ManagerStats {
+            total_installed: Some(45),
+            total_upgradable: Some(3),
+            download_size_mb: Some(120.0),
+            ..ManagerStats::default()
+        };
+        let sections = [
+            SourceStats {
+                name: "pacman".to_string(),
+                stats: fake_stats(),
+            },
+            SourceStats {
+                name: "flatpak".to_string(),
+                stats: flatpak,
+            },
+        ];
+        insta::assert_snapshot!(format_aggregate(&sections, &config, Some(100)));
+    }
+
+    #[test]
+    fn usage_output() {
+        let now = 1_720_000_000;
+        let usage = [
+            CommandUsage {
+                command: "stats".to_string(),
+                runs: 42,
+                failures: 1,
+                avg_secs: 1.25,
+                max_secs: 6.5,
+                last_run: now - 3600,
+            },
+            CommandUsage {
+                command: "-Syu".to_string(),
+                runs: 6,
+                failures: 0,
+                avg_secs: 95.0,
+                max_secs: 240.0,
+                last_run: now - 3 * 86400,
+            },
+        ];
+        insta::assert_snapshot!(format_usage(&usage, now));
+    }
+
+    #[test]
+    fn short_output() {
+        insta::assert_snapshot!(format_short(&fake_stats()));
+    }
+
+    #[test]
+    fn json_output() {
+        insta::assert_snapshot!(format_facts(&fake_stats(), &fake_config(), false));
+    }
+
+    #[test]
+    fn ini_output() {
+        insta::assert_snapshot!(format_facts(&fake_stats(), &fake_config(), true));
+    }
+
+    #[test]
+    fn exports_counts_as_integers() {
+        let json = format_facts(&fake_stats(), &fake_config(), false);
+        let facts: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let ini = format_facts(&fake_stats(), &fake_config(), true);
+        for id in StatId::ALL.into_iter().filter(StatId::is_count) {
+            assert!(facts[id.name()].is_u64(), "{} in {}", id.name(), json);
+            let line = ini
+                .lines()
+                .find_map(|line| line.strip_prefix(&format!("{}=", id.name())))
+                .unwrap();
+            assert!(line.parse::<u64>().is_ok(), "{}={}", id.name(), line);
+        }
+        assert!(facts["cache_size"].is_f64());
+    }
+
+    #[test]
+    fn openmetrics_output() {
+        insta::assert_snapshot!(format_openmetrics(&fake_stats()));
+    }
+
+    #[test]
+    fn line_protocol_output() {
+        insta::assert_snapshot!(format_line_protocol(
+            &fake_stats(),
+            "testhost",
+            1_700_000_000
+        ));
+    }
+
+    #[test]
+    fn zabbix_output() {
+        insta::assert_snapshot!(format_zabbix(&fake_stats()));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn formats_cache_savings() {
+        let one = CacheSavings {
+            packages: 1,
+            bytes: 3 * 1048576,
+        };
+        assert_eq!(
+            format_cache_savings(&one, (40.5, 8)),
+            "Saved 3.00 MiB via cache (1 package already downloaded); 40.50 MiB over the last 8 upgrades"
+        );
+        let partial = CacheSavings {
+            packages: 0,
+            bytes: 1048576 / 2,
+        };
+        assert_eq!(
+            format_cache_savings(&partial, (0.5, 1)),
+            "Saved 0.50 MiB via cache; 0.50 MiB over the last 1 upgrade"
+        );
+    }
 }
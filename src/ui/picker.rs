@@ -0,0 +1,281 @@
+use crate::terminal::TerminalGuard;
+use crate::util;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::Terminal;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{List, ListItem, ListState, Paragraph};
+use std::collections::BTreeSet;
+use std::fs::{File, OpenOptions};
+use std::io;
+
+/// One choice: `key` is what the query matches against, `label` what's shown.
+#[derive(Clone)]
+pub struct PickItem {
+    pub key: String,
+    pub label: String,
+}
+
+impl PickItem {
+    pub fn new(key: impl Into<String>, label: impl Into<String>) -> PickItem {
+        PickItem {
+            key: key.into(),
+            label: label.into(),
+        }
+    }
+}
+
+/// Indices of the items matching `query`, best first; every item in order for no query.
+fn rank(query: &str, items: &[PickItem]) -> Vec<usize> {
+    let mut scored: Vec<(i64, usize)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| util::fuzzy_score(query, &item.key).map(|s| (s, i)))
+        .collect();
+    if !query.is_empty() {
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    }
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Finds the items for a query, in the order to show them.
+type Search<'a> = dyn FnMut(&str) -> Vec<PickItem> + 'a;
+
+struct Picker<'a> {
+    prompt: &'a str,
+    items: Vec<PickItem>,
+    /// Asked for the items on every change of the query, instead of filtering `items`.
+    search: Option<&'a mut Search<'a>>,
+    multi: bool,
+    query: String,
+    visible: Vec<usize>,
+    list_state: ListState,
+    /// Keys of the marked items, which stay marked while a search changes the list.
+    marked: BTreeSet<String>,
+}
+
+impl<'a> Picker<'a> {
+    fn new(prompt: &'a str, items: Vec<PickItem>, multi: bool) -> Self {
+        Picker {
+            prompt,
+            items,
+            search: None,
+            multi,
+            query: String::new(),
+            visible: Vec::new(),
+            list_state: ListState::default(),
+            marked: BTreeSet::new(),
+        }
+    }
+
+    fn refilter(&mut self) {
+        self.visible = match self.search.as_mut() {
+            Some(search) => {
+                self.items = search(&self.query);
+                (0..self.items.len()).collect()
+            }
+            None => rank(&self.query, &self.items),
+        };
+        self.list_state
+            .select((!self.visible.is_empty()).then_some(0));
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let last = self.visible.len() as isize - 1;
+        self.list_state
+            .select(Some(current.saturating_add(delta).clamp(0, last) as usize));
+    }
+
+    fn current(&self) -> Option<usize> {
+        self.visible.get(self.list_state.selected()?).copied()
+    }
+
+    /// Keys of the marked items, or of the one under the cursor when nothing is marked.
+    fn chosen(&self) -> Vec<String> {
+        if self.marked.is_empty() {
+            self.current()
+                .map(|i| self.items[i].key.clone())
+                .into_iter()
+                .collect()
+        } else {
+            self.marked.iter().cloned().collect()
+        }
+    }
+
+    fn run(&mut self, terminal: &mut Terminal<CrosstermBackend<File>>) -> io::Result<Vec<String>> {
+        loop {
+            terminal.draw(|frame| self.render(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                KeyCode::Esc => return Ok(Vec::new()),
+                KeyCode::Char('c') if ctrl => return Ok(Vec::new()),
+                KeyCode::Enter => return Ok(self.chosen()),
+                KeyCode::Down => self.move_selection(1),
+                KeyCode::Char('n') if ctrl => self.move_selection(1),
+                KeyCode::Up => self.move_selection(-1),
+                KeyCode::Char('p') if ctrl => self.move_selection(-1),
+                KeyCode::PageDown => self.move_selection(10),
+                KeyCode::PageUp => self.move_selection(-10),
+                KeyCode::Tab if self.multi => {
+                    if let Some(key) = self.current().map(|i| self.items[i].key.clone())
+                        && !self.marked.remove(&key)
+                    {
+                        self.marked.insert(key);
+                    }
+                    self.move_selection(1);
+                }
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.refilter();
+                }
+                KeyCode::Char('u') if ctrl => {
+                    self.query.clear();
+                    self.refilter();
+                }
+                KeyCode::Char(c) if !ctrl => {
+                    self.query.push(c);
+                    self.refilter();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn render(&mut self, frame: &mut ratatui::Frame) {
+        let [input_area, count_area, list_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Min(1),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(
+            Paragraph::new(Line::from(vec![
+                Span::styled(format!("{} ", self.prompt), Style::new().fg(Color::Cyan)),
+                Span::raw(self.query.clone()),
+            ])),
+            input_area,
+        );
+        frame.set_cursor_position((
+            input_area.x + (self.prompt.chars().count() + 1 + self.query.chars().count()) as u16,
+            input_area.y,
+        ));
+
+        let mut count = format!("  {}/{}", self.visible.len(), self.items.len());
+        if self.multi {
+            count.push_str(&format!(" ({} marked, tab to mark)", self.marked.len()));
+        }
+        frame.render_widget(Paragraph::new(count).dark_gray(), count_area);
+
+        let rows: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let mark = if self.marked.contains(&self.items[i].key) {
+                    "* "
+                } else {
+                    "  "
+                };
+                ListItem::new(format!("{}{}", mark, self.items[i].label))
+            })
+            .collect();
+        let list = List::new(rows)
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, list_area, &mut self.list_state);
+    }
+}
+
+/// Let the user fuzzy-pick from `items` and return the chosen indices, empty when they
+/// cancel. Draws on the controlling terminal, so stdout can be piped to another command.
+pub fn pick(prompt: &str, items: &[PickItem], multi: bool) -> Result<Vec<usize>, String> {
+    if items.is_empty() {
+        return Ok(Vec::new());
+    }
+    let chosen = run_picker(Picker::new(prompt, items.to_vec(), multi))?;
+    Ok((0..items.len())
+        .filter(|&i| chosen.contains(&items[i].key))
+        .collect())
+}
+
+/// Let the user pick from what `search` finds for the query as they type it, starting from
+/// `query`, and return the keys of the chosen items.
+pub fn pick_live(
+    prompt: &str,
+    query: &str,
+    multi: bool,
+    mut search: impl FnMut(&str) -> Vec<PickItem>,
+) -> Result<Vec<String>, String> {
+    let mut picker = Picker::new(prompt, Vec::new(), multi);
+    picker.query = query.to_string();
+    picker.search = Some(&mut search);
+    run_picker(picker)
+}
+
+fn run_picker(mut picker: Picker) -> Result<Vec<String>, String> {
+    let tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|_| "the picker needs a terminal".to_string())?;
+    picker.refilter();
+
+    let result = (|| {
+        let _screen = TerminalGuard::fullscreen(tty.try_clone()?, false)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(tty))?;
+        picker.run(&mut terminal)
+    })();
+    result.map_err(|e| format!("Picker failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_by_fuzzy_score() {
+        let items: Vec<PickItem> = ["python-pip", "pipewire", "vim", "lib32-pipewire"]
+            .into_iter()
+            .map(|name| PickItem::new(name, name))
+            .collect();
+        assert_eq!(rank("", &items), [0, 1, 2, 3]);
+        let ranked = rank("pipew", &items);
+        assert_eq!(ranked[0], 1);
+        assert!(!ranked.contains(&2));
+    }
+
+    #[test]
+    fn keeps_marks_while_a_search_changes_the_list() {
+        let mut search = |query: &str| {
+            ["vim", "vim-airline", "neovim"]
+                .into_iter()
+                .filter(|name| name.contains(query))
+                .map(|name| PickItem::new(name, name))
+                .collect()
+        };
+        let mut picker = Picker::new("Packages>", Vec::new(), true);
+        picker.query = "vim-".to_string();
+        picker.search = Some(&mut search);
+        picker.refilter();
+        assert_eq!(picker.visible, [0]);
+        picker.marked.insert("vim-airline".to_string());
+
+        picker.query = "neo".to_string();
+        picker.refilter();
+        assert_eq!(picker.items[picker.current().unwrap()].key, "neovim");
+        assert_eq!(picker.chosen(), ["vim-airline"]);
+    }
+}
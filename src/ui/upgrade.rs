@@ -0,0 +1,433 @@
+use crate::config::SymbolSet;
+use crate::progress::{PHASES, Phase, ProgressParser};
+use crate::pty::PtyHandler;
+use crate::terminal::TerminalGuard;
+use crate::util;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{LineGauge, Paragraph};
+use ratatui::{Frame, Terminal, TerminalOptions, Viewport};
+use std::collections::VecDeque;
+use std::io::{self, Stdout};
+use std::time::{Duration, Instant};
+
+/// Lines of raw pacman output kept for the output pane.
+const RAW_LINES: usize = 8;
+/// Height of the inline viewport: borders, phases, activity line, pane header and pane.
+const SCREEN_HEIGHT: u16 = 2 + PHASES.len() as u16 + 2 + RAW_LINES as u16;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, PartialEq)]
+enum PhaseState {
+    Pending,
+    Skipped,
+    Running,
+    Done,
+}
+
+struct PhaseProgress {
+    phase: Phase,
+    state: PhaseState,
+    ratio: f64,
+    started: Option<Instant>,
+    elapsed: Option<Duration>,
+}
+
+/// Structured view of `pacman -Su` once the user has confirmed the transaction.
+///
+/// Until then output goes to `passthrough` so the package list and confirmation prompt look
+/// exactly like plain mode.
+pub struct UpgradeScreen<'a> {
+    passthrough: &'a mut dyn PtyHandler,
+    terminal: Option<Terminal<CrosstermBackend<Stdout>>>,
+    /// Raw mode while the viewport is up; dropped with the terminal.
+    screen: Option<TerminalGuard>,
+    parser: ProgressParser,
+    phases: Vec<PhaseProgress>,
+    activity: String,
+    raw: VecDeque<String>,
+    raw_redrawing: bool,
+    show_raw: bool,
+    finished: bool,
+    prompt: Option<(String, String)>,
+    warnings: Vec<String>,
+    started: Instant,
+    last_draw: Instant,
+    /// Draw the gauge like pacman's ILoveCandy progress bar.
+    candy: bool,
+}
+
+impl<'a> UpgradeScreen<'a> {
+    pub fn new(passthrough: &'a mut dyn PtyHandler, synced: bool, candy: bool) -> Self {
+        let phases = PHASES
+            .iter()
+            .map(|&phase| PhaseProgress {
+                phase,
+                state: if phase != Phase::Sync {
+                    PhaseState::Pending
+                } else if synced {
+                    PhaseState::Done
+                } else {
+                    PhaseState::Skipped
+                },
+                ratio: if phase == Phase::Sync && synced {
+                    1.0
+                } else {
+                    0.0
+                },
+                started: None,
+                elapsed: None,
+            })
+            .collect();
+
+        UpgradeScreen {
+            passthrough,
+            terminal: None,
+            screen: None,
+            parser: ProgressParser::default(),
+            phases,
+            activity: String::new(),
+            raw: VecDeque::with_capacity(RAW_LINES),
+            raw_redrawing: false,
+            show_raw: false,
+            finished: false,
+            prompt: None,
+            warnings: Vec::new(),
+            started: Instant::now(),
+            last_draw: Instant::now(),
+            candy,
+        }
+    }
+
+    fn start(&mut self) {
+        let Ok(screen) = TerminalGuard::raw() else {
+            return;
+        };
+        let options = TerminalOptions {
+            viewport: Viewport::Inline(SCREEN_HEIGHT),
+        };
+        // Without a terminal the guard drops here, leaving raw mode again
+        if let Ok(terminal) = Terminal::with_options(CrosstermBackend::new(io::stdout()), options) {
+            self.terminal = Some(terminal);
+            self.screen = Some(screen);
+            self.started = Instant::now();
+            self.draw(true);
+        }
+    }
+
+    /// Render the final summary and hand the terminal back.
+    pub fn finish(&mut self) {
+        if self.terminal.is_none() {
+            return;
+        }
+        for progress in &mut self.phases {
+            match progress.state {
+                PhaseState::Running => {
+                    progress.state = PhaseState::Done;
+                    progress.ratio = 1.0;
+                    progress.elapsed = progress.started.map(|s| s.elapsed());
+                }
+                PhaseState::Pending => progress.state = PhaseState::Skipped,
+                _ => {}
+            }
+        }
+        self.prompt = None;
+        self.activity = format!(
+            "Finished in {} with {} warning{}",
+            format_elapsed(self.started.elapsed()),
+            self.warnings.len(),
+            if self.warnings.len() != 1 { "s" } else { "" }
+        );
+        self.show_raw = true;
+        self.finished = true;
+        self.draw(true);
+        self.restore();
+    }
+
+    fn restore(&mut self) {
+        if self.terminal.take().is_some() {
+            self.screen = None;
+            println!();
+        }
+    }
+
+    fn phase_mut(&mut self, phase: Phase) -> &mut PhaseProgress {
+        let index = PHASES.iter().position(|&p| p == phase).unwrap_or(0);
+        &mut self.phases[index]
+    }
+
+    fn record(&mut self, line: &str, redraw: bool) {
+        let clean = util::strip_ansi(line);
+        let trimmed = clean.trim();
+        if trimmed.is_empty() {
+            return;
+        }
+
+        if trimmed.starts_with("warning:") || trimmed.starts_with("error:") {
+            self.warnings.push(trimmed.to_string());
+        }
+
+        if let Some(update) = self.parser.feed(trimmed) {
+            // Reaching a phase means every earlier one has ended
+            let now = Instant::now();
+            for progress in &mut self.phases {
+                if progress.phase == update.phase {
+                    break;
+                }
+                if progress.state == PhaseState::Running {
+                    progress.state = PhaseState::Done;
+                    progress.ratio = 1.0;
+                    progress.elapsed = progress.started.map(|s| now - s);
+                } else if progress.state == PhaseState::Pending {
+                    progress.state = PhaseState::Skipped;
+                }
+            }
+
+            let progress = self.phase_mut(update.phase);
+            if progress.state != PhaseState::Running {
+                progress.state = PhaseState::Running;
+                progress.started = Some(now);
+            }
+            if let Some(ratio) = update.ratio {
+                progress.ratio = ratio;
+            }
+            if let Some(item) = update.item {
+                self.activity = item;
+            }
+        }
+
+        // A redrawn line replaces its previous version, like it would on the terminal
+        if self.raw_redrawing {
+            self.raw.pop_back();
+        } else if self.raw.len() == RAW_LINES {
+            self.raw.pop_front();
+        }
+        self.raw.push_back(trimmed.to_string());
+        self.raw_redrawing = redraw;
+    }
+
+    fn draw(&mut self, force: bool) {
+        if !force && self.last_draw.elapsed() < REDRAW_INTERVAL {
+            return;
+        }
+        self.last_draw = Instant::now();
+
+        let Some(mut terminal) = self.terminal.take() else {
+            return;
+        };
+        let _ = terminal.draw(|frame| self.render(frame));
+        self.terminal = Some(terminal);
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let block = super::block()
+            .title(" pacfetch upgrade ".bold().yellow())
+            .border_style(Style::new().fg(Color::Cyan));
+        let inner = block.inner(frame.area());
+        frame.render_widget(block, frame.area());
+
+        let rows = Layout::vertical([
+            Constraint::Length(PHASES.len() as u16),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Min(0),
+        ])
+        .split(inner);
+
+        let phase_rows = Layout::vertical(vec![Constraint::Length(1); PHASES.len()]).split(rows[0]);
+        for (progress, area) in self.phases.iter().zip(phase_rows.iter()) {
+            self.render_phase(frame, progress, *area);
+        }
+
+        let activity = match &self.prompt {
+            Some((prompt, input)) => Line::from(vec![
+                prompt.clone().bold().yellow(),
+                input.clone().into(),
+                util::symbols().cursor.into(),
+            ]),
+            None => Line::from(self.activity.clone().dark_gray()),
+        };
+        frame.render_widget(Paragraph::new(activity), rows[1]);
+
+        let rule = util::symbols().rule;
+        let header = if self.show_raw {
+            format!("{} pacman output (o to hide) {}", rule, rule)
+        } else {
+            format!("{} pacman output hidden (o to show) {}", rule, rule)
+        };
+        frame.render_widget(Paragraph::new(header.dark_gray()), rows[2]);
+
+        if self.show_raw {
+            let lines: Vec<Line> = if self.finished && !self.warnings.is_empty() {
+                self.warnings
+                    .iter()
+                    .rev()
+                    .take(RAW_LINES)
+                    .rev()
+                    .map(|w| Line::from(w.clone().red()))
+                    .collect()
+            } else {
+                self.raw.iter().map(|l| Line::from(l.clone())).collect()
+            };
+            frame.render_widget(Paragraph::new(lines), rows[3]);
+        }
+    }
+
+    fn render_phase(&self, frame: &mut Frame, progress: &PhaseProgress, area: Rect) {
+        let symbols = util::symbols();
+        let (marker, style) = match progress.state {
+            PhaseState::Pending => (symbols.pending, Style::new().fg(Color::DarkGray)),
+            PhaseState::Skipped => ("-", Style::new().fg(Color::DarkGray)),
+            PhaseState::Running => (symbols.pointer, Style::new().fg(Color::Yellow)),
+            PhaseState::Done => (symbols.check, Style::new().fg(Color::Green)),
+        };
+
+        let columns = Layout::horizontal([Constraint::Length(18), Constraint::Min(10)]).split(area);
+        frame.render_widget(
+            Paragraph::new(format!("{} {}", marker, progress.phase.label())).style(style),
+            columns[0],
+        );
+
+        let label = match (progress.state, progress.elapsed, progress.started) {
+            (PhaseState::Skipped, _, _) => "skipped".to_string(),
+            (_, Some(elapsed), _) => format_elapsed(elapsed),
+            (PhaseState::Running, None, Some(started)) => {
+                format!(
+                    "{:>3.0}% {}",
+                    progress.ratio * 100.0,
+                    format_elapsed(started.elapsed())
+                )
+            }
+            _ => String::new(),
+        };
+        let mut gauge = LineGauge::default().ratio(progress.ratio);
+        if self.candy {
+            gauge = gauge.filled_symbol("-").unfilled_symbol("o");
+        } else if util::symbol_set() == SymbolSet::Ascii {
+            gauge = gauge.filled_symbol("=").unfilled_symbol("-");
+        }
+        let gauge = gauge
+            .label(label)
+            .filled_style(style.add_modifier(Modifier::BOLD))
+            .unfilled_style(Style::new().fg(Color::DarkGray));
+        frame.render_widget(gauge, columns[1]);
+    }
+
+    fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Option<String> {
+        if code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL) {
+            return Some("\x03".to_string());
+        }
+        if code == KeyCode::Char('o') {
+            self.show_raw = !self.show_raw;
+            self.draw(true);
+        }
+        None
+    }
+
+    /// Reads an answer inside the viewport, since stdin is in raw mode.
+    fn read_answer(&mut self, prompt: &str) -> Option<String> {
+        self.prompt = Some((prompt.trim().to_string(), String::new()));
+        self.draw(true);
+
+        let answer = loop {
+            let key = match event::read() {
+                Ok(Event::Key(key)) => key,
+                Ok(_) => continue,
+                Err(_) => break None,
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let Some((_, input)) = self.prompt.as_mut() else {
+                break None;
+            };
+            match key.code {
+                KeyCode::Enter => break Some(input.clone()),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    break Some("n".to_string());
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            }
+            self.draw(true);
+        };
+
+        self.prompt = None;
+        self.draw(true);
+        answer
+    }
+}
+
+impl Drop for UpgradeScreen<'_> {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+impl PtyHandler for UpgradeScreen<'_> {
+    fn line(&mut self, line: &str) {
+        if self.terminal.is_none() {
+            self.passthrough.line(line);
+            return;
+        }
+        self.record(line, false);
+        self.draw(false);
+    }
+
+    fn redraw(&mut self, line: &str) {
+        if self.terminal.is_none() {
+            self.passthrough.redraw(line);
+            return;
+        }
+        self.record(line, true);
+        self.draw(false);
+    }
+
+    fn prompt(&mut self, prompt: &str) -> Option<String> {
+        if self.terminal.is_some() {
+            return self.read_answer(prompt);
+        }
+
+        let answer = self.passthrough.prompt(prompt);
+        let confirmed = answer
+            .as_deref()
+            .is_some_and(|a| a.trim().is_empty() || a.trim().eq_ignore_ascii_case("y"));
+        if prompt.contains("Proceed with installation") && confirmed {
+            self.start();
+        }
+        answer
+    }
+
+    fn idle(&mut self) -> Option<String> {
+        if self.terminal.is_none() {
+            return self.passthrough.idle();
+        }
+
+        let mut input = None;
+        while let Ok(true) = event::poll(Duration::ZERO) {
+            if let Ok(Event::Key(key)) = event::read()
+                && key.kind == KeyEventKind::Press
+                && let Some(send) = self.handle_key(key.code, key.modifiers)
+            {
+                input = Some(send);
+            }
+        }
+        self.draw(false);
+        input
+    }
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
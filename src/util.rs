@@ -1,6 +1,116 @@
+use crate::config::{DurationStyle, SymbolSet};
+use chrono::{Local, Locale};
 use indicatif::{ProgressBar, ProgressStyle};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use std::time::Duration;
 
+/// How pacfetch decorates its terminal output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Spinners, progress bars, colors and ascii art.
+    Graphics,
+    /// Plain linear status lines with explicit percentages, for screen readers.
+    Accessible,
+    /// No ANSI codes, spinners or PTY; used when stdout is not a terminal.
+    Plain,
+}
+
+static OUTPUT_MODE: OnceLock<OutputMode> = OnceLock::new();
+
+/// Set the output mode for the rest of the run. Only the first call has an effect.
+pub fn set_output_mode(mode: OutputMode) {
+    let _ = OUTPUT_MODE.set(mode);
+}
+
+pub fn output_mode() -> OutputMode {
+    OUTPUT_MODE.get().copied().unwrap_or(OutputMode::Graphics)
+}
+
+pub fn is_accessible() -> bool {
+    output_mode() == OutputMode::Accessible
+}
+
+pub fn is_plain() -> bool {
+    output_mode() == OutputMode::Plain
+}
+
+static PROGRESS_JSON: OnceLock<bool> = OnceLock::new();
+
+/// Emit upgrade progress as JSON lines on stderr. Only the first call has an effect.
+pub fn set_progress_json(enabled: bool) {
+    let _ = PROGRESS_JSON.set(enabled);
+}
+
+pub fn progress_json() -> bool {
+    PROGRESS_JSON.get().copied().unwrap_or(false)
+}
+
+/// Glyphs for status markers and decorations.
+pub struct Symbols {
+    pub check: &'static str,
+    pub pending: &'static str,
+    pub pointer: &'static str,
+    pub arrow: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+    pub up_down: &'static str,
+    pub separator: &'static str,
+    pub cursor: &'static str,
+    pub rule: &'static str,
+    pub ellipsis: &'static str,
+    pub spinner: &'static str,
+}
+
+const UNICODE_SYMBOLS: Symbols = Symbols {
+    check: "✓",
+    pending: "·",
+    pointer: "›",
+    arrow: "→",
+    up: "↑",
+    down: "↓",
+    up_down: "↑↓",
+    separator: "·",
+    cursor: "█",
+    rule: "──",
+    ellipsis: "…",
+    spinner: "⠁⠂⠄⡀⢀⠠⠐⠈ ",
+};
+
+const ASCII_SYMBOLS: Symbols = Symbols {
+    check: "ok",
+    pending: ".",
+    pointer: ">",
+    arrow: "->",
+    up: "^",
+    down: "v",
+    up_down: "up/down",
+    separator: "|",
+    cursor: "_",
+    rule: "--",
+    ellipsis: "...",
+    spinner: "|/-\\ ",
+};
+
+static SYMBOL_SET: OnceLock<SymbolSet> = OnceLock::new();
+
+/// Set the symbol set for the rest of the run. Only the first call has an effect.
+pub fn set_symbol_set(set: SymbolSet) {
+    let _ = SYMBOL_SET.set(set);
+}
+
+pub fn symbol_set() -> SymbolSet {
+    SYMBOL_SET.get().copied().unwrap_or_default()
+}
+
+pub fn symbols() -> &'static Symbols {
+    match symbol_set() {
+        SymbolSet::Unicode => &UNICODE_SYMBOLS,
+        SymbolSet::Ascii => &ASCII_SYMBOLS,
+    }
+}
+
 /// Convert seconds to a human-readable duration string
 pub fn normalize_duration(seconds: i64) -> String {
     if seconds < 60 {
@@ -29,19 +139,87 @@ pub fn normalize_duration(seconds: i64) -> String {
     )
 }
 
+/// Convert seconds to a short duration string like "3d 4h"
+pub fn compact_duration(seconds: i64) -> String {
+    if seconds < 60 {
+        return format!("{}s", seconds);
+    }
+    if seconds < 3600 {
+        return format!("{}m", seconds / 60);
+    }
+    if seconds < 86400 {
+        let minutes = (seconds % 3600) / 60;
+        if minutes == 0 {
+            return format!("{}h", seconds / 3600);
+        }
+        return format!("{}h {}m", seconds / 3600, minutes);
+    }
+
+    let hours = (seconds % 86400) / 3600;
+    if hours == 0 {
+        return format!("{}d", seconds / 86400);
+    }
+    format!("{}d {}h", seconds / 86400, hours)
+}
+
+pub fn format_duration(seconds: i64, style: DurationStyle) -> String {
+    match style {
+        DurationStyle::Long => normalize_duration(seconds),
+        DurationStyle::Compact => compact_duration(seconds),
+    }
+}
+
+/// The locale for dates, from LC_ALL, LC_TIME or LANG (e.g. "de_DE.UTF-8").
+fn time_locale() -> Locale {
+    ["LC_ALL", "LC_TIME", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| {
+            let name = value.split(['.', '@']).next().unwrap_or_default();
+            Locale::try_from(name).ok()
+        })
+        .unwrap_or(Locale::POSIX)
+}
+
+/// Format the local time `offset_secs` from now, in the locale's date and time by default.
+/// Offsets past what chrono can represent come out as "-".
+pub fn format_time(offset_secs: i64, format: Option<&str>) -> String {
+    let Some(time) = chrono::Duration::try_seconds(offset_secs)
+        .and_then(|offset| Local::now().checked_add_signed(offset))
+    else {
+        return "-".to_string();
+    };
+    time.format_localized(format.unwrap_or("%c"), time_locale())
+        .to_string()
+}
+
 /// Create a spinner with the given message
 pub fn create_spinner(message: &str) -> ProgressBar {
+    match output_mode() {
+        OutputMode::Graphics => {}
+        OutputMode::Accessible => {
+            println!("{}...", message);
+            return ProgressBar::hidden();
+        }
+        OutputMode::Plain => return ProgressBar::hidden(),
+    }
+
     let pb = ProgressBar::new_spinner();
-    pb.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.cyan} {msg}")
-            .unwrap(),
-    );
+    pb.set_style(spinner_style("{spinner:.cyan} {msg}"));
     pb.set_message(message.to_string());
     pb.enable_steady_tick(Duration::from_millis(80));
     pb
 }
 
+/// Spinner style with the given template, ticking with the configured symbols.
+pub fn spinner_style(template: &str) -> ProgressStyle {
+    ProgressStyle::default_spinner()
+        .template(template)
+        .unwrap()
+        .tick_chars(symbols().spinner)
+}
+
 /// Strip ANSI escape codes from a string
 pub fn strip_ansi(s: &str) -> String {
     let mut result = String::new();
@@ -60,6 +238,133 @@ pub fn strip_ansi(s: &str) -> String {
     result
 }
 
+/// Width of the terminal stdout is attached to, or None when output isn't a terminal.
+pub fn terminal_width() -> Option<usize> {
+    use std::io::IsTerminal;
+
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    crossterm::terminal::size()
+        .ok()
+        .map(|(cols, _)| cols as usize)
+}
+
+/// Shorten a string to `max` characters by replacing its middle with an ellipsis.
+pub fn truncate_middle(s: &str, max: usize) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    if chars.len() <= max {
+        return s.to_string();
+    }
+    let ellipsis = symbols().ellipsis;
+    if max <= ellipsis.chars().count() {
+        return chars[..max].iter().collect();
+    }
+
+    let keep = max - ellipsis.chars().count();
+    let head = keep.div_ceil(2);
+    let tail = keep - head;
+    let mut result: String = chars[..head].iter().collect();
+    result.push_str(ellipsis);
+    result.extend(&chars[chars.len() - tail..]);
+    result
+}
+
+/// Break a comma or space separated list into lines of at most `width` characters.
+pub fn wrap_list(s: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for word in s.split(' ') {
+        let needed = if current.is_empty() {
+            word.chars().count()
+        } else {
+            current.chars().count() + 1 + word.chars().count()
+        };
+        if needed > width && !current.is_empty() {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Shell-style wildcard match with `*` and `?`, where `*` also matches `/` (like fnmatch without flags).
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Check if an executable is on PATH, as e.g. winget.exe on Windows
+pub fn command_exists(name: &str) -> bool {
+    let exe = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(&exe).is_file()))
+}
+
+/// The machine's hostname, for tagging exported metrics.
+pub fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+/// Cache directories of the current user and, under sudo, of the user who ran sudo.
+pub fn user_cache_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = dirs::cache_dir().into_iter().collect();
+    if let Some(home) = sudo_user_home() {
+        let cache = home.join(".cache");
+        if !dirs.contains(&cache) {
+            dirs.push(cache);
+        }
+    }
+    dirs
+}
+
+/// The home directory of the user who ran sudo, when running as root under it.
+pub fn sudo_user_home() -> Option<PathBuf> {
+    #[cfg(unix)]
+    if is_root()
+        && let Ok(user) = std::env::var("SUDO_USER")
+        && let Ok(name) = std::ffi::CString::new(user)
+    {
+        let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+        if !passwd.is_null() {
+            let home = unsafe { std::ffi::CStr::from_ptr((*passwd).pw_dir) };
+            return Some(PathBuf::from(home.to_string_lossy().into_owned()));
+        }
+    }
+    None
+}
+
 /// Check if running as root
 pub fn is_root() -> bool {
     #[cfg(unix)]
@@ -71,3 +376,151 @@ pub fn is_root() -> bool {
         false
     }
 }
+
+/// Score a case-insensitive subsequence match of `pattern` in `text`; higher is better
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last: Option<usize> = None;
+
+    for pc in pattern.to_lowercase().chars() {
+        let idx = pos + text[pos..].iter().position(|&c| c == pc)?;
+
+        score += 1;
+        if idx > 0 && last == Some(idx - 1) {
+            score += 5;
+        }
+        if idx == 0 || !text[idx - 1].is_alphanumeric() {
+            score += 3;
+        }
+        score -= (idx - pos) as i64;
+
+        last = Some(idx);
+        pos = idx + 1;
+    }
+
+    Some(score)
+}
+
+/// Package names in a list read from stdin or a file: whitespace separated, with `#`
+/// starting a comment, so `pacman -Qqe` output and hand-written lists both work.
+pub fn parse_package_list(text: &str) -> Vec<String> {
+    text.lines()
+        .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Package names from the command line, where "-" stands for the list on stdin, plus
+/// those in `from_file`, which can be "-" too.
+pub fn read_package_list(args: &[String], from_file: Option<&Path>) -> Result<Vec<String>, String> {
+    collect_package_list(args, from_file, std::io::stdin().lock())
+}
+
+/// `read_package_list` with `stdin` for "-". It's only read the first time, since a pipe
+/// is empty after that.
+fn collect_package_list(
+    args: &[String],
+    from_file: Option<&Path>,
+    mut stdin: impl Read,
+) -> Result<Vec<String>, String> {
+    let mut stdin_read = false;
+    let mut read_stdin = || -> Result<Vec<String>, String> {
+        let mut text = String::new();
+        if !std::mem::replace(&mut stdin_read, true) {
+            stdin
+                .read_to_string(&mut text)
+                .map_err(|e| format!("Failed to read stdin: {}", e))?;
+        }
+        Ok(parse_package_list(&text))
+    };
+
+    let mut names = Vec::new();
+    for arg in args {
+        if arg == "-" {
+            names.extend(read_stdin()?);
+        } else {
+            names.push(arg.clone());
+        }
+    }
+    match from_file {
+        Some(path) if path == Path::new("-") => names.extend(read_stdin()?),
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            names.extend(parse_package_list(&text));
+        }
+        None => {}
+    }
+    Ok(names)
+}
+
+/// A client with the short timeout mirror checks use.
+pub fn http_client() -> Option<reqwest::blocking::Client> {
+    reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(5))
+        .build()
+        .ok()
+}
+
+/// Seconds from a logged time to `now`, or a warning when the log dates it after `now`,
+/// which means the clock was set back or the log was written under another timezone.
+pub fn seconds_since_logged(logged: i64, now: i64) -> Result<i64, String> {
+    if logged > now {
+        Err(format!(
+            "logged {} in the future, check the clock",
+            compact_duration(logged - now)
+        ))
+    } else {
+        Ok(now - logged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn parses_package_lists() {
+        let list = "linux linux-headers\n# kept back\nmesa  # until 24.1 is fixed\n\n";
+        assert_eq!(parse_package_list(list), ["linux", "linux-headers", "mesa"]);
+    }
+
+    #[test]
+    fn reads_package_lists_from_stdin() {
+        let args = ["-".to_string(), "mesa".to_string()];
+        let names = collect_package_list(
+            &args,
+            Some(Path::new("-")),
+            "linux # kernel\nzstd\n".as_bytes(),
+        );
+        assert_eq!(names.unwrap(), ["linux", "zstd", "mesa"]);
+        let names = collect_package_list(&[], Some(Path::new("-")), "linux\n".as_bytes());
+        assert_eq!(names.unwrap(), ["linux"]);
+    }
+
+    proptest! {
+        #[test]
+        fn durations_never_panic(seconds in any::<i64>()) {
+            prop_assert!(!normalize_duration(seconds).is_empty());
+            prop_assert!(!compact_duration(seconds).is_empty());
+        }
+
+        #[test]
+        fn format_time_never_panics(offset in any::<i64>()) {
+            prop_assert!(!format_time(offset, None).is_empty());
+        }
+
+        #[test]
+        fn compact_duration_splits_days(days in 1..10_000i64, hours in 0..24i64) {
+            let expected = if hours == 0 {
+                format!("{}d", days)
+            } else {
+                format!("{}d {}h", days, hours)
+            };
+            prop_assert_eq!(compact_duration(days * 86400 + hours * 3600), expected);
+        }
+    }
+}